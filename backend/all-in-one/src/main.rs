@@ -0,0 +1,61 @@
+//! Runs the API server, DCL and Analytics Server in a single process.
+//!
+//! The components communicate over an [`InMemoryBus`] instead of Kafka, meaning only `MongoDB`
+//! is needed to run the whole flow from uploading a dataset to receiving predictions. Messages
+//! are lost when the process exits, so this is only intended for development and testing.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use config::Environment;
+use messages::{InMemoryBus, MessageBus};
+
+#[actix_rt::main]
+async fn main() -> anyhow::Result<()> {
+    let filters = vec![
+        ("all_in_one", log::LevelFilter::Debug),
+        ("api_server", log::LevelFilter::Debug),
+        ("analytics", log::LevelFilter::Debug),
+        ("dcl", log::LevelFilter::Debug),
+        ("config", log::LevelFilter::Debug),
+        ("messages", log::LevelFilter::Debug),
+        ("models", log::LevelFilter::Debug),
+        ("utils", log::LevelFilter::Debug),
+        ("actix_server", log::LevelFilter::Info),
+    ];
+
+    utils::setup_logger_with_filters(filters);
+
+    let environment = if cfg!(debug_assertions) {
+        Environment::Development
+    } else {
+        Environment::Production
+    };
+
+    config::load(environment);
+
+    let bus: Arc<dyn MessageBus> = Arc::new(InMemoryBus::new());
+
+    log::info!("Running every component in a single process over an in-memory bus");
+
+    let dcl_bus = Arc::clone(&bus);
+    let dcl = tokio::spawn(async move { dcl::run_with_bus(dcl_bus).await });
+
+    let analytics_bus = Arc::clone(&bus);
+    tokio::spawn(async move {
+        if let Err(e) = analytics::run_with_bus(analytics_bus).await {
+            log::error!("Analytics Server failed: {}", e);
+        }
+    });
+
+    // Both the server and the DCL stop when the process receives a termination signal
+    let server = api_server::build_server_with_bus(bus)
+        .await
+        .map_err(|e| anyhow!("Failed to build the API server: {}", e))?;
+    server.await?;
+
+    log::info!("API server stopped, waiting for the DCL to shut down");
+    dcl.await??;
+
+    Ok(())
+}
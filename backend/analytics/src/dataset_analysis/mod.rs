@@ -9,6 +9,7 @@ use mongodb::{
     Database,
 };
 
+use messages::{KafkaWsMessage, MessageBus};
 use models::dataset_analysis::DatasetAnalysis;
 use models::dataset_analysis::{CategoricalAnalysis, ColumnAnalysis, NumericalAnalysis};
 use models::dataset_details::DatasetDetails;
@@ -22,7 +23,12 @@ const BIN_COUNT: u64 = 4;
 /// Takes the project id and locates the linked dataset
 /// Extracts + decompresses the data and the dataset column info
 /// Calls the analysis function and stores the results in the database
-pub async fn prepare_dataset(database: &Arc<Database>, project_id: &ObjectId) -> Result<()> {
+/// Tells the project's user through the message bus once the analysis is stored
+pub async fn prepare_dataset(
+    database: &Arc<Database>,
+    bus: &dyn MessageBus,
+    project_id: &ObjectId,
+) -> Result<()> {
     let datasets = database.collection("datasets");
     let dataset_details = database.collection("dataset_details");
     let dataset_analysis = database.collection("dataset_analysis");
@@ -40,7 +46,7 @@ pub async fn prepare_dataset(database: &Arc<Database>, project_id: &ObjectId) ->
     let doc = files
         .find_one(train_filter, None)
         .await?
-        .expect("Failed to find a document with the previous filter");
+        .ok_or_else(|| anyhow!("Training data doesn't exist"))?;
     let train_file: gridfs::File = mongodb::bson::de::from_document(doc)?;
     let comp_train: Vec<u8> = train_file.download_dataset(&database).await?;
 
@@ -71,6 +77,13 @@ pub async fn prepare_dataset(database: &Arc<Database>, project_id: &ObjectId) ->
     let analysis = DatasetAnalysis::new(project_id.clone(), analysis);
     let document = to_document(&analysis)?;
     dataset_analysis.insert_one(document, None).await?;
+
+    let project_id = project_id.to_string();
+    let message = KafkaWsMessage::AnalysisCompleteMessage {
+        project_id: project_id.clone(),
+    };
+    message.notify(database, bus, &project_id).await;
+
     Ok(())
 }
 
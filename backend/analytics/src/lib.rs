@@ -6,36 +6,38 @@
 #![warn(missing_debug_implementations)]
 #![warn(missing_docs)]
 
-use anyhow::Result;
-use mongodb::bson::oid::ObjectId;
+use anyhow::{Context, Result};
 use mongodb::options::ClientOptions;
-use mongodb::Client;
+use mongodb::{Client, Database};
 use std::env;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::{stream_consumer::StreamConsumer, Consumer, DefaultConsumerContext};
-use rdkafka::Message;
-use std::net::{Ipv4Addr, SocketAddrV4};
-use tokio_stream::StreamExt;
+use messages::{envelope, AnalyticsRequested, Consumer, MessageBus};
+use utils::metrics;
 
 mod dataset_analysis;
 
 /// Main runner function for the Analytics Server
 ///
-/// This function is called when starting up the Analytics Server. It starts the
-/// tokio runtime and sets up its connection with the MongoDB database.
-/// It will then spawn threads for the different parts of the DCL to
-/// offer the full functionality of the product.
-
+/// This function is called when starting up the Analytics Server. It creates
+/// the [`MessageBus`] described by the environment, before running the server
+/// with [`run_with_bus`].
 pub async fn run() -> Result<()> {
+    let bus = messages::bus::from_env()?;
+
+    run_with_bus(bus).await
+}
+
+/// Runs the Analytics Server using the given [`MessageBus`]
+///
+/// Sets up the connection with the MongoDB database and then consumes
+/// analytics jobs from the bus until the subscription ends.
+pub async fn run_with_bus(bus: Arc<dyn MessageBus>) -> Result<()> {
     let conn_str = env::var("CONN_STR").expect("CONN_STR must be set");
     let app_name = env::var("APP_NAME").expect("APP_NAME must be set");
 
-    let broker_socket =
-        u16::from_str(&env::var("BROKER_PORT").unwrap_or_else(|_| "9092".to_string()))
-            .expect("BROKER_PORT must be a u16");
     let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| String::from("sybl"));
 
     let mut client_options = ClientOptions::parse(&conn_str).await.unwrap();
@@ -44,46 +46,57 @@ pub async fn run() -> Result<()> {
     let client = Client::with_options(client_options).unwrap();
     let database = Arc::new(client.database(&database_name));
 
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, broker_socket).to_string();
-    log::info!("Broker Socket: {:?}", addr);
+    if let Ok(port) = env::var("ANALYTICS_METRICS_PORT") {
+        let port = u16::from_str(&port).expect("ANALYTICS_METRICS_PORT must be a u16");
 
-    let consumer: StreamConsumer<DefaultConsumerContext> = ClientConfig::new()
-        .set("group.id", "analytics")
-        .set("bootstrap.servers", addr)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()
-        .expect("Consumer creation failed");
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(port).await {
+                log::error!("Metrics endpoint failed: {}", e);
+            }
+        });
+    }
+
+    consume(database, bus).await
+}
+
+/// Consumes analytics jobs from the message bus
+///
+/// Each message contains the identifier of a project whose dataset should be analysed. This runs
+/// until the subscription ends, so it can be used to run the Analytics Server alongside other
+/// components that share the same [`MessageBus`]. Messages that cannot be read, or whose dataset
+/// cannot be analysed, are sent to the dead letter topic, `analytics.dlq`.
+pub async fn consume(database: Arc<Database>, bus: Arc<dyn MessageBus>) -> Result<()> {
+    let consumer = Consumer::new(Arc::clone(&bus), "analytics", &["analytics"]);
 
     consumer
-        .subscribe(&["analytics"])
-        .expect("Can't subscribe to analytics");
-
-    // Ignore any errors in the stream
-    let mut message_stream = consumer.stream().filter_map(Result::ok);
-
-    while let Some(message) = message_stream.next().await {
-        // Interpret the content as a string
-        let payload = match message.payload_view::<[u8]>() {
-            // This cannot fail, `rdkafka` always returns `Ok(bytes)`
-            Some(view) => view.unwrap(),
-            None => {
-                log::warn!("Received an empty message from Kafka");
-                continue;
-            }
-        };
+        .run(|message| {
+            let database = Arc::clone(&database);
+            let bus = Arc::clone(&bus);
 
-        let project_id: ObjectId = serde_json::from_slice(&payload).unwrap();
+            async move {
+                let event: AnalyticsRequested = envelope::decode(&message.payload)
+                    .context("Failed to deserialize an analytics job")?;
+                let project_id = event.project_id;
 
-        log::debug!(
-            "Timestamp: {:?}, Payload: {}",
-            message.timestamp(),
-            &project_id
-        );
+                log::debug!(
+                    "Timestamp: {:?}, Payload: {}",
+                    message.timestamp,
+                    &project_id
+                );
 
-        dataset_analysis::prepare_dataset(&database, &project_id).await?;
-    }
-    Ok(())
+                metrics::record_kafka_lag("analytics", message.timestamp);
+
+                let start = Instant::now();
+                let result =
+                    dataset_analysis::prepare_dataset(&database, bus.as_ref(), &project_id).await;
+
+                let outcome = if result.is_ok() { "success" } else { "failure" };
+                metrics::ANALYTICS_PROCESSING_TIME
+                    .with_label_values(&[outcome])
+                    .observe(start.elapsed().as_secs_f64());
+
+                result
+            }
+        })
+        .await
 }
@@ -5,6 +5,8 @@ use mongodb::bson;
 use serde::Serialize;
 use thiserror::Error;
 
+use model_auth::AuthError;
+
 /// Defines a custom result type.
 pub type ServerResult<T> = std::result::Result<T, ServerError>;
 /// Defines the default response type of each handler.
@@ -34,6 +36,9 @@ pub enum ServerError {
     /// Payment Required HTTP Error
     #[error("Payment Required")]
     PaymentRequired,
+    /// Service Unavailable HTTP Error
+    #[error("Service Unavailable")]
+    ServiceUnavailable,
 }
 
 impl ServerError {
@@ -47,6 +52,7 @@ impl ServerError {
             Self::Conflict => "Conflict",
             Self::Unauthorized => "Unauthorized",
             Self::PaymentRequired => "PaymentRequired",
+            Self::ServiceUnavailable => "ServiceUnavailable",
         }
     }
 }
@@ -81,6 +87,21 @@ error_map! {
     actix_multipart::MultipartError => UnprocessableEntity,
 }
 
+impl From<AuthError> for ServerError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::NotFound => Self::NotFound,
+            AuthError::Forbidden => Self::Forbidden,
+            AuthError::Conflict => Self::Conflict,
+            AuthError::Unauthorized | AuthError::Locked | AuthError::Expired { .. } => {
+                Self::Unauthorized
+            }
+            AuthError::Invalid => Self::UnprocessableEntity,
+            AuthError::Internal(_) | AuthError::Unavailable(_) => Self::Unknown,
+        }
+    }
+}
+
 impl ResponseError for ServerError {
     /// Function to return the HTTP status code of Enum
     fn status_code(&self) -> StatusCode {
@@ -92,6 +113,7 @@ impl ResponseError for ServerError {
             Self::Conflict => StatusCode::CONFLICT,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::PaymentRequired => StatusCode::PAYMENT_REQUIRED,
+            Self::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 
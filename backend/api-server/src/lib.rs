@@ -11,19 +11,25 @@ extern crate serde;
 #[macro_use]
 extern crate serde_json;
 
-use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
-use actix::prelude::Addr;
 use actix_cors::Cors;
-use actix_web::{middleware, web, App, HttpServer, Result};
+use actix_web::{dev::Service, middleware, web, App, HttpServer, Result};
+use futures::future::FutureExt;
 use mongodb::{options::ClientOptions, Client, Database};
 
+use mailer::Mailer;
+use messages::MessageBus;
+use sessions::Sessions;
+
 pub mod auth;
 pub mod error;
 pub mod routes;
+pub mod sessions;
+pub mod webhooks;
 
 /// Defines the state for each request to access.
 #[derive(Clone, Debug)]
@@ -34,13 +40,21 @@ pub struct State {
     pub pepper: Arc<String>,
     /// The number of iterations to use for hashing
     pub pbkdf2_iterations: u32,
+    /// The bus used to send jobs to the other components
+    pub bus: Arc<dyn MessageBus>,
+    /// Delivers events to the webhooks users have registered
+    pub dispatcher: webhooks::Dispatcher,
+    /// Sends emails to users, if emails are enabled
+    pub mailer: Option<Mailer>,
 }
 
 /// State to pass to websockets and the kafka consumer
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct WebsocketState {
-    /// Map of userids to open sockets
-    pub map: Arc<RwLock<HashMap<String, Addr<routes::websockets::ProjectUpdateWs>>>>,
+    /// The open sessions and the projects they want updates for
+    pub sessions: Arc<RwLock<Sessions>>,
+    /// An instance of the MongoDB database, holding the history of updates
+    pub database: Arc<Database>,
 }
 
 /// Builds the default logging middleware for request logging.
@@ -65,6 +79,16 @@ fn build_logging_middleware() -> middleware::Logger {
 /// }
 /// ```
 pub async fn build_server() -> Result<actix_web::dev::Server> {
+    let bus = messages::bus::from_env().expect("Failed to create the message bus");
+
+    build_server_with_bus(bus).await
+}
+
+/// Builds the `actix-web` server using the given [`MessageBus`].
+///
+/// This behaves the same as [`build_server`], but allows the bus to be shared with other
+/// components running in the same process.
+pub async fn build_server_with_bus(bus: Arc<dyn MessageBus>) -> Result<actix_web::dev::Server> {
     let conn_str = env::var("CONN_STR").expect("CONN_STR must be set");
     let app_name = env::var("APP_NAME").expect("APP_NAME must be set");
     let pepper = env::var("PEPPER").expect("PEPPER must be set");
@@ -77,14 +101,43 @@ pub async fn build_server() -> Result<actix_web::dev::Server> {
     let client = Client::with_options(client_options).unwrap();
     let database = Arc::new(client.database(&database_name));
 
-    let map = HashMap::new();
-    let shared_state = Arc::new(RwLock::new(map));
-    let consumer_state = Arc::clone(&shared_state);
+    if let Err(e) = models::project_events::ProjectEvent::create_indexes(&database).await {
+        log::error!("Failed to create the project event indexes: {}", e);
+    }
 
-    let websocket_state_data = web::Data::new(WebsocketState { map: shared_state });
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+    let consumer_state = Arc::clone(&sessions);
+    let consumer_bus = Arc::clone(&bus);
+    let consumer_database = Arc::clone(&database);
+
+    let websocket_state_data = web::Data::new(WebsocketState {
+        sessions,
+        database: Arc::clone(&database),
+    });
 
     tokio::spawn(async move {
-        routes::websockets::consume_updates(9092, consumer_state).await;
+        routes::websockets::consume_updates(consumer_bus, consumer_database, consumer_state).await;
+    });
+
+    let mailer = Mailer::from_env().expect("Failed to create the mailer");
+
+    // Metrics are served on their own port, so that they are not exposed with the API itself
+    if let Ok(port) = env::var("API_METRICS_PORT") {
+        let port = u16::from_str(&port).expect("API_METRICS_PORT must be a u16");
+
+        tokio::spawn(async move {
+            if let Err(e) = utils::metrics::serve(port).await {
+                log::error!("Metrics endpoint failed: {}", e);
+            }
+        });
+    }
+    let dispatcher = webhooks::Dispatcher::new(Arc::clone(&database));
+    let webhook_bus = Arc::clone(&bus);
+
+    let webhook_dispatcher = dispatcher.clone();
+
+    tokio::spawn(async move {
+        webhooks::consume_updates(webhook_bus, webhook_dispatcher).await;
     });
 
     let server = HttpServer::new(move || {
@@ -100,11 +153,28 @@ pub async fn build_server() -> Result<actix_web::dev::Server> {
         App::new()
             .wrap(cors_middleware)
             .wrap(build_logging_middleware())
+            .wrap_fn(|req, srv| {
+                let start = Instant::now();
+                let method = req.method().to_string();
+
+                srv.call(req).map(move |res| {
+                    if let Ok(res) = &res {
+                        let route = res.request().match_pattern();
+                        let status = res.status().as_u16();
+                        routes::metrics::record_request(&method, route, status, start);
+                    }
+
+                    res
+                })
+            })
             .data(State {
                 database: Arc::clone(&database),
                 pepper: Arc::new(pepper.clone()),
                 pbkdf2_iterations: u32::from_str(&pbkdf2_iterations)
                     .expect("PBKDF2_ITERATIONS must be parseable as an integer"),
+                bus: Arc::clone(&bus),
+                dispatcher: dispatcher.clone(),
+                mailer: mailer.clone(),
             })
             .app_data(websocket_state_data.clone())
             .route(
@@ -160,6 +230,10 @@ pub async fn build_server() -> Result<actix_web::dev::Server> {
                 "/api/projects/{project_id}/job_statistics",
                 web::get().to(routes::projects::get_job_statistics),
             )
+            .route(
+                "/api/projects/{project_id}/events",
+                web::get().to(routes::projects::get_events),
+            )
             // Clients
             .route(
                 "/api/clients/register",
@@ -193,6 +267,28 @@ pub async fn build_server() -> Result<actix_web::dev::Server> {
                 "/api/clients/models/{model_id}/performance",
                 web::get().to(routes::clients::get_model_performance),
             )
+            .route(
+                "/api/clients/models/{model_id}/telemetry",
+                web::get().to(routes::clients::get_model_telemetry),
+            )
+            // webhooks
+            .route(
+                "/api/webhooks",
+                web::get().to(routes::webhooks::get_user_webhooks),
+            )
+            .route("/api/webhooks/new", web::post().to(routes::webhooks::new))
+            .route(
+                "/api/webhooks/{webhook_id}",
+                web::delete().to(routes::webhooks::delete),
+            )
+            .route(
+                "/api/webhooks/{webhook_id}/deliveries",
+                web::get().to(routes::webhooks::get_deliveries),
+            )
+            .route(
+                "/api/webhooks/{webhook_id}/test",
+                web::post().to(routes::webhooks::test),
+            )
             // users
             .route("/api/users", web::get().to(routes::users::get))
             .route("/api/users/filter", web::post().to(routes::users::filter))
@@ -206,11 +302,16 @@ pub async fn build_server() -> Result<actix_web::dev::Server> {
                 web::get().to(routes::users::get_avatar),
             )
             .route("/api/users/edit", web::post().to(routes::users::edit))
+            .route(
+                "/api/users/notifications",
+                web::post().to(routes::users::edit_notifications),
+            )
             .route("/api/users/login", web::post().to(routes::users::login))
             .route("/api/users/delete", web::post().to(routes::users::delete))
             .service(
                 web::resource("/project_updates").route(web::get().to(routes::websockets::index)),
             )
+            .route("/project_updates/stream", web::get().to(routes::sse::index))
     })
     .bind("0.0.0.0:3001")?
     .run();
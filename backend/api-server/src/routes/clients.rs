@@ -4,11 +4,15 @@ use std::sync::Arc;
 use actix_web::{http::StatusCode, web};
 use mongodb::bson::de::from_document;
 use mongodb::bson::ser::to_document;
-use mongodb::bson::{self, doc, document::Document, oid::ObjectId, Binary};
+use mongodb::bson::{doc, document::Document, oid::ObjectId};
 use tokio_stream::StreamExt;
 
+use model_auth::{
+    AuthBackend, AuthError, AuthResponse, DatabaseBackend, AUTHENTICATION_SUCCESSFUL,
+};
 use models::job_performance::JobPerformance;
-use models::models::{AccessToken, ClientModel};
+use models::models::ClientModel;
+use models::telemetry::{Metric, Telemetry};
 use models::users::{Client, User};
 
 use crate::{
@@ -18,6 +22,21 @@ use crate::{
     State,
 };
 
+/// The maximum number of telemetry samples returned for a model
+const TELEMETRY_LIMIT: i64 = 500;
+
+/// Struct to capture query string information
+#[derive(Deserialize, Debug)]
+pub struct TelemetryOptions {
+    /// The metric to return samples of, or all of them if missing
+    pub metric: Option<Metric>,
+}
+
+/// Builds the backend used to authenticate models against the database.
+fn auth_backend(state: &State) -> DatabaseBackend {
+    DatabaseBackend::new(Arc::clone(&state.database), Arc::clone(&state.pepper))
+}
+
 /// Upgrades a user account to a client account.
 ///
 /// Checks whether the user is already a client before ensuring the email provided is the same as
@@ -128,50 +147,11 @@ pub async fn new_model(
     state: web::Data<State>,
     payload: web::Json<payloads::NewModelOptions>,
 ) -> ServerResponse {
-    let users = state.database.collection("users");
-    let models = state.database.collection("models");
-
-    let email = crypto::clean(&payload.email);
-
-    let filter = doc! { "email": &email };
-    let document = users
-        .find_one(filter, None)
-        .await?
-        .ok_or(ServerError::NotFound)?;
-
-    let user: User = from_document(document)?;
-    let peppered = format!("{}{}", &payload.password, &state.pepper);
-    crypto::verify_password(&peppered, &user.hash)?;
-
-    if !user.client {
-        return Err(ServerError::Forbidden);
-    }
-
-    let filter = doc! { "user_id": &user.id, "name": &payload.model_name };
-    if models.find_one(filter, None).await?.is_some() {
-        log::warn!(
-            "User with id={} already has a model with name={}",
-            user.id,
-            payload.model_name
-        );
-
-        return Err(ServerError::Conflict);
-    }
-
-    // Generate challenge
-    let challenge = crypto::generate_challenge();
-    let client_model = ClientModel::new(user.id, payload.model_name.clone(), challenge.clone());
+    let challenge = auth_backend(&state)
+        .new_model(&payload.email, &payload.password, &payload.model_name)
+        .await?;
 
-    // insert model into database
-    let document = to_document(&client_model)?;
-    models.insert_one(document, None).await?;
-
-    // return challenge
-    response_from_json(doc! {
-        "Challenge": {
-            "challenge": base64::encode(challenge),
-        }
-    })
+    response_from_json(AuthResponse::Challenge(challenge))
 }
 
 /// Verifies a challenge response from a client.
@@ -184,67 +164,16 @@ pub async fn verify_challenge(
     state: web::Data<State>,
     payload: web::Json<payloads::VerifyChallengeOptions>,
 ) -> ServerResponse {
-    let users = state.database.collection("users");
-    let clients = state.database.collection("clients");
-    let models = state.database.collection("models");
-
-    let email = crypto::clean(&payload.email);
-    let filter = doc! { "email": &email };
-
-    let user_doc = users
-        .find_one(filter, None)
-        .await?
-        .ok_or(ServerError::NotFound)?;
-    let user: User = from_document(user_doc)?;
-
-    // get clients public key matching with that users id
-    let filter = doc! { "user_id": &user.id };
-    let client_doc = clients
-        .find_one(filter, None)
-        .await?
-        .ok_or(ServerError::NotFound)?;
-    let client: Client = from_document(client_doc)?;
-
-    let filter = doc! { "user_id": &user.id, "name": &payload.model_name };
-    let model_doc = models
-        .find_one(filter.clone(), None)
-        .await?
-        .ok_or(ServerError::NotFound)?;
-    let mut model: ClientModel = from_document(model_doc)?;
-
-    let public_key = client.public_key;
-    let challenge = &model.challenge.ok_or(ServerError::Unauthorized)?.bytes;
-
-    // needs converting to Vec<u8>
-    let challenge_response = base64::decode(&payload.challenge_response)?;
-
-    if !crypto::verify_challenge(&challenge, &challenge_response, &public_key) {
-        log::warn!(
-            "Provided challenge did not match expected, deleting model_id={}",
-            model.id
-        );
-
-        models.delete_one(filter, None).await?;
-
-        return Err(ServerError::Unauthorized);
-    }
-
-    let access_token = AccessToken::new();
-    model.authenticated = true;
-    model.access_token = Some(access_token.clone());
-    model.challenge = None;
-
-    let update = doc! { "$set": to_document(&model)? };
-    models.find_one_and_update(filter, update, None).await?;
+    let token = auth_backend(&state)
+        .verify_challenge(
+            &payload.email,
+            &payload.model_name,
+            &payload.challenge_response,
+        )
+        .await?;
 
     // return the access token to the model
-    response_from_json(doc! {
-        "AccessToken": {
-            "id": model.id.to_string(),
-            "token": base64::encode(access_token.clone().token.bytes),
-            "expires": access_token.expires.to_rfc3339()
-        }
-    })
+    response_from_json(AuthResponse::AccessToken(token))
 }
 
 /// Unlocks a given model using multifactor authentication.
@@ -314,52 +243,21 @@ pub async fn authenticate_model(
     model_id: web::Path<String>,
     payload: web::Json<payloads::AuthenticateModelOptions>,
 ) -> ServerResponse {
-    let models = state.database.collection("models");
-
-    let model_id = ObjectId::with_string(&model_id)?;
-    let filter = doc! { "_id": &model_id };
-    let model_doc = models
-        .find_one(filter, None)
-        .await?
-        .ok_or(ServerError::Unauthorized)?;
-    let mut model: ClientModel = from_document(model_doc)?;
-
-    if model.locked {
-        return response_from_json_with_code(doc! {"message": "Locked"}, StatusCode::UNAUTHORIZED);
-    }
-
-    let token = base64::decode(&payload.token)?;
-
-    if !model.is_authenticated(&token) {
-        log::warn!(
-            "Model failed to authenticate with token={} (base-64)",
-            payload.token
-        );
+    let outcome = auth_backend(&state)
+        .authenticate_model(&model_id, &payload.token)
+        .await;
 
-        return Err(ServerError::Unauthorized);
-    }
-
-    // Check whether their token has expired
-    if model.token_has_not_expired() {
+    match outcome {
         // TODO: authenticate the model in the session
-        response_from_json(doc! {"message": "Authentication successful"})
-    } else {
-        log::warn!("Model with id={} has an expired token", model.id);
-
-        let challenge = crypto::generate_challenge();
-        model.authenticated = false;
-        model.challenge = Some(Binary {
-            subtype: bson::spec::BinarySubtype::Generic,
-            bytes: challenge.clone(),
-        });
-
-        let filter = doc! { "_id": &model_id };
-        let update = doc! { "$set": to_document(&model)? };
-        models.find_one_and_update(filter, update, None).await?;
-
-        let json = doc! { "challenge": base64::encode(challenge) };
-
-        response_from_json_with_code(json, StatusCode::UNAUTHORIZED)
+        Ok(()) => response_from_json(doc! {"message": AUTHENTICATION_SUCCESSFUL}),
+        Err(AuthError::Locked) => {
+            response_from_json_with_code(doc! {"message": "Locked"}, StatusCode::UNAUTHORIZED)
+        }
+        Err(AuthError::Expired { challenge }) => {
+            let json = doc! { "challenge": challenge };
+            response_from_json_with_code(json, StatusCode::UNAUTHORIZED)
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -391,3 +289,35 @@ pub async fn get_model_performance(
 
     response_from_json(performances)
 }
+
+/// Gets the most recent telemetry samples recorded for a model.
+///
+/// Given a model identifier, checks that the requesting user owns the model before returning its
+/// most recent heartbeat round trip times, transfer rates and compute times per row, newest first.
+/// The `metric` parameter can be used to only return samples of a single metric.
+pub async fn get_model_telemetry(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    model_id: web::Path<String>,
+    options: web::Query<TelemetryOptions>,
+) -> ServerResponse {
+    let models = state.database.collection("models");
+
+    let model_id = ObjectId::with_string(&model_id)?;
+    let filter = doc! { "_id": &model_id };
+    let model_doc = models
+        .find_one(filter, None)
+        .await?
+        .ok_or(ServerError::NotFound)?;
+    let model: ClientModel = from_document(model_doc)?;
+
+    // Check the current user owns this model
+    if model.user_id != claims.id {
+        return Err(ServerError::Forbidden);
+    }
+
+    let samples =
+        Telemetry::get_recent(&state.database, &model_id, options.metric, TELEMETRY_LIMIT).await?;
+
+    response_from_json(samples)
+}
@@ -0,0 +1,21 @@
+//! Records metrics about the requests the API server handles.
+//!
+//! The metrics themselves are served on `API_METRICS_PORT` rather than through the API, so that
+//! they are not exposed publicly.
+
+use std::time::Instant;
+
+use utils::metrics;
+
+/// Records the latency of a request, labelled by its method, matched route and status.
+///
+/// Routes are labelled by their pattern rather than their path, such that requests for different
+/// projects are grouped together.
+pub fn record_request(method: &str, route: Option<String>, status: u16, start: Instant) {
+    let route = route.unwrap_or_else(|| String::from("unmatched"));
+    let status = status.to_string();
+
+    metrics::API_REQUEST_DURATION
+        .with_label_values(&[method, &route, &status])
+        .observe(start.elapsed().as_secs_f64());
+}
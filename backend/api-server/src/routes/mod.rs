@@ -11,9 +11,12 @@ use models::projects::Project;
 use crate::error::{ServerError, ServerResponse, ServerResult};
 
 pub mod clients;
+pub mod metrics;
 pub mod payloads;
 pub mod projects;
+pub mod sse;
 pub mod users;
+pub mod webhooks;
 pub mod websockets;
 
 /// Builds a [`Response`] with a 200 OK and JSON payload.
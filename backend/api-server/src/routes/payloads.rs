@@ -1,10 +1,13 @@
 //! Contains the expected payloads for each endpoint.
-use actix::prelude::Message;
+use std::convert::TryFrom;
 
-use mongodb::bson::{oid::ObjectId, Array, Document};
+use actix::prelude::Message;
+use mongodb::bson::{self, oid::ObjectId, Array, Document};
 
 use messages::kafka_message::KafkaWsMessage;
-use models::jobs::PredictionType;
+use models::jobs::{JobRequirements, PredictionType};
+use models::project_events::ProjectEvent;
+use models::webhooks::WebhookEvent;
 
 /// Stores the options for filtering all users.
 #[derive(Debug, Deserialize)]
@@ -66,6 +69,18 @@ pub struct NewProjectOptions {
     pub tags: Array,
 }
 
+/// Stores the options for registering a webhook.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewWebhookOptions {
+    /// Where to send events
+    pub url: String,
+    /// The project to send events for, or all of the user's projects if missing
+    pub project_id: Option<String>,
+    /// The events to send, or all of them if missing
+    pub events: Option<Vec<WebhookEvent>>,
+}
+
 /// Stores the options for uploading a dataset.
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -96,6 +111,9 @@ pub struct ProcessingOptions {
     pub prediction_type: PredictionType,
     /// The column to use for prediction
     pub prediction_column: String,
+    /// The resources nodes require to be considered for the job
+    #[serde(default)]
+    pub requirements: JobRequirements,
 }
 
 /// Stores the options for registering a new client.
@@ -158,6 +176,23 @@ pub enum WebsocketMessage {
         /// User token to authenticate
         token: String,
     },
+    /// Message sent by the client to receive updates for a project
+    ///
+    /// Until a session subscribes to a project, it receives updates for all of them.
+    Subscribe {
+        /// ID of the project to receive updates for
+        project_id: String,
+    },
+    /// Message sent by the client to stop receiving updates for a project
+    Unsubscribe {
+        /// ID of the project to stop receiving updates for
+        project_id: String,
+    },
+    /// Message sent by a reconnecting client to receive the updates it missed
+    Replay {
+        /// The sequence number of the last update the client received
+        since: i64,
+    },
     /// Message sent from server when a model completes
     ModelComplete {
         /// project id which node completed
@@ -169,11 +204,60 @@ pub enum WebsocketMessage {
         /// If the model was successfull
         success: bool,
     },
+    /// Message sent from server when a model reports its progress
+    ModelProgress {
+        /// project id which the node is working on
+        project_id: String,
+        /// The stage the model is currently at
+        stage: String,
+        /// The fraction of the job the model has completed
+        fraction: f64,
+        /// Additional information provided by the model
+        message: String,
+    },
     /// Message sent when all models complete
     ProjectComplete {
         /// ID of the completed project
         project_id: String,
     },
+    /// Message sent when the DCL has queued a job for a project
+    JobQueued {
+        /// ID of the project the job is for
+        project_id: String,
+        /// ID of the queued job
+        job_id: String,
+    },
+    /// Message sent when a job is waiting for enough nodes to become available
+    WaitingForNodes {
+        /// ID of the project the job is for
+        project_id: String,
+        /// The number of nodes the job needs
+        cluster_size: usize,
+    },
+    /// Message sent when a cluster of nodes has been chosen for a job
+    ClusterBuilt {
+        /// ID of the project the job is for
+        project_id: String,
+        /// The number of nodes in the cluster
+        cluster_size: usize,
+    },
+    /// Message sent when a project's data has been sent to a node
+    DataSent {
+        /// ID of the project the data belongs to
+        project_id: String,
+    },
+    /// Message sent when the analysis of a project's dataset is finished
+    AnalysisComplete {
+        /// ID of the analysed project
+        project_id: String,
+    },
+    /// Message sent when a job for a project cannot be run
+    ProjectFailed {
+        /// ID of the failed project
+        project_id: String,
+        /// Why the job failed
+        reason: String,
+    },
     /// Message sent from server when the user authenticates
     Hello {
         /// ID of the user
@@ -181,8 +265,36 @@ pub enum WebsocketMessage {
     },
 }
 
-impl From<&KafkaWsMessage<'_>> for WebsocketMessage {
-    fn from(msg: &KafkaWsMessage<'_>) -> Self {
+/// A project update sent to the client, numbered so that missed updates can be replayed
+///
+/// This serializes as the [`WebsocketMessage`] itself with the `sequence` and `timestamp` added
+/// after it, such as `{ "projectComplete": { .. }, "sequence": 4, "timestamp": .. }`.
+#[derive(Debug, Clone, Serialize, Message)]
+#[rtype(result = "()")]
+pub struct ProjectUpdate {
+    /// The update itself
+    #[serde(flatten)]
+    pub message: WebsocketMessage,
+    /// The position of the update in the history of all updates
+    pub sequence: i64,
+    /// When the update happened, in milliseconds since the epoch
+    pub timestamp: i64,
+}
+
+impl TryFrom<ProjectEvent> for ProjectUpdate {
+    type Error = bson::de::Error;
+
+    fn try_from(event: ProjectEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            message: bson::from_document(event.event)?,
+            sequence: event.sequence,
+            timestamp: event.date_created.0.timestamp_millis(),
+        })
+    }
+}
+
+impl From<&KafkaWsMessage> for WebsocketMessage {
+    fn from(msg: &KafkaWsMessage) -> Self {
         match msg {
             KafkaWsMessage::ClientCompleteMessage {
                 project_id,
@@ -200,6 +312,51 @@ impl From<&KafkaWsMessage<'_>> for WebsocketMessage {
                     project_id: project_id.to_string(),
                 }
             }
+            KafkaWsMessage::ClientProgressMessage {
+                project_id,
+                stage,
+                fraction,
+                message,
+            } => WebsocketMessage::ModelProgress {
+                project_id: project_id.to_string(),
+                stage: stage.to_string(),
+                fraction: *fraction,
+                message: message.to_string(),
+            },
+            KafkaWsMessage::JobQueuedMessage { project_id, job_id } => {
+                WebsocketMessage::JobQueued {
+                    project_id: project_id.to_string(),
+                    job_id: job_id.to_string(),
+                }
+            }
+            KafkaWsMessage::WaitingForNodesMessage {
+                project_id,
+                cluster_size,
+            } => WebsocketMessage::WaitingForNodes {
+                project_id: project_id.to_string(),
+                cluster_size: *cluster_size,
+            },
+            KafkaWsMessage::ClusterBuiltMessage {
+                project_id,
+                cluster_size,
+            } => WebsocketMessage::ClusterBuilt {
+                project_id: project_id.to_string(),
+                cluster_size: *cluster_size,
+            },
+            KafkaWsMessage::DataSentMessage { project_id } => WebsocketMessage::DataSent {
+                project_id: project_id.to_string(),
+            },
+            KafkaWsMessage::AnalysisCompleteMessage { project_id } => {
+                WebsocketMessage::AnalysisComplete {
+                    project_id: project_id.to_string(),
+                }
+            }
+            KafkaWsMessage::JobFailedMessage { project_id, reason } => {
+                WebsocketMessage::ProjectFailed {
+                    project_id: project_id.to_string(),
+                    reason: reason.to_string(),
+                }
+            }
         }
     }
 }
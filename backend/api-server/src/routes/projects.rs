@@ -1,5 +1,7 @@
 //! Defines the routes specific to project operations.
 
+use std::convert::TryFrom;
+use std::sync::Arc;
 use std::task::Poll;
 
 use actix_multipart::Multipart;
@@ -17,16 +19,18 @@ use mongodb::{
 };
 use tokio_stream::StreamExt;
 
-use messages::kafka_message::produce_message;
+use mailer::Notification;
+use messages::{envelope, AnalyticsRequested, JobRequested, KafkaWsMessage, MessageBus};
 use models::dataset_details::DatasetDetails;
 use models::datasets::Dataset;
 use models::gridfs;
 use models::jobs::{Job, JobConfiguration};
 use models::predictions::Prediction;
+use models::project_events::ProjectEvent;
 use models::projects::{Project, Status};
 use models::users::User;
 use utils::compress::{compress_data, decompress_data};
-use utils::finance::{job_cost, pay};
+use utils::finance::{job_cost, pay, LOW_CREDITS_THRESHOLD};
 use utils::ColumnType;
 
 use crate::{
@@ -38,6 +42,9 @@ use crate::{
 
 static CHUNK_SIZE: usize = 10_000;
 
+/// The maximum number of events returned for a project, or replayed to a session, at once
+pub(crate) const EVENT_LIMIT: i64 = 500;
+
 /// Enum to decide type of dataset to return
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
@@ -61,6 +68,15 @@ pub struct QueryInfo {
     pub per_page: usize,
 }
 
+/// Struct to capture the query string when fetching a project's events
+#[derive(Deserialize, Debug)]
+pub struct EventOptions {
+    /// The sequence number of the last event received, or the start of the history if missing
+    pub since: Option<i64>,
+    /// How many events to return, up to [`EVENT_LIMIT`]
+    pub limit: Option<i64>,
+}
+
 /// Struct to capture query string information
 #[derive(Deserialize, Debug)]
 pub struct DataCollection {
@@ -389,9 +405,7 @@ pub async fn upload_train_and_predict(
     let _id = datasets.insert_one(document, None).await?.inserted_id;
 
     // Inform the analysis server of the new job
-    let analytics_job = serde_json::to_string(&object_id).unwrap();
-    let topic = "analytics";
-    produce_message(&analytics_job, &analytics_job, &topic).await;
+    request_analytics(state.bus.as_ref(), &object_id).await?;
 
     let (analysis_doc, details_doc) = get_all_project_info(&object_id, &state.database).await?;
     let mut response = doc! {"project": project_doc};
@@ -601,9 +615,7 @@ pub async fn upload_and_split(
         .unwrap();
 
     // Inform the analysis server of the new job
-    let analytics_job = serde_json::to_string(&object_id).unwrap();
-    let topic = "analytics";
-    produce_message(&analytics_job, &analytics_job, &topic).await;
+    request_analytics(state.bus.as_ref(), &object_id).await?;
 
     let (analysis_doc, details_doc) = get_all_project_info(&object_id, &state.database).await?;
     let mut response = doc! {"project": project_doc};
@@ -616,10 +628,6 @@ pub async fn upload_and_split(
         // Insert the details as well
         response.insert("analysis", analysis_doc);
     }
-    // Communicate with Analytics Server
-    let analytics_job = serde_json::to_string(&object_id).unwrap();
-    let topic = "analytics";
-    produce_message(&analytics_job, &analytics_job, &topic).await;
 
     response_from_json(response)
 }
@@ -1157,10 +1165,11 @@ pub async fn begin_processing(
 
     // Ensure a dataset exists for this project
     let filter = doc! { "project_id": &object_id };
-    datasets
+    let document = datasets
         .find_one(filter, None)
         .await?
         .ok_or(ServerError::NotFound)?;
+    let dataset: Dataset = from_document(document)?;
 
     let filter = doc! { "project_id": &object_id };
     let document = dataset_details
@@ -1216,23 +1225,18 @@ pub async fn begin_processing(
         prediction_column: payload.prediction_column.clone(),
         prediction_type: payload.prediction_type,
         cost,
+        requirements: payload.requirements.clone(),
     };
-    let job = Job::new(config);
+    let job = Job::new(config).with_dataset(dataset.id);
 
     log::debug!("Created a new job: {:?}", job);
 
     pay(state.database.clone(), &claims.id, -cost).await?;
     log::debug!("Charged user {} {} credits", &claims.id, cost);
 
-    // Delete previous predictions for project
+    // Find the previous predictions for the project, which are replaced once the job is queued
     let filter = doc! {"project_id": &object_id};
-
-    let prediction_removed = predictions.find_one(filter, None).await?;
-
-    if let Some(prediction_removed) = prediction_removed {
-        let prediction: Prediction = from_document(prediction_removed)?;
-        prediction.delete(&state.database).await?;
-    }
+    let previous_predictions = predictions.find_one(filter, None).await?;
 
     // Mark the project as processing
     let filter = doc! { "_id": &object_id};
@@ -1243,11 +1247,75 @@ pub async fn begin_processing(
     // Insert to MongoDB first, so the interface can immediately mark as processed if needed
     insert_to_queue(&job, state.database.collection("jobs")).await?;
 
-    let job_message = serde_json::to_string(&job).unwrap();
-    let job_key = &job.id.to_string();
-    let topic = "jobs";
+    let job_id = job.id.to_string();
+    let event = JobRequested::from(&job);
+
+    if let Err(e) = envelope::publish(state.bus.as_ref(), &job_id, &event, &job_id).await {
+        log::error!("Failed to send job_id={} to the DCL: {}", job.id, e);
+
+        // The job will never run, so refund the user first and let them try again, continuing
+        // to clean up even if part of it fails
+        let reason = "The job could not be queued for processing";
+
+        match pay(state.database.clone(), &claims.id, cost).await {
+            Ok(_) => log::debug!("Refunded user {} {} credits", &claims.id, cost),
+            Err(e) => log::error!(
+                "Failed to refund user_id={} {} credits for job_id={}: {}",
+                claims.id,
+                cost,
+                job.id,
+                e
+            ),
+        }
+
+        if let Err(e) = job.mark_as_failed(&state.database, reason).await {
+            log::error!("Failed to mark job_id={} as failed: {}", job.id, e);
+        }
 
-    produce_message(&job_message, job_key, &topic).await;
+        let filter = doc! { "_id": &object_id };
+        let update = doc! { "$set": { "status": Status::Ready } };
+
+        if let Err(e) = projects.update_one(filter, update, None).await {
+            log::error!("Failed to make project_id={} ready again: {}", object_id, e);
+        }
+
+        let failed = KafkaWsMessage::JobFailedMessage {
+            project_id: object_id.to_string(),
+            reason: reason.to_string(),
+        };
+        failed
+            .notify(&state.database, state.bus.as_ref(), &job_id)
+            .await;
+
+        return Err(ServerError::ServiceUnavailable);
+    }
+
+    if let Some(previous_predictions) = previous_predictions {
+        let prediction: Prediction = from_document(previous_predictions)?;
+        prediction.delete(&state.database).await?;
+    }
+
+    // Warn the user if this job took them below the threshold
+    let remaining = user.credits - cost;
+
+    if let Some(mailer) = state.mailer.clone() {
+        if user.credits >= LOW_CREDITS_THRESHOLD && remaining < LOW_CREDITS_THRESHOLD {
+            let database = Arc::clone(&state.database);
+            let user_id = claims.id.clone();
+
+            tokio::spawn(async move {
+                let notification = Notification::LowCredits { credits: remaining };
+
+                if let Err(e) = mailer.notify_user(&database, &user_id, notification).await {
+                    log::warn!(
+                        "Failed to email user_id={} about low credits: {}",
+                        user_id,
+                        e
+                    );
+                }
+            });
+        }
+    }
 
     response_from_json(job)
 }
@@ -1312,6 +1380,55 @@ pub async fn get_job_statistics(
     response_from_json(job_statistic)
 }
 
+/// Gets the history of updates sent about a project, oldest first.
+///
+/// Each update is returned as it was sent over the websocket, including its sequence number. Only
+/// updates after the `since` parameter are returned, up to `limit` of them, allowing clients to
+/// page through the history by passing the sequence number of the last update they received.
+pub async fn get_events(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    project_id: web::Path<String>,
+    options: web::Query<EventOptions>,
+) -> ServerResponse {
+    let projects = state.database.collection("projects");
+    let project_id = check_user_owns_project(&claims.id, &project_id, &projects).await?;
+
+    let since = options.since.unwrap_or(0);
+    let limit = options.limit.unwrap_or(EVENT_LIMIT).clamp(1, EVENT_LIMIT);
+
+    let events: Vec<_> =
+        ProjectEvent::for_project_since(&state.database, &project_id, since, limit)
+            .await?
+            .into_iter()
+            .map(payloads::ProjectUpdate::try_from)
+            .collect::<Result<_, _>>()?;
+
+    response_from_json(events)
+}
+
+/// Asks the Analytics Server to analyse the dataset for a project
+///
+/// Projects cannot be shown properly without their analysis, so failing to send the request is
+/// reported to the user, who can upload the data again.
+async fn request_analytics(bus: &dyn MessageBus, project_id: &ObjectId) -> ServerResult<()> {
+    let key = project_id.to_string();
+    let event = AnalyticsRequested {
+        project_id: project_id.clone(),
+    };
+
+    if let Err(e) = envelope::publish(bus, &key, &event, &key).await {
+        log::error!(
+            "Failed to send the analytics job for project_id={}: {}",
+            project_id,
+            e
+        );
+        return Err(ServerError::ServiceUnavailable);
+    }
+
+    Ok(())
+}
+
 /// Inserts a [`JobConfiguration`] into MongoDB.
 async fn insert_to_queue(job: &Job, collection: Collection) -> ServerResult<()> {
     let document = to_document(&job)?;
@@ -0,0 +1,235 @@
+//! Defines the Server-Sent Events stream of project updates, for clients that cannot use the
+//! websocket.
+//!
+//! Each connection is registered as a session in the same way as a websocket, so it receives the
+//! same [`ProjectUpdate`] payloads. Every update is sent with its sequence number as the event
+//! identifier, which allows browsers to resume with the `Last-Event-ID` header after reconnecting.
+//!
+//! Clients that fall too far behind are disconnected rather than having updates dropped, so that
+//! they reconnect and replay everything they missed. Replays are read a page at a time, and new
+//! updates are held back until the replay has caught up so that they are never sent out of order.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix::{Actor, ActorContext, AsyncContext, Context, Handler, Running};
+use actix_web::{web, web::Bytes, HttpRequest, HttpResponse};
+use futures::channel::mpsc;
+use futures::sink::SinkExt;
+use futures::stream::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+
+use crate::error::ServerResponse;
+use crate::routes::payloads::ProjectUpdate;
+use crate::routes::websockets::{self, ReplayPage};
+use crate::sessions::{ReplayBuffer, SessionId, Sessions};
+use crate::{auth, WebsocketState};
+
+/// How often a comment is sent to keep proxies from closing the connection
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The number of events that can be waiting to be sent to a slow client before it is disconnected
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Struct to capture query string information
+#[derive(Deserialize, Debug)]
+pub struct StreamOptions {
+    /// A comma separated list of the projects to receive updates for, or all of them if missing
+    pub projects: Option<String>,
+    /// The sequence number of the last update received, used if `Last-Event-ID` is not sent
+    pub since: Option<i64>,
+}
+
+/// ProjectUpdateStream struct
+/// session is the session registered when the stream starts
+/// user_id is the authenticated user
+/// projects are the projects the stream is subscribed to
+/// since is the sequence number to replay updates from
+/// sessions is the set of open sessions to receive updates
+/// database is used to replay the updates a client missed
+/// sender writes events to the response body
+/// replay holds back live updates while a replay is running
+#[derive(Debug)]
+pub struct ProjectUpdateStream {
+    session: Option<SessionId>,
+    user_id: ObjectId,
+    projects: Vec<String>,
+    since: Option<i64>,
+    sessions: Arc<RwLock<Sessions>>,
+    database: Arc<Database>,
+    sender: mpsc::Sender<Bytes>,
+    replay: ReplayBuffer,
+}
+
+impl Actor for ProjectUpdateStream {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let id = {
+            let mut sessions = self.sessions.write().unwrap();
+            let id = sessions.register(&self.user_id.to_string(), ctx.address().recipient());
+
+            for project_id in &self.projects {
+                sessions.subscribe(id, project_id);
+            }
+
+            id
+        };
+
+        self.session = Some(id);
+
+        if let Some(since) = self.since {
+            self.replay.start();
+            self.replay_from(ctx, since);
+        }
+
+        ctx.run_interval(KEEP_ALIVE_INTERVAL, |act, ctx| {
+            act.send(ctx, Bytes::from_static(b": keep-alive\n\n"));
+        });
+    }
+
+    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+        if let Some(id) = self.session.take() {
+            self.sessions.write().unwrap().remove(id);
+        }
+        Running::Stop
+    }
+}
+
+impl ProjectUpdateStream {
+    /// Reads the next page of the updates the client missed in the background
+    fn replay_from(&self, ctx: &mut Context<Self>, since: i64) {
+        if let Some(id) = self.session {
+            let future = websockets::replay(
+                Arc::clone(&self.database),
+                Arc::clone(&self.sessions),
+                id,
+                self.user_id.clone(),
+                since,
+                ctx.address().recipient(),
+            );
+
+            ctx.spawn(actix::fut::wrap_future(future));
+        }
+    }
+
+    /// Writes to the response body, stopping once the client has disconnected or fallen behind
+    fn send(&mut self, ctx: &mut Context<Self>, bytes: Bytes) {
+        if let Err(e) = self.sender.try_send(bytes) {
+            if e.is_disconnected() {
+                log::info!("Event stream closed for user_id={}", self.user_id);
+            } else {
+                log::warn!(
+                    "Closing the event stream for slow user_id={}, it can resume from its last event",
+                    self.user_id
+                );
+            }
+
+            ctx.stop();
+        }
+    }
+}
+
+impl Handler<ProjectUpdate> for ProjectUpdateStream {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProjectUpdate, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(update) = self.replay.receive(msg) {
+            log::debug!("Sending msg to client {:?}", update);
+            self.send(ctx, event(&update));
+        }
+    }
+}
+
+impl Handler<ReplayPage> for ProjectUpdateStream {
+    type Result = ();
+
+    fn handle(&mut self, page: ReplayPage, ctx: &mut Self::Context) -> Self::Result {
+        let mut updates = page.updates;
+
+        self.replay.replayed(page.last_sequence);
+
+        if page.complete {
+            updates.extend(self.replay.finish());
+        } else {
+            self.replay_from(ctx, page.last_sequence);
+        }
+
+        let mut sender = self.sender.clone();
+
+        // Wait for the client to read the replayed updates rather than treating it as too slow,
+        // which also stops new updates being sent before them
+        ctx.wait(actix::fut::wrap_future(async move {
+            for update in &updates {
+                if sender.send(event(update)).await.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+}
+
+/// Formats an update as an event, using its sequence number as the identifier
+fn event(update: &ProjectUpdate) -> Bytes {
+    let event = format!(
+        "id: {}\ndata: {}\n\n",
+        update.sequence,
+        serde_json::to_string(update).unwrap()
+    );
+
+    Bytes::from(event)
+}
+
+/// Streams the authenticated user's project updates as Server-Sent Events
+///
+/// Updates are replayed from the `Last-Event-ID` header or `since` parameter if either is given.
+pub async fn index(
+    claims: auth::Claims,
+    req: HttpRequest,
+    state: web::Data<WebsocketState>,
+    options: web::Query<StreamOptions>,
+) -> ServerResponse {
+    let options = options.into_inner();
+
+    let since = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or(options.since);
+
+    let projects = options
+        .projects
+        .map(|projects| {
+            projects
+                .split(',')
+                .filter(|project_id| !project_id.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    log::info!("Opening an event stream for user_id={}", claims.id);
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+    ProjectUpdateStream {
+        session: None,
+        user_id: claims.id,
+        projects,
+        since,
+        sessions: Arc::clone(&state.sessions),
+        database: Arc::clone(&state.database),
+        sender,
+        replay: ReplayBuffer::new(),
+    }
+    .start();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Stops proxies such as nginx from buffering the events
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(receiver.map(Ok::<_, actix_web::Error>)))
+}
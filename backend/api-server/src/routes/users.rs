@@ -8,7 +8,7 @@ use mongodb::options::UpdateOptions;
 use tokio_stream::StreamExt;
 
 use models::projects::Project;
-use models::users::User;
+use models::users::{NotificationPreferences, User};
 
 use crate::{
     auth,
@@ -178,6 +178,25 @@ pub async fn edit(
     response_from_json(doc! {"status": "changed"})
 }
 
+/// Updates which optional emails a user wants to receive.
+///
+/// Replaces all of the user's preferences with those provided, returning a message once they have
+/// been changed.
+pub async fn edit_notifications(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    payload: web::Json<NotificationPreferences>,
+) -> ServerResponse {
+    let users = state.database.collection("users");
+
+    let filter = doc! { "_id": &claims.id };
+    let update = doc! { "$set": { "notifications": to_document(&payload.into_inner())? } };
+
+    users.update_one(filter, update, None).await?;
+
+    response_from_json(doc! {"status": "changed"})
+}
+
 /// Verifies a user's password against the one in the database.
 ///
 /// Given an email and password, finds the user in the database and checks that the two hashes
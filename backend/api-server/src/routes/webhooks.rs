@@ -0,0 +1,137 @@
+//! Defines the routes for managing webhooks and inspecting their deliveries.
+
+use actix_web::{web, HttpResponse};
+use mongodb::bson::{de::from_document, doc, oid::ObjectId, ser::to_document};
+use serde_json::Value;
+
+use models::webhooks::{Webhook, WebhookDelivery, WebhookEvent};
+
+use crate::{
+    auth,
+    error::{ServerError, ServerResponse, ServerResult},
+    routes::{check_user_owns_project, payloads, response_from_json},
+    webhooks::WebhookPayload,
+    State,
+};
+
+/// The number of delivery attempts returned when inspecting a webhook
+const DELIVERY_LOG_LIMIT: i64 = 50;
+
+/// Finds a webhook with the given ID, checking that the given user owns it.
+async fn find_user_webhook(
+    user_id: &ObjectId,
+    webhook_id: &str,
+    state: &State,
+) -> ServerResult<Webhook> {
+    let webhooks = state.database.collection("webhooks");
+
+    let filter = doc! { "_id": ObjectId::with_string(webhook_id)? };
+    let document = webhooks
+        .find_one(filter, None)
+        .await?
+        .ok_or(ServerError::NotFound)?;
+
+    let webhook: Webhook = from_document(document)?;
+
+    if webhook.user_id == *user_id {
+        Ok(webhook)
+    } else {
+        Err(ServerError::Forbidden)
+    }
+}
+
+/// Registers a webhook for a user, either for one of their projects or all of them.
+///
+/// Returns the webhook, including the secret used to sign its payloads.
+pub async fn new(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    payload: web::Json<payloads::NewWebhookOptions>,
+) -> ServerResponse {
+    let payload = payload.into_inner();
+
+    if let Err(e) = state.dispatcher.check_url(&payload.url).await {
+        log::warn!(
+            "Refusing to register a webhook for url={}: {}",
+            payload.url,
+            e
+        );
+        return Err(ServerError::UnprocessableEntity);
+    }
+
+    let project_id = match payload.project_id {
+        Some(project_id) => {
+            let projects = state.database.collection("projects");
+            Some(check_user_owns_project(&claims.id, &project_id, &projects).await?)
+        }
+        None => None,
+    };
+
+    let events: Vec<_> = payload
+        .events
+        .unwrap_or_else(|| WebhookEvent::DEFAULT.to_vec())
+        .into_iter()
+        .filter(|event| WebhookEvent::DEFAULT.contains(event))
+        .collect();
+
+    if events.is_empty() {
+        return Err(ServerError::UnprocessableEntity);
+    }
+
+    let webhook = Webhook::new(claims.id, project_id, payload.url, events);
+
+    let webhooks = state.database.collection("webhooks");
+    webhooks.insert_one(to_document(&webhook)?, None).await?;
+
+    response_from_json(webhook)
+}
+
+/// Gets the webhooks a user has registered.
+pub async fn get_user_webhooks(claims: auth::Claims, state: web::Data<State>) -> ServerResponse {
+    let webhooks = Webhook::for_user(&state.database, &claims.id).await?;
+
+    response_from_json(webhooks)
+}
+
+/// Deletes a webhook, along with its delivery log.
+pub async fn delete(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    webhook_id: web::Path<String>,
+) -> ServerResponse {
+    let webhook = find_user_webhook(&claims.id, &webhook_id, &state).await?;
+    webhook.delete(&state.database).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Gets the most recent attempts to deliver events to a webhook, newest first.
+pub async fn get_deliveries(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    webhook_id: web::Path<String>,
+) -> ServerResponse {
+    let webhook = find_user_webhook(&claims.id, &webhook_id, &state).await?;
+    let deliveries =
+        WebhookDelivery::for_webhook(&state.database, &webhook.id, DELIVERY_LOG_LIMIT).await?;
+
+    response_from_json(deliveries)
+}
+
+/// Sends a `webhook.test` event to a webhook, returning how the attempt went.
+///
+/// The event is only sent once, so that users get an answer straight away.
+pub async fn test(
+    claims: auth::Claims,
+    state: web::Data<State>,
+    webhook_id: web::Path<String>,
+) -> ServerResponse {
+    let webhook = find_user_webhook(&claims.id, &webhook_id, &state).await?;
+
+    let project_id = webhook.project_id.as_ref().map(ObjectId::to_string);
+    let payload = WebhookPayload::new(WebhookEvent::Test, project_id, Value::Null);
+
+    let delivery = state.dispatcher.attempt(&webhook, &payload, 1).await?;
+
+    response_from_json(delivery)
+}
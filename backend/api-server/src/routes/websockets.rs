@@ -1,36 +1,48 @@
 //! Defines the websocket and related functions for realtime communication with the client
 
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::convert::TryFrom;
 use std::sync::{Arc, RwLock};
 
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::{stream_consumer::StreamConsumer, Consumer, DefaultConsumerContext};
-use rdkafka::Message;
-use tokio_stream::StreamExt;
+use anyhow::{anyhow, Context};
 
-use actix::prelude::Addr;
-use actix::{Actor, ActorContext, AsyncContext, Handler, Running, StreamHandler};
+use actix::{
+    Actor, ActorContext, AsyncContext, Handler, Message, Recipient, Running, StreamHandler,
+};
 use actix_web::{web, HttpResponse};
 use actix_web_actors::ws;
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, ser::to_document};
+use mongodb::Database;
 use std::time::{Duration, Instant};
 
-use crate::{auth, routes::payloads::WebsocketMessage, WebsocketState};
-use messages::KafkaWsMessage;
+use crate::sessions::{ReplayBuffer, SessionId, Sessions};
+use crate::{
+    auth,
+    routes::payloads::{ProjectUpdate, WebsocketMessage},
+    routes::projects::EVENT_LIMIT,
+    WebsocketState,
+};
+use messages::{envelope, Consumer, KafkaWsMessage, MessageBus};
+use models::project_events::ProjectEvent;
+use utils::metrics;
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// ProjectUpdateWs struct
 /// hb is the heartbeat to guarantee websocket is alive
-/// id is the userid which opened this connection
-/// map is the map of userids to websocket connections
+/// session is the session registered once the user authenticates
+/// user_id is the authenticated user
+/// sessions is the set of open sessions to receive updates
+/// database is used to replay the updates a client missed
+/// replay holds back live updates while a replay is running
 #[derive(Debug)]
 pub struct ProjectUpdateWs {
     hb: Instant,
-    id: Option<ObjectId>,
-    map: Arc<RwLock<HashMap<String, Addr<ProjectUpdateWs>>>>,
+    session: Option<SessionId>,
+    user_id: Option<ObjectId>,
+    sessions: Arc<RwLock<Sessions>>,
+    database: Arc<Database>,
+    replay: ReplayBuffer,
 }
 
 impl Actor for ProjectUpdateWs {
@@ -41,8 +53,8 @@ impl Actor for ProjectUpdateWs {
     }
 
     fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
-        if let Some(id) = &self.id {
-            self.map.write().unwrap().remove(&id.to_string());
+        if let Some(id) = self.session.take() {
+            self.sessions.write().unwrap().remove(id);
         }
         Running::Stop
     }
@@ -50,14 +62,38 @@ impl Actor for ProjectUpdateWs {
 
 impl ProjectUpdateWs {
     /// Creates a new ProjectUpdateWs
-    pub fn new(map: Arc<RwLock<HashMap<String, Addr<ProjectUpdateWs>>>>) -> ProjectUpdateWs {
+    pub fn new(sessions: Arc<RwLock<Sessions>>, database: Arc<Database>) -> ProjectUpdateWs {
         ProjectUpdateWs {
-            id: None,
             hb: Instant::now(),
-            map: map,
+            session: None,
+            user_id: None,
+            sessions,
+            database,
+            replay: ReplayBuffer::new(),
         }
     }
 
+    /// Reads the next page of the updates the client missed in the background
+    fn replay_from(&self, ctx: &mut ws::WebsocketContext<Self>, since: i64) {
+        if let (Some(id), Some(user_id)) = (self.session, &self.user_id) {
+            let future = replay(
+                Arc::clone(&self.database),
+                Arc::clone(&self.sessions),
+                id,
+                user_id.clone(),
+                since,
+                ctx.address().recipient(),
+            );
+
+            ctx.spawn(actix::fut::wrap_future(future));
+        }
+    }
+
+    fn send_update(ctx: &mut ws::WebsocketContext<Self>, update: &ProjectUpdate) {
+        log::debug!("Sending msg to client {:?}", update);
+        ctx.text(serde_json::to_string(update).unwrap());
+    }
+
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
         ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
             if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
@@ -71,20 +107,66 @@ impl ProjectUpdateWs {
     }
 
     fn handle_message(&mut self, ctx: &mut ws::WebsocketContext<Self>, content: &str) {
-        let message: WebsocketMessage = serde_json::from_str(&content).unwrap();
+        let message: WebsocketMessage = match serde_json::from_str(&content) {
+            Ok(message) => message,
+            Err(e) => {
+                log::warn!("Received an invalid websocket message: {}", e);
+                return;
+            }
+        };
+
         match message {
             WebsocketMessage::Authentication { token } => {
-                let claims = auth::Claims::from_token(&token).unwrap();
+                let claims = match auth::Claims::from_token(&token) {
+                    Ok(claims) => claims,
+                    Err(e) => {
+                        log::warn!("Failed to authenticate a websocket: {}", e);
+                        ctx.stop();
+                        return;
+                    }
+                };
+
                 log::info!("claims id {:?}", claims.id);
-                self.id = Some(claims.id.clone());
-                self.map
-                    .write()
-                    .unwrap()
-                    .insert(claims.id.to_string(), ctx.address());
+
+                let mut sessions = self.sessions.write().unwrap();
+
+                // Authenticating again replaces the previous session
+                if let Some(id) = self.session.take() {
+                    sessions.remove(id);
+                }
+
+                let recipient = ctx.address().recipient();
+                self.session = Some(sessions.register(&claims.id.to_string(), recipient));
+                self.user_id = Some(claims.id.clone());
+
                 ctx.text(
                     serde_json::to_string(&WebsocketMessage::Hello { id: claims.id }).unwrap(),
                 );
             }
+            WebsocketMessage::Subscribe { project_id } => match self.session {
+                Some(id) => {
+                    log::debug!("Session {} subscribed to project_id={}", id, project_id);
+                    self.sessions.write().unwrap().subscribe(id, &project_id);
+                }
+                None => log::warn!("Ignoring a subscription from an unauthenticated websocket"),
+            },
+            WebsocketMessage::Unsubscribe { project_id } => match self.session {
+                Some(id) => {
+                    log::debug!("Session {} unsubscribed from project_id={}", id, project_id);
+                    self.sessions.write().unwrap().unsubscribe(id, &project_id);
+                }
+                None => log::warn!("Ignoring a subscription from an unauthenticated websocket"),
+            },
+            WebsocketMessage::Replay { since } => match self.session {
+                Some(_) if self.replay.is_replaying() => {
+                    log::warn!("Ignoring a replay request while another is running");
+                }
+                Some(_) => {
+                    self.replay.start();
+                    self.replay_from(ctx, since);
+                }
+                None => log::warn!("Ignoring a replay request from an unauthenticated websocket"),
+            },
             _ => (),
         }
     }
@@ -117,12 +199,33 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ProjectUpdateWs {
     }
 }
 
-impl Handler<WebsocketMessage> for ProjectUpdateWs {
+impl Handler<ProjectUpdate> for ProjectUpdateWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProjectUpdate, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(update) = self.replay.receive(msg) {
+            Self::send_update(ctx, &update);
+        }
+    }
+}
+
+impl Handler<ReplayPage> for ProjectUpdateWs {
     type Result = ();
 
-    fn handle(&mut self, msg: WebsocketMessage, ctx: &mut Self::Context) -> Self::Result {
-        log::debug!("Sending msg to client {:?}", msg);
-        ctx.text(serde_json::to_string(&msg).unwrap());
+    fn handle(&mut self, page: ReplayPage, ctx: &mut Self::Context) -> Self::Result {
+        for update in &page.updates {
+            Self::send_update(ctx, update);
+        }
+
+        self.replay.replayed(page.last_sequence);
+
+        if page.complete {
+            for update in self.replay.finish() {
+                Self::send_update(ctx, &update);
+            }
+        } else {
+            self.replay_from(ctx, page.last_sequence);
+        }
     }
 }
 
@@ -132,58 +235,154 @@ pub async fn index(
     state: web::Data<WebsocketState>,
     stream: web::Payload,
 ) -> Result<HttpResponse, actix_web::error::Error> {
-    let map = Arc::clone(&state.map);
-    let resp = ws::start(ProjectUpdateWs::new(map), &req, stream);
+    let sessions = Arc::clone(&state.sessions);
+    let database = Arc::clone(&state.database);
+    let resp = ws::start(ProjectUpdateWs::new(sessions, database), &req, stream);
     resp
 }
 
-/// Consumes from kafka
-pub async fn consume_updates(port: u16, map: Arc<RwLock<HashMap<String, Addr<ProjectUpdateWs>>>>) {
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port).to_string();
-    log::info!("Broker Socket: {:?}", addr);
-
-    let consumer: StreamConsumer<DefaultConsumerContext> = ClientConfig::new()
-        .set("group.id", "project_update")
-        .set("bootstrap.servers", addr)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()
-        .expect("Consumer creation failed");
-
-    consumer
-        .subscribe(&["project_updates"])
-        .expect("Can't subscribe to project_updates");
-
-    // Ignore any errors in the stream
-    let mut message_stream = consumer.stream().filter_map(Result::ok);
-
-    while let Some(message) = message_stream.next().await {
-        // Interpret the content as a string
-        let payload = match message.payload_view::<[u8]>() {
-            // This cannot fail, `rdkafka` always returns `Ok(bytes)`
-            Some(view) => view.unwrap(),
-            None => {
-                log::warn!("Received an empty message from Kafka");
-                continue;
+/// A page of the updates a session missed, sent to the session once they have been read
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ReplayPage {
+    /// The updates the session wants, oldest first
+    pub updates: Vec<ProjectUpdate>,
+    /// The sequence number of the last update read, which the next page follows on from
+    pub last_sequence: i64,
+    /// Whether every update the session missed has been read
+    pub complete: bool,
+}
+
+/// Reads the next page of updates a session missed after `since` and sends it to the session
+///
+/// At most [`EVENT_LIMIT`] updates are read at once, so sessions continue from the
+/// `last_sequence` of each page until one is `complete`. Updates for projects the session does
+/// not want are skipped, but still count towards the page.
+pub(crate) async fn replay(
+    database: Arc<Database>,
+    sessions: Arc<RwLock<Sessions>>,
+    id: SessionId,
+    user_id: ObjectId,
+    since: i64,
+    recipient: Recipient<ReplayPage>,
+) {
+    let events = match ProjectEvent::for_user_since(&database, &user_id, since, EVENT_LIMIT).await {
+        Ok(events) => events,
+        Err(e) => {
+            log::warn!(
+                "Failed to get the updates for user_id={} since sequence={}: {}",
+                user_id,
+                since,
+                e
+            );
+            Vec::new()
+        }
+    };
+
+    log::debug!(
+        "Replaying up to {} updates for user_id={} since sequence={}",
+        events.len(),
+        user_id,
+        since
+    );
+
+    // A failed or short read leaves nothing more to replay, so the session can move on
+    let complete = events.len() < EVENT_LIMIT as usize;
+    let last_sequence = events.last().map_or(since, |event| event.sequence);
+
+    let updates = events
+        .into_iter()
+        .filter(|event| {
+            sessions
+                .read()
+                .unwrap()
+                .wants(id, &event.project_id.to_string())
+        })
+        .filter_map(|event| match ProjectUpdate::try_from(event) {
+            Ok(update) => Some(update),
+            Err(e) => {
+                log::warn!("Failed to read a recorded update: {}", e);
+                None
             }
-        };
+        })
+        .collect();
 
-        log::debug!(
-            "Message key: {:?}, Payload: {:?}, timestamp: {:?}",
-            message.key(),
-            &payload,
-            message.timestamp()
-        );
+    // The session has closed if this fails, so there is no one left to replay to
+    let _ = recipient.do_send(ReplayPage {
+        updates,
+        last_sequence,
+        complete,
+    });
+}
 
-        let project_update: KafkaWsMessage<'_> = serde_json::from_slice(&payload).unwrap();
-        let ws_msg = WebsocketMessage::from(&project_update);
+/// Consumes project updates from the message bus and forwards them to websockets
+///
+/// Each update is recorded with the next sequence number, so that clients can replay it later,
+/// and then sent to every session of the project's owner that wants updates for the project.
+/// Updates that cannot be read or recorded are sent to the dead letter topic,
+/// `project_updates.dlq`.
+pub async fn consume_updates(
+    bus: Arc<dyn MessageBus>,
+    database: Arc<Database>,
+    sessions: Arc<RwLock<Sessions>>,
+) {
+    let consumer = Consumer::new(bus, "project_update", &["project_updates"]);
 
-        let user_id = std::str::from_utf8(&message.key().unwrap()).unwrap();
-        let socket_map = map.read().unwrap();
-        if let Some(socket) = socket_map.get(user_id) {
-            socket.try_send(ws_msg).unwrap();
-        }
+    let result = consumer
+        .run(|message| {
+            let database = Arc::clone(&database);
+            let sessions = Arc::clone(&sessions);
+
+            async move {
+                log::debug!(
+                    "Message key: {:?}, Payload: {:?}, timestamp: {:?}",
+                    message.key,
+                    &message.payload,
+                    message.timestamp
+                );
+
+                metrics::record_kafka_lag("project_updates", message.timestamp);
+
+                let project_update: KafkaWsMessage = envelope::decode(&message.payload)
+                    .context("Failed to deserialize a project update")?;
+                let ws_msg = WebsocketMessage::from(&project_update);
+
+                let user_id = message
+                    .key
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Project update has no user to send it to"))?;
+
+                let project_id = project_update.project_id();
+
+                let event = ProjectEvent::record(
+                    &database,
+                    ObjectId::with_string(project_id)?,
+                    ObjectId::with_string(user_id)?,
+                    to_document(&ws_msg)?,
+                )
+                .await?;
+
+                let update = ProjectUpdate {
+                    message: ws_msg,
+                    sequence: event.sequence,
+                    timestamp: event.date_created.0.timestamp_millis(),
+                };
+
+                let sessions = sessions.read().unwrap();
+
+                for recipient in sessions.recipients(user_id, project_id) {
+                    // Updates are best effort, so a closed or busy socket is not a failure
+                    if let Err(e) = recipient.try_send(update.clone()) {
+                        log::warn!("Failed to forward an update to user_id={}: {}", user_id, e);
+                    }
+                }
+
+                Ok(())
+            }
+        })
+        .await;
+
+    if let Err(e) = result {
+        log::error!("Stopped consuming project_updates: {:#}", e);
     }
 }
@@ -0,0 +1,224 @@
+//! Keeps track of the clients listening for project updates and which updates they want.
+//!
+//! A user can have any number of sessions open at once, such as one per browser tab. Each session
+//! receives every update for the user's projects until it subscribes to a specific project, after
+//! which it only receives updates for the projects it is subscribed to. Unsubscribing before then
+//! stops updates for that project alone.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use actix::Recipient;
+
+use crate::routes::payloads::ProjectUpdate;
+
+/// Identifies a single session
+pub type SessionId = u64;
+
+/// The projects a session wants updates for
+#[derive(Clone, Debug, PartialEq)]
+enum Interest {
+    /// Updates for every project the user owns except these
+    Except(HashSet<String>),
+    /// Updates for only these projects
+    Only(HashSet<String>),
+}
+
+impl Interest {
+    fn includes(&self, project_id: &str) -> bool {
+        match self {
+            Interest::Except(projects) => !projects.contains(project_id),
+            Interest::Only(projects) => projects.contains(project_id),
+        }
+    }
+}
+
+struct Session<T> {
+    user_id: String,
+    interest: Interest,
+    recipient: T,
+}
+
+/// The sessions that are currently open, and where to send their updates
+pub struct Sessions<T = Recipient<ProjectUpdate>> {
+    next_id: SessionId,
+    sessions: HashMap<SessionId, Session<T>>,
+}
+
+impl<T> Default for Sessions<T> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            sessions: HashMap::new(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sessions<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sessions")
+            .field("open", &self.sessions.len())
+            .finish()
+    }
+}
+
+impl<T> Sessions<T> {
+    /// Creates an empty set of sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a session for a user, returning its identifier
+    pub fn register(&mut self, user_id: &str, recipient: T) -> SessionId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.sessions.insert(
+            id,
+            Session {
+                user_id: user_id.to_string(),
+                interest: Interest::Except(HashSet::new()),
+                recipient,
+            },
+        );
+
+        id
+    }
+
+    /// Closes a session, after which it receives no more updates
+    pub fn remove(&mut self, id: SessionId) {
+        self.sessions.remove(&id);
+    }
+
+    /// Adds a project to those a session receives updates for
+    ///
+    /// Returns `false` if the session does not exist.
+    pub fn subscribe(&mut self, id: SessionId, project_id: &str) -> bool {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return false,
+        };
+
+        match &mut session.interest {
+            Interest::Except(_) => {
+                let projects = std::iter::once(project_id.to_string()).collect();
+                session.interest = Interest::Only(projects);
+            }
+            Interest::Only(projects) => {
+                projects.insert(project_id.to_string());
+            }
+        }
+
+        true
+    }
+
+    /// Stops a session receiving updates for a project
+    ///
+    /// Returns `false` if the session does not exist.
+    pub fn unsubscribe(&mut self, id: SessionId, project_id: &str) -> bool {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return false,
+        };
+
+        match &mut session.interest {
+            Interest::Except(projects) => {
+                projects.insert(project_id.to_string());
+            }
+            Interest::Only(projects) => {
+                projects.remove(project_id);
+            }
+        }
+
+        true
+    }
+
+    /// Checks whether a session wants updates for a project
+    pub fn wants(&self, id: SessionId, project_id: &str) -> bool {
+        self.sessions
+            .get(&id)
+            .map_or(false, |session| session.interest.includes(project_id))
+    }
+
+    /// Gets where to send an update about one of a user's projects
+    pub fn recipients<'a>(
+        &'a self,
+        user_id: &'a str,
+        project_id: &'a str,
+    ) -> impl Iterator<Item = &'a T> + 'a {
+        self.sessions
+            .values()
+            .filter(move |session| session.user_id == user_id)
+            .filter(move |session| session.interest.includes(project_id))
+            .map(|session| &session.recipient)
+    }
+
+    /// Gets the number of sessions that are open
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Checks whether any sessions are open
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Holds back the live updates for a session while it replays the ones it missed
+///
+/// Updates are recorded before they are sent live, so any update at or below the last sequence
+/// number replayed has already been sent and is dropped rather than sent twice.
+#[derive(Debug, Default)]
+pub struct ReplayBuffer {
+    held: Option<Vec<ProjectUpdate>>,
+    replayed_to: i64,
+}
+
+impl ReplayBuffer {
+    /// Creates a buffer that is not replaying anything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks whether live updates are being held back
+    pub fn is_replaying(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Holds back live updates until the replay finishes
+    pub fn start(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    /// Records that the replay has sent every update up to `sequence`
+    pub fn replayed(&mut self, sequence: i64) {
+        self.replayed_to = self.replayed_to.max(sequence);
+    }
+
+    /// Returns a live update if it should be sent now, holding it back during a replay
+    pub fn receive(&mut self, update: ProjectUpdate) -> Option<ProjectUpdate> {
+        if update.sequence <= self.replayed_to {
+            return None;
+        }
+
+        match &mut self.held {
+            Some(held) => {
+                held.push(update);
+                None
+            }
+            None => Some(update),
+        }
+    }
+
+    /// Finishes the replay, returning the held back updates it did not already send
+    pub fn finish(&mut self) -> Vec<ProjectUpdate> {
+        let replayed_to = self.replayed_to;
+
+        self.held
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|update| update.sequence > replayed_to)
+            .collect()
+    }
+}
@@ -0,0 +1,344 @@
+//! Delivers project events to the webhooks users have registered.
+//!
+//! Webhooks are driven by the `project_updates` topic, using their own consumer group so that they
+//! receive every update regardless of which websockets are open. Each event is posted as JSON and
+//! signed with the webhook's secret, with the hex-encoded HMAC-SHA256 of the body sent in the
+//! [`SIGNATURE_HEADER`] as `sha256=<signature>`.
+//!
+//! Failed deliveries are retried with an exponential backoff, and every attempt is recorded as a
+//! [`WebhookDelivery`] for users to inspect. Retries are only held in memory, so any that are
+//! pending when the server restarts are lost.
+//!
+//! Webhooks cannot be used to reach the server's own network, so their hosts are resolved both
+//! when they are registered and before every delivery, and any that resolve to an internal address
+//! are refused. Redirects are never followed, as they could lead anywhere.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use mongodb::bson::{self, oid::ObjectId};
+use mongodb::Database;
+use serde_json::Value;
+
+use messages::bus::RetryPolicy;
+use messages::{envelope, Consumer, KafkaWsMessage, MessageBus};
+use models::webhooks::{Webhook, WebhookDelivery, WebhookEvent};
+
+/// The header containing the signature of the payload
+pub const SIGNATURE_HEADER: &str = "X-Sybl-Signature";
+
+/// The header containing the name of the event
+pub const EVENT_HEADER: &str = "X-Sybl-Event";
+
+/// The header containing the identifier of the delivery, which is the same for every attempt
+pub const DELIVERY_HEADER: &str = "X-Sybl-Delivery";
+
+/// How long to wait for a webhook to respond
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The body posted to a webhook
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// Identifies the delivery, allowing receivers to discard retries they have already handled
+    pub id: String,
+    /// The event that occurred
+    pub event: WebhookEvent,
+    /// The project the event is about, if any
+    pub project_id: Option<String>,
+    /// When the event occurred, in milliseconds since the epoch
+    pub timestamp: i64,
+    /// Further details about the event
+    pub data: Value,
+}
+
+impl WebhookPayload {
+    /// Creates a new [`WebhookPayload`] with a unique identifier.
+    pub fn new(event: WebhookEvent, project_id: Option<String>, data: Value) -> Self {
+        Self {
+            id: ObjectId::new().to_string(),
+            event,
+            project_id,
+            timestamp: Utc::now().timestamp_millis(),
+            data,
+        }
+    }
+
+    /// Creates the [`WebhookPayload`] for a project update, if webhooks are sent for it.
+    pub fn from_update(update: &KafkaWsMessage) -> Option<Self> {
+        let (event, data) = match update {
+            KafkaWsMessage::JobCompleteMessage { .. } => (WebhookEvent::JobCompleted, json!({})),
+            KafkaWsMessage::JobFailedMessage { reason, .. } => {
+                (WebhookEvent::JobFailed, json!({ "reason": reason }))
+            }
+            KafkaWsMessage::AnalysisCompleteMessage { .. } => {
+                (WebhookEvent::AnalysisReady, json!({}))
+            }
+            _ => return None,
+        };
+
+        Some(Self::new(
+            event,
+            Some(update.project_id().to_string()),
+            data,
+        ))
+    }
+}
+
+/// Checks whether an address is internal, such as a loopback, private, link-local or unspecified
+/// address, which webhooks are not allowed to reach.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // 0.0.0.0/8 and the shared address space, 100.64.0.0/10
+                || octets[0] == 0
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local addresses, fc00::/7, and link-local addresses, fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                // IPv4 addresses embedded in IPv6 ones
+                || ip.to_ipv4().map_or(false, |ip| is_internal(IpAddr::V4(ip)))
+        }
+    }
+}
+
+/// Sends payloads to webhooks and records each attempt
+#[derive(Clone, Debug)]
+pub struct Dispatcher {
+    client: reqwest::Client,
+    database: Arc<Database>,
+    retry_policy: RetryPolicy,
+    allow_internal: bool,
+}
+
+impl Dispatcher {
+    /// Creates a new [`Dispatcher`], retrying failed deliveries for around 5 minutes
+    pub fn new(database: Arc<Database>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to create the webhook client");
+
+        Self {
+            client,
+            database,
+            retry_policy: RetryPolicy {
+                attempts: 6,
+                initial_backoff: Duration::from_secs(10),
+                max_backoff: Duration::from_secs(120),
+            },
+            allow_internal: false,
+        }
+    }
+
+    /// Sets how failed deliveries are retried
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Allows webhooks to be delivered to internal addresses, such as when testing locally
+    pub fn allow_internal_addresses(mut self) -> Self {
+        self.allow_internal = true;
+        self
+    }
+
+    /// Checks that a URL uses HTTP and that its host does not resolve to an internal address
+    pub async fn check_url(&self, url: &str) -> Result<()> {
+        let url = reqwest::Url::parse(url)?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow!("Webhooks must use HTTP or HTTPS"));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Webhooks must have a host"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        // IPv6 hosts are given in brackets, which cannot be resolved
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        if self.allow_internal {
+            return Ok(());
+        }
+
+        let mut addresses = tokio::net::lookup_host((host, port)).await?.peekable();
+
+        if addresses.peek().is_none() {
+            return Err(anyhow!("{} could not be resolved", host));
+        }
+
+        if let Some(address) = addresses.find(|address| is_internal(address.ip())) {
+            return Err(anyhow!("{} resolves to an internal address", address.ip()));
+        }
+
+        Ok(())
+    }
+
+    /// Delivers a payload to a webhook, retrying until it succeeds or the attempts run out
+    ///
+    /// Returns the last attempt that was made.
+    pub async fn deliver(
+        &self,
+        webhook: &Webhook,
+        payload: &WebhookPayload,
+    ) -> Result<WebhookDelivery> {
+        let mut attempt = 1;
+
+        loop {
+            let delivery = self.attempt(webhook, payload, attempt).await?;
+
+            if delivery.success || attempt >= self.retry_policy.attempts {
+                return Ok(delivery);
+            }
+
+            let backoff = self.retry_policy.backoff(attempt);
+
+            log::warn!(
+                "Failed to deliver {} to webhook_id={} on attempt {}, retrying in {:?}",
+                payload.id,
+                webhook.id,
+                attempt,
+                backoff
+            );
+
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Makes a single attempt to deliver a payload to a webhook, recording the outcome
+    pub async fn attempt(
+        &self,
+        webhook: &Webhook,
+        payload: &WebhookPayload,
+        attempt: u32,
+    ) -> Result<WebhookDelivery> {
+        let body = serde_json::to_string(payload)?;
+        let signature = crypto::sign_hmac(webhook.secret.as_bytes(), body.as_bytes())?;
+
+        // Hosts can change what they resolve to after being registered
+        let (status, error) = match self.check_url(&webhook.url).await {
+            Ok(()) => {
+                let response = self
+                    .client
+                    .post(&webhook.url)
+                    .header("Content-Type", "application/json")
+                    .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+                    .header(EVENT_HEADER, payload.event.name())
+                    .header(DELIVERY_HEADER, &payload.id)
+                    .body(body)
+                    .send()
+                    .await;
+
+                match response {
+                    Ok(response) if response.status().is_success() => {
+                        (Some(response.status()), None)
+                    }
+                    Ok(response) => (
+                        Some(response.status()),
+                        Some(format!("Received {}", response.status())),
+                    ),
+                    Err(e) => (None, Some(e.to_string())),
+                }
+            }
+            Err(e) => {
+                log::warn!("Refusing to deliver to webhook_id={}: {}", webhook.id, e);
+                (None, Some(e.to_string()))
+            }
+        };
+
+        let delivery = WebhookDelivery {
+            id: ObjectId::new(),
+            webhook_id: webhook.id.clone(),
+            delivery_id: ObjectId::with_string(&payload.id)?,
+            event: payload.event,
+            attempt,
+            status: status.map(|status| status.as_u16()),
+            success: error.is_none(),
+            error,
+            date_created: bson::DateTime(Utc::now()),
+        };
+
+        delivery.record(&self.database).await?;
+
+        Ok(delivery)
+    }
+}
+
+/// Consumes project updates from the message bus and delivers them to webhooks
+///
+/// Deliveries are made in the background, so a slow webhook does not hold up the others. Updates
+/// that cannot be read, or whose webhooks cannot be found, are sent to the dead letter topic,
+/// `project_updates.dlq`.
+pub async fn consume_updates(bus: Arc<dyn MessageBus>, dispatcher: Dispatcher) {
+    let consumer = Consumer::new(bus, "webhooks", &["project_updates"]);
+
+    let result = consumer
+        .run(|message| {
+            let dispatcher = dispatcher.clone();
+
+            async move {
+                let update: KafkaWsMessage = envelope::decode(&message.payload)
+                    .context("Failed to deserialize a project update")?;
+
+                let payload = match WebhookPayload::from_update(&update) {
+                    Some(payload) => payload,
+                    None => return Ok(()),
+                };
+
+                let user_id = message
+                    .key
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Project update has no user to send it to"))?;
+
+                let webhooks = Webhook::for_event(
+                    &dispatcher.database,
+                    &ObjectId::with_string(user_id)?,
+                    &ObjectId::with_string(update.project_id())?,
+                    payload.event,
+                )
+                .await?;
+
+                for webhook in webhooks {
+                    let dispatcher = dispatcher.clone();
+                    let payload = payload.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(e) = dispatcher.deliver(&webhook, &payload).await {
+                            log::error!(
+                                "Failed to deliver {} to webhook_id={}: {}",
+                                payload.id,
+                                webhook.id,
+                                e
+                            );
+                        }
+                    });
+                }
+
+                Ok(())
+            }
+        })
+        .await;
+
+    if let Err(e) = result {
+        log::error!("Stopped consuming project_updates for webhooks: {:#}", e);
+    }
+}
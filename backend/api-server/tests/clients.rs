@@ -96,6 +96,24 @@ async fn model_performances_can_be_fetched() {
     assert_eq!(performances, results);
 }
 
+#[actix_rt::test]
+async fn telemetry_cannot_be_fetched_for_missing_models() {
+    let mut app = api_with! {
+        get: "/api/clients/models/{model_id}/telemetry" => clients::get_model_telemetry,
+    };
+
+    let url = format!("/api/clients/models/{}/telemetry", common::MODEL_ID);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::GET)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::NOT_FOUND, res.status());
+}
+
 #[actix_rt::test]
 async fn non_clients_cannot_request_new_private_keys() {
     let mut app = api_with! {
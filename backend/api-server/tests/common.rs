@@ -4,10 +4,14 @@ use std::sync::Arc;
 
 use mongodb::bson::{self, document::Document, oid::ObjectId, ser::to_document};
 
-use api_server::{auth, State};
+use api_server::{auth, webhooks::Dispatcher, State};
 use config::Environment;
+use messages::InMemoryBus;
 use models::users::{Client, User};
-use models::{job_performance::JobPerformance, jobs::JobConfiguration};
+use models::{
+    job_performance::JobPerformance,
+    jobs::{JobConfiguration, JobRequirements},
+};
 use models::{jobs::Job, projects::Project};
 
 #[allow(unused_macros)]
@@ -125,6 +129,10 @@ pub async fn build_app_state() -> State {
         pepper: Arc::new(pepper.clone()),
         pbkdf2_iterations: u32::from_str(&pbkdf2_iterations)
             .expect("PBKDF2_ITERATIONS must be parseable as an integer"),
+        bus: Arc::new(InMemoryBus::new()),
+        // Webhooks are delivered to a local mock server
+        dispatcher: Dispatcher::new(Arc::clone(&database)).allow_internal_addresses(),
+        mailer: None,
     }
 }
 
@@ -324,6 +332,7 @@ async fn insert_test_jobs(database: &mongodb::Database) {
         prediction_column: String::new(),
         prediction_type: models::jobs::PredictionType::Regression,
         cost: 100,
+        requirements: JobRequirements::default(),
     };
 
     // Initial one to ensure they can be retrieved
@@ -0,0 +1,19 @@
+use std::time::Instant;
+
+use api_server::routes::metrics;
+use utils::metrics::gather;
+
+#[test]
+fn request_latencies_are_exported() {
+    metrics::record_request(
+        "GET",
+        Some(String::from("/api/projects/{project_id}")),
+        200,
+        Instant::now(),
+    );
+
+    let body = gather();
+
+    assert!(body.contains("# TYPE api_request_duration_seconds histogram"));
+    assert!(body.contains("route=\"/api/projects/{project_id}\""));
+}
@@ -1,12 +1,19 @@
+use std::sync::Arc;
+
 use actix_web::web::{delete, get, patch, post, put};
 use actix_web::{middleware, test, App, Result};
-use mongodb::bson::{doc, document::Document};
+use async_trait::async_trait;
+use mongodb::bson::{de::from_document, doc, document::Document, oid::ObjectId, ser::to_document};
 use serde::{Deserialize, Serialize};
 use tokio::time::{sleep, Duration};
 
 use api_server::routes::projects;
+use api_server::State;
+use messages::{InMemoryBus, MessageBus, MessageStream};
 use models::dataset_details::DatasetDetails;
-use models::projects::Project;
+use models::project_events::ProjectEvent;
+use models::projects::{Project, Status};
+use models::users::User;
 use models::{dataset_analysis::DatasetAnalysis, jobs::Job};
 
 #[macro_use]
@@ -640,6 +647,121 @@ async fn users_cannot_submit_jobs_with_insufficient_funds() -> Result<()> {
     Ok(())
 }
 
+/// A bus that cannot send jobs to the DCL
+#[derive(Debug, Default)]
+struct JobsUnavailable {
+    inner: InMemoryBus,
+}
+
+#[async_trait]
+impl MessageBus for JobsUnavailable {
+    async fn produce(&self, topic: &str, key: &str, payload: &str) -> anyhow::Result<()> {
+        if topic == "jobs" {
+            anyhow::bail!("broker unavailable");
+        }
+
+        self.inner.produce(topic, key, payload).await
+    }
+
+    async fn subscribe(&self, group: &str, topics: &[&str]) -> anyhow::Result<MessageStream> {
+        self.inner.subscribe(group, topics).await
+    }
+}
+
+#[actix_rt::test]
+async fn jobs_that_cannot_be_queued_are_refunded() -> Result<()> {
+    let state = common::initialise().await;
+
+    // Use a user of its own, so that other tests cannot change its credits
+    let user = User::new("unqueued@email.com", "password", "Unqueued", "User");
+    let project = Project::new("Unqueued", "Cannot be queued", vec![], user.id.clone());
+
+    let users = state.database.collection("users");
+    users
+        .insert_one(to_document(&user).unwrap(), None)
+        .await
+        .unwrap();
+    let projects = state.database.collection("projects");
+    projects
+        .insert_one(to_document(&project).unwrap(), None)
+        .await
+        .unwrap();
+
+    let mut uploader = api_with! {
+        put: "/api/projects/{project_id}/upload_and_split" => projects::upload_and_split,
+    };
+
+    let url = format!("/api/projects/{}/upload_and_split", project.id);
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::PUT)
+        .insert_header(("Authorization", get_bearer_token(&user.id.to_string())))
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .uri(&url)
+        .set_payload(ASL_CSV)
+        .to_request();
+
+    let res = test::call_service(&mut uploader, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let state = State {
+        bus: Arc::new(JobsUnavailable::default()),
+        ..state
+    };
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .data(state.clone())
+            .route(
+                "/api/projects/{project_id}/process",
+                post().to(projects::begin_processing),
+            ),
+    )
+    .await;
+
+    let url = format!("/api/projects/{}/process", project.id);
+    let doc = doc! { "nodeComputationTime": 10, "clusterSize": 2, "predictionType": "classification", "predictionColumn": "name" };
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(&user.id.to_string())))
+        .uri(&url)
+        .set_json(&doc)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(
+        actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
+        res.status()
+    );
+
+    let document = users
+        .find_one(doc! { "_id": &user.id }, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let refunded: User = from_document(document).unwrap();
+    assert_eq!(refunded.credits, user.credits);
+
+    let jobs = state.database.collection("jobs");
+    let document = jobs
+        .find_one(doc! { "config.project_id": &project.id }, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let job: Job = from_document(document).unwrap();
+    assert!(job.processed);
+    assert!(job.failure.is_some());
+
+    let document = projects
+        .find_one(doc! { "_id": &project.id }, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let project: Project = from_document(document).unwrap();
+    assert!(matches!(project.status, Status::Ready));
+
+    Ok(())
+}
+
 #[actix_rt::test]
 async fn recent_jobs_can_be_found() -> Result<()> {
     let mut app = api_with! {
@@ -689,3 +811,110 @@ async fn most_recent_job_is_returned() -> Result<()> {
 
     Ok(())
 }
+
+#[actix_rt::test]
+async fn project_events_are_returned_in_order() -> Result<()> {
+    let mut app = api_with! {
+        get: "/api/projects/{project_id}/events" => projects::get_events,
+    };
+
+    let database = common::initialise().await.database;
+    let project_id = ObjectId::with_string(common::MAIN_PROJECT_ID).unwrap();
+    let user_id = ObjectId::with_string(common::MAIN_USER_ID).unwrap();
+
+    for event in &["dataSent", "analysisComplete"] {
+        let event = doc! { *event: { "project_id": common::MAIN_PROJECT_ID } };
+        ProjectEvent::record(&database, project_id.clone(), user_id.clone(), event)
+            .await
+            .unwrap();
+    }
+
+    let url = format!("/api/projects/{}/events", common::MAIN_PROJECT_ID);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::GET)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let body: Vec<serde_json::Value> = test::read_body_json(res).await;
+    let sent = body
+        .iter()
+        .position(|e| e.get("dataSent").is_some())
+        .unwrap();
+    let complete = body
+        .iter()
+        .position(|e| e.get("analysisComplete").is_some())
+        .unwrap();
+
+    assert!(sent < complete);
+    assert!(body[sent]["sequence"].as_i64() < body[complete]["sequence"].as_i64());
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn project_events_can_be_paged_through() -> Result<()> {
+    let mut app = api_with! {
+        get: "/api/projects/{project_id}/events" => projects::get_events,
+    };
+
+    let database = common::initialise().await.database;
+    let project_id = ObjectId::with_string(common::MAIN_PROJECT_ID).unwrap();
+    let user_id = ObjectId::with_string(common::MAIN_USER_ID).unwrap();
+
+    let mut sequences = Vec::new();
+
+    for _ in 0..3 {
+        let event = doc! { "dataSent": { "project_id": common::MAIN_PROJECT_ID } };
+        let recorded = ProjectEvent::record(&database, project_id.clone(), user_id.clone(), event)
+            .await
+            .unwrap();
+        sequences.push(recorded.sequence);
+    }
+
+    let url = format!(
+        "/api/projects/{}/events?since={}&limit=1",
+        common::MAIN_PROJECT_ID,
+        sequences[0]
+    );
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::GET)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let body: Vec<serde_json::Value> = test::read_body_json(res).await;
+
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0]["sequence"].as_i64(), Some(sequences[1]));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+async fn project_events_cannot_be_fetched_by_users_who_do_not_own_it() -> Result<()> {
+    let mut app = api_with! {
+        get: "/api/projects/{project_id}/events" => projects::get_events,
+    };
+
+    let url = format!("/api/projects/{}/events", common::MAIN_PROJECT_ID);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::GET)
+        .insert_header(("Authorization", get_bearer_token(common::ALONE_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::FORBIDDEN, res.status());
+
+    Ok(())
+}
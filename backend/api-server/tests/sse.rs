@@ -0,0 +1,295 @@
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix_web::web::{self, get, Bytes};
+use actix_web::{http::StatusCode, middleware, test, App};
+use futures::stream::{Stream, StreamExt};
+use mongodb::bson::{oid::ObjectId, ser::to_document};
+use serde_json::Value;
+
+use api_server::routes::payloads::{ProjectUpdate, WebsocketMessage};
+use api_server::routes::sse;
+use api_server::sessions::Sessions;
+use api_server::WebsocketState;
+use models::project_events::ProjectEvent;
+
+#[macro_use]
+mod common;
+
+use common::get_bearer_token;
+
+fn project_complete(project_id: &ObjectId) -> WebsocketMessage {
+    WebsocketMessage::ProjectComplete {
+        project_id: project_id.to_string(),
+    }
+}
+
+/// Reads the next event from a stream, returning its identifier and data
+async fn next_event<S, E>(body: &mut S) -> (i64, Value)
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Debug,
+{
+    let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+        .await
+        .expect("No event was sent in time")
+        .expect("The event stream ended")
+        .unwrap();
+
+    let frame = std::str::from_utf8(&chunk).unwrap();
+    assert!(frame.ends_with("\n\n"), "{:?} is not a full event", frame);
+
+    let mut lines = frame.trim_end().lines();
+    let id = lines.next().unwrap().strip_prefix("id: ").unwrap();
+    let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+
+    (id.parse().unwrap(), serde_json::from_str(data).unwrap())
+}
+
+#[actix_rt::test]
+async fn updates_can_be_streamed() {
+    let state = common::initialise().await;
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(WebsocketState {
+                sessions: Arc::clone(&sessions),
+                database: Arc::clone(&state.database),
+            }))
+            .route("/project_updates/stream", get().to(sse::index)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri("/project_updates/stream?projects=a,b")
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+
+    assert_eq!(StatusCode::OK, res.status());
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "text/event-stream"
+    );
+}
+
+#[actix_rt::test]
+async fn streams_require_authentication() {
+    let state = common::initialise().await;
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(WebsocketState {
+                sessions: Arc::new(RwLock::new(Sessions::new())),
+                database: Arc::clone(&state.database),
+            }))
+            .route("/project_updates/stream", get().to(sse::index)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/project_updates/stream")
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+
+    assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+}
+
+#[actix_rt::test]
+async fn missed_updates_are_replayed_before_new_ones() {
+    let state = common::initialise().await;
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+
+    let user_id = ObjectId::new();
+    let project_id = ObjectId::new();
+
+    let mut recorded = Vec::new();
+
+    for _ in 0..3 {
+        let event = to_document(&project_complete(&project_id)).unwrap();
+        let event =
+            ProjectEvent::record(&state.database, project_id.clone(), user_id.clone(), event)
+                .await
+                .unwrap();
+
+        recorded.push(event.sequence);
+    }
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(WebsocketState {
+                sessions: Arc::clone(&sessions),
+                database: Arc::clone(&state.database),
+            }))
+            .route("/project_updates/stream", get().to(sse::index)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .insert_header(("Authorization", get_bearer_token(&user_id.to_string())))
+        .insert_header(("Last-Event-ID", recorded[0].to_string()))
+        .uri("/project_updates/stream")
+        .to_request();
+
+    let mut res = test::call_service(&mut app, req).await;
+    assert_eq!(StatusCode::OK, res.status());
+
+    let mut body = res.take_body();
+
+    // Only the updates after the last one the client saw are replayed
+    for sequence in &recorded[1..] {
+        let (id, data) = next_event(&mut body).await;
+
+        assert_eq!(id, *sequence);
+        assert_eq!(data["sequence"], *sequence);
+        assert!(data.get("projectComplete").is_some());
+    }
+
+    // New updates follow on from the replay
+    let live = ProjectUpdate {
+        message: project_complete(&project_id),
+        sequence: recorded[2] + 1,
+        timestamp: 0,
+    };
+
+    for recipient in sessions
+        .read()
+        .unwrap()
+        .recipients(&user_id.to_string(), &project_id.to_string())
+    {
+        recipient.do_send(live.clone()).unwrap();
+    }
+
+    let (id, _) = next_event(&mut body).await;
+    assert_eq!(id, live.sequence);
+}
+
+#[actix_rt::test]
+async fn replayed_updates_are_not_sent_again() {
+    let state = common::initialise().await;
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+
+    let user_id = ObjectId::new();
+    let project_id = ObjectId::new();
+
+    let mut recorded = Vec::new();
+
+    for _ in 0..2 {
+        let event = to_document(&project_complete(&project_id)).unwrap();
+        let event =
+            ProjectEvent::record(&state.database, project_id.clone(), user_id.clone(), event)
+                .await
+                .unwrap();
+
+        recorded.push(event.sequence);
+    }
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(WebsocketState {
+                sessions: Arc::clone(&sessions),
+                database: Arc::clone(&state.database),
+            }))
+            .route("/project_updates/stream", get().to(sse::index)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .insert_header(("Authorization", get_bearer_token(&user_id.to_string())))
+        .insert_header(("Last-Event-ID", (recorded[0] - 1).to_string()))
+        .uri("/project_updates/stream")
+        .to_request();
+
+    let mut res = test::call_service(&mut app, req).await;
+    assert_eq!(StatusCode::OK, res.status());
+
+    // Wait for the stream to register its session
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The last recorded update arrives live as well as being replayed, followed by a new one
+    for sequence in &[recorded[1], recorded[1] + 1] {
+        let live = ProjectUpdate {
+            message: project_complete(&project_id),
+            sequence: *sequence,
+            timestamp: 0,
+        };
+
+        for recipient in sessions
+            .read()
+            .unwrap()
+            .recipients(&user_id.to_string(), &project_id.to_string())
+        {
+            recipient.do_send(live.clone()).unwrap();
+        }
+    }
+
+    let mut body = res.take_body();
+
+    for sequence in &[recorded[0], recorded[1], recorded[1] + 1] {
+        let (id, _) = next_event(&mut body).await;
+        assert_eq!(id, *sequence);
+    }
+}
+
+#[actix_rt::test]
+async fn slow_clients_are_disconnected() {
+    let state = common::initialise().await;
+    let sessions = Arc::new(RwLock::new(Sessions::new()));
+
+    let user_id = ObjectId::new();
+    let project_id = ObjectId::new();
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .app_data(web::Data::new(WebsocketState {
+                sessions: Arc::clone(&sessions),
+                database: Arc::clone(&state.database),
+            }))
+            .route("/project_updates/stream", get().to(sse::index)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .insert_header(("Authorization", get_bearer_token(&user_id.to_string())))
+        .uri("/project_updates/stream")
+        .to_request();
+
+    // The body is never read, so the client falls behind
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(StatusCode::OK, res.status());
+
+    // Wait for the stream to register its session
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(sessions.read().unwrap().len(), 1);
+
+    let recipients: Vec<_> = sessions
+        .read()
+        .unwrap()
+        .recipients(&user_id.to_string(), &project_id.to_string())
+        .cloned()
+        .collect();
+
+    for sequence in 0..200 {
+        let update = ProjectUpdate {
+            message: project_complete(&project_id),
+            sequence,
+            timestamp: 0,
+        };
+
+        for recipient in &recipients {
+            let _ = recipient.do_send(update.clone());
+        }
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(sessions.read().unwrap().is_empty());
+}
@@ -263,3 +263,42 @@ async fn users_can_be_deleted() -> Result<()> {
 
     Ok(())
 }
+
+#[actix_rt::test]
+async fn users_can_change_their_notification_preferences() -> Result<()> {
+    let mut app = api_with! {
+        post: "/api/users/filter" => users::filter,
+        post: "/api/users/notifications" => users::edit_notifications,
+    };
+
+    let doc = doc! { "project_complete": false, "project_failed": true, "low_credits": false };
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .uri("/api/users/notifications")
+        .insert_header(("Authorization", get_bearer_token(common::ALONE_USER_ID)))
+        .set_json(&doc)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    // Check the preferences were saved
+    let doc = doc! {"filter": { "email": "lone@email.com" } };
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .uri("/api/users/filter")
+        .set_json(&doc)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let users: Vec<User> = test::read_body_json(res).await;
+    let notifications = &users[0].notifications;
+
+    assert!(!notifications.project_complete);
+    assert!(notifications.project_failed);
+    assert!(!notifications.low_credits);
+
+    Ok(())
+}
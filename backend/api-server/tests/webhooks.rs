@@ -0,0 +1,363 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web::{get, post};
+use actix_web::{middleware, test, App};
+use mockito::{mock, Matcher};
+use mongodb::bson::{doc, oid::ObjectId, ser::to_document};
+use serde_json::Value;
+
+use api_server::routes::webhooks;
+use api_server::webhooks::{is_internal, Dispatcher, WebhookPayload};
+use api_server::State;
+use messages::bus::RetryPolicy;
+use messages::KafkaWsMessage;
+use models::webhooks::{Webhook, WebhookDelivery, WebhookEvent};
+
+#[macro_use]
+mod common;
+
+use common::get_bearer_token;
+
+async fn insert_webhook(database: &mongodb::Database, path: &str) -> Webhook {
+    let webhook = Webhook::new(
+        ObjectId::with_string(common::MAIN_USER_ID).unwrap(),
+        None,
+        format!("{}{}", mockito::server_url(), path),
+        WebhookEvent::DEFAULT.to_vec(),
+    );
+
+    let webhooks = database.collection("webhooks");
+    webhooks
+        .insert_one(to_document(&webhook).unwrap(), None)
+        .await
+        .unwrap();
+
+    webhook
+}
+
+#[actix_rt::test]
+async fn webhooks_can_be_registered() {
+    let mut app = api_with! {
+        post: "/api/webhooks/new" => webhooks::new,
+    };
+
+    let doc = doc! { "url": "https://example.com/hook", "events": ["job.completed"] };
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .set_json(&doc)
+        .uri("/api/webhooks/new")
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["events"], serde_json::json!(["job.completed"]));
+    assert!(body["secret"].as_str().map_or(false, |s| !s.is_empty()));
+}
+
+#[actix_rt::test]
+async fn webhooks_must_use_http() {
+    let mut app = api_with! {
+        post: "/api/webhooks/new" => webhooks::new,
+    };
+
+    let doc = doc! { "url": "file:///etc/passwd" };
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .set_json(&doc)
+        .uri("/api/webhooks/new")
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+        res.status()
+    );
+}
+
+#[actix_rt::test]
+async fn webhooks_cannot_be_registered_for_internal_addresses() {
+    let state = common::initialise().await;
+
+    // Only the tests allow webhooks to reach the local mock server
+    let state = State {
+        dispatcher: Dispatcher::new(Arc::clone(&state.database)),
+        ..state
+    };
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(middleware::Logger::default())
+            .data(state)
+            .route("/api/webhooks/new", post().to(webhooks::new)),
+    )
+    .await;
+
+    let urls = [
+        "http://localhost/hook",
+        "http://127.0.0.1:8000/hook",
+        "http://10.0.0.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://0.0.0.0/hook",
+        "http://[::1]/hook",
+    ];
+
+    for url in &urls {
+        let doc = doc! { "url": *url };
+
+        let req = test::TestRequest::default()
+            .method(actix_web::http::Method::POST)
+            .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+            .set_json(&doc)
+            .uri("/api/webhooks/new")
+            .to_request();
+
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+            res.status(),
+            "{} should be refused",
+            url
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn webhooks_cannot_be_registered_for_projects_the_user_does_not_own() {
+    let mut app = api_with! {
+        post: "/api/webhooks/new" => webhooks::new,
+    };
+
+    let doc = doc! { "url": "https://example.com/hook", "projectId": common::MAIN_PROJECT_ID };
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(common::ALONE_USER_ID)))
+        .set_json(&doc)
+        .uri("/api/webhooks/new")
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::FORBIDDEN, res.status());
+}
+
+#[actix_rt::test]
+async fn webhooks_can_be_test_fired() {
+    let mut app = api_with! {
+        post: "/api/webhooks/{webhook_id}/test" => webhooks::test,
+        get: "/api/webhooks/{webhook_id}/deliveries" => webhooks::get_deliveries,
+    };
+
+    let database = common::initialise().await.database;
+    let webhook = insert_webhook(&database, "/test-fire").await;
+
+    let receiver = mock("POST", "/test-fire")
+        .match_header("X-Sybl-Event", "webhook.test")
+        .match_header(
+            "X-Sybl-Signature",
+            Matcher::Regex(String::from("^sha256=[0-9a-f]{64}$")),
+        )
+        .with_status(204)
+        .create();
+
+    let url = format!("/api/webhooks/{}/test", webhook.id);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(body["status"], 204);
+
+    receiver.assert();
+
+    // The attempt should be in the delivery log
+    let url = format!("/api/webhooks/{}/deliveries", webhook.id);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::GET)
+        .insert_header(("Authorization", get_bearer_token(common::MAIN_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::OK, res.status());
+
+    let body: Vec<Value> = test::read_body_json(res).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0]["event"], "webhook.test");
+}
+
+#[actix_rt::test]
+async fn webhooks_cannot_be_test_fired_by_other_users() {
+    let mut app = api_with! {
+        post: "/api/webhooks/{webhook_id}/test" => webhooks::test,
+    };
+
+    let database = common::initialise().await.database;
+    let webhook = insert_webhook(&database, "/not-fired").await;
+
+    let url = format!("/api/webhooks/{}/test", webhook.id);
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Authorization", get_bearer_token(common::ALONE_USER_ID)))
+        .uri(&url)
+        .to_request();
+
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(actix_web::http::StatusCode::FORBIDDEN, res.status());
+}
+
+#[actix_rt::test]
+async fn failed_deliveries_are_retried_and_logged() {
+    let database = common::initialise().await.database;
+    let webhook = insert_webhook(&database, "/failing").await;
+
+    let receiver = mock("POST", "/failing")
+        .match_header("X-Sybl-Event", "job.failed")
+        .with_status(500)
+        .expect(3)
+        .create();
+
+    let dispatcher = Dispatcher::new(Arc::clone(&database)).with_retry_policy(RetryPolicy {
+        attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    });
+
+    let payload = WebhookPayload::new(
+        WebhookEvent::JobFailed,
+        Some(String::from(common::MAIN_PROJECT_ID)),
+        serde_json::json!({ "reason": "No data" }),
+    );
+
+    let delivery = dispatcher.deliver(&webhook, &payload).await.unwrap();
+
+    assert!(!delivery.success);
+    assert_eq!(delivery.attempt, 3);
+    assert_eq!(delivery.status, Some(500));
+
+    receiver.assert();
+
+    let log = WebhookDelivery::for_webhook(&database, &webhook.id, 10)
+        .await
+        .unwrap();
+    assert_eq!(log.len(), 3);
+    assert!(log
+        .iter()
+        .all(|attempt| attempt.delivery_id.to_string() == payload.id));
+}
+
+#[actix_rt::test]
+async fn deliveries_to_internal_addresses_are_refused() {
+    let database = common::initialise().await.database;
+    let webhook = insert_webhook(&database, "/internal").await;
+
+    let receiver = mock("POST", "/internal").expect(0).create();
+
+    let dispatcher = Dispatcher::new(Arc::clone(&database));
+    let payload = WebhookPayload::new(WebhookEvent::Test, None, Value::Null);
+
+    let delivery = dispatcher.attempt(&webhook, &payload, 1).await.unwrap();
+
+    assert!(!delivery.success);
+    assert_eq!(delivery.status, None);
+    assert!(delivery
+        .error
+        .map_or(false, |error| error.contains("internal address")));
+
+    receiver.assert();
+}
+
+#[actix_rt::test]
+async fn redirects_are_not_followed() {
+    let database = common::initialise().await.database;
+    let webhook = insert_webhook(&database, "/redirecting").await;
+
+    let redirect = mock("POST", "/redirecting")
+        .with_status(307)
+        .with_header("Location", &format!("{}/redirected", mockito::server_url()))
+        .create();
+    let redirected = mock("POST", "/redirected").expect(0).create();
+
+    let dispatcher = Dispatcher::new(Arc::clone(&database)).allow_internal_addresses();
+    let payload = WebhookPayload::new(WebhookEvent::Test, None, Value::Null);
+
+    let delivery = dispatcher.attempt(&webhook, &payload, 1).await.unwrap();
+
+    assert!(!delivery.success);
+    assert_eq!(delivery.status, Some(307));
+
+    redirect.assert();
+    redirected.assert();
+}
+
+#[test]
+fn internal_addresses_are_detected() {
+    let internal = [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+    ];
+    let external = ["93.184.216.34", "8.8.8.8", "2606:2800:220:1::1"];
+
+    for address in &internal {
+        assert!(is_internal(address.parse().unwrap()), "{}", address);
+    }
+
+    for address in &external {
+        assert!(!is_internal(address.parse().unwrap()), "{}", address);
+    }
+}
+
+#[test]
+fn only_some_updates_are_sent_to_webhooks() {
+    let completed = KafkaWsMessage::JobCompleteMessage {
+        project_id: String::from("project"),
+    };
+    let failed = KafkaWsMessage::JobFailedMessage {
+        project_id: String::from("project"),
+        reason: String::from("No data"),
+    };
+    let analysed = KafkaWsMessage::AnalysisCompleteMessage {
+        project_id: String::from("project"),
+    };
+    let queued = KafkaWsMessage::JobQueuedMessage {
+        project_id: String::from("project"),
+        job_id: String::from("job"),
+    };
+
+    let event =
+        |update: &KafkaWsMessage| WebhookPayload::from_update(update).map(|payload| payload.event);
+
+    assert_eq!(event(&completed), Some(WebhookEvent::JobCompleted));
+    assert_eq!(event(&failed), Some(WebhookEvent::JobFailed));
+    assert_eq!(event(&analysed), Some(WebhookEvent::AnalysisReady));
+    assert_eq!(event(&queued), None);
+
+    let payload = WebhookPayload::from_update(&failed).unwrap();
+    assert_eq!(payload.project_id.as_deref(), Some("project"));
+    assert_eq!(payload.data["reason"], "No data");
+}
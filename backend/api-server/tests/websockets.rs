@@ -0,0 +1,162 @@
+use serde_json::json;
+
+use api_server::routes::payloads::{ProjectUpdate, WebsocketMessage};
+use api_server::sessions::{ReplayBuffer, Sessions};
+use messages::KafkaWsMessage;
+
+#[test]
+fn lifecycle_updates_are_forwarded_to_websockets() {
+    let update = KafkaWsMessage::WaitingForNodesMessage {
+        project_id: String::from("project"),
+        cluster_size: 3,
+    };
+
+    let message = serde_json::to_value(WebsocketMessage::from(&update)).unwrap();
+
+    assert_eq!(
+        message,
+        json!({ "waitingForNodes": { "project_id": "project", "cluster_size": 3 } })
+    );
+}
+
+#[test]
+fn failures_are_forwarded_with_their_reason() {
+    let update = KafkaWsMessage::JobFailedMessage {
+        project_id: String::from("project"),
+        reason: String::from("No data"),
+    };
+
+    let message = serde_json::to_value(WebsocketMessage::from(&update)).unwrap();
+
+    assert_eq!(
+        message,
+        json!({ "projectFailed": { "project_id": "project", "reason": "No data" } })
+    );
+}
+
+#[test]
+fn updates_keep_their_message_first_and_add_a_sequence() {
+    let update = ProjectUpdate {
+        message: WebsocketMessage::DataSent {
+            project_id: String::from("project"),
+        },
+        sequence: 4,
+        timestamp: 1000,
+    };
+
+    let message = serde_json::to_string(&update).unwrap();
+
+    assert_eq!(
+        message,
+        r#"{"dataSent":{"project_id":"project"},"sequence":4,"timestamp":1000}"#
+    );
+}
+
+fn recipients(
+    sessions: &Sessions<&'static str>,
+    user_id: &str,
+    project_id: &str,
+) -> Vec<&'static str> {
+    let mut recipients: Vec<_> = sessions.recipients(user_id, project_id).copied().collect();
+    recipients.sort_unstable();
+    recipients
+}
+
+#[test]
+fn every_session_for_a_user_receives_updates() {
+    let mut sessions = Sessions::new();
+    sessions.register("user", "first");
+    sessions.register("user", "second");
+    sessions.register("other", "third");
+
+    assert_eq!(
+        recipients(&sessions, "user", "project"),
+        vec!["first", "second"]
+    );
+}
+
+#[test]
+fn subscribed_sessions_only_receive_their_projects() {
+    let mut sessions = Sessions::new();
+    let first = sessions.register("user", "first");
+    sessions.register("user", "second");
+
+    assert!(sessions.subscribe(first, "wanted"));
+
+    assert_eq!(
+        recipients(&sessions, "user", "wanted"),
+        vec!["first", "second"]
+    );
+    assert_eq!(recipients(&sessions, "user", "unwanted"), vec!["second"]);
+
+    assert!(sessions.unsubscribe(first, "wanted"));
+    assert_eq!(recipients(&sessions, "user", "wanted"), vec!["second"]);
+}
+
+#[test]
+fn sessions_can_unsubscribe_from_single_projects() {
+    let mut sessions = Sessions::new();
+    let id = sessions.register("user", "only");
+
+    sessions.unsubscribe(id, "muted");
+
+    assert!(recipients(&sessions, "user", "muted").is_empty());
+    assert_eq!(recipients(&sessions, "user", "other"), vec!["only"]);
+}
+
+#[test]
+fn closed_sessions_receive_nothing() {
+    let mut sessions = Sessions::new();
+    let id = sessions.register("user", "closed");
+
+    sessions.remove(id);
+
+    assert!(sessions.is_empty());
+    assert!(recipients(&sessions, "user", "project").is_empty());
+    assert!(!sessions.subscribe(id, "project"));
+}
+
+#[test]
+fn replays_respect_subscriptions() {
+    let mut sessions = Sessions::new();
+    let id = sessions.register("user", "replayed");
+
+    sessions.subscribe(id, "wanted");
+
+    assert!(sessions.wants(id, "wanted"));
+    assert!(!sessions.wants(id, "unwanted"));
+    assert!(!sessions.wants(id + 1, "wanted"));
+}
+
+fn update(sequence: i64) -> ProjectUpdate {
+    ProjectUpdate {
+        message: WebsocketMessage::DataSent {
+            project_id: String::from("project"),
+        },
+        sequence,
+        timestamp: 0,
+    }
+}
+
+#[test]
+fn live_updates_are_held_back_until_replays_finish() {
+    let mut replay = ReplayBuffer::new();
+
+    assert!(replay.receive(update(1)).is_some());
+
+    replay.start();
+
+    assert!(replay.is_replaying());
+    assert!(replay.receive(update(5)).is_none());
+    assert!(replay.receive(update(7)).is_none());
+
+    // The replay already sent the update with sequence 5
+    replay.replayed(5);
+
+    let released: Vec<_> = replay.finish().iter().map(|u| u.sequence).collect();
+
+    assert_eq!(released, vec![7]);
+    assert!(!replay.is_replaying());
+    assert!(replay.receive(update(4)).is_none());
+    assert!(replay.receive(update(8)).is_some());
+}
@@ -2,10 +2,11 @@
 
 use ammonia::clean_text;
 use html_escape::decode_html_entities;
+use openssl::error::ErrorStack;
 use openssl::hash::MessageDigest as MD;
 use openssl::pkey::{PKey, Private};
 use openssl::rsa::Rsa;
-use openssl::sign::Verifier;
+use openssl::sign::{Signer, Verifier};
 use pbkdf2::{
     password_hash::{
         HasherError, PasswordHash, PasswordHasher, PasswordVerifier, SaltString, VerifyError,
@@ -102,6 +103,31 @@ pub fn verify_challenge(challenge: &[u8], response: &[u8], public_key: &str) ->
     verifier.verify_oneshot(&response, &challenge).unwrap()
 }
 
+/// Returns the response to a challenge, signed with a PEM-encoded RSA `private_key`
+///
+/// This is the inverse of [`verify_challenge`], used by compute nodes when they register a model
+/// or their access token expires.
+pub fn sign_challenge(challenge: &[u8], private_key: &str) -> Result<Vec<u8>, ErrorStack> {
+    let rsa = Rsa::private_key_from_pem(private_key.as_bytes())?;
+    let keypair = PKey::from_rsa(rsa)?;
+    let mut signer = Signer::new(MD::sha256(), &keypair)?;
+    signer.sign_oneshot_to_vec(challenge)
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of `message` using `secret` as the key
+///
+/// Used to sign webhook payloads, allowing the receiver to check that they came from Sybl.
+pub fn sign_hmac(secret: &[u8], message: &[u8]) -> Result<String, ErrorStack> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MD::sha256(), &key)?;
+    let signature = signer.sign_oneshot_to_vec(message)?;
+
+    Ok(signature
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
 /// Generates a user API key of `API_KEY_SIZE` alphanumeric characters.
 pub fn generate_user_api_key() -> String {
     generate_string(API_KEY_SIZE)
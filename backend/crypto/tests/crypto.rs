@@ -33,3 +33,24 @@ fn challenge_response_protocol() {
         std::str::from_utf8(&rsa.public_key_to_pem().unwrap()).unwrap()
     ));
 }
+
+#[test]
+fn signed_challenges_can_be_verified() {
+    let (private_key, public_key) = encoded_key_pair();
+    let challenge = generate_challenge();
+    let response = sign_challenge(&challenge, &private_key).unwrap();
+
+    assert!(verify_challenge(&challenge, &response, &public_key));
+    assert!(sign_challenge(&challenge, "not a key").is_err());
+}
+
+#[test]
+fn hmac_signatures_match_known_values() {
+    // Test case 2 from RFC 4231
+    let signature = sign_hmac(b"Jefe", b"what do ya want for nothing?").unwrap();
+
+    assert_eq!(
+        signature,
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
@@ -0,0 +1,297 @@
+//! Administrative HTTP server for inspecting the state of the DCL
+//!
+//! The server only binds to localhost and exposes the contents of the [`NodePool`], the
+//! [`JobQueue`](crate::JobQueue), the running clusters and the live DCL instances as JSON, along with actions to evict a
+//! node from the pool, requeue a job or replay dead letters. The following routes are available:
+//!
+//! | Method |           Route            |                   Meaning                   |
+//! |--------|----------------------------|---------------------------------------------|
+//! | `GET`  | `/nodes`                   | All nodes in the pool and their failures    |
+//! | `GET`  | `/queue`                   | All jobs waiting in the queue, in order     |
+//! | `GET`  | `/clusters`                | All clusters currently running jobs         |
+//! | `GET`  | `/instances`               | All live DCL instances and their capacity   |
+//! | `POST` | `/nodes/{model_id}/evict`  | Removes a node from the pool                |
+//! | `POST` | `/jobs/{job_id}/requeue`   | Resets a job and sends it back to the bus   |
+//! | `POST` | `/dlq/{topic}/replay`      | Sends dead letters back to their topic      |
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response};
+use mongodb::{bson::oid::ObjectId, Database};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use messages::bus::dead_letter;
+use messages::{envelope, JobRequested};
+use models::instances::Instance;
+use models::jobs::{Job, JobReset};
+use models::models::Status;
+
+use crate::node_end::{update_model_status, NodePool};
+use crate::JobControl;
+
+/// How long to wait for another dead letter before a replay is considered complete
+const REPLAY_IDLE: Duration = Duration::from_secs(5);
+
+/// The state of the DCL that the admin server can access
+#[derive(Debug, Clone)]
+pub struct AdminState {
+    /// The pool of connected nodes
+    pub nodepool: Arc<NodePool>,
+    /// The database connection
+    pub database: Arc<Database>,
+    /// The job queue and running clusters
+    pub job_control: JobControl,
+}
+
+/// Starts the admin server on localhost with the given port
+pub async fn run(port: u16, state: AdminState) -> Result<()> {
+    let socket = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
+    let listener = TcpListener::bind(&socket).await?;
+
+    log::info!("Serving the admin endpoint on: {}", socket);
+
+    serve(listener, state).await
+}
+
+/// Serves admin requests on a listener until the DCL shuts down
+pub async fn serve(listener: TcpListener, state: AdminState) -> Result<()> {
+    loop {
+        let inbound = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = state.job_control.shutdown.triggered() => break,
+        };
+
+        let state = state.clone();
+        let service = service_fn(move |request| handle_request(request, state.clone()));
+
+        tokio::spawn(async move {
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .serve_connection(inbound, service)
+                .await
+            {
+                log::warn!("Error handling an admin request: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Routes a single request and builds its JSON response
+async fn handle_request(
+    request: Request<Body>,
+    state: AdminState,
+) -> Result<Response<Body>, http::Error> {
+    log::debug!(
+        "Received an admin request: {} {}",
+        request.method(),
+        request.uri()
+    );
+
+    let (status, body) = route(&state, request.method().as_str(), request.uri().path()).await;
+
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+/// Routes a request to the relevant handler, returning the status and body of the response
+pub async fn route(state: &AdminState, method: &str, path: &str) -> (StatusCode, Value) {
+    // Ignore any query string and surrounding slashes
+    let path = path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["nodes"]) => get_nodes(state).await,
+        ("GET", ["queue"]) => get_queue(state),
+        ("GET", ["clusters"]) => get_clusters(state),
+        ("GET", ["instances"]) => get_instances(state).await,
+        ("POST", ["nodes", model_id, "evict"]) => evict_node(state, model_id).await,
+        ("POST", ["jobs", job_id, "requeue"]) => requeue_job(state, job_id).await,
+        ("POST", ["dlq", topic, "replay"]) => replay_dead_letters(state, topic).await,
+        (_, ["nodes"]) | (_, ["queue"]) | (_, ["clusters"]) | (_, ["instances"]) => {
+            error(StatusCode::METHOD_NOT_ALLOWED)
+        }
+        (_, ["nodes", _, "evict"]) | (_, ["jobs", _, "requeue"]) | (_, ["dlq", _, "replay"]) => {
+            error(StatusCode::METHOD_NOT_ALLOWED)
+        }
+        _ => error(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Builds an error response with the given status
+fn error(status: StatusCode) -> (StatusCode, Value) {
+    let reason = status.canonical_reason().unwrap_or_default();
+    (status, json!({ "error": reason }))
+}
+
+async fn get_nodes(state: &AdminState) -> (StatusCode, Value) {
+    let nodes_read = state.nodepool.nodes.read().await;
+    let info_read = state.nodepool.info.read().await;
+
+    let mut nodes = Vec::new();
+
+    for (id, info) in info_read.iter() {
+        let failed_heartbeats = match nodes_read.get(id) {
+            Some(node) => node.get_counter().await,
+            None => 0,
+        };
+
+        nodes.push(json!({
+            "model_id": id,
+            "info": info,
+            "failed_heartbeats": failed_heartbeats,
+            "failures": state.nodepool.get_failures(id).await,
+        }));
+    }
+
+    (StatusCode::OK, Value::Array(nodes))
+}
+
+fn get_queue(state: &AdminState) -> (StatusCode, Value) {
+    let jobs: Vec<Value> = state
+        .job_control
+        .job_queue
+        .jobs()
+        .into_iter()
+        .map(|(project_id, job)| {
+            json!({
+                "project_id": project_id.to_string(),
+                "job_id": job.id.to_string(),
+                "cluster_size": job.config.cluster_size,
+                "node_computation_time": job.config.node_computation_time,
+                "prediction_type": job.config.prediction_type,
+                "requirements": job.config.requirements,
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Value::Array(jobs))
+}
+
+fn get_clusters(state: &AdminState) -> (StatusCode, Value) {
+    let clusters = state.job_control.clusters.get_clusters();
+
+    match serde_json::to_value(clusters) {
+        Ok(clusters) => (StatusCode::OK, clusters),
+        Err(_) => error(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn get_instances(state: &AdminState) -> (StatusCode, Value) {
+    // Instances that have missed several advertisements have most likely stopped
+    let max_age = state.job_control.instance.lease();
+
+    let instances = match Instance::get_live(&state.database, max_age).await {
+        Ok(instances) => instances,
+        Err(e) => {
+            log::error!("Failed to get the live instances: {}", e);
+            return error(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let instances: Vec<Value> = instances
+        .into_iter()
+        .map(|instance| {
+            json!({
+                "instance_id": instance.id,
+                "idle_nodes": instance.idle_nodes,
+                "total_nodes": instance.total_nodes,
+                "last_seen": instance.last_seen.0.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Value::Array(instances))
+}
+
+async fn evict_node(state: &AdminState, model_id: &str) -> (StatusCode, Value) {
+    if !state.nodepool.remove(model_id).await {
+        return error(StatusCode::NOT_FOUND);
+    }
+
+    log::info!("Evicted model_id={} through the admin endpoint", model_id);
+
+    if let Err(e) =
+        update_model_status(Arc::clone(&state.database), model_id, Status::Stopped).await
+    {
+        log::warn!("Failed to set model_id={} to stopped: {}", model_id, e);
+    }
+
+    (StatusCode::OK, json!({ "model_id": model_id }))
+}
+
+async fn requeue_job(state: &AdminState, job_id: &str) -> (StatusCode, Value) {
+    let object_id = match ObjectId::with_string(job_id) {
+        Ok(object_id) => object_id,
+        Err(_) => return error(StatusCode::BAD_REQUEST),
+    };
+
+    let queued = state
+        .job_control
+        .job_queue
+        .jobs()
+        .iter()
+        .any(|(_, job)| job.id == object_id);
+
+    // Avoid running the same job twice if it is already waiting or running
+    if queued || state.job_control.clusters.contains(job_id) {
+        return error(StatusCode::CONFLICT);
+    }
+
+    // Jobs leased by other instances may still be running, and completed jobs have nothing to redo
+    let job = match Job::reset(&state.database, &object_id).await {
+        Ok(JobReset::Reset(job)) => job,
+        Ok(JobReset::NotFound) => return error(StatusCode::NOT_FOUND),
+        Ok(JobReset::Completed) | Ok(JobReset::Leased) => return error(StatusCode::CONFLICT),
+        Err(e) => {
+            log::error!("Failed to reset job_id={}: {}", job_id, e);
+            return error(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    log::info!("Requeuing job_id={} through the admin endpoint", job_id);
+    let bus = state.job_control.bus.as_ref();
+    let event = JobRequested::from(&job);
+
+    if let Err(e) = envelope::publish(bus, job_id, &event, job_id).await {
+        log::error!("Failed to requeue job_id={}: {}", job_id, e);
+        return error(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    (StatusCode::OK, json!({ "job_id": job_id }))
+}
+
+async fn replay_dead_letters(state: &AdminState, topic: &str) -> (StatusCode, Value) {
+    // Replaying a dead letter topic onto itself would never finish
+    if topic.ends_with(dead_letter::DEAD_LETTER_SUFFIX) {
+        return error(StatusCode::BAD_REQUEST);
+    }
+
+    log::info!(
+        "Replaying dead letters for topic={} through the admin endpoint",
+        topic
+    );
+    let bus = state.job_control.bus.as_ref();
+
+    match dead_letter::replay(bus, topic, REPLAY_IDLE).await {
+        Ok(replayed) => (
+            StatusCode::OK,
+            json!({ "topic": topic, "replayed": replayed }),
+        ),
+        Err(e) => {
+            log::error!("Failed to replay dead letters for topic={}: {}", topic, e);
+            error(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
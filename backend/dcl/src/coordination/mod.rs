@@ -0,0 +1,189 @@
+//! Coordinates multiple DCL instances sharing the same database
+//!
+//! Every instance consumes all jobs from Kafka, but a job is only run by the instance that manages
+//! to claim a lease on it in `MongoDB`, which it only attempts once it has enough idle nodes. Jobs
+//! held by another instance stay in the local queue until they are processed, so if that instance
+//! stops, its lease expires and is released, and the job is claimed by whichever instance next has
+//! the nodes for it. Leases are renewed while the job runs and released once it finishes or fails.
+//! Instances also periodically advertise their node capacity, which can be read through the admin
+//! endpoint.
+
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use mongodb::Database;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep};
+
+use models::instances::Instance;
+use models::jobs::Job;
+
+use crate::node_end::NodePool;
+use crate::JobControl;
+
+/// The default time a lease on a job lasts for before it must be renewed
+pub const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+/// The name used for an instance if one is not configured
+pub const DEFAULT_INSTANCE_ID: &str = "dcl";
+
+/// The prefix of the Kafka consumer group used by each instance
+pub const CONSUMER_GROUP_PREFIX: &str = "job_config";
+
+/// Configuration identifying this DCL instance to others
+#[derive(Clone, Debug)]
+pub struct InstanceConfig {
+    /// The unique name of this instance
+    pub id: String,
+    /// How long leases on jobs last before they must be renewed
+    pub lease_duration: Duration,
+}
+
+impl InstanceConfig {
+    /// Creates a new [`InstanceConfig`]
+    pub fn new(id: impl Into<String>, lease_duration: Duration) -> Self {
+        Self {
+            id: id.into(),
+            lease_duration,
+        }
+    }
+
+    /// Creates a new [`InstanceConfig`] from the environment
+    ///
+    /// Uses `DCL_INSTANCE` as the name of the instance and `LEASE_DURATION` as the number of
+    /// seconds a lease lasts for, falling back to the defaults if either is not set.
+    pub fn from_env() -> Self {
+        let id = env::var("DCL_INSTANCE").unwrap_or_else(|_| String::from(DEFAULT_INSTANCE_ID));
+        let lease_duration = env::var("LEASE_DURATION")
+            .ok()
+            .map(|v| u64::from_str(&v).expect("LEASE_DURATION must be a u64"))
+            .map_or(DEFAULT_LEASE_DURATION, Duration::from_secs);
+
+        Self::new(id, lease_duration)
+    }
+
+    /// Gets the duration of a lease in the form used by the database
+    pub fn lease(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.lease_duration).unwrap_or_else(|_| chrono::Duration::zero())
+    }
+
+    /// Gets the Kafka consumer group for this instance
+    ///
+    /// Each instance uses its own group so that all of them see every job, leaving the leases to
+    /// decide which instance actually runs it.
+    pub fn consumer_group(&self) -> String {
+        format!("{}-{}", CONSUMER_GROUP_PREFIX, self.id)
+    }
+
+    /// Gets how often leases should be renewed and capacity advertised
+    pub fn renewal_interval(&self) -> Duration {
+        (self.lease_duration / 3).max(Duration::from_secs(1))
+    }
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_INSTANCE_ID, DEFAULT_LEASE_DURATION)
+    }
+}
+
+/// The outcome of attempting to claim a job
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Claim {
+    /// This instance now holds the lease on the job
+    Claimed,
+    /// Another instance holds an unexpired lease on the job
+    Held,
+    /// The job has already been processed and can be discarded
+    Processed,
+}
+
+/// Attempts to claim a job for this instance
+pub async fn claim(database: &Database, job: &Job, config: &InstanceConfig) -> Result<Claim> {
+    if job.claim(database, &config.id, config.lease()).await? {
+        return Ok(Claim::Claimed);
+    }
+
+    if job.is_processed(database).await? {
+        Ok(Claim::Processed)
+    } else {
+        Ok(Claim::Held)
+    }
+}
+
+/// Renews the lease on a job in the background until the returned handle is aborted
+///
+/// The task only finishes by itself if the lease is lost, in which case another instance may
+/// already be running the job, so the caller should stop running it as soon as the handle
+/// completes.
+pub fn renew_while_running(
+    database: Arc<Database>,
+    job: Job,
+    config: InstanceConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            sleep(config.renewal_interval()).await;
+
+            match job.renew_lease(&database, &config.id, config.lease()).await {
+                Ok(true) => log::trace!("Renewed the lease on job_id={}", job.id),
+                Ok(false) => {
+                    log::warn!("Lost the lease on job_id={}", job.id);
+                    return;
+                }
+                Err(e) => log::warn!("Failed to renew the lease on job_id={}: {}", job.id, e),
+            }
+        }
+    })
+}
+
+/// Advertises this instance and releases expired leases until the DCL shuts down
+///
+/// As jobs held by other instances are left in the local queue, the job end is woken on each
+/// iteration so that it can retry them once their leases have been released.
+pub async fn run(
+    database: Arc<Database>,
+    nodepool: Arc<NodePool>,
+    job_control: JobControl,
+) -> Result<()> {
+    let config = &job_control.instance;
+    let mut interval = interval(config.renewal_interval());
+
+    log::info!(
+        "Coordinating as instance={} with lease_duration={:?}",
+        config.id,
+        config.lease_duration
+    );
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = job_control.shutdown.triggered() => break,
+        }
+
+        let (idle, total) = nodepool.capacity().await;
+
+        if let Err(e) = Instance::advertise(&database, &config.id, idle, total).await {
+            log::warn!("Failed to advertise instance={}: {}", config.id, e);
+        }
+
+        match Job::release_expired_leases(&database).await {
+            Ok(0) => {}
+            Ok(released) => log::info!("Released {} expired job lease(s)", released),
+            Err(e) => log::warn!("Failed to release expired job leases: {}", e),
+        }
+
+        job_control.notify.notify_waiters();
+    }
+
+    log::info!("Withdrawing instance={}", config.id);
+    Instance::withdraw(&database, &config.id).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
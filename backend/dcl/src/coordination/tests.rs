@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn instances_use_separate_consumer_groups() {
+    let first = InstanceConfig::new("first", DEFAULT_LEASE_DURATION);
+    let second = InstanceConfig::new("second", DEFAULT_LEASE_DURATION);
+
+    assert_ne!(first.consumer_group(), second.consumer_group());
+}
+
+#[test]
+fn leases_are_renewed_before_they_expire() {
+    let config = InstanceConfig::new("instance", Duration::from_secs(30));
+
+    assert!(config.renewal_interval() < config.lease_duration);
+    assert_eq!(config.lease(), chrono::Duration::seconds(30));
+}
+
+#[test]
+fn renewal_interval_is_never_zero() {
+    let config = InstanceConfig::new("instance", Duration::from_secs(0));
+
+    assert_eq!(config.renewal_interval(), Duration::from_secs(1));
+}
@@ -3,6 +3,8 @@
 //! This will go through and will check each node to make sure each is alive and working. It will
 //! update its status in the [`NodeInfo`] object for the node.
 
+use std::env;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
@@ -10,6 +12,7 @@ use mongodb::{
     bson::{doc, oid::ObjectId},
     Database,
 };
+use rand::Rng;
 use tokio::net::TcpStream;
 use tokio::sync::RwLock;
 use tokio::{io::AsyncWriteExt, time::Instant};
@@ -17,68 +20,196 @@ use tokio::{io::AsyncWriteExt, time::Instant};
 use anyhow::Result;
 
 use crate::node_end::NodePool;
+use crate::shutdown::Shutdown;
 use messages::{ClientMessage, WriteLengthPrefix};
 use models::models::Status;
+use models::telemetry::{Metric, Telemetry};
+
+/// Configuration for the health checking framework
+#[derive(Debug, Copy, Clone)]
+pub struct HealthConfig {
+    /// The time between each sweep of the nodepool
+    pub interval: Duration,
+    /// The maximum time to wait for a node to respond to a heartbeat
+    pub timeout: Duration,
+    /// The number of consecutive failed heartbeats before a node is assumed to be dead
+    pub failure_threshold: u8,
+}
+
+impl HealthConfig {
+    /// Creates a new [`HealthConfig`] with the default timeout and failure threshold.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            timeout: Duration::from_millis(2000),
+            failure_threshold: 10,
+        }
+    }
+
+    /// Builds a configuration from the environment variables.
+    ///
+    /// `HEALTH` must be set, whereas `HEALTH_TIMEOUT_MS` and `HEALTH_FAILURE_THRESHOLD` will fall
+    /// back to their defaults if they are not.
+    pub fn from_env() -> Self {
+        let interval = u64::from_str(&env::var("HEALTH").expect("HEALTH must be set"))
+            .expect("HEALTH must be a u64");
+
+        let mut config = Self::new(Duration::from_secs(interval));
+
+        if let Ok(timeout) = env::var("HEALTH_TIMEOUT_MS") {
+            let millis = u64::from_str(&timeout).expect("HEALTH_TIMEOUT_MS must be a u64");
+            config.timeout = Duration::from_millis(millis);
+        }
+
+        if let Ok(threshold) = env::var("HEALTH_FAILURE_THRESHOLD") {
+            config.failure_threshold =
+                u8::from_str(&threshold).expect("HEALTH_FAILURE_THRESHOLD must be a u8");
+        }
+
+        config
+    }
+
+    /// Gets the maximum delay before a single node is checked within a sweep.
+    ///
+    /// Spreading the heartbeats across half of the interval avoids every node being contacted at
+    /// exactly the same moment.
+    pub fn jitter(&self) -> Duration {
+        self.interval / 2
+    }
+}
 
 /// Runner for health checking
 ///
 /// Runs the health checking framework to go through each node that is not currently being used and
-/// makes sure it is still alive. This will be run every <delay> seconds.
-pub async fn health_runner(database: Arc<Database>, nodepool: Arc<NodePool>, delay: u64) {
-    let duration = Duration::from_secs(delay);
-    log::info!("Running health checking with a delay of {:?}", duration);
+/// makes sure it is still alive. This will be run every `interval` seconds until the DCL begins
+/// shutting down, abandoning any sweep that is in progress at the time.
+pub async fn health_runner(
+    database: Arc<Database>,
+    nodepool: Arc<NodePool>,
+    config: HealthConfig,
+    shutdown: Shutdown,
+) {
+    log::info!("Running health checking with config={:?}", config);
 
-    let mut interval = tokio::time::interval(duration);
+    let mut interval = tokio::time::interval(config.interval);
 
     loop {
         let np = Arc::clone(&nodepool);
-        check_health(Arc::clone(&database), np).await.unwrap();
 
-        interval.tick().await;
+        let sweep = async {
+            if let Err(e) = check_health(Arc::clone(&database), np, config).await {
+                log::error!("Error occurred during health checking: {}", e);
+            }
+
+            interval.tick().await;
+        };
+
+        tokio::select! {
+            _ = sweep => {}
+            _ = shutdown.triggered() => {
+                log::info!("Shutting down, no longer checking the health of nodes");
+                return;
+            }
+        }
     }
 }
 
 /// Go through nodes and check if alive
 ///
-/// Loops through all nodes and checks to see if they are alive.  This information is saved [`in`]
-/// [`NodeInfo`].
-pub async fn check_health(database: Arc<Database>, nodepool: Arc<NodePool>) -> Result<()> {
-    let mut nodes = nodepool.nodes.write().await;
-    let mut clean_list: Vec<String> = Vec::new();
-
-    for (id, node) in nodes.iter() {
-        if !nodepool.is_using(&id).await {
-            let alive = heartbeat(&id, node.get_tcp()).await;
-
-            if !alive {
-                log::trace!("Node with id={} failed to respond", node.get_model_id());
-                node.inc_counter().await;
-
-                if node.get_counter().await == 10 {
-                    log::warn!("Node with id={} is assumed to be dead", node.get_model_id());
-
-                    change_model_status(
-                        Arc::clone(&database),
-                        node.get_model_id(),
-                        Status::Stopped,
-                    )
-                    .await?;
-
-                    clean_list.push(id.clone());
-                }
-            } else if node.get_counter().await > 0 {
-                node.reset_counter().await;
+/// Takes a snapshot of all the idle nodes and checks each of them concurrently to see if they are
+/// alive. No pool-wide locks are held while communicating with the nodes, so a handful of dead
+/// nodes cannot stall the rest of the DCL. This information is saved in [`NodeInfo`].
+pub async fn check_health(
+    database: Arc<Database>,
+    nodepool: Arc<NodePool>,
+    config: HealthConfig,
+) -> Result<()> {
+    let idle = nodepool.idle_nodes().await;
+
+    log::trace!("Checking the health of {} idle nodes", idle.len());
+
+    let checks = idle.into_iter().map(|(id, stream)| {
+        let database = Arc::clone(&database);
+        let nodepool = Arc::clone(&nodepool);
+
+        async move {
+            let outcome = check_node(database, nodepool, &id, stream, config).await;
+            (id, outcome)
+        }
+    });
+
+    for (id, outcome) in futures::future::join_all(checks).await {
+        if let Err(e) = outcome {
+            log::error!("Failed to check the health of model_id={}: {}", id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the health of a single node after a random delay
+///
+/// If the node's stream is already in use, such as when it has just been given a job, it is
+/// skipped for this sweep. Otherwise it is sent a heartbeat, and if it has failed to respond
+/// `failure_threshold` times in a row it is assumed dead and removed from the [`NodePool`].
+async fn check_node(
+    database: Arc<Database>,
+    nodepool: Arc<NodePool>,
+    id: &str,
+    stream_lock: Arc<RwLock<TcpStream>>,
+    config: HealthConfig,
+) -> Result<()> {
+    let jitter = config.jitter().as_millis() as u64;
+    let delay = Duration::from_millis(rand::thread_rng().gen_range(0..=jitter));
+    tokio::time::sleep(delay).await;
+
+    let response_time = {
+        let mut stream = match stream_lock.try_write() {
+            Ok(stream) => stream,
+            Err(_) => {
+                log::trace!("Node with id={} is busy, skipping its heartbeat", id);
+                return Ok(());
             }
+        };
+
+        tokio::time::timeout(config.timeout, heartbeat_stream(id, &mut stream))
+            .await
+            .unwrap_or(None)
+    };
 
-            nodepool.update_node_alive(&id, alive).await;
+    let alive = response_time.is_some();
+
+    if let Some(response_time) = response_time {
+        let rtt = response_time.as_secs_f64() * 1000.0;
+
+        if let Err(e) = Telemetry::record(&database, id, Metric::HeartbeatRtt, rtt).await {
+            log::warn!("Failed to record telemetry for model_id={}: {}", id, e);
         }
     }
 
-    // clean dead nodes from nodepool
-    for id in clean_list {
-        nodes.remove(&id);
+    let failures = match nodepool.record_heartbeat(id, alive).await {
+        Some(failures) => failures,
+        // The node was removed while we were checking it
+        None => return Ok(()),
+    };
+
+    if !alive {
+        log::trace!("Node with id={} failed to respond", id);
     }
 
+    if failures >= config.failure_threshold {
+        log::warn!("Node with id={} is assumed to be dead", id);
+
+        change_model_status(database, id, Status::Stopped).await?;
+
+        // Clean the dead node from the nodepool
+        nodepool.remove(id).await;
+
+        return Ok(());
+    }
+
+    nodepool.update_node_alive(id, alive).await;
+
     Ok(())
 }
 
@@ -90,6 +221,14 @@ pub async fn check_health(database: Arc<Database>, nodepool: Arc<NodePool>) -> R
 pub async fn heartbeat(model_id: &str, stream_lock: Arc<RwLock<TcpStream>>) -> bool {
     let mut stream = stream_lock.write().await;
 
+    heartbeat_stream(model_id, &mut stream).await.is_some()
+}
+
+/// Performs a heartbeat over a stream that the caller already has access to
+///
+/// Returns the round trip time of the heartbeat if the node responded correctly, or `None` if it
+/// should be treated as dead.
+pub async fn heartbeat_stream(model_id: &str, stream: &mut TcpStream) -> Option<Duration> {
     let start_timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
@@ -101,7 +240,7 @@ pub async fn heartbeat(model_id: &str, stream_lock: Arc<RwLock<TcpStream>>) -> b
     .as_bytes();
 
     if stream.write(&message).await.is_err() {
-        return false;
+        return None;
     }
 
     let mut buffer = [0_u8; 64];
@@ -116,9 +255,10 @@ pub async fn heartbeat(model_id: &str, stream_lock: Arc<RwLock<TcpStream>>) -> b
     log::trace!("model_id={} took {:?} to respond", model_id, response_time);
 
     matches!(health_response, Ok(ClientMessage::Alive { timestamp }) if timestamp == start_timestamp)
+        .then(|| response_time)
 }
 
-///
+/// Sets the status of a model in the database, such as marking it stopped once it is assumed dead
 pub async fn change_model_status(
     database: Arc<Database>,
     model_id: &str,
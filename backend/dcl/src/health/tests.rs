@@ -70,3 +70,31 @@ async fn test_heartbeat_fail() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_heartbeat_silent_node_times_out() -> Result<(), Box<dyn Error>> {
+    // Bind to a random unused TCP port
+    let socket = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+    let listener = TcpListener::bind(socket).await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        // Accept the connection but never respond to anything
+        let (_inbound, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let config = HealthConfig::new(Duration::from_secs(30));
+    let start = Instant::now();
+    let verdict = tokio::time::timeout(config.timeout, heartbeat_stream("", &mut stream))
+        .await
+        .unwrap_or(None);
+
+    assert_eq!(verdict, None);
+    assert!(start.elapsed() < config.timeout + Duration::from_millis(500));
+
+    Ok(())
+}
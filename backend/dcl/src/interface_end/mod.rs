@@ -1,103 +1,336 @@
 //! Deals with DCL connection to the interface layer
 //!
-//! Listens to traffic over a socket and maintains a transmitter end of
-//! a mpsc channel which allows it to send data to the job end.
+//! Listens to jobs from the message bus and adds them to the job queue,
+//! which allows it to send data to the job end.
 
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::fmt;
+use std::string::FromUtf8Error;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use mongodb::{
-    bson::{doc, oid::ObjectId},
+    bson::{de::from_document, doc, oid::ObjectId},
     Database,
 };
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::{stream_consumer::StreamConsumer, Consumer, DefaultConsumerContext};
-use rdkafka::Message;
-use tokio_stream::StreamExt;
 
+use mailer::Notification;
+use messages::{envelope, BusMessage, Consumer, JobRequested, KafkaWsMessage};
 use models::datasets::Dataset;
 use models::gridfs;
-use models::jobs::{Job, JobConfiguration};
+use models::jobs::{FailureRecord, Job, JobConfiguration};
+use models::projects::{Project, Status};
+use utils::finance::pay;
+use utils::metrics;
 
 use crate::{DatasetPair, JobControl};
 
+/// Reasons a job could not be ingested.
+#[derive(Debug)]
+pub enum IngestionError {
+    /// The job has already been handled or no longer exists, such as when it is delivered twice
+    Stale,
+    /// The project was deleted after the job was requested
+    ProjectDeleted,
+    /// The project is no longer waiting for the job to run
+    NotProcessing,
+    /// The project's data was removed after the job was requested
+    DatasetDeleted,
+    /// The project's data was replaced after the job was requested
+    DatasetReplaced,
+    /// The files containing the project's data could not be found
+    FilesMissing,
+    /// The project's data could not be read as text
+    Unreadable,
+    /// Any other error, such as failing to reach the database
+    Other(anyhow::Error),
+}
+
+impl IngestionError {
+    /// Gets the reason shown to the user for the job failing.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Stale => "The job has already been handled",
+            Self::ProjectDeleted => "The project was deleted before the job could start",
+            Self::NotProcessing => "The project was changed before the job could start",
+            Self::DatasetDeleted => "The project's data was removed before the job could start",
+            Self::DatasetReplaced => "The project's data was replaced before the job could start",
+            Self::FilesMissing => "The project's data could not be found",
+            Self::Unreadable => "The project's data could not be read",
+            Self::Other(_) => "The data for the job could not be loaded",
+        }
+    }
+
+    /// Checks whether the project is still waiting on this job, and should be made ready to run
+    /// again once the job has failed.
+    fn project_awaits_job(&self) -> bool {
+        matches!(self, Self::FilesMissing | Self::Unreadable | Self::Other(_))
+    }
+}
+
+impl fmt::Display for IngestionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other(e) => write!(f, "{}: {}", self.reason(), e),
+            _ => write!(f, "{}", self.reason()),
+        }
+    }
+}
+
+impl std::error::Error for IngestionError {}
+
+impl From<anyhow::Error> for IngestionError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Other(err)
+    }
+}
+
+impl From<mongodb::error::Error> for IngestionError {
+    fn from(err: mongodb::error::Error) -> Self {
+        Self::Other(err.into())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for IngestionError {
+    fn from(err: mongodb::bson::de::Error) -> Self {
+        Self::Other(err.into())
+    }
+}
+
+impl From<FromUtf8Error> for IngestionError {
+    fn from(_err: FromUtf8Error) -> Self {
+        Self::Unreadable
+    }
+}
+
 /// Starts up interface server
 ///
-/// Takes in socket, db connection and transmitter end of mpsc chaneel and will
-/// read in data from an interface. Messages read over this are taken and the
-/// corresponding dataset is found and decompressed before being passed to the
-/// job end to be sent to a compute node.
-pub async fn run(port: u16, db_conn: Arc<Database>, job_control: JobControl) -> Result<()> {
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port).to_string();
-    log::info!("Listening to messages from Kafka on: {}", addr);
-
-    let consumer: StreamConsumer<DefaultConsumerContext> = ClientConfig::new()
-        .set("group.id", "job_config")
-        .set("bootstrap.servers", addr)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()
-        .expect("Consumer creation failed");
+/// Takes in a db connection and the job control, and will read in jobs from the
+/// message bus. Messages read over this are taken and the corresponding dataset
+/// is found and decompressed before being passed to the job end to be sent to a
+/// compute node. Jobs that are no longer wanted or whose data is missing are marked
+/// as failed and reported to the user, while messages that cannot be read or fail
+/// unexpectedly are sent to the dead letter topic, `jobs.dlq`, without failing the
+/// job, so that they can be replayed.
+pub async fn run(db_conn: Arc<Database>, job_control: JobControl) -> Result<()> {
+    let group = job_control.instance.consumer_group();
+    let consumer = Consumer::new(Arc::clone(&job_control.bus), group.as_str(), &["jobs"]);
 
-    consumer
-        .subscribe(&["jobs"])
-        .expect("Can't subscribe to jobs");
-
-    // Ignore any errors in the stream
-    let mut message_stream = consumer.stream().filter_map(Result::ok);
-
-    while let Some(message) = message_stream.next().await {
-        // Interpret the content as a string
-        let payload = match message.payload_view::<[u8]>() {
-            // This cannot fail, `rdkafka` always returns `Ok(bytes)`
-            Some(view) => view.unwrap(),
-            None => {
-                log::warn!("Received an empty message from Kafka");
-                continue;
-            }
-        };
-
-        log::debug!(
-            "Message key={:?}, timestamp={:?}",
-            message.key(),
-            message.timestamp()
-        );
+    log::info!("Listening to jobs as consumer group={}", group);
 
+    let handler = |message: BusMessage| {
         let database = Arc::clone(&db_conn);
         let jc_clone = job_control.clone();
 
-        let job_config = match serde_json::from_slice(&payload) {
-            Ok(config) => config,
-            Err(e) => {
-                log::error!("Failed to deserialize a message from Kafka: {}", e);
-                continue;
-            }
-        };
-
-        tokio::spawn(async move {
-            process_job(database, jc_clone, job_config).await.unwrap();
-        });
-    }
+        async move {
+            log::debug!(
+                "Message key={:?}, timestamp={:?}",
+                message.key,
+                message.timestamp
+            );
+
+            metrics::record_kafka_lag("jobs", message.timestamp);
+
+            let event: JobRequested = envelope::decode(&message.payload)
+                .context("Failed to deserialize a job from the message bus")?;
+
+            // Jobs are only sent by identifier, so read the rest of the job from the database
+            let job = match Job::find(&database, &event.job_id).await? {
+                Some(job) => job,
+                None => {
+                    log::info!(
+                        "Ignoring job_id={} for project_id={} as it no longer exists",
+                        event.job_id,
+                        event.project_id
+                    );
+                    return Ok(());
+                }
+            };
+
+            process_job(database, jc_clone, job).await
+        }
+    };
+
+    consumer
+        .run_until(handler, job_control.shutdown.triggered())
+        .await?;
+
+    log::info!("No longer accepting jobs from the message bus");
 
     Ok(())
 }
 
-async fn download_dataset(database: &Database, identifier: &ObjectId) -> Result<Vec<u8>> {
+async fn download_dataset(
+    database: &Database,
+    identifier: &ObjectId,
+) -> Result<Vec<u8>, IngestionError> {
     let files = database.collection("files");
 
     let filter = doc! { "_id": identifier };
     let doc = files
         .find_one(filter, None)
         .await?
-        .expect("Failed to find a document with the previous filter");
+        .ok_or(IngestionError::FilesMissing)?;
 
-    let file: gridfs::File = mongodb::bson::de::from_document(doc)?;
+    let file: gridfs::File = from_document(doc)?;
     Ok(file.download_dataset(&database).await?)
 }
 
+/// Loads the data for a job, checking it is still wanted.
+///
+/// The project may have changed since the job was requested, so this checks that the job has not
+/// already been handled, that the project still exists and is waiting for the job, and that its
+/// dataset is the one the job was requested for.
+pub async fn ingest(database: &Database, job: &Job) -> Result<DatasetPair, IngestionError> {
+    let project_id = &job.config.project_id;
+
+    // Check the job has not already been handled by this or another instance
+    let jobs = database.collection("jobs");
+    let document = jobs
+        .find_one(doc! { "_id": &job.id }, None)
+        .await?
+        .ok_or(IngestionError::Stale)?;
+    let current: Job = from_document(document)?;
+
+    if current.processed {
+        return Err(IngestionError::Stale);
+    }
+
+    let projects = database.collection("projects");
+    let document = projects
+        .find_one(doc! { "_id": project_id }, None)
+        .await?
+        .ok_or(IngestionError::ProjectDeleted)?;
+    let project: Project = from_document(document)?;
+
+    if !matches!(project.status, Status::Processing { .. }) {
+        return Err(IngestionError::NotProcessing);
+    }
+
+    // Query the dataset currently associated with the project
+    let datasets = database.collection("datasets");
+    let document = datasets
+        .find_one(doc! { "project_id": project_id }, None)
+        .await?
+        .ok_or(IngestionError::DatasetDeleted)?;
+    let dataset: Dataset = from_document(document)?;
+
+    if matches!(&job.dataset_id, Some(requested) if *requested != dataset.id) {
+        return Err(IngestionError::DatasetReplaced);
+    }
+
+    log::debug!("Found the dataset with id={}", dataset.id);
+
+    // Get the decompressed data from GridFS
+    let compressed_train = download_dataset(database, &dataset.dataset).await?;
+    let compressed_predict = download_dataset(database, &dataset.predict).await?;
+
+    // Convert it to a string
+    let train = String::from_utf8(compressed_train)?;
+    let predict = String::from_utf8(compressed_predict)?;
+
+    Ok(DatasetPair { train, predict })
+}
+
+/// Records that a job could not be ingested and tells the user why.
+///
+/// If the project is still waiting on the job, it is made ready to run again.
+pub async fn fail_job(
+    database: Arc<Database>,
+    job_control: &JobControl,
+    job: &Job,
+    error: &IngestionError,
+) -> Result<()> {
+    record_failure(
+        database,
+        job_control,
+        job,
+        error.reason(),
+        error.project_awaits_job(),
+    )
+    .await
+}
+
+/// Records that a job failed and tells the user why.
+///
+/// The job is marked as failed and its cost refunded, unless it was refunded when it previously
+/// failed. If `project_awaits_job` is set, a project that is still processing is made ready to
+/// run again. Nothing is done if the job has already been processed, such as by another caller
+/// failing it first.
+pub async fn record_failure(
+    database: Arc<Database>,
+    job_control: &JobControl,
+    job: &Job,
+    reason: &str,
+    project_awaits_job: bool,
+) -> Result<()> {
+    let refund = match job.mark_as_failed_once(&database, reason).await? {
+        FailureRecord::First => true,
+        // Jobs that failed before being requeued have already been refunded
+        FailureRecord::Repeated => false,
+        FailureRecord::AlreadyProcessed => {
+            log::debug!(
+                "job_id={} has already been processed, not failing it",
+                job.id
+            );
+            return Ok(());
+        }
+    };
+
+    let projects = database.collection("projects");
+    let project_id = &job.config.project_id;
+
+    // Without the project there is nobody to refund or tell
+    let project: Project = match projects.find_one(doc! { "_id": project_id }, None).await? {
+        Some(document) => from_document(document)?,
+        None => return Ok(()),
+    };
+
+    if refund {
+        pay(Arc::clone(&database), &project.user_id, job.config.cost).await?;
+        log::debug!(
+            "Refunded user_id={} {} credits for job_id={}",
+            project.user_id,
+            job.config.cost,
+            job.id
+        );
+    }
+
+    if project_awaits_job {
+        let filter = doc! { "_id": project_id, "status.Processing": { "$exists": true } };
+        let update = doc! { "$set": { "status": Status::Ready } };
+        projects.update_one(filter, update, None).await?;
+    }
+
+    let failed = KafkaWsMessage::JobFailedMessage {
+        project_id: project_id.to_string(),
+        reason: reason.to_string(),
+    };
+    failed
+        .notify(&database, job_control.bus.as_ref(), &job.id.to_string())
+        .await;
+
+    let notification = Notification::ProjectFailed {
+        project_id: project_id.to_string(),
+        project_name: project.name,
+        reason: reason.to_string(),
+    };
+
+    if let Some(mailer) = &job_control.mailer {
+        if let Err(e) = mailer
+            .notify_user(&database, &project.user_id, notification)
+            .await
+        {
+            log::warn!(
+                "Failed to email the user about the failure of project_id={}: {}",
+                project_id,
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
 async fn process_job(db_conn: Arc<Database>, job_control: JobControl, job: Job) -> Result<()> {
     let JobConfiguration {
         project_id,
@@ -112,31 +345,30 @@ async fn process_job(db_conn: Arc<Database>, job_control: JobControl, job: Job)
         node_computation_time,
     );
 
-    let datasets = db_conn.collection("datasets");
+    let dataset_pair = match ingest(&db_conn, &job).await {
+        Ok(dataset_pair) => dataset_pair,
+        Err(IngestionError::Stale) => {
+            log::info!("Ignoring job_id={} as it has already been handled", job.id);
+            return Ok(());
+        }
+        // Unexpected errors may be transient, so the job is left as it is and the message is sent
+        // to the dead letter topic, allowing it to be replayed once the cause has been fixed
+        Err(IngestionError::Other(e)) => {
+            log::error!("Failed to ingest job_id={}: {}", job.id, e);
+            return Err(e.context(format!("Failed to ingest job_id={}", job.id)));
+        }
+        Err(error) => {
+            log::warn!("Failed to ingest job_id={}: {}", job.id, error);
 
-    // Query the dataset currently associated with the project
-    let filter = doc! { "project_id": &project_id };
+            fail_job(Arc::clone(&db_conn), &job_control, &job, &error).await?;
 
-    let doc = datasets
-        .find_one(filter, None)
-        .await?
-        .expect("Failed to find a document with the previous filter");
-
-    let dataset: Dataset = mongodb::bson::de::from_document(doc)?;
-
-    log::debug!("Found the dataset with id={}", dataset.id);
-
-    // Get the decompressed data from GridFS
-    let compressed_train = download_dataset(&db_conn, &dataset.dataset).await?;
-    let compressed_predict = download_dataset(&db_conn, &dataset.predict).await?;
-
-    // Convert it to a string
-    let train = String::from_utf8(compressed_train)?;
-    let predict = String::from_utf8(compressed_predict)?;
+            return Ok(());
+        }
+    };
 
     job_control
         .job_queue
-        .push((dataset.project_id, DatasetPair { train, predict }, job));
+        .push((project_id.clone(), dataset_pair, job));
 
     log::trace!("Notifying waiters of an incoming job");
     job_control.notify.notify_waiters();
@@ -1,42 +1,49 @@
 //! Part of DCL that takes a DCN and a dataset and comunicates with node
 
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
-use std::env;
+use std::fmt;
+use std::io;
 use std::ops::DerefMut;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use bytes::Bytes;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 use mongodb::{
-    bson::{doc, from_document, oid::ObjectId},
+    bson::{doc, oid::ObjectId},
     Database,
 };
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use tokio::sync::{Notify, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::{timeout, Instant};
 
-use crate::node_end::NodePool;
+use crate::coordination::{self, Claim};
+use crate::interface_end::record_failure;
+use crate::node_end::{update_model_status, NodePool};
 use crate::JobControl;
-use messages::{ClientMessage, KafkaWsMessage, ReadLengthPrefix, WriteLengthPrefix};
+use mailer::{Mailer, Notification};
+use messages::{ClientMessage, KafkaWsMessage, MessageBus, ReadLengthPrefix, WriteLengthPrefix};
 use models::gridfs;
 use models::jobs::PredictionType;
 use models::jobs::{Job, JobStatistics};
 use models::predictions::Prediction;
-use models::projects::{Project, Status};
-use models::users::User;
+use models::projects::Status;
+use models::telemetry::{Metric, Telemetry};
 
 use utils::anon::{anonymise_dataset, deanonymise_dataset, infer_dataset_columns};
 use utils::compress::compress_data;
 use utils::finance::reimburse;
 use utils::generate_ids;
+use utils::metrics;
 use utils::{Column, Columns};
 
 pub mod ml;
@@ -59,6 +66,12 @@ pub struct ClusterInfo {
     pub prediction_rids: HashMap<(ModelID, String), usize>,
     /// The amount of time each node is allowed to compute for
     pub node_computation_time: Duration,
+    /// The bus to send updates about the job on
+    pub bus: Arc<dyn MessageBus>,
+    /// Emails the owner of the project once the job completes, if emails are enabled
+    pub mailer: Option<Mailer>,
+    /// The name of the instance holding the lease on the job
+    pub instance_id: String,
 }
 
 /// The `String` predictions of model `ModelID` on test example `usize`
@@ -139,6 +152,8 @@ pub struct ClusterControl {
     pub counter: Arc<RwLock<usize>>,
     /// Cluster notifier
     pub notify: Arc<Notify>,
+    /// Whether the user has been told that data was sent to the cluster
+    pub data_sent: Arc<AtomicBool>,
 }
 
 impl ClusterControl {
@@ -147,9 +162,15 @@ impl ClusterControl {
         ClusterControl {
             counter: Arc::new(RwLock::new(counter)),
             notify: Arc::new(Notify::new()),
+            data_sent: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Marks data as sent to the cluster, returning true only for the first node to do so
+    pub fn mark_data_sent(&self) -> bool {
+        !self.data_sent.swap(true, Ordering::SeqCst)
+    }
+
     /// Decrements the cluster counter
     pub async fn decrement(&self) -> usize {
         let mut write_cc = self.counter.write().await;
@@ -161,25 +182,51 @@ impl ClusterControl {
     }
 }
 
-/// The configuration for sending emails.
-#[derive(Debug)]
-pub struct Config {
-    /// The address to send from.
-    pub from_address: String,
-    /// The name to send from.
-    pub from_name: String,
-    /// The application specific password for Gmail.
-    pub app_password: String,
+/// Details of a cluster that is currently running a job
+#[derive(Debug, Clone, Serialize)]
+pub struct RunningCluster {
+    /// The identifier of the job being run
+    pub job_id: String,
+    /// The identifier of the project the job is for
+    pub project_id: String,
+    /// The model identifiers of the nodes in the cluster
+    pub nodes: Vec<ModelID>,
+    /// The Unix timestamp at which the cluster started running
+    pub started: i64,
 }
 
-impl Config {
-    /// Builds a configuration from the environment variables.
-    pub fn from_env() -> Result<Self, std::env::VarError> {
-        Ok(Self {
-            from_address: env::var("FROM_ADDRESS")?,
-            from_name: env::var("FROM_NAME")?,
-            app_password: env::var("APP_PASSWORD")?,
-        })
+/// The clusters currently running in this instance, indexed by job identifier
+#[derive(Debug, Clone, Default)]
+pub struct RunningClusters(Arc<Mutex<HashMap<String, RunningCluster>>>);
+
+impl RunningClusters {
+    /// Creates a new, empty instance of RunningClusters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a cluster has started running
+    pub fn insert(&self, cluster: RunningCluster) {
+        let mut clusters = self.0.lock().unwrap();
+        clusters.insert(cluster.job_id.clone(), cluster);
+    }
+
+    /// Records that the cluster running the given job has finished
+    pub fn remove(&self, job_id: &str) {
+        let mut clusters = self.0.lock().unwrap();
+        clusters.remove(job_id);
+    }
+
+    /// Checks whether a cluster is running the given job
+    pub fn contains(&self, job_id: &str) -> bool {
+        let clusters = self.0.lock().unwrap();
+        clusters.contains_key(job_id)
+    }
+
+    /// Gets cloned version of the running clusters
+    pub fn get_clusters(&self) -> Vec<RunningCluster> {
+        let clusters = self.0.lock().unwrap();
+        clusters.values().cloned().collect()
     }
 }
 
@@ -189,9 +236,74 @@ const VALIDATION_SPLIT: f64 = 0.2;
 // inclusion probability used for Bernoulli sampling
 const INCLUSION_PROBABILITY: f64 = 0.95;
 
+/// The largest proportion of the computation time a node can be granted beyond its deadline
+pub const MAX_DEADLINE_EXTENSION: f64 = 0.25;
+
+/// The fraction of progress required before completion estimates are trusted
+pub const MIN_ESTIMATE_FRACTION: f64 = 0.1;
+
+/// The interval at which busy nodes that answer heartbeats are sent them over their job connection
+pub const BUSY_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The length of time a busy node that answers heartbeats can stay silent before it is given up on
+pub const BUSY_NODE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The shortest time between forwarding progress updates from a node within the same stage
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of times every node in a cluster can fail to run a job before the job is failed
+pub const MAX_CLUSTER_ATTEMPTS: i32 = 3;
+
 /// ModelID type
 pub type ModelID = String;
 
+/// The ways in which a node can fail while it is computing predictions
+#[derive(Debug)]
+pub enum NodeFailure {
+    /// The connection to the node failed
+    Stream(anyhow::Error),
+    /// The node stopped responding to heartbeats
+    Unresponsive,
+    /// The node did not return predictions before its deadline
+    Timeout,
+    /// The node reported progress meaning it cannot finish before its deadline
+    TooSlow,
+    /// The node reported that it could not compute predictions, giving the reason
+    Failed(String),
+}
+
+impl NodeFailure {
+    /// Checks whether the failure means the node is likely dead, rather than just slow.
+    ///
+    /// Nodes that go quiet may still be busy computing, so only a broken connection is fatal.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::Stream(_))
+    }
+
+    /// Gets a short description of the failure, used when labelling metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Stream(_) => "stream",
+            Self::Unresponsive => "unresponsive",
+            Self::Timeout => "timeout",
+            Self::TooSlow => "too_slow",
+            Self::Failed(_) => "failed",
+        }
+    }
+}
+
+impl fmt::Display for NodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stream(error) => write!(f, "stream error: {}", error),
+            Self::Unresponsive => write!(f, "stopped responding to heartbeats"),
+            Self::Timeout => write!(f, "failed to respond in time"),
+            Self::TooSlow => write!(f, "will not finish in time at its current rate"),
+            Self::Failed(reason) => write!(f, "failed to compute predictions: {}", reason),
+        }
+    }
+}
+
 /// Starts up and runs the job end
 ///
 /// Takes in nodepool and mpsc receiver and will listen for incoming datasets.
@@ -203,18 +315,72 @@ pub async fn run(
     database: Arc<Database>,
     job_control: JobControl,
 ) -> Result<()> {
+    // Jobs whose users have been told they are waiting for nodes, so they are only told once
+    let mut waiting = HashSet::new();
+    // Jobs whose users have been told they are queued, which happens once this instance claims them
+    let mut queued = HashSet::new();
+
     loop {
+        if job_control.shutdown.is_triggered() {
+            log::info!("Shutting down, no longer starting new jobs");
+            return Ok(());
+        }
+
+        // Forget about jobs that have left the queue, so the sets only hold queued jobs
+        waiting.retain(|job_id| job_control.job_queue.contains(job_id));
+        queued.retain(|job_id| job_control.job_queue.contains(job_id));
+
         let jq_filter = job_control.job_queue.filter(&nodepool.active);
 
         if jq_filter.is_empty() {
             log::trace!("No jobs can be completed, waiting on changes");
-            job_control.notify.notified().await;
+            wait_for_changes(&job_control).await;
             log::trace!("Some change has occurred, attempting to complete some jobs");
             continue;
         }
 
+        // Jobs that have already been processed elsewhere are dropped, shifting later indices
+        let mut dropped = 0;
+
         for index in jq_filter {
+            if job_control.shutdown.is_triggered() {
+                break;
+            }
+
+            let index = index - dropped;
             let (project_id, msg, job) = job_control.job_queue.remove(index);
+
+            // Only run the job if no other instance of the DCL is already running it
+            match coordination::claim(&database, &job, &job_control.instance).await {
+                Ok(Claim::Claimed) => {
+                    // Only the instance that claims the job tells the user about it
+                    if queued.insert(job.id.clone()) {
+                        let message = KafkaWsMessage::JobQueuedMessage {
+                            project_id: project_id.to_string(),
+                            job_id: job.id.to_string(),
+                        };
+                        message
+                            .notify(&database, job_control.bus.as_ref(), &job.id.to_string())
+                            .await;
+                    }
+                }
+                Ok(Claim::Held) => {
+                    log::debug!("job_id={} is held by another instance, skipping it", job.id);
+                    job_control.job_queue.insert(index, (project_id, msg, job));
+                    continue;
+                }
+                Ok(Claim::Processed) => {
+                    log::info!("job_id={} has already been processed, dropping it", job.id);
+                    dropped += 1;
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Failed to claim job_id={}: {}", job.id, e);
+                    job_control.job_queue.insert(index, (project_id, msg, job));
+                    continue;
+                }
+            }
+
             let config = &job.config;
 
             let data = msg
@@ -237,6 +403,9 @@ pub async fn run(
             // Anonymise the prediction column for the job
             let anonymised_config = config.anonymise(&columns);
 
+            let job_id = job.id.to_string();
+            let cluster_size = config.cluster_size as usize;
+
             let cluster = match nodepool.build_cluster(anonymised_config).await {
                 Some(c) => c,
                 _ => {
@@ -246,12 +415,33 @@ pub async fn run(
                         config
                     );
 
+                    if waiting.insert(job.id.clone()) {
+                        let message = KafkaWsMessage::WaitingForNodesMessage {
+                            project_id: project_id.to_string(),
+                            cluster_size,
+                        };
+                        message
+                            .notify(&database, job_control.bus.as_ref(), &job_id)
+                            .await;
+                    }
+
+                    release_lease(&database, &job, &job_control).await;
                     job_control.job_queue.insert(index, (project_id, msg, job));
 
                     continue;
                 }
             };
 
+            waiting.remove(&job.id);
+
+            let message = KafkaWsMessage::ClusterBuiltMessage {
+                project_id: project_id.to_string(),
+                cluster_size: cluster.len(),
+            };
+            message
+                .notify(&database, job_control.bus.as_ref(), &job_id)
+                .await;
+
             let mut train = msg
                 .train
                 .trim()
@@ -288,25 +478,159 @@ pub async fn run(
                 node_computation_time: Duration::from_secs(
                     (config.node_computation_time * 60) as u64,
                 ),
+                bus: Arc::clone(&job_control.bus),
+                mailer: job_control.mailer.clone(),
+                instance_id: job_control.instance.id.clone(),
             };
 
             let np_clone = Arc::clone(&nodepool);
             let database_clone = Arc::clone(&database);
 
-            run_cluster(
+            let nodes: Vec<String> = cluster.keys().cloned().collect();
+
+            job_control.clusters.insert(RunningCluster {
+                job_id: job_id.clone(),
+                project_id: project_id.to_string(),
+                nodes: nodes.clone(),
+                started: chrono::Utc::now().timestamp(),
+            });
+
+            // Keep hold of the job for as long as the cluster is running
+            let mut renewal = coordination::renew_while_running(
+                Arc::clone(&database),
+                job.clone(),
+                job_control.instance.clone(),
+            );
+
+            let cluster_future = run_cluster(
                 np_clone,
                 database_clone,
                 cluster,
                 info.clone(),
                 bags.clone(),
-            )
-            .await?;
+            );
+
+            // Let the cluster finish if the DCL begins shutting down, but only up to a deadline,
+            // and stop it as soon as the lease is lost as another instance may now run the job
+            let started = Instant::now();
+            let mut lease_lost = false;
+            let completed = tokio::select! {
+                completed = cluster_future => completed,
+                _ = job_control.shutdown.deadline() => {
+                    log::warn!(
+                        "Cluster for project_id={} did not finish before shutting down",
+                        project_id
+                    );
+
+                    Ok(false)
+                }
+                _ = &mut renewal => {
+                    log::warn!(
+                        "Lost the lease on job_id={}, stopping the cluster for project_id={}",
+                        job_id,
+                        project_id
+                    );
+
+                    lease_lost = true;
+                    Ok(false)
+                }
+            };
+
+            renewal.abort();
+            job_control.clusters.remove(&job_id);
+
+            let outcome = match completed {
+                Ok(true) => "completed",
+                Ok(false) if lease_lost => "abandoned",
+                Ok(false) => "requeued",
+                Err(_) => "failed",
+            };
+            metrics::JOB_DURATION
+                .with_label_values(&[outcome])
+                .observe(started.elapsed().as_secs_f64());
+
+            // The instance now holding the lease is responsible for the job, but keep it queued in
+            // case that instance stops before processing it
+            if lease_lost {
+                // The nodes were stopped part way through the job, so make them reconnect rather
+                // than reading the rest of it as a response to the next one
+                for model_id in &nodes {
+                    nodepool.remove(model_id).await;
+                }
+
+                job_control.job_queue.push((project_id, msg, job));
+                break;
+            }
+
+            // Errors are treated as a failed attempt, so the job is retried up to a limit rather
+            // than stopping the job end
+            let completed = completed.unwrap_or_else(|e| {
+                log::error!("Cluster for project_id={} failed: {}", project_id, e);
+                false
+            });
+
+            // Every node in the cluster failed or the DCL is shutting down, so reschedule the job
+            // unless it has already failed too many times
+            if !completed {
+                if !job_control.shutdown.is_triggered() && out_of_attempts(&database, &job).await {
+                    log::error!(
+                        "Every cluster failed for project_id={} after {} attempts, failing the job",
+                        project_id,
+                        MAX_CLUSTER_ATTEMPTS
+                    );
+
+                    let reason = "No compute nodes were able to process the job";
+                    let failed =
+                        record_failure(Arc::clone(&database), &job_control, &job, reason, true);
+
+                    if let Err(e) = failed.await {
+                        log::error!("Failed to record the failure of job_id={}: {}", job.id, e);
+                    }
+
+                    break;
+                }
+
+                log::warn!(
+                    "Cluster did not complete for project_id={}, requeuing the job",
+                    project_id
+                );
+
+                release_lease(&database, &job, &job_control).await;
+                job_control.job_queue.push((project_id, msg, job));
+            }
 
             break;
         }
 
         log::info!("No jobs could be completed at the moment, waiting for changes");
-        job_control.notify.notified().await;
+        wait_for_changes(&job_control).await;
+    }
+}
+
+/// Releases the lease on a job so that other instances can claim it
+async fn release_lease(database: &Database, job: &Job, job_control: &JobControl) {
+    if let Err(e) = job.release_lease(database, &job_control.instance.id).await {
+        log::warn!("Failed to release the lease on job_id={}: {}", job.id, e);
+    }
+}
+
+/// Records a failed attempt at running a job, returning whether it should no longer be requeued.
+async fn out_of_attempts(database: &Database, job: &Job) -> bool {
+    match job.record_failed_attempt(database).await {
+        Ok(attempts) => attempts >= MAX_CLUSTER_ATTEMPTS,
+        Err(e) => {
+            log::warn!("Failed to record an attempt at job_id={}: {}", job.id, e);
+            false
+        }
+    }
+}
+
+/// Waits for a change that may allow more jobs to be completed, or for the DCL to begin shutting
+/// down.
+async fn wait_for_changes(job_control: &JobControl) {
+    tokio::select! {
+        _ = job_control.notify.notified() => {},
+        _ = job_control.shutdown.triggered() => {},
     }
 }
 
@@ -443,15 +767,43 @@ pub fn prepare_cluster(
     (bags, validation_ans, prediction_rids)
 }
 
+/// The tasks running the protocol with each node of a cluster
+///
+/// Any tasks still running when this is dropped are aborted, so that stopping a cluster also stops
+/// its nodes from holding onto their streams in the background.
+#[derive(Debug, Default)]
+struct NodeTasks(Vec<JoinHandle<()>>);
+
+impl NodeTasks {
+    /// Lets the tasks finish in the background instead of aborting them
+    fn detach(mut self) {
+        self.0.clear();
+    }
+}
+
+impl Drop for NodeTasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Runs a job on a cluster of nodes and processes the results.
+///
+/// Returns `false` without completing the project if every node in the cluster failed, meaning
+/// the job should be rescheduled. Dropping the returned future before every node has responded
+/// aborts the tasks running the protocol with each node.
 async fn run_cluster(
     nodepool: Arc<NodePool>,
     database: Arc<Database>,
     cluster: HashMap<String, Arc<RwLock<TcpStream>>>,
     info: ClusterInfo,
     prediction_bag: HashMap<ModelID, (String, String)>,
-) -> Result<()> {
+) -> Result<bool> {
     let cc: ClusterControl = ClusterControl::new(cluster.len());
     let wbm: WriteBackMemory = WriteBackMemory::new();
+    let mut tasks = NodeTasks::default();
 
     for (model_id, dcn_stream) in cluster {
         let np_clone = Arc::clone(&nodepool);
@@ -461,11 +813,15 @@ async fn run_cluster(
         let cc_clone = cc.clone();
         let train_predict = prediction_bag.get(&model_id).unwrap().clone();
 
-        tokio::spawn(async move {
-            let wait = info_clone.node_computation_time;
+        tasks.0.push(tokio::spawn(async move {
+            // Nodes may be granted extra time if they report progress, so only enforce the
+            // maximum possible deadline here and leave the rest to the protocol itself
+            let wait = info_clone
+                .node_computation_time
+                .mul_f64(1.0 + MAX_DEADLINE_EXTENSION);
 
             let future = dcl_protocol(
-                np_clone,
+                Arc::clone(&np_clone),
                 database_clone,
                 &model_id,
                 dcn_stream,
@@ -477,14 +833,22 @@ async fn run_cluster(
 
             if timeout(wait, future).await.is_err() {
                 log::warn!("Model with id={} failed to respond in time", model_id);
+                metrics::NODE_FAILURES.with_label_values(&["timeout"]).inc();
+                np_clone.record_failure(&model_id, "timeout").await;
                 cc_clone.decrement().await;
             }
-        });
+        }));
     }
 
     let project_id = info.project_id.clone();
 
     cc.notify.notified().await;
+    tasks.detach();
+
+    // No node managed to respond at all, so there is nothing to evaluate
+    if wbm.get_errors().is_empty() {
+        return Ok(false);
+    }
 
     let (weights, predictions) =
         ml::weight_predictions(&wbm.get_predictions(), &wbm.get_errors(), &info);
@@ -543,23 +907,37 @@ async fn run_cluster(
     change_status(&database, &project_id, Status::Complete).await?;
 
     // Mark the job as processed
-    info.job.mark_as_processed(&database).await?;
+    info.job
+        .mark_as_processed(&database, &info.instance_id)
+        .await?;
 
     let message = KafkaWsMessage::JobCompleteMessage {
-        project_id: &project_id.to_string(),
+        project_id: project_id.to_string(),
     };
-    message.produce(&database).await?;
+    let job_id = info.job.id.to_string();
+
+    // The job has already been processed, so it should not be retried if the update is lost
+    message.notify(&database, info.bus.as_ref(), &job_id).await;
 
     // Status has been updated to complete, so email the user
-    if let Err(e) = email_user_on_project_finish(&database, &project_id).await {
-        log::warn!(
-            "Failed to email the user upon finishing processing of project_id={}: {}",
-            project_id,
-            e
-        );
+    if let Some(mailer) = &info.mailer {
+        let notified = mailer.notify_project_owner(&database, &project_id, |project| {
+            Notification::ProjectComplete {
+                project_id: project.id.to_string(),
+                project_name: project.name.clone(),
+            }
+        });
+
+        if let Err(e) = notified.await {
+            log::warn!(
+                "Failed to email the user upon finishing processing of project_id={}: {}",
+                project_id,
+                e
+            );
+        }
     }
 
-    Ok(())
+    Ok(true)
 }
 
 /// Decodes the incoming data and decompresses it.
@@ -572,6 +950,185 @@ fn decode_and_decompress(data: &str) -> Result<String> {
     Ok(String::from_utf8(decompressed)?)
 }
 
+/// Wraps the reading half of a connection, recording when it last received any bytes.
+struct ActivityReader<'a, R> {
+    inner: R,
+    last_seen: &'a Mutex<Instant>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ActivityReader<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if buf.filled().len() > filled {
+            *self.last_seen.lock().unwrap() = Instant::now();
+        }
+
+        poll
+    }
+}
+
+/// Waits for a node to send its predictions while it is busy with a job.
+///
+/// If the node declared that it answers `heartbeats` while busy, it is sent one every
+/// [`BUSY_HEARTBEAT_INTERVAL`] while the DCL waits. Any bytes received from the node count as a
+/// sign of life, so large messages that take a while to arrive do not count as silence, and nodes
+/// that stay silent for longer than [`BUSY_NODE_TIMEOUT`] are given up on. Other nodes are only
+/// held to their deadline. Progress updates are used to adjust the deadline and forwarded to the
+/// user, although only once every [`PROGRESS_INTERVAL`] unless the node moves on to a new stage.
+async fn wait_for_predictions(
+    database: &Database,
+    model_id: &str,
+    stream: &mut TcpStream,
+    info: &ClusterInfo,
+    heartbeats: bool,
+    start: Instant,
+) -> std::result::Result<String, NodeFailure> {
+    let (reader, mut writer) = stream.split();
+    let mut buffer = [0_u8; 1024];
+
+    let last_seen = Mutex::new(Instant::now());
+    let mut reader = ActivityReader {
+        inner: reader,
+        last_seen: &last_seen,
+    };
+
+    let mut deadline = start + info.node_computation_time;
+    let mut heartbeat =
+        tokio::time::interval_at(start + BUSY_HEARTBEAT_INTERVAL, BUSY_HEARTBEAT_INTERVAL);
+    let mut last_forwarded: Option<(Instant, String)> = None;
+
+    loop {
+        let message = {
+            let read = ClientMessage::from_stream(&mut reader, &mut buffer);
+            tokio::pin!(read);
+
+            loop {
+                tokio::select! {
+                    result = &mut read => break result.map_err(NodeFailure::Stream)?,
+                    _ = tokio::time::sleep_until(deadline) => return Err(NodeFailure::Timeout),
+                    _ = heartbeat.tick(), if heartbeats => {
+                        if last_seen.lock().unwrap().elapsed() > BUSY_NODE_TIMEOUT {
+                            return Err(NodeFailure::Unresponsive);
+                        }
+
+                        let timestamp = SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .unwrap()
+                            .as_secs();
+
+                        let alive = ClientMessage::Alive { timestamp }.as_bytes();
+                        writer
+                            .write_all(&alive)
+                            .await
+                            .map_err(|e| NodeFailure::Stream(e.into()))?;
+                    }
+                }
+            }
+        };
+
+        match message {
+            ClientMessage::Predictions(predictions) => return Ok(predictions),
+            ClientMessage::PredictionsFailed { reason } => return Err(NodeFailure::Failed(reason)),
+            ClientMessage::Alive { .. } => {
+                log::trace!("Busy node with model_id={} is still alive", model_id);
+            }
+            ClientMessage::Progress {
+                stage,
+                fraction,
+                message,
+            } => {
+                log::debug!(
+                    "model_id={} reported stage={}, fraction={}, message={}",
+                    model_id,
+                    stage,
+                    fraction,
+                    message
+                );
+
+                let allowed =
+                    adjust_deadline(info.node_computation_time, start.elapsed(), fraction)
+                        .ok_or(NodeFailure::TooSlow)?;
+
+                deadline = start + allowed;
+
+                let last = last_forwarded
+                    .as_ref()
+                    .map(|(at, previous)| (at.elapsed(), previous.as_str()));
+
+                if !should_forward_progress(last, &stage) {
+                    continue;
+                }
+
+                last_forwarded = Some((Instant::now(), stage.clone()));
+
+                let progress = KafkaWsMessage::ClientProgressMessage {
+                    project_id: info.project_id.to_string(),
+                    stage,
+                    fraction,
+                    message,
+                };
+                let job_id = info.job.id.to_string();
+
+                if let Err(e) = progress.produce(database, info.bus.as_ref(), &job_id).await {
+                    log::warn!(
+                        "Failed to forward progress for model_id={}: {}",
+                        model_id,
+                        e
+                    );
+                }
+            }
+            other => {
+                log::warn!(
+                    "Received an unexpected message from model_id={} while waiting for predictions: {:?}",
+                    model_id,
+                    other
+                );
+            }
+        }
+    }
+}
+
+/// Updates the [`NodePool`] after a node has failed to compute predictions.
+///
+/// Nodes whose connection broke are removed from the pool and marked as stopped, whereas nodes
+/// that were too slow or went quiet are released and marked as not alive, allowing health checking
+/// to bring them back once they respond again. Nodes that reported their own failure are still
+/// responsive, so they are simply released.
+async fn handle_node_failure(
+    nodepool: &NodePool,
+    database: &Arc<Database>,
+    model_id: &str,
+    failure: &NodeFailure,
+) -> Result<()> {
+    metrics::NODE_FAILURES
+        .with_label_values(&[failure.reason()])
+        .inc();
+    nodepool.record_failure(model_id, failure.reason()).await;
+
+    if failure.is_fatal() {
+        update_model_status(
+            Arc::clone(database),
+            model_id,
+            models::models::Status::Stopped,
+        )
+        .await?;
+        nodepool.remove(model_id).await;
+    } else if let NodeFailure::Failed(_) = failure {
+        nodepool.end(model_id).await?;
+    } else {
+        nodepool.end(model_id).await?;
+        nodepool.update_node_alive(model_id, false).await;
+    }
+
+    Ok(())
+}
+
 /// Function to execute DCL protocol
 pub async fn dcl_protocol(
     nodepool: Arc<NodePool>,
@@ -587,35 +1144,68 @@ pub async fn dcl_protocol(
 
     let mut dcn_stream = stream.write().await;
 
-    let mut buffer = [0_u8; 1024];
-
     // Compress the data beforehand
     let dataset_message = ClientMessage::from_train_and_predict(&train, &predict);
 
     // Start a timer to track execution time and send the data across
     let start = Instant::now();
-    dcn_stream.write(&dataset_message.as_bytes()).await.unwrap();
+    let dataset_bytes = dataset_message.as_bytes();
+    dcn_stream.write_all(&dataset_bytes).await.unwrap();
+
+    // Tell the user once per cluster, without delaying the node's timer
+    if cluster_control.mark_data_sent() {
+        let message = KafkaWsMessage::DataSentMessage {
+            project_id: info.project_id.to_string(),
+        };
+        let (database_clone, bus, job_id) = (
+            Arc::clone(&database),
+            Arc::clone(&info.bus),
+            info.job.id.to_string(),
+        );
+        tokio::spawn(async move {
+            message.notify(&database_clone, bus.as_ref(), &job_id).await;
+        });
+    }
+
+    // Record how quickly the data could be sent to the node
+    let transfer_secs = start.elapsed().as_secs_f64();
+
+    if transfer_secs > 0.0 {
+        let transfer_rate = dataset_bytes.len() as f64 / transfer_secs;
+        record_telemetry(&database, model_id, Metric::TransferRate, transfer_rate).await;
+    }
+
+    let heartbeats = nodepool.capabilities(model_id).await.busy_heartbeats;
 
     // TODO: Propagate this error forward to the frontend so that it can say a node has failed
-    let prediction_message =
-        match ClientMessage::from_stream(dcn_stream.deref_mut(), &mut buffer).await {
-            Ok(pm) => pm,
-            Err(error) => {
-                nodepool.update_node_alive(&model_id, false).await;
-                cluster_control.decrement().await;
-
-                log::error!(
-                    "Node with id={} failed to deal with predictions: {}",
-                    model_id,
-                    error
-                );
+    let waiting = wait_for_predictions(
+        &database,
+        model_id,
+        dcn_stream.deref_mut(),
+        &info,
+        heartbeats,
+        start,
+    );
 
-                return Ok(());
-            }
-        };
+    let predictions = match waiting.await {
+        Ok(predictions) => predictions,
+        Err(failure) => {
+            log::error!(
+                "Node with id={} failed to deal with predictions: {}",
+                model_id,
+                failure
+            );
+
+            handle_node_failure(&nodepool, &database, model_id, &failure).await?;
+            cluster_control.decrement().await;
+
+            return Ok(());
+        }
+    };
 
     // Stop the timer and record how long was spent processing
-    let processing_time_secs = (Instant::now() - start).as_secs();
+    let processing_time = Instant::now() - start;
+    let processing_time_secs = processing_time.as_secs();
     log::trace!(
         "model_id={} spent {:?} processing {} training bytes and {} predictions bytes",
         model_id,
@@ -624,11 +1214,19 @@ pub async fn dcl_protocol(
         predict.len()
     );
 
-    // Ensure it is the right message and decode + decompress it
-    let anonymised_predictions = match prediction_message {
-        ClientMessage::Predictions(s) => decode_and_decompress(&s),
-        _ => unreachable!(),
-    };
+    // Record the compute time per row, ignoring the headers of each dataset
+    let rows = (train.lines().count() + predict.lines().count()).saturating_sub(2);
+
+    if rows > 0 {
+        let compute_per_row = processing_time.as_secs_f64() * 1000.0 / rows as f64;
+        record_telemetry(&database, model_id, Metric::ComputePerRow, compute_per_row).await;
+        nodepool
+            .update_node_compute_per_row(model_id, &database)
+            .await;
+    }
+
+    // Decode and decompress the predictions
+    let anonymised_predictions = decode_and_decompress(&predictions);
 
     // Evaluate the model
     let mut model_success = true;
@@ -661,16 +1259,55 @@ pub async fn dcl_protocol(
     let cluster_size = info.job.config.cluster_size as usize;
     // Produce message
     let message = KafkaWsMessage::ClientCompleteMessage {
-        project_id: &info.project_id.to_string(),
+        project_id: info.project_id.to_string(),
         cluster_size,
         model_complete_count: cluster_size - remaining_nodes,
         success: model_success,
     };
-    message.produce(&database).await?;
+    let job_id = info.job.id.to_string();
+    message
+        .produce(&database, info.bus.as_ref(), &job_id)
+        .await?;
 
     Ok(())
 }
 
+/// Decides whether to forward a progress update from a node to its user.
+///
+/// Forwarding costs a database lookup and a publish, so updates are only forwarded if `last`, the
+/// time since the previous update was forwarded and its stage, is at least [`PROGRESS_INTERVAL`]
+/// ago or the node has moved on to another `stage`.
+pub fn should_forward_progress(last: Option<(Duration, &str)>, stage: &str) -> bool {
+    match last {
+        Some((elapsed, previous)) => elapsed >= PROGRESS_INTERVAL || previous != stage,
+        None => true,
+    }
+}
+
+/// Decides how long a node should be allowed to compute for based on its reported progress.
+///
+/// Given the `base` computation time for the job, the time that has `elapsed` so far and the
+/// `fraction` of the job the node claims to have completed, this projects the total time the node
+/// will take. Nodes that are on course to finish slightly late are given extra time, up to
+/// [`MAX_DEADLINE_EXTENSION`] of the `base` time, whereas nodes that are projected to finish
+/// beyond that return `None` so they can be cut early instead of holding up the cluster. Until
+/// the node reaches [`MIN_ESTIMATE_FRACTION`], its estimates are ignored and the `base` time is
+/// returned unchanged.
+pub fn adjust_deadline(base: Duration, elapsed: Duration, fraction: f64) -> Option<Duration> {
+    if !fraction.is_finite() || fraction < MIN_ESTIMATE_FRACTION {
+        return Some(base);
+    }
+
+    let maximum = base.mul_f64(1.0 + MAX_DEADLINE_EXTENSION);
+    let projected = elapsed.div_f64(fraction.min(1.0));
+
+    if projected > maximum {
+        return None;
+    }
+
+    Some(projected.max(base))
+}
+
 /// Writes predictions back to the Mongo database for long term storage.
 ///
 /// Write predictions back to the database using the GridFS interface. This allows
@@ -700,6 +1337,17 @@ pub async fn write_predictions(
     Ok(())
 }
 
+/// Records a telemetry sample for a model, logging rather than failing if it cannot be stored.
+async fn record_telemetry(database: &Database, model_id: &str, metric: Metric, value: f64) {
+    if let Err(e) = Telemetry::record(database, model_id, metric, value).await {
+        log::warn!(
+            "Failed to record telemetry for model_id={}: {}",
+            model_id,
+            e
+        );
+    }
+}
+
 /// Updates the non-performance related statistics for the given model.
 ///
 /// This increments the number of times it has been run and adds the amount of time it spent
@@ -752,66 +1400,5 @@ pub async fn change_status(
     Ok(())
 }
 
-/// Emails the user to inform them the project has finished.
-///
-/// If any of the appropriate environment variables are not set (being the `FROM_ADDRESS`,
-/// `FROM_NAME` and `APP_PASSWORD`), no emails will sent. Otherwise, this will retrieve the project
-/// information from the database and the user who created it, before formatting an email and
-/// sending it to them. This is currently hardcoded to use Google's mail servers and is largely
-/// based on similar code that runs the email system for `blackboards.pl`.
-pub async fn email_user_on_project_finish(
-    database: &Arc<Database>,
-    project_id: &ObjectId,
-) -> Result<()> {
-    let users = database.collection("users");
-    let projects = database.collection("projects");
-
-    // Find the project itself and convert it
-    let document = projects
-        .find_one(doc! { "_id": &project_id }, None)
-        .await?
-        .expect("Project did not exist in the database");
-
-    let project = from_document::<Project>(document)?;
-
-    // Find the user associated with the project and deserialize it
-    let user_document = users
-        .find_one(doc! { "_id": &project.user_id }, None)
-        .await?
-        .expect("Failed to find user associated with project");
-
-    let user: User = from_document(user_document)?;
-
-    // Get the configuration from the environment if it exists
-    let email_config = Config::from_env()?;
-
-    // Form the sender and receiver addresses, as well as the body
-    let from = format!("{} <{}>", email_config.from_name, email_config.from_address);
-    let to = format!("{} {} <{}>", user.first_name, user.last_name, user.email);
-    let body = format!(
-        r#"Hi {},
-
-Your project '{}' has finished processing, and results are now available. You can view them at https://sybl.tech/dashboard/{}"#,
-        user.first_name, project.name, project_id
-    );
-
-    // Build the message to send
-    let email = Message::builder()
-        .from(from.parse()?)
-        .to(to.parse()?)
-        .subject("Sybl Project Completion")
-        .body(body)?;
-
-    // Use the credentials from the configuration parsed earlier
-    let creds = Credentials::new(email_config.from_address, email_config.app_password);
-
-    // Open a remote connection to gmail
-    let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay("smtp.gmail.com")?
-        .credentials(creds)
-        .build();
-
-    // Send the email itself
-    mailer.send(email).await?;
-
-    Ok(())
-}
+#[cfg(test)]
+mod tests;
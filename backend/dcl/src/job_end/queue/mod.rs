@@ -72,6 +72,42 @@ impl JobQueue {
 
         job_queue_write.push_back(job);
     }
+
+    /// Gets the number of jobs in the [`JobQueue`].
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Checks whether the [`JobQueue`] is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Checks whether the job with the given identifier is in the [`JobQueue`].
+    pub fn contains(&self, job_id: &ObjectId) -> bool {
+        let jq_mutex = self.0.lock().unwrap();
+
+        jq_mutex.iter().any(|(_, _, job)| &job.id == job_id)
+    }
+
+    /// Gets the project identifier and [`Job`] for everything in the [`JobQueue`], in order,
+    /// without the datasets.
+    pub fn jobs(&self) -> Vec<(ObjectId, Job)> {
+        let jq_mutex = self.0.lock().unwrap();
+
+        jq_mutex
+            .iter()
+            .map(|(project_id, _, job)| (project_id.clone(), job.clone()))
+            .collect()
+    }
+
+    /// Removes every job from the [`JobQueue`], returning them in the order they were queued.
+    /// This is used when the DCL shuts down so that unfinished jobs can be handed back.
+    pub fn drain(&self) -> Vec<(ObjectId, DatasetPair, Job)> {
+        let mut job_queue_write = self.0.lock().unwrap();
+
+        job_queue_write.drain(..).collect()
+    }
 }
 
 #[cfg(test)]
@@ -104,3 +104,33 @@ fn jobs_can_be_inserted_at_specific_indices() {
     // Check the second element
     assert_eq!(queue.0.lock().unwrap()[1], element);
 }
+
+#[test]
+fn draining_empties_the_queue_in_order() {
+    let queue = JobQueue::new();
+    let first = create_job_element();
+    let second = create_job_element();
+
+    queue.push(first.clone());
+    queue.push(second.clone());
+
+    let drained = queue.drain();
+
+    assert_eq!(drained, vec![first, second]);
+    assert!(queue.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn jobs_are_only_contained_until_removed() {
+    let queue = JobQueue::new();
+    let element = create_job_element();
+    let job_id = element.2.id.clone();
+
+    assert!(!queue.contains(&job_id));
+
+    queue.push(element);
+    assert!(queue.contains(&job_id));
+
+    queue.remove(0);
+    assert!(!queue.contains(&job_id));
+}
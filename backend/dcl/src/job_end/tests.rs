@@ -0,0 +1,38 @@
+use super::*;
+
+use tokio::sync::oneshot;
+
+#[tokio::test]
+async fn dropping_node_tasks_aborts_them() {
+    let (sender, receiver) = oneshot::channel::<()>();
+    let mut tasks = NodeTasks::default();
+
+    tasks.0.push(tokio::spawn(async move {
+        let _sender = sender;
+        futures::future::pending::<()>().await
+    }));
+
+    drop(tasks);
+
+    // The sender is only dropped if the task was aborted
+    let received = timeout(Duration::from_secs(1), receiver).await.unwrap();
+    assert!(received.is_err());
+}
+
+#[tokio::test]
+async fn detached_node_tasks_keep_running() {
+    let (sender, receiver) = oneshot::channel();
+    let (release, released) = oneshot::channel::<()>();
+    let mut tasks = NodeTasks::default();
+
+    tasks.0.push(tokio::spawn(async move {
+        let _ = released.await;
+        let _ = sender.send(());
+    }));
+
+    tasks.detach();
+    release.send(()).unwrap();
+
+    let received = timeout(Duration::from_secs(1), receiver).await.unwrap();
+    assert!(received.is_ok());
+}
@@ -11,20 +11,31 @@ extern crate serde;
 
 use std::env;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{atomic::Ordering, Arc};
 
 use anyhow::Result;
 use mongodb::options::ClientOptions;
 use mongodb::Client;
 use tokio::sync::Notify;
 
+use mailer::Mailer;
+use messages::{InMemoryBus, MessageBus};
+use utils::metrics;
+
+use coordination::InstanceConfig;
+use job_end::RunningClusters;
+
+pub mod admin;
+pub mod coordination;
 pub mod health;
 pub mod interface_end;
 pub mod job_end;
 pub mod node_end;
 pub mod protocol;
+pub mod shutdown;
 
 pub use job_end::queue::JobQueue;
+pub use shutdown::Shutdown;
 
 /// A pair of datasets, one for training and one for predicting.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
@@ -36,38 +47,99 @@ pub struct DatasetPair {
 }
 
 /// Data structures for running job control in the DCL
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct JobControl {
     /// Job Queue for jobs coming from the interface
     pub job_queue: JobQueue,
     /// Notify struct to improve performance of job end
     pub notify: Arc<Notify>,
+    /// Signals that the DCL is shutting down
+    pub shutdown: Shutdown,
+    /// Identifies this instance when coordinating with other instances
+    pub instance: InstanceConfig,
+    /// Clusters that are currently running jobs
+    pub clusters: RunningClusters,
+    /// The bus used to receive jobs and send updates to other components
+    pub bus: Arc<dyn MessageBus>,
+    /// Sends emails to users, if emails are enabled
+    pub mailer: Option<Mailer>,
+}
+
+impl Default for JobControl {
+    fn default() -> Self {
+        Self {
+            job_queue: JobQueue::default(),
+            notify: Arc::default(),
+            shutdown: Shutdown::default(),
+            instance: InstanceConfig::default(),
+            clusters: RunningClusters::default(),
+            bus: Arc::new(InMemoryBus::new()),
+            mailer: None,
+        }
+    }
 }
 
 impl JobControl {
-    /// New instance of JobControl
+    /// New instance of JobControl, which only sends messages within the process
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// New instance of JobControl using the given [`Shutdown`], [`InstanceConfig`] and
+    /// [`MessageBus`]
+    pub fn with_config(
+        shutdown: Shutdown,
+        instance: InstanceConfig,
+        bus: Arc<dyn MessageBus>,
+    ) -> Self {
+        Self {
+            shutdown,
+            instance,
+            bus,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the [`Mailer`] used to email users, if emails are enabled
+    pub fn with_mailer(mut self, mailer: Option<Mailer>) -> Self {
+        self.mailer = mailer;
+        self
+    }
 }
 
 /// Main runner function for the DCL
 ///
 /// This function is called when starting up the DCL. It starts the
-/// tokio runtime and sets up its connection with the MongoDB database.
-/// It will then spawn threads for the different parts of the DCL to
-/// offer the full functionality of the product.
+/// tokio runtime and creates the [`MessageBus`] described by the environment,
+/// before running the DCL with [`run_with_bus`].
 #[tokio::main]
 pub async fn run() -> Result<()> {
+    let bus = messages::bus::from_env()?;
+
+    run_with_bus(bus).await
+}
+
+/// Runs the DCL using the given [`MessageBus`]
+///
+/// Sets up the connection with the MongoDB database and then spawns tasks
+/// for the different parts of the DCL to offer the full functionality of the
+/// product. This runs inside an existing runtime, allowing the DCL to share a
+/// process and an in-memory bus with the other components.
+pub async fn run_with_bus(bus: Arc<dyn MessageBus>) -> Result<()> {
     let conn_str = env::var("CONN_STR").expect("CONN_STR must be set");
     let app_name = env::var("APP_NAME").expect("APP_NAME must be set");
-    let broker_socket =
-        u16::from_str(&env::var("BROKER_PORT").unwrap_or_else(|_| "9092".to_string()))
-            .expect("BROKER_PORT must be a u16");
     let node_socket =
         u16::from_str(&env::var("NODE_SOCKET").expect("NODE_SOCKET must be set")).unwrap();
 
-    let health = u64::from_str(&env::var("HEALTH").expect("HEALTH must be set")).unwrap();
+    let admin_port = env::var("ADMIN_PORT")
+        .ok()
+        .map(|v| u16::from_str(&v).expect("ADMIN_PORT must be a u16"));
+
+    let metrics_port = env::var("DCL_METRICS_PORT")
+        .ok()
+        .map(|v| u16::from_str(&v).expect("DCL_METRICS_PORT must be a u16"));
+
+    let health = health::HealthConfig::from_env();
     let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| String::from("sybl"));
 
     let mut client_options = ClientOptions::parse(&conn_str).await.unwrap();
@@ -78,22 +150,31 @@ pub async fn run() -> Result<()> {
             .database(&database_name),
     );
 
-    let job_control = JobControl::new();
+    if let Err(e) = models::telemetry::Telemetry::create_indexes(&client).await {
+        log::error!("Failed to create the telemetry indexes: {}", e);
+    }
+
+    let mailer = Mailer::from_env()?;
+    let job_control =
+        JobControl::with_config(Shutdown::from_env(), InstanceConfig::from_env(), bus)
+            .with_mailer(mailer);
     let job_notify = Arc::clone(&job_control.notify);
     let nodepool = Arc::new(node_end::NodePool::new(job_notify));
 
     let db_conn_interface = Arc::clone(&client);
     let jc_clone = job_control.clone();
-    tokio::spawn(async move {
-        interface_end::run(broker_socket, db_conn_interface, jc_clone)
+    let interface_handle = tokio::spawn(async move {
+        interface_end::run(db_conn_interface, jc_clone)
             .await
             .unwrap();
     });
 
     let nodepool_clone = Arc::clone(&nodepool);
     let node_client = Arc::clone(&client);
+    let auth = protocol::auth_backend_from_env(Arc::clone(&client));
+    let shutdown = job_control.shutdown.clone();
     tokio::spawn(async move {
-        node_end::run(nodepool_clone, node_client, node_socket)
+        node_end::run(nodepool_clone, node_client, node_socket, auth, shutdown)
             .await
             .unwrap();
     });
@@ -101,18 +182,97 @@ pub async fn run() -> Result<()> {
     let nodepool_clone = Arc::clone(&nodepool);
     let job_client = Arc::clone(&client);
     let jc_clone = job_control.clone();
-    tokio::spawn(async move {
+    let job_handle = tokio::spawn(async move {
         job_end::run(nodepool_clone, job_client, jc_clone)
             .await
             .unwrap();
     });
 
+    let nodepool_clone = Arc::clone(&nodepool);
+    let coordination_client = Arc::clone(&client);
+    let jc_clone = job_control.clone();
+    let coordination_handle = tokio::spawn(async move {
+        coordination::run(coordination_client, nodepool_clone, jc_clone)
+            .await
+            .unwrap();
+    });
+
+    if let Some(admin_port) = admin_port {
+        let state = admin::AdminState {
+            nodepool: Arc::clone(&nodepool),
+            database: Arc::clone(&client),
+            job_control: job_control.clone(),
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = admin::run(admin_port, state).await {
+                log::error!("Admin endpoint failed: {}", e);
+            }
+        });
+    }
+
+    if let Some(metrics_port) = metrics_port {
+        let nodepool_clone = Arc::clone(&nodepool);
+        let job_queue = job_control.job_queue.clone();
+
+        metrics::on_gather(move || {
+            let active = nodepool_clone.active.load(Ordering::SeqCst);
+            metrics::ACTIVE_NODES.set(active as f64);
+            metrics::QUEUE_DEPTH.set(job_queue.len() as f64);
+        });
+
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_port).await {
+                log::error!("Metrics endpoint failed: {}", e);
+            }
+        });
+    }
+
     let health_client = Arc::clone(&client);
     let nodepool_clone = Arc::clone(&nodepool);
-    tokio::spawn(async move {
-        health::health_runner(health_client, nodepool_clone, health).await;
-    })
-    .await?;
+    let shutdown = job_control.shutdown.clone();
+    let mut health_handle = tokio::spawn(async move {
+        health::health_runner(health_client, nodepool_clone, health, shutdown).await;
+    });
+
+    let health_stopped = tokio::select! {
+        result = shutdown::signal_received() => {
+            result?;
+            false
+        }
+        result = &mut health_handle => {
+            result?;
+            true
+        }
+    };
+
+    log::info!("Beginning graceful shutdown");
+    job_control.shutdown.trigger();
+
+    // Stop sending heartbeats before the nodes are told that the DCL is shutting down
+    if !health_stopped {
+        if let Err(e) = health_handle.await {
+            log::error!("Health checking failed during shutdown: {}", e);
+        }
+    }
+
+    // Wait for any running cluster to finish, which is bounded by the grace period
+    if let Err(e) = interface_handle.await {
+        log::error!("Interface end failed during shutdown: {}", e);
+    }
+
+    if let Err(e) = job_handle.await {
+        log::error!("Job end failed during shutdown: {}", e);
+    }
+
+    if let Err(e) = coordination_handle.await {
+        log::error!("Coordination failed during shutdown: {}", e);
+    }
+
+    shutdown::requeue_jobs(&job_control.job_queue, job_control.bus.as_ref()).await;
+    nodepool.shutdown(Arc::clone(&client)).await;
+
+    log::info!("Shutdown complete");
 
     Ok(())
 }
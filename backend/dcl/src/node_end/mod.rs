@@ -11,8 +11,11 @@ use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Duration;
 
 use anyhow::Result;
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
 use mongodb::{
     bson::{doc, oid::ObjectId},
     Database,
@@ -21,12 +24,28 @@ use rand::Rng;
 use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{Notify, RwLock};
+use tokio::time::{error::Elapsed, timeout, timeout_at, Instant};
 
-use messages::{ClientMessage, WriteLengthPrefix};
+use messages::{ClientMessage, NodeCapabilities, WriteLengthPrefix};
+use model_auth::AuthBackend;
 use models::models::Status;
+use models::telemetry::{Metric, Telemetry};
 use models::{job_performance::JobPerformance, jobs::JobConfiguration};
 
 use crate::protocol;
+use crate::shutdown::Shutdown;
+
+/// The number of recent telemetry samples to average when estimating node speed
+const TELEMETRY_WINDOW: i64 = 5;
+
+/// The number of rows above which a job is considered large enough to avoid slow nodes
+pub const LARGE_JOB_ROWS: usize = 10_000;
+
+/// The time nodes are given to respond to an offer of a job
+pub const JOB_OFFER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The time to wait for a busy node's stream when telling it that the DCL is shutting down
+const SHUTDOWN_NOTIFY_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Defines information about a Node
 #[derive(Debug)]
@@ -66,7 +85,7 @@ impl Node {
     /// Increment the dead counter for node
     pub async fn inc_counter(&self) {
         let mut counter = self.counter.write().await;
-        *counter += 1;
+        *counter = counter.saturating_add(1);
     }
 
     /// Reset the dead counter for node
@@ -82,7 +101,7 @@ impl Node {
 }
 
 /// Information about a connected [`Node`]
-#[derive(Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NodeInfo {
     /// Flag to specify if [`Node`] is alive or not
     pub alive: bool,
@@ -90,6 +109,10 @@ pub struct NodeInfo {
     pub using: bool,
     /// Performance of the [`Node`] on previous jobs
     pub performance: f64,
+    /// Average milliseconds spent computing each row of data on previous jobs
+    pub compute_per_row: Option<f64>,
+    /// Capabilities and limits declared by the [`Node`] when it authenticated
+    pub capabilities: NodeCapabilities,
 }
 
 impl NodeInfo {
@@ -101,9 +124,19 @@ impl NodeInfo {
             alive: true,
             using: false,
             performance,
+            compute_per_row: None,
+            capabilities: NodeCapabilities::default(),
         }
     }
 
+    /// Estimates how long the [`Node`] will take to compute a job with the given number of rows.
+    ///
+    /// Returns `None` if the node has not reported any compute times yet.
+    pub fn estimate_compute_time(&self, rows: usize) -> Option<Duration> {
+        self.compute_per_row
+            .map(|ms| Duration::from_secs_f64(ms * rows as f64 / 1000.0))
+    }
+
     /// gets the past 5 performances of node from DB
     /// and averages them out. To be used when a node
     /// is created.
@@ -116,10 +149,30 @@ impl NodeInfo {
             perf = performances.iter().sum::<f64>() / performances.len() as f64;
         }
 
-        Ok(NodeInfo::new(perf))
+        let mut info = NodeInfo::new(perf);
+        info.compute_per_row = recent_compute_per_row(&database, model_id).await;
+
+        Ok(info)
     }
 }
 
+/// Averages the milliseconds per row a model spent computing over its most recent jobs.
+///
+/// This is the only estimate of node speed, used both when a node joins the pool and after each
+/// job it runs. Returns `None` if the model has no samples or they cannot be read.
+async fn recent_compute_per_row(database: &Database, model_id: &str) -> Option<f64> {
+    Telemetry::average(database, model_id, Metric::ComputePerRow, TELEMETRY_WINDOW)
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!(
+                "Failed to read the compute times for model_id={}: {}",
+                model_id,
+                e
+            );
+            None
+        })
+}
+
 impl Default for NodeInfo {
     fn default() -> Self {
         Self::new(0.0)
@@ -137,6 +190,10 @@ pub struct NodePool {
     pub active: AtomicUsize,
     /// Notify struct for alerting changes to Job End
     pub job_notify: Arc<Notify>,
+    /// How many times each node has failed while running jobs, by reason
+    ///
+    /// Counts are kept after a node leaves the pool, so they continue if it reconnects.
+    pub failures: RwLock<HashMap<String, HashMap<String, u64>>>,
 }
 
 impl NodePool {
@@ -147,6 +204,7 @@ impl NodePool {
             info: RwLock::new(HashMap::new()),
             active: AtomicUsize::new(0),
             job_notify,
+            failures: RwLock::new(HashMap::new()),
         }
     }
 
@@ -154,10 +212,19 @@ impl NodePool {
     ///
     /// Function will take in a new [`Node`] and will create an ID for it. It will also create an
     /// associated [`NodeInfo`] instance to also be stored under the same ID. These are then stored
-    /// in their respective [`HashMap`]s
-    pub async fn add(&self, node: Node, database: Arc<Database>) {
+    /// in their respective [`HashMap`]s, along with the capabilities the node declared.
+    pub async fn add(&self, node: Node, capabilities: NodeCapabilities, database: Arc<Database>) {
         let id = node.get_model_id().to_string();
 
+        // Read the node's history before locking the pool, which would block every other task
+        let mut info = NodeInfo::from_database(database, &id)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to read the history of model_id={}: {}", id, e);
+                NodeInfo::default()
+            });
+        info.capabilities = capabilities;
+
         let mut node_map = self.nodes.write().await;
         let mut info_map = self.info.write().await;
 
@@ -172,14 +239,89 @@ impl NodePool {
         };
 
         node_map.insert(id.clone(), node);
-        info_map.insert(
-            id.clone(),
-            NodeInfo::from_database(database, &id).await.unwrap(),
-        );
+        info_map.insert(id.clone(), info);
 
         self.job_notify.notify_waiters();
     }
 
+    /// Records that a [`Node`] failed while running a job, for the given reason
+    pub async fn record_failure(&self, id: &str, reason: &str) {
+        let mut failures = self.failures.write().await;
+
+        *failures
+            .entry(id.to_string())
+            .or_default()
+            .entry(reason.to_string())
+            .or_default() += 1;
+    }
+
+    /// Gets how many times a [`Node`] has failed while running jobs, by reason
+    pub async fn get_failures(&self, id: &str) -> HashMap<String, u64> {
+        let failures = self.failures.read().await;
+        failures.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Removes a [`Node`] and its [`NodeInfo`] from the [`NodePool`]
+    ///
+    /// Used when a node is assumed to be dead, ensuring that the number of active nodes stays
+    /// consistent if the node was still counted as being available. Returns whether the node was
+    /// in the pool.
+    pub async fn remove(&self, id: &str) -> bool {
+        let mut node_map = self.nodes.write().await;
+        let mut info_map = self.info.write().await;
+
+        log::info!("Removing node from the pool with id={}", id);
+
+        let removed = node_map.remove(id).is_some();
+
+        if let Some(info) = info_map.remove(id) {
+            if info.alive && !info.using {
+                self.active.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        removed
+    }
+
+    /// Gets the identifiers and [`TcpStream`]s of all nodes that are not in use
+    ///
+    /// This takes a snapshot of the pool, meaning the locks are only held briefly and callers can
+    /// communicate with the nodes without blocking the rest of the DCL.
+    pub async fn idle_nodes(&self) -> Vec<(String, Arc<RwLock<TcpStream>>)> {
+        let nodes_read = self.nodes.read().await;
+        let info_read = self.info.read().await;
+
+        nodes_read
+            .iter()
+            .filter(|(id, _)| matches!(info_read.get(*id), Some(info) if !info.using))
+            .map(|(id, node)| (id.clone(), node.get_tcp()))
+            .collect()
+    }
+
+    /// Gets the number of idle nodes and the total number of nodes in the [`NodePool`]
+    pub async fn capacity(&self) -> (usize, usize) {
+        let total = self.nodes.read().await.len();
+
+        (self.active.load(Ordering::SeqCst), total)
+    }
+
+    /// Records the outcome of a heartbeat for a [`Node`]
+    ///
+    /// Resets the dead counter for the node if it responded, or increments it otherwise. Returns
+    /// the number of consecutive failures, or `None` if the node is no longer in the pool.
+    pub async fn record_heartbeat(&self, id: &str, alive: bool) -> Option<u8> {
+        let nodes_read = self.nodes.read().await;
+        let node = nodes_read.get(id)?;
+
+        if alive {
+            node.reset_counter().await;
+        } else {
+            node.inc_counter().await;
+        }
+
+        Some(node.get_counter().await)
+    }
+
     /// Gets [`TcpStream`] reference and its [`ObjectId`]
     ///
     /// Function is used to choose the next Node to use. When this is found, the [`TcpStream`] is
@@ -205,11 +347,15 @@ impl NodePool {
     /// Creates a cluster based on a JobConfig `config`
     ///
     /// It is given a cluster size and searches the nodepool for available clusters and builds the
-    /// cluster as a hashmap.  When the size is reached, the cluster is output. If it is empty then
-    /// the None Option is returned. If it has nodes in it, but less than the size of the cluster,
-    /// it is still returned. This also uses the performance metric to build well performing clusters.
+    /// cluster as a hashmap. When the size is reached, the cluster is output, whereas `None` is
+    /// returned if not enough nodes accept the job.
+    ///
+    /// The job is offered to all suitable nodes concurrently, with each of them sharing the same
+    /// deadline of [`JOB_OFFER_TIMEOUT`] to respond. No locks on the pool are held while waiting
+    /// for responses, and the cluster is built from the first nodes to accept, so it favours
+    /// nodes that respond quickly rather than those that have performed well.
     pub async fn build_cluster(
-        &self,
+        self: &Arc<Self>,
         config: JobConfiguration,
     ) -> Option<HashMap<String, Arc<RwLock<TcpStream>>>> {
         // Convert to usize as MongoDB stores as i32
@@ -217,11 +363,6 @@ impl NodePool {
 
         log::debug!("Attempting to build a cluster with size={}", cluster_size);
 
-        let nodes_read = self.nodes.read().await;
-        let mut accepted_job: Vec<(String, f64)> = Vec::new();
-        let mut better_nodes: Vec<(String, f64)> = Vec::new();
-        let mut info_write = self.info.write().await;
-
         let active = self.active.load(Ordering::SeqCst);
 
         if active < cluster_size {
@@ -234,44 +375,123 @@ impl NodePool {
             return None;
         }
 
-        // Ask all alive and free nodes if they want the job
-        // If they do, their ID is added to accepted_job
-        for (id, info) in info_write.iter_mut() {
-            if info.alive && !info.using {
-                info.using = true;
-                let stream = nodes_read.get(id).unwrap().get_tcp();
-                let config_response = NodePool::job_accepted(&stream, &config, &id).await;
-
-                // Check all 3 possible states:
-                //      - Explicit acceptance
-                //      - Explicit rejection
-                //      - An error in the stream itself
-                match config_response {
-                    Ok(true) => {
-                        log::info!("Node with id={} accepted the job", id);
-                        accepted_job.push((id.clone(), info.performance));
-
-                        if info.performance > 0.5 {
-                            better_nodes.push((id.clone(), info.performance));
-                        }
-                        self.active.fetch_sub(1, Ordering::SeqCst);
+        // For large jobs, avoid nodes that are known to be too slow to finish in time
+        let rows = (config.train_size + config.predict_size).max(0) as usize;
+        let budget = Duration::from_secs((config.node_computation_time * 60).max(0) as u64);
+        let too_slow = |info: &NodeInfo| {
+            rows >= LARGE_JOB_ROWS
+                && matches!(info.estimate_compute_time(rows), Some(estimate) if estimate > budget)
+        };
 
-                        continue;
-                    }
-                    Ok(false) => {
-                        log::debug!("model_id={} explicitly rejected the job", id);
-                    }
-                    Err(e) => {
-                        log::warn!("Error occurred asking model_id={} about the job: {}", id, e);
-                    }
+        // Reserve all alive and free nodes that could run the job while holding the locks, so
+        // that they can be asked about it without blocking the rest of the pool
+        let mut candidates = Vec::new();
+
+        {
+            let nodes_read = self.nodes.read().await;
+            let mut info_write = self.info.write().await;
+
+            for (id, info) in info_write.iter_mut() {
+                if !info.alive || info.using {
+                    continue;
+                }
+
+                // Avoid a round trip if the node cannot possibly run the job
+                if !info.capabilities.satisfies(&config) {
+                    log::debug!(
+                        "Skipping model_id={} as its capabilities do not satisfy the job",
+                        id
+                    );
+
+                    continue;
                 }
 
-                info.using = false;
+                if too_slow(&*info) {
+                    log::debug!(
+                        "Skipping model_id={} as it is too slow for a job with {} rows",
+                        id,
+                        rows
+                    );
+
+                    continue;
+                }
+
+                if let Some(node) = nodes_read.get(id) {
+                    info.using = true;
+                    self.active.fetch_sub(1, Ordering::SeqCst);
+                    candidates.push((id.clone(), info.performance, node.get_tcp()));
+                }
             }
         }
 
-        // We don't need the lock past here, so drop it to prevent deadlocks
-        drop(info_write);
+        if candidates.len() < cluster_size {
+            log::warn!(
+                "Only {} node(s) are able to run the job, required at least {}",
+                candidates.len(),
+                cluster_size
+            );
+
+            for (model_id, _, _) in &candidates {
+                self.release(model_id).await;
+            }
+
+            return None;
+        }
+
+        // Offer the job to every candidate at once, giving them all the same deadline
+        let config = Arc::new(config);
+        let deadline = Instant::now() + JOB_OFFER_TIMEOUT;
+
+        let mut offers: FuturesUnordered<_> = candidates
+            .into_iter()
+            .map(|(id, performance, stream)| {
+                let config = Arc::clone(&config);
+
+                async move {
+                    let response =
+                        timeout_at(deadline, NodePool::job_accepted(&stream, &config, &id)).await;
+
+                    (id, performance, stream, response)
+                }
+            })
+            .collect();
+
+        let mut accepted_job: Vec<(String, f64)> = Vec::new();
+        let mut better_nodes: Vec<(String, f64)> = Vec::new();
+        let mut streams: HashMap<String, Arc<RwLock<TcpStream>>> = HashMap::new();
+
+        // Stop waiting as soon as enough nodes have accepted the job
+        while accepted_job.len() < cluster_size {
+            let (id, performance, stream, response) = match offers.next().await {
+                Some(offer) => offer,
+                None => break,
+            };
+
+            if NodePool::offer_accepted(&id, response) {
+                accepted_job.push((id.clone(), performance));
+
+                if performance > 0.5 {
+                    better_nodes.push((id.clone(), performance));
+                }
+
+                streams.insert(id, stream);
+            } else {
+                self.release(&id).await;
+            }
+        }
+
+        // Any outstanding offers are left to finish in the background, so that their responses
+        // are still read from the streams before the nodes are released again
+        if !offers.is_empty() {
+            let nodepool = Arc::clone(self);
+
+            tokio::spawn(async move {
+                while let Some((id, _, _, response)) = offers.next().await {
+                    NodePool::offer_accepted(&id, response);
+                    nodepool.release(&id).await;
+                }
+            });
+        }
 
         // Checks if number of nodes that accepted the job is less than
         // the size of the cluster required.
@@ -284,9 +504,7 @@ impl NodePool {
 
             // Reset all the nodes that accepted to not in use
             for model_id in accepted_job.iter().map(|x| &x.0) {
-                self.end(&model_id)
-                    .await
-                    .expect("Failed to update the node status");
+                self.release(model_id).await;
             }
 
             return None;
@@ -306,7 +524,7 @@ impl NodePool {
             );
 
             // Get the node stream
-            let stream = nodes_read.get(&chosen_node).unwrap().get_tcp();
+            let stream = streams.remove(&chosen_node).unwrap();
 
             // Add node id with stream to cluster
             cluster.insert(chosen_node.clone(), stream);
@@ -320,9 +538,7 @@ impl NodePool {
 
         // Reset all the nodes that accepted to not in use
         for model_id in accepted_job.iter().map(|x| &x.0) {
-            self.end(&model_id)
-                .await
-                .expect("Failed to update the node status");
+            self.release(model_id).await;
         }
 
         log::info!(
@@ -338,6 +554,38 @@ impl NodePool {
         }
     }
 
+    /// Releases a node that was reserved for a job, logging if it could not be released
+    async fn release(&self, id: &str) {
+        if let Err(e) = self.end(id).await {
+            log::error!("Failed to release model_id={}: {}", id, e);
+        }
+    }
+
+    /// Interprets the response of a node to a job offer
+    ///
+    /// Checks all 3 possible states, namely explicit acceptance, explicit rejection and an error
+    /// in the stream itself, along with the node failing to respond before the deadline.
+    fn offer_accepted(id: &str, response: Result<Result<bool>, Elapsed>) -> bool {
+        match response {
+            Ok(Ok(true)) => {
+                log::info!("Node with id={} accepted the job", id);
+                true
+            }
+            Ok(Ok(false)) => {
+                log::debug!("model_id={} explicitly rejected the job", id);
+                false
+            }
+            Ok(Err(e)) => {
+                log::warn!("Error occurred asking model_id={} about the job: {}", id, e);
+                false
+            }
+            Err(_) => {
+                log::warn!("model_id={} did not respond to the job in time", id);
+                false
+            }
+        }
+    }
+
     /// Returns random model id from list
     ///
     /// Function is given a list of nodes which are prepared to do
@@ -368,6 +616,53 @@ impl NodePool {
         }
     }
 
+    /// Tells every [`Node`] in the pool that the DCL is shutting down
+    ///
+    /// Each node is sent a [`ClientMessage::Shutdown`] message before its connection is closed,
+    /// has its model status set to [`Status::Stopped`] and is then removed from the pool. Nodes
+    /// are notified concurrently, and those whose streams are still busy are only given
+    /// [`SHUTDOWN_NOTIFY_TIMEOUT`] to become free.
+    pub async fn shutdown(&self, database: Arc<Database>) {
+        let nodes: Vec<_> = self
+            .nodes
+            .read()
+            .await
+            .iter()
+            .map(|(id, node)| (id.clone(), node.get_tcp()))
+            .collect();
+
+        log::info!("Notifying {} node(s) of the shutdown", nodes.len());
+
+        let notifications = nodes.into_iter().map(|(id, stream)| {
+            let database = Arc::clone(&database);
+
+            async move {
+                match timeout(SHUTDOWN_NOTIFY_TIMEOUT, stream.write()).await {
+                    Ok(mut stream) => {
+                        let message = ClientMessage::Shutdown.as_bytes();
+
+                        if let Err(e) = stream.write_all(&message).await {
+                            log::warn!("Failed to notify model_id={} of the shutdown: {}", id, e);
+                        }
+
+                        let _ = stream.shutdown().await;
+                    }
+                    Err(_) => log::warn!("model_id={} was busy and could not be notified", id),
+                }
+
+                if let Err(e) = update_model_status(database, &id, Status::Stopped).await {
+                    log::warn!("Failed to set model_id={} to stopped: {}", id, e);
+                }
+
+                id
+            }
+        });
+
+        for id in join_all(notifications).await {
+            self.remove(&id).await;
+        }
+    }
+
     /// Checks with a node if it will accept a job or not
     pub async fn job_accepted(
         stream: &Arc<RwLock<TcpStream>>,
@@ -380,7 +675,7 @@ impl NodePool {
 
         let mut buffer = [0_u8; 1024];
         let message = ClientMessage::from(config);
-        dcn_stream.write(&message.as_bytes()).await?;
+        dcn_stream.write_all(&message.as_bytes()).await?;
 
         let config_response = ClientMessage::read_until(&mut *dcn_stream, &mut buffer, |m| {
             matches!(m, ClientMessage::ConfigResponse { .. })
@@ -419,10 +714,14 @@ impl NodePool {
         log::trace!("Finished using model_id={}, updating its status", key);
 
         let mut info_write = self.info.write().await;
-        info_write.get_mut(key).unwrap().using = false;
 
-        self.active.fetch_add(1, Ordering::SeqCst);
-        self.job_notify.notify_waiters();
+        // The node may have been removed from the pool while it was in use
+        if let Some(info) = info_write.get_mut(key) {
+            info.using = false;
+
+            self.active.fetch_add(1, Ordering::SeqCst);
+            self.job_notify.notify_waiters();
+        }
 
         Ok(())
     }
@@ -433,7 +732,10 @@ impl NodePool {
     /// currently is.
     pub async fn update_node_alive(&self, id: &str, status: bool) {
         let mut info_write = self.info.write().await;
-        let node_info = info_write.get_mut(id).unwrap();
+        let node_info = match info_write.get_mut(id) {
+            Some(node_info) => node_info,
+            None => return,
+        };
 
         log::trace!(
             "Updating liveness status of model_id={}, setting to alive={}",
@@ -462,6 +764,36 @@ impl NodePool {
         node_info.using
     }
 
+    /// Gets the capabilities a node declared when it connected
+    ///
+    /// Returns the default capabilities if the node is no longer in the pool.
+    pub async fn capabilities(&self, id: &str) -> NodeCapabilities {
+        let info_read = self.info.read().await;
+
+        info_read
+            .get(id)
+            .map(|node_info| node_info.capabilities.clone())
+            .unwrap_or_default()
+    }
+
+    /// Updates the compute speed of a [`NodeInfo`] object
+    ///
+    /// Recomputes the average milliseconds per row from the node's most recent samples, in the
+    /// same way as when it joined the pool, so the sample for the job it just finished should be
+    /// recorded first. The previous value is kept if the samples cannot be read.
+    pub async fn update_node_compute_per_row(&self, id: &str, database: &Database) {
+        let compute_per_row = match recent_compute_per_row(database, id).await {
+            Some(compute_per_row) => compute_per_row,
+            None => return,
+        };
+
+        let mut info_write = self.info.write().await;
+
+        if let Some(node_info) = info_write.get_mut(id) {
+            node_info.compute_per_row = Some(compute_per_row);
+        }
+    }
+
     /// Updates a [`NodeInfo`] object
     ///
     /// Gets the correct [`NodeInfo`] struct and updates its average performance.
@@ -470,7 +802,10 @@ impl NodePool {
     /// while retaining historical performance.
     pub async fn update_node_performance(&self, id: &str, performance: f64) {
         let mut info_write = self.info.write().await;
-        let node_info = info_write.get_mut(id).unwrap();
+        let node_info = match info_write.get_mut(id) {
+            Some(node_info) => node_info,
+            None => return,
+        };
 
         log::trace!("Updating model_id={} with performance={}", id, performance);
 
@@ -487,7 +822,13 @@ impl NodePool {
 /// Starts up node end which allows DCNs to register their connection. This will create a Node
 /// object if given a correct API Key. This allows the job end to connect and communicate with the
 /// DCNs.
-pub async fn run(nodepool: Arc<NodePool>, database: Arc<Database>, port: u16) -> Result<()> {
+pub async fn run(
+    nodepool: Arc<NodePool>,
+    database: Arc<Database>,
+    port: u16,
+    auth: Arc<dyn AuthBackend>,
+    shutdown: Shutdown,
+) -> Result<()> {
     // Bind to the external socket in production mode
     #[cfg(not(debug_assertions))]
     let ip = Ipv4Addr::UNSPECIFIED;
@@ -500,13 +841,24 @@ pub async fn run(nodepool: Arc<NodePool>, database: Arc<Database>, port: u16) ->
 
     log::info!("Listening for client connections on: {}", socket);
 
-    while let Ok((inbound, _)) = listener.accept().await {
+    loop {
+        let inbound = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((inbound, _)) => inbound,
+                Err(_) => break,
+            },
+            _ = shutdown.triggered() => {
+                log::info!("Shutting down, no longer accepting node connections");
+                break;
+            }
+        };
+
         let sp_clone = Arc::clone(&nodepool);
         let db_clone = Arc::clone(&database);
 
         log::info!("Received a node connection from: {}", inbound.peer_addr()?);
 
-        let fut = process_connection(inbound, db_clone, sp_clone);
+        let fut = process_connection(inbound, db_clone, sp_clone, Arc::clone(&auth));
 
         if let Err(e) = tokio::spawn(async move { fut.await }).await? {
             log::error!("Error processing connection: {:?}", e);
@@ -520,17 +872,20 @@ async fn process_connection(
     mut stream: TcpStream,
     database: Arc<Database>,
     nodepool: Arc<NodePool>,
+    auth: Arc<dyn AuthBackend>,
 ) -> Result<()> {
-    let mut handler = protocol::Handler::new(&mut stream);
-    let model_id = match handler.get_access_token().await? {
-        Some(t) => t.0,
+    let mut handler = protocol::Handler::new(&mut stream, auth.as_ref());
+    let (model_id, capabilities) = match handler.get_access_token().await? {
+        Some((model_id, _, capabilities)) => (model_id, capabilities),
         None => return Ok(()),
     };
 
     update_model_status(Arc::clone(&database), &model_id, Status::Running).await?;
 
     let node = Node::new(stream, model_id);
-    nodepool.add(node, Arc::clone(&database)).await;
+    nodepool
+        .add(node, capabilities, Arc::clone(&database))
+        .await;
 
     Ok(())
 }
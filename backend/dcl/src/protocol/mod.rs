@@ -1,13 +1,19 @@
 //! Encodes the protocol for handling node connections in the DCL.
 
-use std::fmt::{self, Debug};
+use std::env;
+use std::fmt;
+use std::sync::Arc;
 
-use mongodb::bson::bson;
+use mongodb::Database;
 use serde::Serialize;
+use serde_json::json;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 
-use messages::{ClientMessage, RawMessage, ReadLengthPrefix, WriteLengthPrefix};
+use messages::{ClientMessage, NodeCapabilities, RawMessage, ReadLengthPrefix, WriteLengthPrefix};
+use model_auth::{
+    AuthBackend, AuthError, AuthResponse, DatabaseBackend, RemoteBackend, AUTHENTICATION_SUCCESSFUL,
+};
 
 #[cfg(test)]
 mod tests;
@@ -17,14 +23,9 @@ mod tests;
 pub enum HandlerError {
     /// An error occurred in the stream itself.
     Stream,
-    /// An error occurred when sending something with [`reqwest`].
-    Reqwest {
-        /// The formatted error from [`reqwest`].
-        error: String,
-    },
-    /// The API server responded with an error.
+    /// The authentication backend rejected the request.
     Server {
-        /// The response status code.
+        /// The HTTP status code corresponding to the error.
         code: u16,
         /// Body of the response
         text: String,
@@ -48,9 +49,12 @@ impl fmt::Display for HandlerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Stream => write!(f, "stream error"),
-            Self::Reqwest { error } => write!(f, "{}", error),
             Self::Server { code, text } => {
-                write!(f, "API server returned status={}, body={}", code, text)
+                write!(
+                    f,
+                    "authentication failed with status={}, body={}",
+                    code, text
+                )
             }
         }
     }
@@ -64,29 +68,62 @@ impl From<std::io::Error> for HandlerError {
     }
 }
 
-impl From<reqwest::Error> for HandlerError {
-    fn from(error: reqwest::Error) -> Self {
-        Self::Reqwest {
-            error: error.to_string(),
+impl From<AuthError> for HandlerError {
+    fn from(error: AuthError) -> Self {
+        let code = error.status_code();
+
+        // Mirror the bodies of the API server, as nodes need the challenge for expired tokens
+        let text = match &error {
+            AuthError::Locked => json!({ "message": "Locked" }),
+            AuthError::Expired { challenge } => json!({ "challenge": challenge }),
+            _ => json!({ "code": code, "message": error.to_string() }),
+        };
+
+        Self::Server {
+            code,
+            text: text.to_string(),
         }
     }
 }
 
 type HandlerResult<T> = std::result::Result<T, HandlerError>;
 
+/// The model identifier, access token and declared capabilities of an authenticated node.
+pub type Credentials = (String, String, NodeCapabilities);
+
+/// Builds the backend used to authenticate nodes.
+///
+/// If `AUTH_URL` is set, requests are forwarded to the API server at that address. Otherwise,
+/// nodes are authenticated directly against the database, using `PEPPER` to verify passwords.
+pub fn auth_backend_from_env(database: Arc<Database>) -> Arc<dyn AuthBackend> {
+    match env::var("AUTH_URL") {
+        Ok(url) => {
+            log::info!("Authenticating nodes through the API server at: {}", url);
+            Arc::new(RemoteBackend::new(url))
+        }
+        Err(_) => {
+            let pepper = env::var("PEPPER").expect("PEPPER must be set");
+            log::info!("Authenticating nodes directly against the database");
+            Arc::new(DatabaseBackend::new(database, Arc::new(pepper)))
+        }
+    }
+}
+
 /// The internal state for the protocol.
 #[derive(Debug)]
 pub struct Handler<'a> {
     stream: &'a mut TcpStream,
+    auth: &'a dyn AuthBackend,
     buffer: [u8; 4096],
     current_msg: Option<ClientMessage>,
 }
 
 impl<'a> Handler<'a> {
-    /// Begins the protocol handling.
-    pub fn new(stream: &'a mut TcpStream) -> Self {
+    /// Begins the protocol handling, authenticating nodes with the given backend.
+    pub fn new(stream: &'a mut TcpStream, auth: &'a dyn AuthBackend) -> Self {
         Self {
             stream,
+            auth,
             buffer: [0_u8; 4096],
             current_msg: None,
         }
@@ -123,8 +160,8 @@ impl<'a> Handler<'a> {
     ///
     /// Begins the protocol either by getting a [`Message::NewModel`] and setting up the model for
     /// them along with the challenge response, or by instantly receiving a [`Message::AccessToken`]
-    /// from the user.
-    pub async fn get_access_token(&mut self) -> HandlerResult<Option<(String, String)>> {
+    /// from the user, along with any capabilities the node declares.
+    pub async fn get_access_token(&mut self) -> HandlerResult<Option<Credentials>> {
         let outcome = self.get_access_token_or_error().await;
 
         if let Err(err) = outcome.as_ref() {
@@ -135,19 +172,19 @@ impl<'a> Handler<'a> {
     }
 
     /// Wrapper method that tries to get the user's access token.
-    async fn get_access_token_or_error(&mut self) -> HandlerResult<Option<(String, String)>> {
+    async fn get_access_token_or_error(&mut self) -> HandlerResult<Option<Credentials>> {
         if let ClientMessage::NewModel { .. } = self.peek_message().await? {
             self.register_new_model().await?;
             self.authenticate_challenge_response().await?;
             return Ok(None);
         };
 
-        let (id, token) = self.verify_access_token().await?;
+        let credentials = self.verify_access_token().await?;
 
-        Ok(Some((id, token)))
+        Ok(Some(credentials))
     }
 
-    /// Registers a new model with the API server.
+    /// Registers a new model with the authentication backend.
     async fn register_new_model(&mut self) -> HandlerResult<()> {
         let (email, password, model_name) = match self.current_msg.take().unwrap() {
             ClientMessage::NewModel {
@@ -160,17 +197,8 @@ impl<'a> Handler<'a> {
 
         log::info!("Setting up a new model '{}' for: {}", model_name, email);
 
-        // Query the API server
-        let body = bson!({
-            "email": &email,
-            "password": &password,
-            "modelName": &model_name,
-        });
-
-        let endpoint = "/api/clients/models/new";
-        let text = get_response_text(endpoint, body).await?;
-
-        let message = RawMessage::new(text);
+        let challenge = self.auth.new_model(&email, &password, &model_name).await?;
+        let message = RawMessage::new(json!(AuthResponse::Challenge(challenge)).to_string());
 
         // Send the response back to the client
         self.respond(&message.as_bytes()).await?;
@@ -178,8 +206,9 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
-    /// Authenticates a user's challenge response with the API server.
+    /// Authenticates a user's challenge response with the authentication backend.
     async fn authenticate_challenge_response(&mut self) -> HandlerResult<()> {
+        let auth = self.auth;
         let (response, email, model_name) = match self.peek_message().await? {
             ClientMessage::ChallengeResponse {
                 response,
@@ -191,17 +220,8 @@ impl<'a> Handler<'a> {
 
         log::info!("Sending challenge response: {}", response);
 
-        // Query the API server
-        let body = bson!({
-            "modelName": &model_name,
-            "email": &email,
-            "challengeResponse": &response,
-        });
-
-        let endpoint = "/api/clients/models/verify";
-        let text = get_response_text(endpoint, body).await?;
-
-        let message = RawMessage::new(text);
+        let token = auth.verify_challenge(email, model_name, response).await?;
+        let message = RawMessage::new(json!(AuthResponse::AccessToken(token)).to_string());
 
         // Send the response back to the client
         self.stream.write(&message.as_bytes()).await?;
@@ -209,58 +229,25 @@ impl<'a> Handler<'a> {
         Ok(())
     }
 
-    /// Verifies a user's access token with the API server.
-    async fn verify_access_token(&mut self) -> HandlerResult<(String, String)> {
-        let (id, token) = match self.peek_message().await? {
-            ClientMessage::AccessToken { id, token } => (id.to_string(), token.to_string()),
+    /// Verifies a user's access token with the authentication backend.
+    async fn verify_access_token(&mut self) -> HandlerResult<Credentials> {
+        let (id, token, capabilities) = match self.peek_message().await? {
+            ClientMessage::AccessToken {
+                id,
+                token,
+                capabilities,
+            } => (id.to_string(), token.to_string(), capabilities.clone()),
             _ => unreachable!(),
         };
 
         log::info!("Verifying access token {} for model {}", token, id);
 
-        // Query the API server
-        let body = bson!({
-            "token": &token,
-        });
-
-        let endpoint = format!("/api/clients/models/{}/authenticate", &id);
-        let text = get_response_text(&endpoint, body).await?;
-
-        let message = RawMessage::new(text);
+        self.auth.authenticate_model(&id, &token).await?;
+        let message = RawMessage::new(json!({ "message": AUTHENTICATION_SUCCESSFUL }).to_string());
 
         // Send the response back to the client
         self.stream.write(&message.as_bytes()).await?;
 
-        Ok((id, token))
-    }
-}
-
-/// Queries the API server and returns the response text.
-async fn get_response_text<S: Debug + Serialize>(endpoint: &str, body: S) -> HandlerResult<String> {
-    #[cfg(test)]
-    let base = mockito::server_url();
-
-    #[cfg(not(test))]
-    let base = "http://localhost:3001";
-
-    let url = format!("{}{}", base, endpoint);
-
-    log::debug!("Sending: {:?} to {}", &body, &url);
-
-    let request = reqwest::Client::new().post(&url).json(&body);
-    let response = request.send().await?;
-    let status = response.status().clone();
-    let text = response.text().await?;
-
-    log::debug!("Response body: {:?}", text);
-
-    // Check the status code of the response
-    if !status.is_success() {
-        return Err(HandlerError::Server {
-            code: status.into(),
-            text: text,
-        });
+        Ok((id, token, capabilities))
     }
-
-    Ok(text)
 }
@@ -7,7 +7,8 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
 
 use crate::protocol;
-use messages::{ClientMessage, WriteLengthPrefix};
+use messages::{ClientMessage, NodeCapabilities, WriteLengthPrefix};
+use model_auth::{AuthError, RemoteBackend};
 
 #[tokio::test]
 async fn nodes_can_immediately_send_tokens() -> Result<(), Box<dyn Error>> {
@@ -30,7 +31,8 @@ async fn nodes_can_immediately_send_tokens() -> Result<(), Box<dyn Error>> {
         let mut stream = listener.accept().await.unwrap().0;
 
         // Setup the handler and get the access token
-        let mut handler = protocol::Handler::new(&mut stream);
+        let backend = RemoteBackend::new(mockito::server_url());
+        let mut handler = protocol::Handler::new(&mut stream, &backend);
         let token = handler.get_access_token().await.unwrap().unwrap().1;
 
         assert_eq!(token, "abc");
@@ -44,6 +46,7 @@ async fn nodes_can_immediately_send_tokens() -> Result<(), Box<dyn Error>> {
     let message = ClientMessage::AccessToken {
         id: String::from("5fe8b9d85511355cdab720aa"),
         token: String::from("abc"),
+        capabilities: NodeCapabilities::default(),
     };
 
     // Write our access token and shutdown the stream
@@ -71,7 +74,8 @@ async fn invalid_protocol_states_cause_panics() -> Result<(), Box<dyn Error>> {
         let mut stream = listener.accept().await.unwrap().0;
 
         // Setup the handler and get the access token
-        let mut handler = protocol::Handler::new(&mut stream);
+        let backend = RemoteBackend::new(mockito::server_url());
+        let mut handler = protocol::Handler::new(&mut stream, &backend);
         handler.get_access_token().await.unwrap();
     });
 
@@ -104,7 +108,8 @@ async fn incorrect_ordering_fails() -> Result<(), Box<dyn Error>> {
         let mut stream = listener.accept().await.unwrap().0;
 
         // Setup the handler and get the access token
-        let mut handler = protocol::Handler::new(&mut stream);
+        let backend = RemoteBackend::new(mockito::server_url());
+        let mut handler = protocol::Handler::new(&mut stream, &backend);
         handler.get_access_token().await.unwrap().unwrap().1;
     });
 
@@ -150,7 +155,8 @@ async fn protocol_cares_about_api_responses() -> Result<(), Box<dyn Error>> {
         let mut stream = listener.accept().await.unwrap().0;
 
         // Setup the handler and try to get the access token
-        let mut handler = protocol::Handler::new(&mut stream);
+        let backend = RemoteBackend::new(mockito::server_url());
+        let mut handler = protocol::Handler::new(&mut stream, &backend);
         let token = handler.get_access_token().await.unwrap().unwrap().1;
 
         assert_eq!(token, "abc");
@@ -164,6 +170,7 @@ async fn protocol_cares_about_api_responses() -> Result<(), Box<dyn Error>> {
     let message = ClientMessage::AccessToken {
         id: String::from("5fe8b9d85511355cdab720aa"),
         token: String::from("abc"),
+        capabilities: NodeCapabilities::default(),
     };
 
     // Write our access token and shutdown the stream
@@ -178,3 +185,18 @@ async fn protocol_cares_about_api_responses() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn expired_tokens_forward_the_new_challenge() {
+    let error = protocol::HandlerError::from(AuthError::Expired {
+        challenge: String::from("Y2hhbGxlbmdl"),
+    });
+
+    match error {
+        protocol::HandlerError::Server { code, text } => {
+            assert_eq!(code, 401);
+            assert_eq!(text, r#"{"challenge":"Y2hhbGxlbmdl"}"#);
+        }
+        _ => panic!("Expected a server error"),
+    }
+}
@@ -0,0 +1,132 @@
+//! Handles graceful shutdown of the DCL
+//!
+//! When the DCL receives a termination signal, it stops accepting new jobs and nodes and gives any
+//! running cluster a grace period to finish. Jobs that are still unfinished afterwards are handed
+//! back to the message bus so that they can be picked up again, before every connected node is
+//! told that the DCL is going away and has its model status set to `Stopped`.
+
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+use messages::{envelope, JobRequested, MessageBus};
+
+use crate::JobQueue;
+
+/// The default time given to running clusters to finish once shutdown begins
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(120);
+
+/// Shared flag used to tell each part of the DCL that it should stop
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    /// Sending half, used to begin the shutdown
+    sender: Arc<watch::Sender<bool>>,
+    /// Receiving half, cloned by anything waiting for the shutdown
+    receiver: watch::Receiver<bool>,
+    /// How long running clusters may continue for once the shutdown begins
+    grace_period: Duration,
+}
+
+impl Shutdown {
+    /// Creates a new [`Shutdown`] that allows clusters `grace_period` to finish
+    pub fn new(grace_period: Duration) -> Self {
+        let (sender, receiver) = watch::channel(false);
+
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+            grace_period,
+        }
+    }
+
+    /// Creates a new [`Shutdown`], reading the grace period from the environment
+    ///
+    /// Uses `SHUTDOWN_GRACE` as the number of seconds to give running clusters, falling back to
+    /// [`DEFAULT_GRACE_PERIOD`] if it is not set.
+    pub fn from_env() -> Self {
+        let grace_period = env::var("SHUTDOWN_GRACE")
+            .ok()
+            .map(|v| u64::from_str(&v).expect("SHUTDOWN_GRACE must be a u64"))
+            .map_or(DEFAULT_GRACE_PERIOD, Duration::from_secs);
+
+        Self::new(grace_period)
+    }
+
+    /// Begins the shutdown, waking anything waiting for it
+    pub fn trigger(&self) {
+        // This can only fail if there are no receivers, but we always hold one ourselves
+        let _ = self.sender.send(true);
+    }
+
+    /// Checks whether the shutdown has begun
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until the shutdown has begun
+    pub async fn triggered(&self) {
+        let mut receiver = self.receiver.clone();
+
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Waits until the grace period has passed after the shutdown began
+    pub async fn deadline(&self) {
+        self.triggered().await;
+        sleep(self.grace_period).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRACE_PERIOD)
+    }
+}
+
+/// Waits for the DCL to be asked to terminate, either through `SIGTERM` or `SIGINT`
+pub async fn signal_received() -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        _ = terminate.recv() => log::info!("Received SIGTERM"),
+        result = tokio::signal::ctrl_c() => {
+            result?;
+            log::info!("Received SIGINT");
+        }
+    }
+
+    Ok(())
+}
+
+/// Hands every job still in the [`JobQueue`] back to the [`MessageBus`]
+///
+/// This allows another instance of the DCL, or this one once it restarts, to pick them up again.
+pub async fn requeue_jobs(job_queue: &JobQueue, bus: &dyn MessageBus) {
+    let jobs = job_queue.drain();
+
+    log::info!("Requeuing {} unfinished job(s)", jobs.len());
+
+    for (project_id, _, job) in jobs {
+        log::debug!("Requeuing job_id={} for project_id={}", job.id, project_id);
+
+        let job_id = job.id.to_string();
+        let event = JobRequested::from(&job);
+
+        if let Err(e) = envelope::publish(bus, &job_id, &event, &job_id).await {
+            log::error!("Failed to requeue job_id={}: {}", job_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
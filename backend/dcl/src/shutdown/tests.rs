@@ -0,0 +1,67 @@
+use super::*;
+
+use mongodb::bson::oid::ObjectId;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+
+use messages::{envelope, InMemoryBus, JobRequested};
+use models::jobs::{Job, JobConfiguration};
+
+use crate::DatasetPair;
+
+#[tokio::test]
+async fn shutdown_is_not_triggered_by_default() {
+    let shutdown = Shutdown::default();
+
+    assert!(!shutdown.is_triggered());
+    assert!(timeout(Duration::from_millis(50), shutdown.triggered())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn triggering_wakes_all_clones() {
+    let shutdown = Shutdown::new(Duration::from_millis(0));
+    let clone = shutdown.clone();
+
+    let waiter = tokio::spawn(async move { clone.triggered().await });
+    shutdown.trigger();
+
+    assert!(shutdown.is_triggered());
+    assert!(timeout(Duration::from_millis(500), waiter).await.is_ok());
+}
+
+#[tokio::test]
+async fn deadline_waits_for_the_grace_period() {
+    let shutdown = Shutdown::new(Duration::from_millis(200));
+    shutdown.trigger();
+
+    assert!(timeout(Duration::from_millis(50), shutdown.deadline())
+        .await
+        .is_err());
+    assert!(timeout(Duration::from_millis(500), shutdown.deadline())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn unfinished_jobs_are_requeued_on_the_bus() -> Result<()> {
+    let bus = InMemoryBus::new();
+    let mut jobs = bus.subscribe("dcl", &["jobs"]).await?;
+
+    let job_queue = JobQueue::default();
+    let job = Job::new(JobConfiguration::default());
+    let job_id = job.id.to_string();
+    job_queue.push((ObjectId::new(), DatasetPair::default(), job));
+
+    requeue_jobs(&job_queue, &bus).await;
+
+    let message = jobs.next().await.unwrap().message;
+    let requeued = envelope::decode::<JobRequested>(&message.payload)?;
+
+    assert!(job_queue.is_empty());
+    assert_eq!(message.key.as_deref(), Some(job_id.as_str()));
+    assert_eq!(requeued.job_id.to_string(), job_id);
+
+    Ok(())
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use http::StatusCode;
+use mongodb::bson::{de::from_document, doc, oid::ObjectId, ser::to_document};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio_stream::StreamExt;
+
+use dcl::admin::{self, AdminState};
+use dcl::job_end::RunningCluster;
+use dcl::node_end::{NodeInfo, NodePool};
+use dcl::{DatasetPair, JobControl};
+use messages::{BusMessage, DeadLetter};
+use models::instances::Instance;
+use models::jobs::{Job, JobConfiguration};
+
+mod common;
+
+async fn admin_state() -> AdminState {
+    let (database, _) = common::initialise_with_db().await;
+
+    AdminState {
+        nodepool: Arc::new(NodePool::new(Arc::new(Notify::new()))),
+        database: Arc::new(database),
+        job_control: JobControl::new(),
+    }
+}
+
+#[tokio::test]
+async fn queued_jobs_are_listed_without_datasets() {
+    let state = admin_state().await;
+
+    let job = Job::new(JobConfiguration::default());
+    let project_id = ObjectId::new();
+    let dataset = DatasetPair {
+        train: String::from("secret"),
+        predict: String::from("secret"),
+    };
+
+    state
+        .job_control
+        .job_queue
+        .push((project_id.clone(), dataset, job.clone()));
+
+    let (status, body) = admin::route(&state, "GET", "/queue").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["job_id"], job.id.to_string());
+    assert_eq!(body[0]["project_id"], project_id.to_string());
+    assert!(!body.to_string().contains("secret"));
+}
+
+#[tokio::test]
+async fn running_clusters_are_listed() {
+    let state = admin_state().await;
+
+    state.job_control.clusters.insert(RunningCluster {
+        job_id: String::from("job"),
+        project_id: String::from("project"),
+        nodes: vec![String::from("model")],
+        started: 0,
+    });
+
+    let (status, body) = admin::route(&state, "GET", "/clusters").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["nodes"][0], "model");
+}
+
+#[tokio::test]
+async fn advertised_instances_are_listed() {
+    let state = admin_state().await;
+
+    Instance::advertise(&state.database, "advertised", 2, 3)
+        .await
+        .unwrap();
+
+    let (status, body) = admin::route(&state, "GET", "/instances").await;
+    let instances = body.as_array().unwrap();
+    let advertised = instances
+        .iter()
+        .find(|instance| instance["instance_id"] == "advertised")
+        .unwrap();
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(advertised["idle_nodes"], 2);
+    assert_eq!(advertised["total_nodes"], 3);
+}
+
+#[tokio::test]
+async fn nodes_are_listed_with_their_failures() {
+    let state = admin_state().await;
+
+    state
+        .nodepool
+        .info
+        .write()
+        .await
+        .insert(String::from("model"), NodeInfo::default());
+
+    state.nodepool.record_failure("model", "timeout").await;
+    state.nodepool.record_failure("model", "timeout").await;
+    state.nodepool.record_failure("model", "stream").await;
+
+    let (status, body) = admin::route(&state, "GET", "/nodes").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["model_id"], "model");
+    assert_eq!(body[0]["failures"]["timeout"], 2);
+    assert_eq!(body[0]["failures"]["stream"], 1);
+}
+
+#[tokio::test]
+async fn missing_nodes_cannot_be_evicted() {
+    let state = admin_state().await;
+
+    let (status, _) = admin::route(&state, "POST", "/nodes/missing/evict").await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn queued_jobs_cannot_be_requeued() {
+    let state = admin_state().await;
+
+    let job = Job::new(JobConfiguration::default());
+    let path = format!("/jobs/{}/requeue", job.id);
+
+    state
+        .job_control
+        .job_queue
+        .push((ObjectId::new(), DatasetPair::default(), job));
+
+    let (status, _) = admin::route(&state, "POST", &path).await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+async fn insert_job(state: &AdminState) -> Job {
+    let job = Job::new(JobConfiguration::default());
+
+    state
+        .database
+        .collection("jobs")
+        .insert_one(to_document(&job).unwrap(), None)
+        .await
+        .unwrap();
+
+    job
+}
+
+#[tokio::test]
+async fn jobs_leased_by_other_instances_cannot_be_requeued() {
+    let state = admin_state().await;
+    let job = insert_job(&state).await;
+
+    let claimed = job
+        .claim(&state.database, "other", chrono::Duration::seconds(60))
+        .await
+        .unwrap();
+    assert!(claimed);
+
+    let path = format!("/jobs/{}/requeue", job.id);
+    let (status, _) = admin::route(&state, "POST", &path).await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn completed_jobs_cannot_be_requeued() {
+    let state = admin_state().await;
+    let job = insert_job(&state).await;
+
+    let lease = chrono::Duration::seconds(60);
+    job.claim(&state.database, "dcl", lease).await.unwrap();
+    job.mark_as_processed(&state.database, "dcl").await.unwrap();
+
+    let path = format!("/jobs/{}/requeue", job.id);
+    let (status, _) = admin::route(&state, "POST", &path).await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn failed_jobs_can_be_requeued_and_keep_their_failure() {
+    let state = admin_state().await;
+    let job = insert_job(&state).await;
+    let mut jobs = state
+        .job_control
+        .bus
+        .subscribe("dcl", &["jobs"])
+        .await
+        .unwrap();
+
+    job.mark_as_failed(&state.database, "Failed").await.unwrap();
+
+    let path = format!("/jobs/{}/requeue", job.id);
+    let (status, _) = admin::route(&state, "POST", &path).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(jobs.next().await.is_some());
+
+    let document = state
+        .database
+        .collection("jobs")
+        .find_one(doc! { "_id": &job.id }, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let reset: Job = from_document(document).unwrap();
+
+    assert!(!reset.processed);
+    assert_eq!(reset.failure.as_deref(), Some("Failed"));
+}
+
+#[tokio::test]
+async fn unknown_routes_and_methods_are_rejected() {
+    let state = admin_state().await;
+
+    let (status, _) = admin::route(&state, "GET", "/unknown").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = admin::route(&state, "DELETE", "/nodes").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    let (status, _) = admin::route(&state, "GET", "/jobs/abc/requeue").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    let (status, _) = admin::route(&state, "GET", "/dlq/jobs/replay").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn dead_letters_can_be_replayed() {
+    let state = admin_state().await;
+    let bus = &state.job_control.bus;
+    let mut jobs = bus.subscribe("dcl", &["jobs"]).await.unwrap();
+
+    let message = BusMessage {
+        topic: String::from("jobs"),
+        key: Some(String::from("job")),
+        payload: b"{}".to_vec(),
+        timestamp: None,
+    };
+    let error = anyhow::anyhow!("invalid job");
+    DeadLetter::new("dcl", &message, &error)
+        .send(bus.as_ref())
+        .await
+        .unwrap();
+
+    let (status, body) = admin::route(&state, "POST", "/dlq/jobs/replay").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["replayed"], 1);
+
+    let replayed = jobs.next().await.unwrap().message;
+    assert_eq!(replayed.payload, b"{}");
+    assert_eq!(replayed.key.as_deref(), Some("job"));
+}
+
+#[tokio::test]
+async fn dead_letter_topics_cannot_be_replayed() {
+    let state = admin_state().await;
+
+    let (status, _) = admin::route(&state, "POST", "/dlq/jobs.dlq/replay").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn requests_are_served_over_http() {
+    let state = admin_state().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(admin::serve(listener, state));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /nodes HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let body_start = response.find("\r\n\r\n").unwrap() + 4;
+    let body: Value = serde_json::from_str(&response[body_start..]).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert_eq!(body, Value::Array(Vec::new()));
+}
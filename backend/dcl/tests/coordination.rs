@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use mongodb::bson::{doc, ser::to_document};
+use mongodb::Database;
+
+use dcl::coordination::{self, Claim, InstanceConfig};
+use models::jobs::{Job, JobConfiguration, JobReset};
+
+mod common;
+
+async fn insert_job(database: &Database) -> Job {
+    let job = Job::new(JobConfiguration::default());
+
+    database
+        .collection("jobs")
+        .insert_one(to_document(&job).unwrap(), None)
+        .await
+        .unwrap();
+
+    job
+}
+
+fn instance(id: &str, lease_secs: u64) -> InstanceConfig {
+    InstanceConfig::new(id, Duration::from_secs(lease_secs))
+}
+
+#[tokio::test]
+async fn only_one_instance_can_claim_a_job() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    let first = instance("first", 60);
+    let second = instance("second", 60);
+
+    let claim = coordination::claim(&database, &job, &first).await.unwrap();
+    assert_eq!(claim, Claim::Claimed);
+
+    let claim = coordination::claim(&database, &job, &second).await.unwrap();
+    assert_eq!(claim, Claim::Held);
+}
+
+#[tokio::test]
+async fn instances_cannot_claim_jobs_they_already_hold() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    let config = instance("first", 60);
+
+    let claim = coordination::claim(&database, &job, &config).await.unwrap();
+    assert_eq!(claim, Claim::Claimed);
+
+    let claim = coordination::claim(&database, &job, &config).await.unwrap();
+    assert_eq!(claim, Claim::Held);
+}
+
+#[tokio::test]
+async fn leases_can_only_be_renewed_by_their_holder() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    let config = instance("first", 60);
+    coordination::claim(&database, &job, &config).await.unwrap();
+
+    let renewed = job
+        .renew_lease(&database, "first", config.lease())
+        .await
+        .unwrap();
+    assert!(renewed);
+
+    let renewed = job
+        .renew_lease(&database, "second", config.lease())
+        .await
+        .unwrap();
+    assert!(!renewed);
+}
+
+#[tokio::test]
+async fn expired_leases_can_be_claimed_by_other_instances() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    // A lease of no time at all expires straight away
+    let first = instance("first", 0);
+    let second = instance("second", 60);
+
+    let claim = coordination::claim(&database, &job, &first).await.unwrap();
+    assert_eq!(claim, Claim::Claimed);
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let claim = coordination::claim(&database, &job, &second).await.unwrap();
+    assert_eq!(claim, Claim::Claimed);
+
+    // The original holder has lost the lease
+    let renewed = job
+        .renew_lease(&database, "first", first.lease())
+        .await
+        .unwrap();
+    assert!(!renewed);
+}
+
+#[tokio::test]
+async fn expired_leases_are_released() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    let config = instance("first", 0);
+    coordination::claim(&database, &job, &config).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let released = Job::release_expired_leases(&database).await.unwrap();
+    assert!(released >= 1);
+
+    let document = database
+        .collection("jobs")
+        .find_one(doc! { "_id": &job.id, "lease": null }, None)
+        .await
+        .unwrap();
+    assert!(document.is_some());
+}
+
+#[tokio::test]
+async fn processed_jobs_cannot_be_claimed() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    job.mark_as_failed(&database, "Failed").await.unwrap();
+
+    let claim = coordination::claim(&database, &job, &instance("first", 60))
+        .await
+        .unwrap();
+    assert_eq!(claim, Claim::Processed);
+}
+
+#[tokio::test]
+async fn only_the_lease_holder_can_mark_a_job_as_processed() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    let config = instance("first", 60);
+    coordination::claim(&database, &job, &config).await.unwrap();
+
+    assert!(job.mark_as_processed(&database, "second").await.is_err());
+    job.mark_as_processed(&database, "first").await.unwrap();
+
+    // The lease is released once the job is processed, so it cannot be marked again
+    assert!(job.mark_as_processed(&database, "first").await.is_err());
+
+    let claim = coordination::claim(&database, &job, &config).await.unwrap();
+    assert_eq!(claim, Claim::Processed);
+}
+
+#[tokio::test]
+async fn failed_attempts_are_counted_until_the_job_is_reset() {
+    let (database, _) = common::initialise_with_db().await;
+    let job = insert_job(&database).await;
+
+    assert_eq!(job.record_failed_attempt(&database).await.unwrap(), 1);
+    assert_eq!(job.record_failed_attempt(&database).await.unwrap(), 2);
+
+    let reset = Job::reset(&database, &job.id).await.unwrap();
+    assert!(matches!(reset, JobReset::Reset(job) if job.attempts == 0));
+}
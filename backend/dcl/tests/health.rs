@@ -0,0 +1,226 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mongodb::bson::{self, doc, oid::ObjectId};
+use mongodb::Database;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::time::timeout;
+
+use dcl::health::{check_health, health_runner, HealthConfig};
+use dcl::node_end::{Node, NodeInfo, NodePool};
+use dcl::Shutdown;
+use messages::{ClientMessage, ReadLengthPrefix, WriteLengthPrefix};
+use models::models::{ClientModel, Status};
+
+mod common;
+
+/// Connects a fake node to the pool, returning the node's end of the connection.
+async fn connect_node(nodepool: &NodePool, listener: &TcpListener, model_id: &str) -> TcpStream {
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    nodepool
+        .nodes
+        .write()
+        .await
+        .insert(model_id.to_string(), Node::new(server, model_id));
+    nodepool
+        .info
+        .write()
+        .await
+        .insert(model_id.to_string(), NodeInfo::default());
+    nodepool.active.fetch_add(1, Ordering::SeqCst);
+
+    client
+}
+
+/// Reads every heartbeat sent to a node, only responding while `responsive` is set.
+async fn respond_to_heartbeats(mut stream: TcpStream, responsive: Arc<AtomicBool>) {
+    let mut buffer = [0_u8; 64];
+
+    while let Ok(message) = ClientMessage::from_stream(&mut stream, &mut buffer).await {
+        if let ClientMessage::Alive { timestamp } = message {
+            if responsive.load(Ordering::SeqCst) {
+                let response = ClientMessage::Alive { timestamp };
+                stream.write_all(&response.as_bytes()).await.unwrap();
+            }
+        }
+    }
+}
+
+/// Builds a configuration that sweeps quickly and gives up on nodes after `failure_threshold`
+/// missed heartbeats.
+fn config(failure_threshold: u8) -> HealthConfig {
+    HealthConfig {
+        interval: Duration::from_millis(20),
+        timeout: Duration::from_millis(200),
+        failure_threshold,
+    }
+}
+
+/// Gets the status of a model in the database.
+async fn model_status(database: &Database, model_id: &str) -> Status {
+    let models = database.collection("models");
+    let filter = doc! { "_id": ObjectId::with_string(model_id).unwrap() };
+    let document = models.find_one(filter, None).await.unwrap().unwrap();
+    let model: ClientModel = bson::de::from_document(document).unwrap();
+
+    model.status
+}
+
+#[tokio::test]
+async fn silent_nodes_are_only_removed_after_the_failure_threshold() {
+    let (database, _) = common::initialise_with_db().await;
+    let database = Arc::new(database);
+
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let model_id = common::MODEL1_ID;
+    let config = config(3);
+
+    // Keep the connection open without ever responding
+    let _silent = connect_node(&nodepool, &listener, model_id).await;
+
+    for _ in 1..config.failure_threshold {
+        check_health(Arc::clone(&database), Arc::clone(&nodepool), config)
+            .await
+            .unwrap();
+
+        assert!(nodepool.nodes.read().await.contains_key(model_id));
+        assert!(!nodepool.info.read().await.get(model_id).unwrap().alive);
+        assert_eq!(nodepool.active.load(Ordering::SeqCst), 0);
+    }
+
+    assert!(!matches!(
+        model_status(&database, model_id).await,
+        Status::Stopped
+    ));
+
+    check_health(Arc::clone(&database), Arc::clone(&nodepool), config)
+        .await
+        .unwrap();
+
+    assert!(!nodepool.nodes.read().await.contains_key(model_id));
+    assert!(!nodepool.info.read().await.contains_key(model_id));
+    assert_eq!(nodepool.active.load(Ordering::SeqCst), 0);
+    assert!(matches!(
+        model_status(&database, model_id).await,
+        Status::Stopped
+    ));
+}
+
+#[tokio::test]
+async fn responding_resets_the_failure_count() {
+    let (database, _) = common::initialise_with_db().await;
+    let database = Arc::new(database);
+
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let model_id = common::MODEL2_ID;
+    let config = config(3);
+
+    let responsive = Arc::new(AtomicBool::new(false));
+    let stream = connect_node(&nodepool, &listener, model_id).await;
+    tokio::spawn(respond_to_heartbeats(stream, Arc::clone(&responsive)));
+
+    // Miss one fewer heartbeat than the threshold, respond once and then miss them again
+    for _ in 1..config.failure_threshold {
+        check_health(Arc::clone(&database), Arc::clone(&nodepool), config)
+            .await
+            .unwrap();
+    }
+
+    responsive.store(true, Ordering::SeqCst);
+    check_health(Arc::clone(&database), Arc::clone(&nodepool), config)
+        .await
+        .unwrap();
+
+    assert!(nodepool.info.read().await.get(model_id).unwrap().alive);
+    assert_eq!(nodepool.active.load(Ordering::SeqCst), 1);
+
+    responsive.store(false, Ordering::SeqCst);
+
+    for _ in 1..config.failure_threshold {
+        check_health(Arc::clone(&database), Arc::clone(&nodepool), config)
+            .await
+            .unwrap();
+    }
+
+    assert!(nodepool.nodes.read().await.contains_key(model_id));
+    assert!(!nodepool.info.read().await.get(model_id).unwrap().alive);
+}
+
+#[tokio::test]
+async fn silent_nodes_do_not_block_the_pool() {
+    let (database, _) = common::initialise_with_db().await;
+    let database = Arc::new(database);
+
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = HealthConfig {
+        timeout: Duration::from_secs(2),
+        ..config(10)
+    };
+
+    let _silent = connect_node(&nodepool, &listener, common::MODEL3_ID).await;
+
+    let check = tokio::spawn(check_health(
+        Arc::clone(&database),
+        Arc::clone(&nodepool),
+        config,
+    ));
+
+    // Wait until the heartbeat has been sent and the check is waiting for a response
+    tokio::time::sleep(config.jitter() + Duration::from_millis(100)).await;
+
+    let locks = async {
+        drop(nodepool.nodes.write().await);
+        drop(nodepool.info.write().await);
+        nodepool.capacity().await
+    };
+
+    let capacity = timeout(Duration::from_millis(100), locks)
+        .await
+        .expect("The pool was blocked by the health check");
+
+    assert_eq!(capacity, (1, 1));
+
+    check.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn health_checking_stops_when_shutting_down() {
+    let (database, _) = common::initialise_with_db().await;
+    let database = Arc::new(database);
+
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let shutdown = Shutdown::default();
+    let config = HealthConfig {
+        timeout: Duration::from_secs(30),
+        ..config(10)
+    };
+
+    // Leave a sweep waiting on a silent node, which should be abandoned
+    let _silent = connect_node(&nodepool, &listener, common::MODEL3_ID).await;
+
+    let runner = tokio::spawn(health_runner(
+        database,
+        Arc::clone(&nodepool),
+        config,
+        shutdown.clone(),
+    ));
+
+    tokio::time::sleep(config.jitter() + Duration::from_millis(100)).await;
+    shutdown.trigger();
+
+    timeout(Duration::from_secs(1), runner)
+        .await
+        .expect("Health checking continued after shutting down")
+        .unwrap();
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use mongodb::bson::{de::from_document, doc, oid::ObjectId, ser::to_document};
+use mongodb::Database;
+use tokio_stream::StreamExt;
+
+use dcl::interface_end::{self, fail_job, ingest, IngestionError};
+use dcl::JobControl;
+use messages::{envelope, DeadLetter, JobRequested};
+use models::datasets::Dataset;
+use models::jobs::{Job, JobConfiguration};
+use models::projects::{Project, Status};
+use models::users::User;
+
+mod common;
+
+const COST: i32 = 25;
+
+/// Inserts a user with a project that is waiting for a job, along with the job itself.
+async fn insert_processing_project(database: &Database) -> (User, Project, Job) {
+    let user = User::new("ingest@email.com", "hash", "Ingest", "User");
+
+    let mut project = Project::new("Ingestion", "Ingestion tests", vec![], user.id.clone());
+    project.status = Status::Processing {
+        model_success: 0,
+        model_err: 0,
+    };
+
+    let config = JobConfiguration {
+        project_id: project.id.clone(),
+        cost: COST,
+        ..JobConfiguration::default()
+    };
+    let job = Job::new(config);
+
+    database
+        .collection("users")
+        .insert_one(to_document(&user).unwrap(), None)
+        .await
+        .unwrap();
+    database
+        .collection("projects")
+        .insert_one(to_document(&project).unwrap(), None)
+        .await
+        .unwrap();
+    database
+        .collection("jobs")
+        .insert_one(to_document(&job).unwrap(), None)
+        .await
+        .unwrap();
+
+    (user, project, job)
+}
+
+async fn insert_dataset(database: &Database, project_id: &ObjectId) -> Dataset {
+    // The files themselves are never uploaded
+    let dataset = Dataset::new(project_id.clone(), ObjectId::new(), ObjectId::new());
+
+    database
+        .collection("datasets")
+        .insert_one(to_document(&dataset).unwrap(), None)
+        .await
+        .unwrap();
+
+    dataset
+}
+
+async fn find<T: serde::de::DeserializeOwned>(
+    database: &Database,
+    collection: &str,
+    id: &ObjectId,
+) -> T {
+    let document = database
+        .collection(collection)
+        .find_one(doc! { "_id": id }, None)
+        .await
+        .unwrap()
+        .unwrap();
+
+    from_document(document).unwrap()
+}
+
+#[tokio::test]
+async fn jobs_for_deleted_datasets_are_rejected() {
+    let (database, _) = common::initialise_with_db().await;
+    let (_, _, job) = insert_processing_project(&database).await;
+
+    let result = ingest(&database, &job).await;
+    assert!(matches!(result, Err(IngestionError::DatasetDeleted)));
+}
+
+#[tokio::test]
+async fn jobs_for_replaced_datasets_are_rejected() {
+    let (database, _) = common::initialise_with_db().await;
+    let (_, project, job) = insert_processing_project(&database).await;
+
+    insert_dataset(&database, &project.id).await;
+    let job = job.with_dataset(ObjectId::new());
+
+    let result = ingest(&database, &job).await;
+    assert!(matches!(result, Err(IngestionError::DatasetReplaced)));
+}
+
+#[tokio::test]
+async fn jobs_for_projects_that_are_not_processing_are_rejected() {
+    let (database, _) = common::initialise_with_db().await;
+    let (_, project, job) = insert_processing_project(&database).await;
+
+    database
+        .collection("projects")
+        .update_one(
+            doc! { "_id": &project.id },
+            doc! { "$set": { "status": Status::Unfinished } },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let result = ingest(&database, &job).await;
+    assert!(matches!(result, Err(IngestionError::NotProcessing)));
+}
+
+#[tokio::test]
+async fn jobs_that_have_been_handled_are_stale() {
+    let (database, _) = common::initialise_with_db().await;
+    let (_, _, job) = insert_processing_project(&database).await;
+
+    job.mark_as_failed(&database, "Already failed")
+        .await
+        .unwrap();
+
+    let result = ingest(&database, &job).await;
+    assert!(matches!(result, Err(IngestionError::Stale)));
+}
+
+#[tokio::test]
+async fn failed_ingestion_is_recorded_and_refunded() {
+    let (database, _) = common::initialise_with_db().await;
+    let (user, project, job) = insert_processing_project(&database).await;
+
+    let dataset = insert_dataset(&database, &project.id).await;
+    let job = job.with_dataset(dataset.id);
+
+    let error = ingest(&database, &job).await.unwrap_err();
+    assert!(matches!(error, IngestionError::FilesMissing));
+
+    let database = Arc::new(database);
+    fail_job(Arc::clone(&database), &JobControl::new(), &job, &error)
+        .await
+        .unwrap();
+
+    let failed: Job = find(&database, "jobs", &job.id).await;
+    assert!(failed.processed);
+    assert_eq!(failed.failure.as_deref(), Some(error.reason()));
+
+    let refunded: User = find(&database, "users", &user.id).await;
+    assert_eq!(refunded.credits, user.credits + COST);
+
+    // The project can be run again once the data is fixed
+    let project: Project = find(&database, "projects", &project.id).await;
+    assert!(matches!(project.status, Status::Ready));
+}
+
+#[tokio::test]
+async fn failing_a_job_twice_only_refunds_once() {
+    let (database, _) = common::initialise_with_db().await;
+    let (user, _, job) = insert_processing_project(&database).await;
+
+    let error = IngestionError::DatasetDeleted;
+    let database = Arc::new(database);
+    let job_control = JobControl::new();
+
+    // Simulates two consumers failing the same job
+    fail_job(Arc::clone(&database), &job_control, &job, &error)
+        .await
+        .unwrap();
+    fail_job(Arc::clone(&database), &job_control, &job, &error)
+        .await
+        .unwrap();
+
+    let refunded: User = find(&database, "users", &user.id).await;
+    assert_eq!(refunded.credits, user.credits + COST);
+}
+
+#[tokio::test]
+async fn unexpected_errors_are_dead_lettered_without_failing_the_job() {
+    let (database, _) = common::initialise_with_db().await;
+    let (user, project, job) = insert_processing_project(&database).await;
+
+    // A dataset that cannot be deserialized causes an unexpected error
+    database
+        .collection("datasets")
+        .insert_one(
+            doc! { "project_id": &project.id, "dataset": "malformed" },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let database = Arc::new(database);
+    let job_control = JobControl::new();
+    let bus = Arc::clone(&job_control.bus);
+    let mut dead_letters = bus.subscribe("inspect", &["jobs.dlq"]).await.unwrap();
+
+    let interface = tokio::spawn(interface_end::run(
+        Arc::clone(&database),
+        job_control.clone(),
+    ));
+
+    let job_id = job.id.to_string();
+    envelope::publish(bus.as_ref(), &job_id, &JobRequested::from(&job), &job_id)
+        .await
+        .unwrap();
+
+    let delivery = tokio::time::timeout(Duration::from_secs(5), dead_letters.next())
+        .await
+        .unwrap()
+        .unwrap();
+    let letter: DeadLetter = serde_json::from_slice(&delivery.message.payload).unwrap();
+    assert_eq!(letter.topic, "jobs");
+
+    job_control.shutdown.trigger();
+    interface.await.unwrap().unwrap();
+
+    // The job can still be run once the message is replayed
+    let unfailed: Job = find(&database, "jobs", &job.id).await;
+    assert!(!unfailed.processed);
+    assert!(unfailed.failure.is_none());
+
+    let unrefunded: User = find(&database, "users", &user.id).await;
+    assert_eq!(unrefunded.credits, user.credits);
+
+    let project: Project = find(&database, "projects", &project.id).await;
+    assert!(matches!(project.status, Status::Processing { .. }));
+}
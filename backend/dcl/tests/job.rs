@@ -9,8 +9,12 @@ use futures::stream::StreamExt;
 use mongodb::bson::{doc, oid::ObjectId};
 
 use dcl::job_end::ml::{evaluate_model, model_performance, penalise, weight_predictions};
-use dcl::job_end::{ClusterInfo, ModelID, WriteBackMemory};
-use models::jobs::{Job, JobConfiguration, PredictionType};
+use dcl::job_end::{
+    adjust_deadline, should_forward_progress, ClusterInfo, ModelID, NodeFailure, WriteBackMemory,
+    PROGRESS_INTERVAL,
+};
+use messages::InMemoryBus;
+use models::jobs::{Job, JobConfiguration, JobRequirements, PredictionType};
 use models::users::User;
 use utils::finance::reimburse;
 
@@ -75,6 +79,71 @@ async fn test_write_back_errors() {
     assert_eq!(&error, error_val);
 }
 
+#[test]
+fn deadlines_are_unchanged_without_reliable_progress() {
+    let base = Duration::from_secs(60);
+
+    assert_eq!(
+        Some(base),
+        adjust_deadline(base, Duration::from_secs(50), 0.0)
+    );
+    assert_eq!(
+        Some(base),
+        adjust_deadline(base, Duration::from_secs(50), 0.05)
+    );
+    assert_eq!(
+        Some(base),
+        adjust_deadline(base, Duration::from_secs(50), f64::NAN)
+    );
+}
+
+#[test]
+fn frequent_progress_is_only_forwarded_once_per_interval() {
+    let recently = Some((Duration::from_millis(10), "training"));
+    let a_while_ago = Some((PROGRESS_INTERVAL, "training"));
+
+    assert!(should_forward_progress(None, "training"));
+    assert!(!should_forward_progress(recently, "training"));
+    assert!(should_forward_progress(a_while_ago, "training"));
+
+    // Moving on to another stage is always forwarded
+    assert!(should_forward_progress(recently, "predicting"));
+}
+
+#[test]
+fn deadlines_are_extended_for_slightly_slow_nodes() {
+    let base = Duration::from_secs(60);
+
+    // Projected to take 70 seconds, which is within the allowed extension
+    let allowed = adjust_deadline(base, Duration::from_secs(35), 0.5);
+    assert_eq!(Some(Duration::from_secs(70)), allowed);
+
+    // Fast nodes keep their original deadline
+    let allowed = adjust_deadline(base, Duration::from_secs(10), 0.5);
+    assert_eq!(Some(base), allowed);
+}
+
+#[test]
+fn deadlines_are_cut_for_nodes_that_cannot_finish() {
+    let base = Duration::from_secs(60);
+
+    // Projected to take 200 seconds, far beyond the maximum extension
+    assert_eq!(None, adjust_deadline(base, Duration::from_secs(40), 0.2));
+}
+
+#[test]
+fn only_broken_connections_are_fatal() {
+    assert!(NodeFailure::Stream(anyhow::anyhow!("Connection reset")).is_fatal());
+
+    // Nodes that go quiet or run slowly may still be alive
+    assert!(!NodeFailure::Unresponsive.is_fatal());
+    assert!(!NodeFailure::Timeout.is_fatal());
+    assert!(!NodeFailure::TooSlow.is_fatal());
+
+    // Nodes that report their own failure are still connected
+    assert!(!NodeFailure::Failed(String::from("Out of memory")).is_fatal());
+}
+
 #[test]
 fn test_evaluate_model() {
     let id = ModelID::from("ModelID1");
@@ -114,6 +183,7 @@ fn test_evaluate_model() {
         prediction_column: "".to_string(),
         prediction_type: PredictionType::Classification,
         cost: 0,
+        requirements: JobRequirements::default(),
     };
 
     let info = ClusterInfo {
@@ -123,6 +193,9 @@ fn test_evaluate_model() {
         validation_ans,
         prediction_rids,
         node_computation_time: Duration::from_secs(6000),
+        bus: Arc::new(InMemoryBus::new()),
+        mailer: None,
+        instance_id: String::from("dcl"),
     };
 
     let predictions = "2,3\n3,2\n4,1\n5,0\n6,0\n7,0\n8,0".to_owned();
@@ -199,6 +272,7 @@ fn test_weight_predictions() {
         prediction_column: "".to_string(),
         prediction_type: PredictionType::Classification,
         cost: 0,
+        requirements: JobRequirements::default(),
     };
 
     let info = ClusterInfo {
@@ -208,6 +282,9 @@ fn test_weight_predictions() {
         validation_ans: validation_ans.clone(),
         prediction_rids: prediction_rids.clone(),
         node_computation_time: Duration::from_secs(6000),
+        bus: Arc::new(InMemoryBus::new()),
+        mailer: None,
+        instance_id: String::from("dcl"),
     };
 
     let mut model_predictions: HashMap<(ModelID, usize), String> = HashMap::new();
@@ -239,6 +316,7 @@ fn test_weight_predictions() {
         prediction_column: "".to_string(),
         prediction_type: PredictionType::Regression,
         cost: 0,
+        requirements: JobRequirements::default(),
     };
 
     let info = ClusterInfo {
@@ -248,6 +326,9 @@ fn test_weight_predictions() {
         validation_ans,
         prediction_rids,
         node_computation_time: Duration::from_secs(6000),
+        bus: Arc::new(InMemoryBus::new()),
+        mailer: None,
+        instance_id: String::from("dcl"),
     };
 
     let mut model_predictions: HashMap<(ModelID, usize), String> = HashMap::new();
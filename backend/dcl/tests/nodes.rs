@@ -1,4 +1,50 @@
-use dcl::node_end::NodePool;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::time::{timeout, Instant};
+
+use dcl::node_end::{Node, NodeInfo, NodePool, JOB_OFFER_TIMEOUT};
+use messages::{ClientMessage, ReadLengthPrefix, WriteLengthPrefix};
+use models::jobs::JobConfiguration;
+
+/// Connects a fake node to the pool, returning the node's end of the connection.
+async fn connect_node(nodepool: &NodePool, listener: &TcpListener, model_id: &str) -> TcpStream {
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+
+    nodepool
+        .nodes
+        .write()
+        .await
+        .insert(model_id.to_string(), Node::new(server, model_id));
+    nodepool
+        .info
+        .write()
+        .await
+        .insert(model_id.to_string(), NodeInfo::default());
+    nodepool.active.fetch_add(1, Ordering::SeqCst);
+
+    client
+}
+
+/// Responds to a single job offer on the given stream.
+async fn respond_to_offer(mut stream: TcpStream, accept: bool) -> TcpStream {
+    let mut buffer = [0_u8; 1024];
+    let offer = ClientMessage::from_stream(&mut stream, &mut buffer)
+        .await
+        .unwrap();
+    assert!(matches!(offer, ClientMessage::JobConfig { .. }));
+
+    let response = ClientMessage::ConfigResponse { accept };
+    stream.write_all(&response.as_bytes()).await.unwrap();
+
+    stream
+}
 
 #[tokio::test]
 pub async fn test_choose_random_model() {
@@ -32,3 +78,61 @@ pub async fn test_choose_random_model() {
     let taken = NodePool::choose_random_node(&mut nodes, &mut better_nodes, cluster_performance);
     assert_eq!(better_nodes.contains(&taken), nodes.contains(&taken));
 }
+
+#[tokio::test]
+async fn clusters_are_built_without_waiting_for_silent_nodes() {
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let eager = connect_node(&nodepool, &listener, "eager").await;
+    let _silent = connect_node(&nodepool, &listener, "silent").await;
+
+    let eager = tokio::spawn(respond_to_offer(eager, true));
+
+    let config = JobConfiguration {
+        cluster_size: 1,
+        ..JobConfiguration::default()
+    };
+
+    let start = Instant::now();
+    let cluster = nodepool.build_cluster(config).await.unwrap();
+
+    assert!(start.elapsed() < JOB_OFFER_TIMEOUT);
+    assert!(cluster.contains_key("eager"));
+
+    // The silent node should be released once its offer expires
+    let _eager = eager.await.unwrap();
+    tokio::time::sleep(JOB_OFFER_TIMEOUT + Duration::from_millis(500)).await;
+
+    assert!(!nodepool.info.read().await.get("silent").unwrap().using);
+    assert!(nodepool.info.read().await.get("eager").unwrap().using);
+}
+
+#[tokio::test]
+async fn clusters_are_not_built_when_too_few_nodes_accept() {
+    let nodepool = Arc::new(NodePool::new(Arc::new(Notify::new())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let first = connect_node(&nodepool, &listener, "first").await;
+    let second = connect_node(&nodepool, &listener, "second").await;
+
+    let first = tokio::spawn(respond_to_offer(first, true));
+    let second = tokio::spawn(respond_to_offer(second, false));
+
+    let config = JobConfiguration {
+        cluster_size: 2,
+        ..JobConfiguration::default()
+    };
+
+    let cluster = timeout(
+        JOB_OFFER_TIMEOUT + Duration::from_millis(500),
+        nodepool.build_cluster(config),
+    )
+    .await
+    .unwrap();
+
+    assert!(cluster.is_none());
+    assert_eq!(nodepool.active.load(Ordering::SeqCst), 2);
+
+    let _streams = (first.await.unwrap(), second.await.unwrap());
+}
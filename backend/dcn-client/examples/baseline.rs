@@ -0,0 +1,62 @@
+//! Runs a compute node using the [`Baseline`] model.
+//!
+//! The node is configured through the environment:
+//!
+//! |      Variable      |                        Meaning                         |
+//! |--------------------|--------------------------------------------------------|
+//! |   `DCL_ADDRESS`    | The address of the DCL (default `127.0.0.1:7000`)      |
+//! |      `EMAIL`       | The email address of the client that owns the model    |
+//! |    `MODEL_NAME`    | The name of the model                                  |
+//! |   `PRIVATE_KEY`    | The path to the client's private key                   |
+//! |   `CREDENTIALS`    | Where to store the access token (default `token.json`) |
+//! |     `PASSWORD`     | The client's password, only needed to register         |
+//!
+//! Run `cargo run --example baseline register` to register the model, unlock it on the website
+//! and then run `cargo run --example baseline` to start serving jobs.
+
+use std::env;
+
+use anyhow::{Context, Result};
+
+use dcn_client::{Baseline, CredentialStore, Node, NodeConfig};
+
+fn var(name: &str) -> Result<String> {
+    env::var(name).with_context(|| format!("{} must be set", name))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let filters = vec![
+        ("baseline", log::LevelFilter::Info),
+        ("dcn_client", log::LevelFilter::Debug),
+    ];
+
+    utils::setup_logger_with_filters(filters);
+
+    let address = env::var("DCL_ADDRESS").unwrap_or_else(|_| String::from("127.0.0.1:7000"));
+    let credentials = env::var("CREDENTIALS").unwrap_or_else(|_| String::from("token.json"));
+    let private_key_path = var("PRIVATE_KEY")?;
+    let private_key = tokio::fs::read_to_string(&private_key_path)
+        .await
+        .with_context(|| format!("Failed to read the private key at {}", private_key_path))?;
+
+    let config = NodeConfig::new(
+        address,
+        var("EMAIL")?,
+        var("MODEL_NAME")?,
+        private_key,
+        CredentialStore::new(credentials),
+    );
+
+    let node = Node::new(config, Baseline::new());
+
+    match env::args().nth(1).as_deref() {
+        Some("register") => {
+            let token = node.register(&var("PASSWORD")?).await?;
+            log::info!("Unlock model_id={} on the website to start", token.id);
+        }
+        _ => node.run_forever().await?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! A baseline model that ignores all features of a dataset.
+//!
+//! For classification problems the most common class in the training data is predicted for every
+//! record, and for regression problems the mean of the training data is predicted. This is useful
+//! for testing nodes end to end and as a lower bound for the performance of real models.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use csv::{Reader, StringRecord, Writer};
+
+use models::jobs::PredictionType;
+
+use crate::{JobConfig, Model, Progress};
+
+/// A model that always predicts the majority class or the mean value
+#[derive(Debug, Default)]
+pub struct Baseline {
+    headers: StringRecord,
+    records: Vec<StringRecord>,
+}
+
+impl Baseline {
+    /// Creates a new [`Baseline`] model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Computes the single prediction for every record from the training values of a column
+    fn prediction(&self, column: usize, prediction_type: PredictionType) -> Result<String> {
+        let values = self
+            .records
+            .iter()
+            .filter_map(|record| record.get(column))
+            .filter(|value| !value.is_empty());
+
+        match prediction_type {
+            PredictionType::Classification => {
+                let mut counts: HashMap<&str, usize> = HashMap::new();
+
+                for value in values {
+                    *counts.entry(value).or_insert(0) += 1;
+                }
+
+                // Break ties by the value itself so that predictions are deterministic
+                counts
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+                    .map(|(value, _)| value.to_string())
+                    .ok_or_else(|| anyhow!("The training data has no values to predict"))
+            }
+            PredictionType::Regression => {
+                let values = values
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if values.is_empty() {
+                    return Err(anyhow!("The training data has no values to predict"));
+                }
+
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                Ok(mean.to_string())
+            }
+        }
+    }
+}
+
+/// Finds the column that is empty for every record, which is the one to predict
+///
+/// The DCL anonymises column names, so this is the only reliable way for a model to know which
+/// column of the training data it should learn.
+pub fn prediction_column(headers: &StringRecord, records: &[StringRecord]) -> Option<usize> {
+    (0..headers.len())
+        .filter(|&i| headers.get(i) != Some("record_id"))
+        .find(|&i| {
+            records
+                .iter()
+                .all(|record| record.get(i).map_or(true, str::is_empty))
+        })
+}
+
+impl Model for Baseline {
+    fn train(&mut self, train: &str, _config: &JobConfig, progress: &Progress) -> Result<()> {
+        let mut reader = Reader::from_reader(train.as_bytes());
+
+        self.headers = reader.headers()?.clone();
+        self.records = reader.records().collect::<Result<_, _>>()?;
+
+        progress.report("training", 0.5, "Read the training data");
+
+        Ok(())
+    }
+
+    fn predict(
+        &mut self,
+        predict: &str,
+        config: &JobConfig,
+        progress: &Progress,
+    ) -> Result<String> {
+        let mut reader = Reader::from_reader(predict.as_bytes());
+
+        let headers = reader.headers()?.clone();
+        let records: Vec<StringRecord> = reader.records().collect::<Result<_, _>>()?;
+
+        let column = prediction_column(&headers, &records)
+            .ok_or_else(|| anyhow!("Failed to find the column to predict"))?;
+        let name = &headers[column];
+
+        // The training data may order its columns differently
+        let train_column = self
+            .headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| anyhow!("The training data has no column named {}", name))?;
+
+        let prediction = self.prediction(train_column, config.prediction_type)?;
+        let record_id = headers
+            .iter()
+            .position(|header| header == "record_id")
+            .ok_or_else(|| anyhow!("The prediction data has no record identifiers"))?;
+
+        let mut writer = Writer::from_writer(vec![]);
+        writer.write_record(&["record_id", name])?;
+
+        for record in &records {
+            writer.write_record(&[&record[record_id], prediction.as_str()])?;
+        }
+
+        progress.report("predicting", 1.0, "Computed all predictions");
+
+        let bytes = writer.into_inner().map_err(|e| anyhow!("{}", e))?;
+
+        Ok(String::from_utf8(bytes)?)
+    }
+}
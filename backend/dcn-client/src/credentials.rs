@@ -0,0 +1,94 @@
+//! Stores the access tokens of models between runs.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+use model_auth::Token;
+
+/// Where a [`CredentialStore`] keeps its token
+#[derive(Clone, Debug)]
+enum Storage {
+    File(PathBuf),
+    Memory(Arc<Mutex<Option<Token>>>),
+}
+
+/// Stores the access token for a model, either as JSON on disk or in memory
+#[derive(Clone, Debug)]
+pub struct CredentialStore {
+    storage: Storage,
+}
+
+impl CredentialStore {
+    /// Creates a new [`CredentialStore`] using the file at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            storage: Storage::File(path.into()),
+        }
+    }
+
+    /// Creates a new [`CredentialStore`] that only keeps the token in memory
+    ///
+    /// This is useful for nodes whose models are created programmatically, such as in tests.
+    pub fn in_memory(token: Option<Token>) -> Self {
+        Self {
+            storage: Storage::Memory(Arc::new(Mutex::new(token))),
+        }
+    }
+
+    /// Loads the stored access token, if one has been saved
+    pub async fn load(&self) -> Result<Option<Token>> {
+        let path = match &self.storage {
+            Storage::File(path) => path,
+            Storage::Memory(token) => return Ok(lock(token)?.clone()),
+        };
+
+        match tokio::fs::read(path).await {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves an access token, replacing any existing one
+    pub async fn save(&self, token: &Token) -> Result<()> {
+        let path = match &self.storage {
+            Storage::File(path) => path,
+            Storage::Memory(stored) => {
+                *lock(stored)? = Some(token.clone());
+                return Ok(());
+            }
+        };
+
+        let contents = serde_json::to_vec_pretty(token)?;
+        tokio::fs::write(path, contents).await?;
+
+        log::info!("Saved the access token to {}", path.display());
+
+        Ok(())
+    }
+
+    /// Removes the stored access token, such as after it has been rejected
+    pub async fn clear(&self) -> Result<()> {
+        let path = match &self.storage {
+            Storage::File(path) => path,
+            Storage::Memory(stored) => {
+                *lock(stored)? = None;
+                return Ok(());
+            }
+        };
+
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Locks an in memory token
+fn lock(token: &Mutex<Option<Token>>) -> Result<std::sync::MutexGuard<'_, Option<Token>>> {
+    token
+        .lock()
+        .map_err(|_| anyhow!("The credential store was poisoned"))
+}
@@ -0,0 +1,20 @@
+//! Encodes datasets and predictions as they are sent between the DCL and nodes.
+//!
+//! Data is compressed with BZip2 and then encoded with Base64 in both directions.
+
+use anyhow::Result;
+
+use utils::compress::{compress_data, decompress_data};
+
+/// Compresses and encodes data to be sent to the DCL.
+pub fn encode(data: &str) -> Result<String> {
+    let compressed = compress_data(data)?;
+    Ok(base64::encode(compressed))
+}
+
+/// Decodes and decompresses data received from the DCL.
+pub fn decode(data: &str) -> Result<String> {
+    let decoded = base64::decode(data)?;
+    let decompressed = decompress_data(&decoded)?;
+    Ok(String::from_utf8(decompressed)?)
+}
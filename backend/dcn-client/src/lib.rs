@@ -0,0 +1,124 @@
+//! A reference client for Distributed Compute Nodes (DCNs) in the Sybl project.
+//!
+//! Compute nodes connect to the DCL, authenticate their model and then wait for jobs. This crate
+//! implements the node side of that protocol, such that a node only needs to implement the
+//! [`Model`] trait and pass it to a [`Node`]. The node takes care of registering models and signing
+//! their challenges, storing access tokens, responding to heartbeats and encoding datasets.
+
+#![warn(rust_2018_idioms)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_docs)]
+
+#[macro_use]
+extern crate serde;
+
+use anyhow::Result;
+use tokio::sync::mpsc::UnboundedSender;
+
+use messages::ClientMessage;
+use models::jobs::PredictionType;
+
+pub mod baseline;
+pub mod credentials;
+pub mod encoding;
+pub mod node;
+
+pub use baseline::Baseline;
+pub use credentials::CredentialStore;
+pub use node::{Disconnect, Node, NodeConfig};
+
+/// The configuration of a job offered to a node by the DCL
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobConfig {
+    /// The current time according to the DCL, as a Unix timestamp
+    pub message_creation_timestamp: i64,
+    /// The Unix timestamp by which predictions must be returned
+    pub prediction_cutoff_timestamp: i64,
+    /// The number of nodes the job will run on
+    pub cluster_size: i32,
+    /// The types of each column in the dataset
+    pub column_types: Vec<String>,
+    /// The name of the column to predict
+    pub prediction_column: String,
+    /// Whether the problem is classification or regression
+    pub prediction_type: PredictionType,
+}
+
+impl JobConfig {
+    /// Extracts the configuration from a [`ClientMessage::JobConfig`] message
+    pub fn from_message(message: ClientMessage) -> Option<Self> {
+        match message {
+            ClientMessage::JobConfig {
+                message_creation_timestamp,
+                prediction_cutoff_timestamp,
+                cluster_size,
+                column_types,
+                prediction_column,
+                prediction_type,
+            } => Some(Self {
+                message_creation_timestamp,
+                prediction_cutoff_timestamp,
+                cluster_size,
+                column_types,
+                prediction_column,
+                prediction_type,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Gets the number of seconds the node has to compute its predictions
+    pub fn time_allowed(&self) -> i64 {
+        self.prediction_cutoff_timestamp - self.message_creation_timestamp
+    }
+}
+
+/// Reports the progress of a model to the DCL while it computes
+///
+/// The DCL uses progress updates to extend the deadline for nodes that are making progress, so
+/// models with long running training should report it periodically.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    sender: UnboundedSender<ClientMessage>,
+}
+
+impl Progress {
+    /// Creates a new [`Progress`] that forwards updates through `sender`
+    pub fn new(sender: UnboundedSender<ClientMessage>) -> Self {
+        Self { sender }
+    }
+
+    /// Reports that the model has completed `fraction` of the job, between 0 and 1
+    pub fn report(&self, stage: &str, fraction: f64, message: &str) {
+        let update = ClientMessage::Progress {
+            stage: stage.to_string(),
+            fraction: fraction.max(0.0).min(1.0),
+            message: message.to_string(),
+        };
+
+        // The connection may have closed, in which case the update no longer matters
+        if self.sender.send(update).is_err() {
+            log::debug!("Dropping a progress update as the connection has closed");
+        }
+    }
+}
+
+/// A machine learning model that can compute predictions for the DCL
+///
+/// Datasets are provided as CSV, with a `record_id` column followed by anonymised columns. The
+/// prediction column is left empty in the prediction dataset, and predictions must be returned as
+/// CSV with a header followed by `record_id,prediction` rows. Training and predicting run on a
+/// blocking thread, so models can perform expensive computation directly.
+pub trait Model: Send + 'static {
+    /// Decides whether to accept a job, which defaults to accepting all of them
+    fn accept_job(&mut self, _config: &JobConfig) -> bool {
+        true
+    }
+
+    /// Trains the model on the training dataset for a job
+    fn train(&mut self, train: &str, config: &JobConfig, progress: &Progress) -> Result<()>;
+
+    /// Computes predictions for the prediction dataset of a job
+    fn predict(&mut self, predict: &str, config: &JobConfig, progress: &Progress)
+        -> Result<String>;
+}
@@ -0,0 +1,434 @@
+//! Connects a [`Model`] to the DCL and serves jobs for it.
+//!
+//! Nodes first register their model, signing the challenge issued by the DCL with the client's
+//! private key and storing the access token they receive. The model must then be unlocked on the
+//! website, after which the node can authenticate with its token and serve jobs until the DCL
+//! closes the connection.
+
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+use messages::{ClientMessage, NodeCapabilities, ReadLengthPrefix, WriteLengthPrefix};
+use model_auth::{AuthResponse, Token, AUTHENTICATION_SUCCESSFUL};
+
+use crate::credentials::CredentialStore;
+use crate::encoding::{decode, encode};
+use crate::{JobConfig, Model, Progress};
+
+/// The default time to wait before reconnecting to the DCL
+pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Errors that mean a node cannot authenticate until the user takes action
+#[derive(Debug, Error)]
+pub enum AuthenticationError {
+    /// No access token has been stored for the model
+    #[error("No access token is stored, so the model must be registered first")]
+    Unregistered,
+    /// The model has not been unlocked on the website
+    #[error("The model is locked and must be unlocked on the website")]
+    Locked,
+    /// The access token for the model has expired
+    #[error("The access token has expired, so the model must be registered again")]
+    Expired,
+    /// The DCL rejected the request for another reason
+    #[error("The DCL rejected the request with status={code}: {text}")]
+    Rejected {
+        /// The HTTP status code corresponding to the error
+        code: u16,
+        /// The body describing the error
+        text: String,
+    },
+}
+
+/// The errors sent by the DCL when authentication fails
+#[derive(Debug, Deserialize)]
+enum HandlerError {
+    Server { code: u16, text: String },
+}
+
+/// The replies the DCL can send while a node authenticates
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AuthReply {
+    Response(AuthResponse),
+    Message { message: String },
+    Error(HandlerError),
+}
+
+impl AuthReply {
+    /// Converts a reply that was not expected into the error it represents
+    fn into_error(self) -> anyhow::Error {
+        let (code, text) = match self {
+            Self::Error(HandlerError::Server { code, text }) => (code, text),
+            other => return anyhow!("Received an unexpected reply from the DCL: {:?}", other),
+        };
+
+        let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+
+        if body.get("challenge").is_some() {
+            AuthenticationError::Expired.into()
+        } else if body["message"] == "Locked" {
+            AuthenticationError::Locked.into()
+        } else {
+            AuthenticationError::Rejected { code, text }.into()
+        }
+    }
+}
+
+/// Why a node stopped serving jobs
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Disconnect {
+    /// The DCL is shutting down and the node should reconnect later
+    Shutdown,
+    /// The connection was closed
+    Closed,
+}
+
+/// The configuration for connecting to the DCL
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    /// The address of the DCL, such as `127.0.0.1:7000`
+    pub address: String,
+    /// The email address of the client that owns the model
+    pub email: String,
+    /// The name of the model
+    pub model_name: String,
+    /// The client's PEM-encoded private key, used to sign challenges
+    pub private_key: String,
+    /// Where the access token for the model is stored
+    pub credentials: CredentialStore,
+    /// The capabilities the node declares when authenticating
+    pub capabilities: NodeCapabilities,
+    /// How long to wait before reconnecting to the DCL
+    pub reconnect_delay: Duration,
+}
+
+impl NodeConfig {
+    /// Creates a new [`NodeConfig`] that declares no limits, only that heartbeats are answered
+    /// while busy
+    pub fn new(
+        address: impl Into<String>,
+        email: impl Into<String>,
+        model_name: impl Into<String>,
+        private_key: impl Into<String>,
+        credentials: CredentialStore,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            email: email.into(),
+            model_name: model_name.into(),
+            private_key: private_key.into(),
+            credentials,
+            capabilities: NodeCapabilities {
+                busy_heartbeats: true,
+                ..NodeCapabilities::default()
+            },
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+        }
+    }
+}
+
+/// A compute node that serves jobs from the DCL using a [`Model`]
+#[derive(Debug)]
+pub struct Node<M> {
+    config: NodeConfig,
+    model: Arc<Mutex<M>>,
+}
+
+impl<M: Model> Node<M> {
+    /// Creates a new [`Node`] for a model
+    pub fn new(config: NodeConfig, model: M) -> Self {
+        Self {
+            config,
+            model: Arc::new(Mutex::new(model)),
+        }
+    }
+
+    /// Registers the model with the DCL, storing the access token it receives.
+    ///
+    /// The DCL issues a challenge which is signed with the client's private key. Once the response
+    /// is verified, the model must be unlocked on the website before the node can serve jobs.
+    pub async fn register(&self, password: &str) -> Result<Token> {
+        let config = &self.config;
+        let mut stream = TcpStream::connect(&config.address).await?;
+        let mut buffer = [0_u8; 4096];
+
+        log::info!("Registering model_name={} with the DCL", config.model_name);
+
+        let message = ClientMessage::NewModel {
+            email: config.email.clone(),
+            password: password.to_string(),
+            model_name: config.model_name.clone(),
+        };
+        stream.write_all(&message.as_bytes()).await?;
+
+        let challenge = match AuthReply::from_stream(&mut stream, &mut buffer).await? {
+            AuthReply::Response(AuthResponse::Challenge(challenge)) => challenge,
+            other => return Err(other.into_error()),
+        };
+
+        let decoded = base64::decode(&challenge.challenge)?;
+        let signed = crypto::sign_challenge(&decoded, &config.private_key)?;
+
+        let message = ClientMessage::ChallengeResponse {
+            email: config.email.clone(),
+            model_name: config.model_name.clone(),
+            response: base64::encode(signed),
+        };
+        stream.write_all(&message.as_bytes()).await?;
+
+        let token = match AuthReply::from_stream(&mut stream, &mut buffer).await? {
+            AuthReply::Response(AuthResponse::AccessToken(token)) => token,
+            other => return Err(other.into_error()),
+        };
+
+        config.credentials.save(&token).await?;
+
+        log::info!(
+            "Registered model_id={}, which must now be unlocked on the website",
+            token.id
+        );
+
+        Ok(token)
+    }
+
+    /// Connects to the DCL, authenticates and serves jobs until the connection ends.
+    pub async fn run(&self) -> Result<Disconnect> {
+        let mut stream = TcpStream::connect(&self.config.address).await?;
+
+        self.authenticate(&mut stream).await?;
+        self.serve(stream).await
+    }
+
+    /// Runs the node, reconnecting whenever the connection ends.
+    ///
+    /// Only returns if the node cannot authenticate, as this requires action from the user.
+    pub async fn run_forever(&self) -> Result<()> {
+        loop {
+            match self.run().await {
+                Ok(Disconnect::Shutdown) => log::info!("The DCL is shutting down"),
+                Ok(Disconnect::Closed) => log::info!("The DCL closed the connection"),
+                Err(e) if e.is::<AuthenticationError>() => return Err(e),
+                Err(e) => log::warn!("Lost the connection to the DCL: {}", e),
+            }
+
+            log::info!("Reconnecting in {:?}", self.config.reconnect_delay);
+            tokio::time::sleep(self.config.reconnect_delay).await;
+        }
+    }
+
+    /// Authenticates with the DCL using the stored access token.
+    async fn authenticate(&self, stream: &mut TcpStream) -> Result<()> {
+        let credentials = &self.config.credentials;
+        let token = credentials
+            .load()
+            .await?
+            .ok_or(AuthenticationError::Unregistered)?;
+
+        log::info!("Authenticating as model_id={}", token.id);
+
+        let message = ClientMessage::AccessToken {
+            id: token.id.clone(),
+            token: token.token.clone(),
+            capabilities: self.config.capabilities.clone(),
+        };
+        stream.write_all(&message.as_bytes()).await?;
+
+        let mut buffer = [0_u8; 4096];
+
+        match AuthReply::from_stream(stream, &mut buffer).await? {
+            AuthReply::Message { message } if message == AUTHENTICATION_SUCCESSFUL => Ok(()),
+            other => {
+                let error = other.into_error();
+
+                // Expired tokens can never be used again
+                let expired = error.downcast_ref::<AuthenticationError>();
+
+                if let Some(AuthenticationError::Expired) = expired {
+                    credentials.clear().await?;
+                }
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Serves heartbeats and jobs from the DCL until the connection ends.
+    ///
+    /// Messages are read on a separate task, so that heartbeats are answered and progress is
+    /// reported while the model computes on a blocking thread. Computations cannot be cancelled,
+    /// so one started before the connection was lost keeps the model busy after reconnecting, and
+    /// job offers are declined until it finishes.
+    async fn serve(&self, stream: TcpStream) -> Result<Disconnect> {
+        let (reader, mut writer) = stream.into_split();
+        let mut messages = spawn_reader(reader);
+
+        let (progress_sender, mut updates) = unbounded_channel();
+        let mut job: Option<JobConfig> = None;
+        let mut work: Option<JoinHandle<Result<String>>> = None;
+
+        loop {
+            tokio::select! {
+                message = messages.recv() => {
+                    let message = match message {
+                        Some(message) => message,
+                        None => return Ok(Disconnect::Closed),
+                    };
+
+                    match message {
+                        ClientMessage::Alive { timestamp } => {
+                            log::trace!("Responding to a heartbeat with timestamp={}", timestamp);
+                            send(&mut writer, ClientMessage::Alive { timestamp }).await?;
+                        }
+                        message @ ClientMessage::JobConfig { .. } => {
+                            // Busy nodes cannot take on another job
+                            let accept = match JobConfig::from_message(message) {
+                                Some(config) if work.is_none() => {
+                                    let accept = self.model_accepts(&config);
+                                    job = if accept { Some(config) } else { None };
+                                    accept
+                                }
+                                _ => false,
+                            };
+
+                            log::info!("Responding to a job offer with accept={}", accept);
+                            send(&mut writer, ClientMessage::ConfigResponse { accept }).await?;
+                        }
+                        ClientMessage::Dataset { train, predict } => {
+                            let config = match job.take() {
+                                Some(config) if work.is_none() => config,
+                                _ => {
+                                    log::warn!("Received a dataset without accepting a job");
+                                    continue;
+                                }
+                            };
+
+                            let (train, predict) = match (decode(&train), decode(&predict)) {
+                                (Ok(train), Ok(predict)) => (train, predict),
+                                _ => {
+                                    log::error!("Failed to decode the datasets for the job");
+                                    let reason = String::from("The datasets could not be decoded");
+                                    send(&mut writer, ClientMessage::PredictionsFailed { reason })
+                                        .await?;
+                                    continue;
+                                }
+                            };
+
+                            log::info!("Computing predictions for a job");
+
+                            let progress = Progress::new(progress_sender.clone());
+                            work = Some(self.compute(train, predict, config, progress));
+                        }
+                        ClientMessage::Shutdown => return Ok(Disconnect::Shutdown),
+                        other => log::warn!("Received an unexpected message: {:?}", other),
+                    }
+                }
+                Some(update) = updates.recv() => send(&mut writer, update).await?,
+                result = finished(&mut work) => {
+                    work = None;
+
+                    // Failures are reported straight away, rather than leaving the DCL to wait
+                    let message = match result {
+                        Ok(Ok(predictions)) => {
+                            log::info!("Sending predictions for the job");
+                            ClientMessage::Predictions(encode(&predictions)?)
+                        }
+                        Ok(Err(e)) => {
+                            log::error!("The model failed to compute predictions: {}", e);
+                            ClientMessage::PredictionsFailed { reason: e.to_string() }
+                        }
+                        Err(e) => {
+                            log::error!("The model panicked while computing: {}", e);
+                            let reason = String::from("The model panicked while computing");
+                            ClientMessage::PredictionsFailed { reason }
+                        }
+                    };
+
+                    send(&mut writer, message).await?;
+                }
+            }
+        }
+    }
+
+    /// Checks whether the model accepts a job, declining if it is still computing a previous one
+    fn model_accepts(&self, config: &JobConfig) -> bool {
+        match self.model.try_lock() {
+            Ok(mut model) => model.accept_job(config),
+            Err(TryLockError::WouldBlock) => {
+                log::warn!("Declining a job while a previous computation is still running");
+                false
+            }
+            Err(TryLockError::Poisoned(_)) => false,
+        }
+    }
+
+    /// Trains the model and computes predictions on a blocking thread
+    fn compute(
+        &self,
+        train: String,
+        predict: String,
+        config: JobConfig,
+        progress: Progress,
+    ) -> JoinHandle<Result<String>> {
+        let model = Arc::clone(&self.model);
+
+        tokio::task::spawn_blocking(move || {
+            let mut model = model
+                .lock()
+                .map_err(|_| anyhow!("The model panicked during a previous job"))?;
+
+            model.train(&train, &config, &progress)?;
+            model.predict(&predict, &config, &progress)
+        })
+    }
+}
+
+/// Reads messages from the DCL on a separate task until the connection closes
+fn spawn_reader(mut reader: tokio::net::tcp::OwnedReadHalf) -> UnboundedReceiver<ClientMessage> {
+    let (sender, receiver) = unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut buffer = [0_u8; 4096];
+
+        loop {
+            match ClientMessage::from_stream(&mut reader, &mut buffer).await {
+                Ok(message) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                // Messages are read in full before parsing, so the stream is still usable
+                Err(e) if e.is::<serde_json::Error>() => {
+                    log::warn!("Received a message that could not be parsed: {}", e);
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Waits for the current job to finish, or forever if there is no job
+async fn finished(
+    work: &mut Option<JoinHandle<Result<String>>>,
+) -> std::result::Result<Result<String>, tokio::task::JoinError> {
+    match work {
+        Some(handle) => handle.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sends a message to the DCL
+async fn send(writer: &mut OwnedWriteHalf, message: ClientMessage) -> Result<()> {
+    writer.write_all(&message.as_bytes()).await?;
+    Ok(())
+}
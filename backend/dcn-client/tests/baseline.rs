@@ -0,0 +1,90 @@
+use anyhow::Result;
+use tokio::sync::mpsc::unbounded_channel;
+
+use dcn_client::{encoding, Baseline, JobConfig, Model, Progress};
+use messages::ClientMessage;
+use models::jobs::PredictionType;
+
+fn config(prediction_type: PredictionType) -> JobConfig {
+    JobConfig {
+        message_creation_timestamp: 0,
+        prediction_cutoff_timestamp: 60,
+        cluster_size: 1,
+        column_types: Vec::new(),
+        prediction_column: String::from("target"),
+        prediction_type,
+    }
+}
+
+fn run(train: &str, predict: &str, prediction_type: PredictionType) -> Result<String> {
+    let (sender, _receiver) = unbounded_channel();
+    let progress = Progress::new(sender);
+    let config = config(prediction_type);
+
+    let mut model = Baseline::new();
+    model.train(train, &config, &progress)?;
+    model.predict(predict, &config, &progress)
+}
+
+#[test]
+fn classification_predicts_the_majority_class() -> Result<()> {
+    let train = "record_id,a,b\n1,1,cat\n2,2,dog\n3,3,cat\n";
+    let predict = "record_id,a,b\n4,1,\n5,2,\n";
+
+    let predictions = run(train, predict, PredictionType::Classification)?;
+
+    assert_eq!(predictions, "record_id,b\n4,cat\n5,cat\n");
+
+    Ok(())
+}
+
+#[test]
+fn regression_predicts_the_mean() -> Result<()> {
+    // The prediction column is matched by name rather than position
+    let train = "record_id,b,a\n1,1.0,x\n2,2.0,y\n3,6.0,z\n";
+    let predict = "record_id,a,b\n4,x,\n";
+
+    let predictions = run(train, predict, PredictionType::Regression)?;
+
+    assert_eq!(predictions, "record_id,b\n4,3\n");
+
+    Ok(())
+}
+
+#[test]
+fn datasets_without_an_empty_column_are_rejected() {
+    let train = "record_id,a,b\n1,1,cat\n";
+    let predict = "record_id,a,b\n2,1,dog\n";
+
+    assert!(run(train, predict, PredictionType::Classification).is_err());
+}
+
+#[tokio::test]
+async fn progress_is_clamped_and_forwarded() {
+    let (sender, mut receiver) = unbounded_channel();
+    let progress = Progress::new(sender);
+
+    progress.report("training", 1.5, "Almost done");
+
+    assert!(matches!(
+        receiver.recv().await,
+        Some(ClientMessage::Progress { fraction, .. }) if (fraction - 1.0).abs() < f64::EPSILON
+    ));
+}
+
+#[test]
+fn data_can_be_encoded_and_decoded() -> Result<()> {
+    let data = "record_id,b\n4,cat\n5,cat\n";
+    let encoded = encoding::encode(data)?;
+
+    assert_eq!(encoding::decode(&encoded)?, data);
+
+    // Data is encoded in the same way as datasets sent by the DCL
+    let message = ClientMessage::from_train_and_predict(data, data);
+
+    if let ClientMessage::Dataset { train, .. } = message {
+        assert_eq!(encoding::decode(&train)?, data);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use dcn_client::node::AuthenticationError;
+use dcn_client::{
+    encoding, Baseline, CredentialStore, Disconnect, JobConfig, Model, Node, NodeConfig, Progress,
+};
+use messages::{ClientMessage, RawMessage, ReadLengthPrefix, WriteLengthPrefix};
+use model_auth::Token;
+use models::jobs::PredictionType;
+
+static MODEL_ID: &str = "5fe8b9d85511355cdab720aa";
+
+fn credentials_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dcn-client-{}-{}.json", name, std::process::id()))
+}
+
+fn token() -> Token {
+    Token {
+        id: String::from(MODEL_ID),
+        token: String::from("dG9rZW4="),
+        expires: String::from("2030-01-01T00:00:00+00:00"),
+    }
+}
+
+async fn listen() -> Result<(TcpListener, String)> {
+    let socket = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+    let listener = TcpListener::bind(socket).await?;
+    let address = listener.local_addr()?.to_string();
+
+    Ok((listener, address))
+}
+
+async fn read(stream: &mut TcpStream) -> Result<ClientMessage> {
+    let mut buffer = [0_u8; 1024];
+    ClientMessage::from_stream(stream, &mut buffer).await
+}
+
+async fn reply(stream: &mut TcpStream, content: &str) -> Result<()> {
+    let message = RawMessage::new(content.to_string());
+    stream.write_all(&message.as_bytes()).await?;
+    Ok(())
+}
+
+async fn send(stream: &mut TcpStream, message: ClientMessage) -> Result<()> {
+    stream.write_all(&message.as_bytes()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn nodes_authenticate_and_compute_predictions() -> Result<()> {
+    let (listener, address) = listen().await?;
+    let credentials = CredentialStore::new(credentials_path("compute"));
+    credentials.save(&token()).await?;
+
+    let config = NodeConfig::new(address, "a@b.com", "model", "", credentials.clone());
+    let node = tokio::spawn(async move { Node::new(config, Baseline::new()).run().await });
+
+    let mut stream = listener.accept().await?.0;
+
+    match read(&mut stream).await? {
+        ClientMessage::AccessToken { id, token, .. } => {
+            assert_eq!(id, MODEL_ID);
+            assert_eq!(token, "dG9rZW4=");
+        }
+        other => panic!("Expected an access token, got {:?}", other),
+    }
+
+    reply(&mut stream, r#"{"message":"Authentication successful"}"#).await?;
+
+    // Heartbeats are echoed back
+    send(&mut stream, ClientMessage::Alive { timestamp: 42 }).await?;
+    assert!(matches!(
+        read(&mut stream).await?,
+        ClientMessage::Alive { timestamp: 42 }
+    ));
+
+    let config = ClientMessage::JobConfig {
+        message_creation_timestamp: 0,
+        prediction_cutoff_timestamp: 60,
+        cluster_size: 1,
+        column_types: vec![String::from("categorical")],
+        prediction_column: String::from("class"),
+        prediction_type: PredictionType::Classification,
+    };
+    send(&mut stream, config).await?;
+    assert!(matches!(
+        read(&mut stream).await?,
+        ClientMessage::ConfigResponse { accept: true }
+    ));
+
+    let train = "record_id,a,b\n1,x,yes\n2,y,no\n3,z,yes\n";
+    let predict = "record_id,a,b\n4,x,\n5,y,\n";
+    send(
+        &mut stream,
+        ClientMessage::from_train_and_predict(train, predict),
+    )
+    .await?;
+
+    // Skip any progress updates until the predictions arrive
+    let predictions = loop {
+        match read(&mut stream).await? {
+            ClientMessage::Predictions(predictions) => break predictions,
+            ClientMessage::Progress { .. } => continue,
+            other => panic!("Expected predictions, got {:?}", other),
+        }
+    };
+
+    let predictions = encoding::decode(&predictions)?;
+    assert_eq!(predictions, "record_id,b\n4,yes\n5,yes\n");
+
+    send(&mut stream, ClientMessage::Shutdown).await?;
+    assert_eq!(node.await??, Disconnect::Shutdown);
+
+    credentials.clear().await?;
+
+    Ok(())
+}
+
+/// A model that always fails to train
+struct Failing;
+
+impl Model for Failing {
+    fn train(&mut self, _train: &str, _config: &JobConfig, _progress: &Progress) -> Result<()> {
+        Err(anyhow::anyhow!("Out of memory"))
+    }
+
+    fn predict(
+        &mut self,
+        _predict: &str,
+        _config: &JobConfig,
+        _progress: &Progress,
+    ) -> Result<String> {
+        unreachable!("Failing models never finish training")
+    }
+}
+
+#[tokio::test]
+async fn model_failures_are_reported_instead_of_predictions() -> Result<()> {
+    let (listener, address) = listen().await?;
+    let credentials = CredentialStore::new(credentials_path("failing"));
+    credentials.save(&token()).await?;
+
+    let config = NodeConfig::new(address, "a@b.com", "model", "", credentials.clone());
+    let node = tokio::spawn(async move { Node::new(config, Failing).run().await });
+
+    let mut stream = listener.accept().await?.0;
+    read(&mut stream).await?;
+    reply(&mut stream, r#"{"message":"Authentication successful"}"#).await?;
+
+    let config = ClientMessage::JobConfig {
+        message_creation_timestamp: 0,
+        prediction_cutoff_timestamp: 60,
+        cluster_size: 1,
+        column_types: vec![String::from("categorical")],
+        prediction_column: String::from("class"),
+        prediction_type: PredictionType::Classification,
+    };
+    send(&mut stream, config).await?;
+    read(&mut stream).await?;
+
+    let train = "record_id,a,b\n1,x,yes\n";
+    let predict = "record_id,a,b\n2,x,\n";
+    send(
+        &mut stream,
+        ClientMessage::from_train_and_predict(train, predict),
+    )
+    .await?;
+
+    let reason = loop {
+        match read(&mut stream).await? {
+            ClientMessage::PredictionsFailed { reason } => break reason,
+            ClientMessage::Progress { .. } => continue,
+            other => panic!("Expected a failure, got {:?}", other),
+        }
+    };
+
+    assert!(reason.contains("Out of memory"));
+
+    send(&mut stream, ClientMessage::Shutdown).await?;
+    assert_eq!(node.await??, Disconnect::Shutdown);
+
+    credentials.clear().await?;
+
+    Ok(())
+}
+
+/// A model that trains until it is released
+struct Blocking(std::sync::mpsc::Receiver<()>);
+
+impl Model for Blocking {
+    fn train(&mut self, _train: &str, _config: &JobConfig, _progress: &Progress) -> Result<()> {
+        let _ = self.0.recv();
+        Ok(())
+    }
+
+    fn predict(
+        &mut self,
+        _predict: &str,
+        _config: &JobConfig,
+        _progress: &Progress,
+    ) -> Result<String> {
+        Ok(String::from("record_id,b\n"))
+    }
+}
+
+fn job_config() -> ClientMessage {
+    ClientMessage::JobConfig {
+        message_creation_timestamp: 0,
+        prediction_cutoff_timestamp: 60,
+        cluster_size: 1,
+        column_types: vec![String::from("categorical")],
+        prediction_column: String::from("class"),
+        prediction_type: PredictionType::Classification,
+    }
+}
+
+#[tokio::test]
+async fn jobs_are_declined_after_reconnecting_during_a_computation() -> Result<()> {
+    let (listener, address) = listen().await?;
+    let credentials = CredentialStore::new(credentials_path("reconnect"));
+    credentials.save(&token()).await?;
+
+    let (release, released) = std::sync::mpsc::channel();
+    let config = NodeConfig::new(address, "a@b.com", "model", "", credentials.clone());
+    let node = Arc::new(Node::new(config, Blocking(released)));
+
+    let first = tokio::spawn({
+        let node = Arc::clone(&node);
+        async move { node.run().await }
+    });
+
+    let mut stream = listener.accept().await?.0;
+    read(&mut stream).await?;
+    reply(&mut stream, r#"{"message":"Authentication successful"}"#).await?;
+
+    send(&mut stream, job_config()).await?;
+    read(&mut stream).await?;
+
+    let train = "record_id,a,b\n1,x,yes\n";
+    let predict = "record_id,a,b\n2,x,\n";
+    send(
+        &mut stream,
+        ClientMessage::from_train_and_predict(train, predict),
+    )
+    .await?;
+
+    // Lose the connection while the model is still training
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    drop(stream);
+    assert_eq!(first.await??, Disconnect::Closed);
+
+    let second = tokio::spawn({
+        let node = Arc::clone(&node);
+        async move { node.run().await }
+    });
+
+    let mut stream = listener.accept().await?.0;
+    read(&mut stream).await?;
+    reply(&mut stream, r#"{"message":"Authentication successful"}"#).await?;
+
+    send(&mut stream, job_config()).await?;
+    assert!(matches!(
+        read(&mut stream).await?,
+        ClientMessage::ConfigResponse { accept: false }
+    ));
+
+    release.send(())?;
+
+    send(&mut stream, ClientMessage::Shutdown).await?;
+    assert_eq!(second.await??, Disconnect::Shutdown);
+
+    credentials.clear().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn locked_models_are_reported() -> Result<()> {
+    let (listener, address) = listen().await?;
+    let credentials = CredentialStore::new(credentials_path("locked"));
+    credentials.save(&token()).await?;
+
+    let config = NodeConfig::new(address, "a@b.com", "model", "", credentials.clone());
+    let node = tokio::spawn(async move { Node::new(config, Baseline::new()).run().await });
+
+    let mut stream = listener.accept().await?.0;
+    read(&mut stream).await?;
+
+    let rejection = r#"{"Server":{"code":401,"text":"{\"message\":\"Locked\"}"}}"#;
+    reply(&mut stream, rejection).await?;
+
+    let error = node.await?.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AuthenticationError>(),
+        Some(AuthenticationError::Locked)
+    ));
+
+    // The token remains usable once the model is unlocked
+    assert_eq!(credentials.load().await?, Some(token()));
+    credentials.clear().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn registration_signs_the_challenge() -> Result<()> {
+    let (listener, address) = listen().await?;
+    let (private_key, public_key) = crypto::encoded_key_pair();
+    let credentials = CredentialStore::new(credentials_path("register"));
+
+    let config = NodeConfig::new(
+        address,
+        "a@b.com",
+        "model",
+        private_key,
+        credentials.clone(),
+    );
+    let node = tokio::spawn(async move {
+        Node::new(config, Baseline::new())
+            .register("password")
+            .await
+    });
+
+    let mut stream = listener.accept().await?.0;
+
+    assert!(matches!(
+        read(&mut stream).await?,
+        ClientMessage::NewModel { model_name, .. } if model_name == "model"
+    ));
+
+    let challenge = crypto::generate_challenge();
+    let message = format!(
+        r#"{{"Challenge":{{"challenge":"{}"}}}}"#,
+        base64::encode(&challenge)
+    );
+    reply(&mut stream, &message).await?;
+
+    let response = match read(&mut stream).await? {
+        ClientMessage::ChallengeResponse { response, .. } => base64::decode(response)?,
+        other => panic!("Expected a challenge response, got {:?}", other),
+    };
+
+    assert!(crypto::verify_challenge(&challenge, &response, &public_key));
+
+    let message = format!(
+        r#"{{"AccessToken":{{"id":"{}","token":"dG9rZW4=","expires":"2030-01-01T00:00:00+00:00"}}}}"#,
+        MODEL_ID
+    );
+    reply(&mut stream, &message).await?;
+
+    assert_eq!(node.await??, token());
+    assert_eq!(credentials.load().await?, Some(token()));
+    credentials.clear().await?;
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Writes emails to a directory or the standard output instead of sending them.
+
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use tokio::io::AsyncWriteExt;
+
+use crate::{Email, Transport};
+
+/// The directory emails are written to if `MAIL_DIRECTORY` is not set
+const DEFAULT_DIRECTORY: &str = "emails";
+
+/// Writes emails out in a readable form, for development and testing
+#[derive(Clone, Debug)]
+pub struct FileTransport {
+    directory: Option<PathBuf>,
+}
+
+impl FileTransport {
+    /// Creates a new [`FileTransport`] that writes each email to its own file in `directory`
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: Some(directory.into()),
+        }
+    }
+
+    /// Creates a new [`FileTransport`] that writes emails to the standard output
+    pub fn stdout() -> Self {
+        Self { directory: None }
+    }
+
+    /// Creates the [`FileTransport`] for the directory in `MAIL_DIRECTORY`, or `emails` if it is
+    /// not set
+    pub fn from_env() -> Self {
+        let directory =
+            env::var("MAIL_DIRECTORY").unwrap_or_else(|_| String::from(DEFAULT_DIRECTORY));
+
+        Self::new(directory)
+    }
+}
+
+/// Formats an email with its headers, followed by both of its bodies
+fn render(email: &Email) -> String {
+    format!(
+        "Date: {}\nFrom: {}\nTo: {}\nSubject: {}\n\n{}\n\n---\n\n{}\n",
+        Utc::now().to_rfc2822(),
+        email.from,
+        email.to,
+        email.subject,
+        email.text,
+        email.html
+    )
+}
+
+#[async_trait]
+impl Transport for FileTransport {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let contents = render(email);
+
+        match &self.directory {
+            Some(directory) => {
+                tokio::fs::create_dir_all(directory).await?;
+
+                let path = directory.join(format!("{}.eml", ObjectId::new()));
+                log::debug!("Writing an email to {}", path.display());
+
+                tokio::fs::write(path, contents).await?;
+            }
+            None => {
+                let mut stdout = tokio::io::stdout();
+                stdout.write_all(contents.as_bytes()).await?;
+                stdout.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn emails_are_written_to_the_directory() -> Result<()> {
+        let directory = env::temp_dir().join(format!("mailer-{}", ObjectId::new()));
+        let transport = FileTransport::new(&directory);
+
+        let email = Email {
+            from: "Sybl <noreply@sybl.tech>".parse()?,
+            to: "Ada Lovelace <ada@example.com>".parse()?,
+            subject: String::from("Hello"),
+            text: String::from("Plain body"),
+            html: String::from("<p>HTML body</p>"),
+        };
+
+        transport.send(&email).await?;
+        transport.send(&email).await?;
+
+        let mut entries = std::fs::read_dir(&directory)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(entries.len(), 2);
+
+        let contents = std::fs::read_to_string(entries.remove(0).path())?;
+        assert!(contents.contains("To: Ada Lovelace <ada@example.com>"));
+        assert!(contents.contains("Subject: Hello"));
+        assert!(contents.contains("Plain body"));
+        assert!(contents.contains("<p>HTML body</p>"));
+
+        std::fs::remove_dir_all(directory)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+//! Sends emails to the users of the Sybl project.
+//!
+//! Emails are built from the templates in [`templates`] and sent through a [`Transport`], which
+//! can either be an SMTP server through [`SmtpTransport`] or a directory or the standard output
+//! through [`FileTransport`] for development and testing. Users choose which of the optional
+//! emails they receive through their [`NotificationPreferences`].
+//!
+//! The transport is configured through the environment, as described by [`Mailer::from_env`].
+
+#![warn(rust_2018_idioms)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_docs)]
+
+use std::env;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use mongodb::bson::{de::from_document, doc, oid::ObjectId};
+use mongodb::Database;
+
+use models::projects::Project;
+use models::users::{NotificationPreferences, User};
+
+pub mod file;
+pub mod smtp;
+pub mod templates;
+
+pub use file::FileTransport;
+pub use smtp::{SmtpTransport, Tls};
+pub use templates::Notification;
+
+/// The website linked to from emails if `WEBSITE_URL` is not set
+const DEFAULT_WEBSITE_URL: &str = "https://sybl.tech";
+
+/// An email that is ready to be sent
+#[derive(Clone, Debug, PartialEq)]
+pub struct Email {
+    /// The sender, such as `Sybl <noreply@sybl.tech>`
+    pub from: Mailbox,
+    /// The recipient, such as `Ada Lovelace <ada@example.com>`
+    pub to: Mailbox,
+    /// The subject line
+    pub subject: String,
+    /// The plain text body, for clients that do not display HTML
+    pub text: String,
+    /// The HTML body
+    pub html: String,
+}
+
+/// Delivers emails to their recipients
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    /// Sends a single email
+    async fn send(&self, email: &Email) -> Result<()>;
+}
+
+/// Renders notifications and sends them to users through a [`Transport`]
+#[derive(Clone, Debug)]
+pub struct Mailer {
+    transport: Arc<dyn Transport>,
+    from: Mailbox,
+    website_url: String,
+}
+
+impl Mailer {
+    /// Creates a new [`Mailer`] sending from `from` and linking to the website at `website_url`
+    pub fn new(
+        transport: Arc<dyn Transport>,
+        from: Mailbox,
+        website_url: impl Into<String>,
+    ) -> Self {
+        let website_url = website_url.into().trim_end_matches('/').to_string();
+
+        Self {
+            transport,
+            from,
+            website_url,
+        }
+    }
+
+    /// Creates the [`Mailer`] described by the environment, if emails are enabled
+    ///
+    /// `MAILER` selects the transport, either `smtp`, `file` or `stdout`, and emails are not sent
+    /// if it is missing. Emails are sent from `FROM_NAME <FROM_ADDRESS>` and link to
+    /// `WEBSITE_URL`. See [`SmtpTransport::from_env`] and [`FileTransport::from_env`] for the
+    /// settings of each transport.
+    ///
+    /// This should be called once when each component starts, with the result shared by everything
+    /// that sends emails.
+    pub fn from_env() -> Result<Option<Self>> {
+        let kind = match env::var("MAILER") {
+            Ok(kind) => kind,
+            Err(_) => {
+                // Emails used to be sent through Gmail whenever these were set
+                if env::var("APP_PASSWORD").is_ok() || env::var("FROM_ADDRESS").is_ok() {
+                    log::warn!(
+                        "FROM_ADDRESS or APP_PASSWORD is set but MAILER is not, so no emails will \
+                         be sent. Set MAILER=smtp along with the SMTP_* variables to send them"
+                    );
+                }
+
+                return Ok(None);
+            }
+        };
+
+        let transport: Arc<dyn Transport> = match kind.as_str() {
+            "smtp" => Arc::new(SmtpTransport::from_env()?),
+            "file" => Arc::new(FileTransport::from_env()),
+            "stdout" => Arc::new(FileTransport::stdout()),
+            other => return Err(anyhow!("Unknown MAILER: {}", other)),
+        };
+
+        let from_address =
+            env::var("FROM_ADDRESS").unwrap_or_else(|_| String::from("noreply@sybl.tech"));
+        let from_name = env::var("FROM_NAME").unwrap_or_else(|_| String::from("Sybl"));
+        let website_url =
+            env::var("WEBSITE_URL").unwrap_or_else(|_| String::from(DEFAULT_WEBSITE_URL));
+
+        let from_address = from_address
+            .parse()
+            .context("FROM_ADDRESS must be an email address")?;
+        let from = Mailbox::new(Some(from_name), from_address);
+
+        Ok(Some(Self::new(transport, from, website_url)))
+    }
+
+    /// Sends a notification to a user, unless they have opted out of it
+    ///
+    /// Returns whether the email was sent.
+    pub async fn notify(&self, user: &User, notification: &Notification) -> Result<bool> {
+        if !wants(&user.notifications, notification) {
+            log::debug!(
+                "Not sending {} to user_id={} as they have opted out",
+                notification.name(),
+                user.id
+            );
+            return Ok(false);
+        }
+
+        let rendered = notification.render(&user.first_name, &self.website_url);
+
+        let address = user
+            .email
+            .parse()
+            .with_context(|| format!("user_id={} has an invalid email address", user.id))?;
+        let name = format!("{} {}", user.first_name, user.last_name);
+
+        let email = Email {
+            from: self.from.clone(),
+            to: Mailbox::new(Some(name), address),
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
+        };
+
+        log::info!("Sending {} to user_id={}", notification.name(), user.id);
+
+        self.transport.send(&email).await?;
+
+        Ok(true)
+    }
+
+    /// Looks up a user and sends them a notification, unless they have opted out of it
+    ///
+    /// Returns whether the email was sent.
+    pub async fn notify_user(
+        &self,
+        database: &Database,
+        user_id: &ObjectId,
+        notification: Notification,
+    ) -> Result<bool> {
+        let users = database.collection("users");
+        let document = users
+            .find_one(doc! { "_id": user_id }, None)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to find user_id={}", user_id))?;
+        let user: User = from_document(document)?;
+
+        self.notify(&user, &notification).await
+    }
+
+    /// Sends a notification about a project to its owner, unless they have opted out of it
+    ///
+    /// Returns whether the email was sent.
+    pub async fn notify_project_owner<F>(
+        &self,
+        database: &Database,
+        project_id: &ObjectId,
+        notification: F,
+    ) -> Result<bool>
+    where
+        F: FnOnce(&Project) -> Notification,
+    {
+        let projects = database.collection("projects");
+        let document = projects
+            .find_one(doc! { "_id": project_id }, None)
+            .await?
+            .ok_or_else(|| anyhow!("Failed to find project_id={}", project_id))?;
+        let project: Project = from_document(document)?;
+
+        self.notify_user(database, &project.user_id, notification(&project))
+            .await
+    }
+}
+
+/// Checks whether a user's preferences allow a notification to be sent
+fn wants(preferences: &NotificationPreferences, notification: &Notification) -> bool {
+    match notification {
+        Notification::ProjectComplete { .. } => preferences.project_complete,
+        Notification::ProjectFailed { .. } => preferences.project_failed,
+        Notification::LowCredits { .. } => preferences.low_credits,
+        Notification::PasswordReset { .. } => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Keeps the emails it is asked to send
+    #[derive(Debug, Default)]
+    struct Outbox(Mutex<Vec<Email>>);
+
+    #[async_trait]
+    impl Transport for Outbox {
+        async fn send(&self, email: &Email) -> Result<()> {
+            self.0.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+
+    fn low_credits() -> Notification {
+        Notification::LowCredits { credits: 10 }
+    }
+
+    fn sybl() -> Mailbox {
+        Mailbox::new(
+            Some(String::from("Sybl")),
+            "noreply@sybl.tech".parse().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn notifications_are_sent_to_users() -> Result<()> {
+        let outbox = Arc::new(Outbox::default());
+        let mailer = Mailer::new(outbox.clone(), sybl(), "http://sybl/");
+        let user = User::new("ada@example.com", "hash", "Ada", "Lovelace");
+
+        assert!(mailer.notify(&user, &low_credits()).await?);
+
+        let sent = outbox.0.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].from, sybl());
+        assert_eq!(sent[0].to.name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(sent[0].to.email.to_string(), "ada@example.com");
+        assert!(sent[0].text.contains("http://sybl/"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn users_can_opt_out_of_notifications() -> Result<()> {
+        let outbox = Arc::new(Outbox::default());
+        let mailer = Mailer::new(outbox.clone(), sybl(), "http://sybl");
+        let mut user = User::new("ada@example.com", "hash", "Ada", "Lovelace");
+        user.notifications.low_credits = false;
+
+        let reset = Notification::PasswordReset {
+            token: String::from("token"),
+        };
+
+        assert!(!mailer.notify(&user, &low_credits()).await?);
+        assert!(mailer.notify(&user, &reset).await?);
+        assert_eq!(outbox.0.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn names_are_not_read_as_part_of_the_address() -> Result<()> {
+        let outbox = Arc::new(Outbox::default());
+        let mailer = Mailer::new(outbox.clone(), sybl(), "http://sybl");
+        let user = User::new("ada@example.com", "hash", "Ada <Countess>", "Lovelace, Jr.");
+
+        assert!(mailer.notify(&user, &low_credits()).await?);
+
+        let sent = outbox.0.lock().unwrap();
+        assert_eq!(
+            sent[0].to.name.as_deref(),
+            Some("Ada <Countess> Lovelace, Jr.")
+        );
+        assert_eq!(sent[0].to.email.to_string(), "ada@example.com");
+
+        Ok(())
+    }
+}
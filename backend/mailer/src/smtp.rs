@@ -0,0 +1,126 @@
+//! Sends emails through an SMTP server.
+
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use lettre::message::{MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::{Email, Transport};
+
+/// How the connection to the SMTP server is secured
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tls {
+    /// The connection is unencrypted, which should only be used for local servers
+    None,
+    /// The connection starts unencrypted and is upgraded with `STARTTLS`
+    StartTls,
+    /// The connection is encrypted from the start
+    Tls,
+}
+
+impl Tls {
+    /// Gets the port SMTP servers usually listen on with this kind of security
+    pub fn default_port(self) -> u16 {
+        match self {
+            Tls::None => 25,
+            Tls::StartTls => 587,
+            Tls::Tls => 465,
+        }
+    }
+}
+
+impl std::str::FromStr for Tls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Tls::None),
+            "starttls" => Ok(Tls::StartTls),
+            "tls" => Ok(Tls::Tls),
+            other => Err(anyhow!("Unknown SMTP_TLS: {}", other)),
+        }
+    }
+}
+
+/// Sends emails through an SMTP server
+#[derive(Clone, Debug)]
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    /// Creates a new [`SmtpTransport`] for the server at `host`
+    ///
+    /// Uses the usual port for `tls` if `port` is not given, and only logs in if `credentials`
+    /// are given.
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        tls: Tls,
+        credentials: Option<(String, String)>,
+    ) -> Result<Self> {
+        let builder = match tls {
+            Tls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            Tls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            Tls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+
+        let mut builder = builder.port(port.unwrap_or_else(|| tls.default_port()));
+
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+
+    /// Creates the [`SmtpTransport`] described by the environment
+    ///
+    /// The server is given by `SMTP_HOST` and `SMTP_PORT`, secured according to `SMTP_TLS`
+    /// (`none`, `starttls` or `tls`, defaulting to `starttls`), and logged into with
+    /// `SMTP_USERNAME` and `SMTP_PASSWORD` if they are set.
+    pub fn from_env() -> Result<Self> {
+        let host = env::var("SMTP_HOST").context("SMTP_HOST must be set to send emails")?;
+
+        let tls = match env::var("SMTP_TLS") {
+            Ok(tls) => tls.parse()?,
+            Err(_) => Tls::StartTls,
+        };
+
+        let port = match env::var("SMTP_PORT") {
+            Ok(port) => Some(port.parse().context("SMTP_PORT must be a number")?),
+            Err(_) => None,
+        };
+
+        let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            _ => None,
+        };
+
+        Self::new(&host, port, tls, credentials)
+    }
+}
+
+#[async_trait]
+impl Transport for SmtpTransport {
+    async fn send(&self, email: &Email) -> Result<()> {
+        let message = Message::builder()
+            .from(email.from.clone())
+            .to(email.to.clone())
+            .subject(&email.subject)
+            .multipart(
+                MultiPart::alternative()
+                    .singlepart(SinglePart::plain(email.text.clone()))
+                    .singlepart(SinglePart::html(email.html.clone())),
+            )?;
+
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Defines the notifications that can be emailed to users and renders them.
+//!
+//! Each notification is rendered as both plain text and HTML, with any values that came from
+//! users escaped before being placed into the HTML.
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// The characters escaped in query string values, leaving only the unreserved ones as they are
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A notification that can be emailed to a user
+#[derive(Clone, Debug, PartialEq)]
+pub enum Notification {
+    /// A project has finished processing and its predictions are available
+    ProjectComplete {
+        /// The identifier of the project
+        project_id: String,
+        /// The name of the project
+        project_name: String,
+    },
+    /// A project could not be processed
+    ProjectFailed {
+        /// The identifier of the project
+        project_id: String,
+        /// The name of the project
+        project_name: String,
+        /// Why the project could not be processed
+        reason: String,
+    },
+    /// A user's balance has fallen below the threshold for warning them
+    LowCredits {
+        /// How many credits the user has left
+        credits: i32,
+    },
+    /// A user has asked to reset their password
+    PasswordReset {
+        /// The token allowing the password to be reset
+        token: String,
+    },
+}
+
+/// The contents of a rendered notification
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rendered {
+    /// The subject line
+    pub subject: String,
+    /// The plain text body
+    pub text: String,
+    /// The HTML body
+    pub html: String,
+}
+
+impl Notification {
+    /// Gets the name of the notification, for logging
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notification::ProjectComplete { .. } => "project_complete",
+            Notification::ProjectFailed { .. } => "project_failed",
+            Notification::LowCredits { .. } => "low_credits",
+            Notification::PasswordReset { .. } => "password_reset",
+        }
+    }
+
+    /// Renders the notification for a user, linking to the website at `website_url`
+    pub fn render(&self, first_name: &str, website_url: &str) -> Rendered {
+        let (subject, paragraphs, link) = match self {
+            Notification::ProjectComplete {
+                project_id,
+                project_name,
+            } => (
+                String::from("Sybl Project Completion"),
+                vec![format!(
+                    "Your project '{}' has finished processing, and results are now available.",
+                    project_name
+                )],
+                (
+                    "View the results",
+                    format!("{}/dashboard/{}", website_url, project_id),
+                ),
+            ),
+            Notification::ProjectFailed {
+                project_id,
+                project_name,
+                reason,
+            } => (
+                String::from("Sybl Project Failure"),
+                vec![
+                    format!(
+                        "Unfortunately, your project '{}' could not be processed.",
+                        project_name
+                    ),
+                    format!("Reason: {}", reason),
+                ],
+                (
+                    "View the project",
+                    format!("{}/dashboard/{}", website_url, project_id),
+                ),
+            ),
+            Notification::LowCredits { credits } => (
+                String::from("Your Sybl credits are running low"),
+                vec![format!(
+                    "You have {} credits remaining. Once they run out, your projects will no \
+                     longer be processed.",
+                    credits
+                )],
+                ("Manage your account", format!("{}/settings", website_url)),
+            ),
+            Notification::PasswordReset { token } => (
+                String::from("Reset your Sybl password"),
+                vec![String::from(
+                    "Someone asked to reset the password for your account. If this was not you, \
+                     you can safely ignore this email.",
+                )],
+                (
+                    "Reset your password",
+                    format!(
+                        "{}/reset-password?token={}",
+                        website_url,
+                        utf8_percent_encode(token, QUERY_VALUE)
+                    ),
+                ),
+            ),
+        };
+
+        let (label, url) = link;
+
+        let text = format!(
+            "Hi {},\n\n{}\n\n{}: {}\n",
+            first_name,
+            paragraphs.join("\n\n"),
+            label,
+            url
+        );
+
+        let body: String = paragraphs
+            .iter()
+            .map(|paragraph| format!("<p>{}</p>", encode_text(paragraph)))
+            .collect();
+
+        let html = format!(
+            "<html><body><p>Hi {},</p>{}<p><a href=\"{}\">{}</a></p></body></html>",
+            encode_text(first_name),
+            body,
+            encode_double_quoted_attribute(&url),
+            label
+        );
+
+        Rendered {
+            subject,
+            text,
+            html,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_completion_links_to_the_dashboard() {
+        let notification = Notification::ProjectComplete {
+            project_id: String::from("5f8ca1a80065f27b0089e8b5"),
+            project_name: String::from("Weather"),
+        };
+
+        let rendered = notification.render("Ada", "https://sybl.tech");
+
+        assert_eq!(rendered.subject, "Sybl Project Completion");
+        assert!(rendered.text.starts_with("Hi Ada,"));
+        assert!(rendered
+            .text
+            .contains("https://sybl.tech/dashboard/5f8ca1a80065f27b0089e8b5"));
+        assert!(rendered
+            .html
+            .contains("href=\"https://sybl.tech/dashboard/5f8ca1a80065f27b0089e8b5\""));
+    }
+
+    #[test]
+    fn user_values_are_escaped_in_html() {
+        let notification = Notification::ProjectFailed {
+            project_id: String::from("id"),
+            project_name: String::from("<script>alert(1)</script>"),
+            reason: String::from("Bad & broken"),
+        };
+
+        let rendered = notification.render("<b>Ada</b>", "https://sybl.tech");
+
+        assert!(!rendered.html.contains("<script>"));
+        assert!(!rendered.html.contains("<b>Ada</b>"));
+        assert!(rendered.html.contains("Bad &amp; broken"));
+        assert!(rendered.text.contains("<script>alert(1)</script>"));
+    }
+
+    #[test]
+    fn reset_tokens_are_encoded_in_links() {
+        let notification = Notification::PasswordReset {
+            token: String::from("a+b/c=&d"),
+        };
+
+        let rendered = notification.render("Ada", "https://sybl.tech");
+
+        assert!(rendered
+            .text
+            .contains("https://sybl.tech/reset-password?token=a%2Bb%2Fc%3D%26d"));
+    }
+}
@@ -0,0 +1,249 @@
+//! Contains the [`Consumer`] used by every component to process messages from the bus.
+
+use std::fmt;
+use std::future::{self, Future};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tokio_stream::StreamExt;
+
+use crate::bus::{BusMessage, DeadLetter, MessageBus, RetryPolicy};
+
+/// Processes messages from a set of topics as part of a consumer group
+///
+/// Messages are handled one at a time and only committed once the handler has finished with
+/// them. If the handler fails, the message is sent to the dead letter topic for its topic along
+/// with the error, so that a single poison message neither stops the consumer nor is lost.
+/// Sending the dead letter is retried with a backoff, and if it still cannot be sent the
+/// consumer stops with an error, leaving the message uncommitted to be delivered again.
+pub struct Consumer {
+    bus: Arc<dyn MessageBus>,
+    group: String,
+    topics: Vec<String>,
+    retry: RetryPolicy,
+}
+
+impl fmt::Debug for Consumer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Consumer")
+            .field("group", &self.group)
+            .field("topics", &self.topics)
+            .finish()
+    }
+}
+
+impl Consumer {
+    /// Creates a new [`Consumer`] for `topics` as part of `group`
+    pub fn new(bus: Arc<dyn MessageBus>, group: impl Into<String>, topics: &[&str]) -> Self {
+        Self {
+            bus,
+            group: group.into(),
+            topics: topics.iter().map(|topic| topic.to_string()).collect(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets how sending dead letters is retried
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Passes each message to `handler` until the subscription ends
+    pub async fn run<H, F>(&self, handler: H) -> Result<()>
+    where
+        H: FnMut(BusMessage) -> F,
+        F: Future<Output = Result<()>>,
+    {
+        self.run_until(handler, future::pending()).await
+    }
+
+    /// Passes each message to `handler` until the subscription ends or `stop` completes
+    ///
+    /// A message that is being handled when `stop` completes is finished first. Returns an error
+    /// if a message could neither be handled nor sent to its dead letter topic.
+    pub async fn run_until<H, F, S>(&self, mut handler: H, stop: S) -> Result<()>
+    where
+        H: FnMut(BusMessage) -> F,
+        F: Future<Output = Result<()>>,
+        S: Future<Output = ()>,
+    {
+        let topics: Vec<&str> = self.topics.iter().map(String::as_str).collect();
+        let mut stream = self.bus.subscribe(&self.group, &topics).await?;
+
+        tokio::pin!(stop);
+
+        loop {
+            let delivery = tokio::select! {
+                delivery = stream.next() => match delivery {
+                    Some(delivery) => delivery,
+                    None => break,
+                },
+                _ = &mut stop => break,
+            };
+
+            if let Err(error) = handler(delivery.message.clone()).await {
+                let letter = DeadLetter::new(&self.group, &delivery.message, &error);
+
+                log::error!(
+                    "Failed to process a message on topic={} as group={}: {:#}",
+                    letter.topic,
+                    self.group,
+                    error
+                );
+
+                self.send_dead_letter(&letter).await.with_context(|| {
+                    format!(
+                        "Failed to send a dead letter for topic={}, leaving it uncommitted",
+                        letter.topic
+                    )
+                })?;
+            }
+
+            if let Err(e) = delivery.commit() {
+                log::warn!("Failed to commit a message as group={}: {}", self.group, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a dead letter, retrying with a backoff until the attempts run out
+    async fn send_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let mut attempt = 1;
+
+        loop {
+            match letter.send(self.bus.as_ref()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retry.attempts => return Err(e),
+                Err(e) => {
+                    let backoff = self.retry.backoff(attempt);
+
+                    log::warn!(
+                        "Failed to send a dead letter on attempt {}, retrying in {:?}: {}",
+                        attempt,
+                        backoff,
+                        e
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::bus::{InMemoryBus, MessageStream};
+
+    /// A bus that cannot send anything to dead letter topics
+    #[derive(Debug, Default)]
+    struct DeadLetterOutage {
+        inner: InMemoryBus,
+        attempts: AtomicU32,
+    }
+
+    #[async_trait]
+    impl MessageBus for DeadLetterOutage {
+        async fn produce(&self, topic: &str, key: &str, payload: &str) -> Result<()> {
+            if topic.ends_with(".dlq") {
+                self.attempts.fetch_add(1, Ordering::SeqCst);
+                return Err(anyhow!("broker unavailable"));
+            }
+
+            self.inner.produce(topic, key, payload).await
+        }
+
+        async fn subscribe(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+            self.inner.subscribe(group, topics).await
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_messages_are_dead_lettered() -> Result<()> {
+        let bus = Arc::new(InMemoryBus::new());
+        let mut dead_letters = bus.subscribe("inspect", &["jobs.dlq"]).await?;
+
+        bus.produce("jobs", "a", "poison").await?;
+        bus.produce("jobs", "b", "valid").await?;
+
+        let consumer = Consumer::new(bus.clone(), "dcl", &["jobs"]);
+        let mut handled = Vec::new();
+
+        let stop = tokio::time::sleep(Duration::from_millis(100));
+        consumer
+            .run_until(
+                |message| {
+                    handled.push(message.key.clone());
+
+                    async move {
+                        match message.payload.as_slice() {
+                            b"poison" => Err(anyhow!("cannot decode")),
+                            _ => Ok(()),
+                        }
+                    }
+                },
+                stop,
+            )
+            .await?;
+
+        // The poison message did not stop the valid one from being processed
+        assert_eq!(
+            handled,
+            vec![Some(String::from("a")), Some(String::from("b"))]
+        );
+
+        let delivery = dead_letters.next().await.unwrap();
+        let letter: DeadLetter = serde_json::from_slice(&delivery.message.payload)?;
+
+        assert_eq!(letter.topic, "jobs");
+        assert_eq!(letter.group, "dcl");
+        assert_eq!(letter.payload, "poison");
+        assert_eq!(letter.error, "cannot decode");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn consumers_stop_if_dead_letters_cannot_be_sent() -> Result<()> {
+        let bus = Arc::new(DeadLetterOutage::default());
+
+        bus.produce("jobs", "a", "poison").await?;
+        bus.produce("jobs", "b", "valid").await?;
+
+        let retry = RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        };
+        let consumer = Consumer::new(bus.clone(), "dcl", &["jobs"]).with_retry_policy(retry);
+        let mut handled = Vec::new();
+
+        let stop = tokio::time::sleep(Duration::from_millis(100));
+        let result = consumer
+            .run_until(
+                |message| {
+                    handled.push(message.key.clone());
+                    async move { Err(anyhow!("cannot decode")) }
+                },
+                stop,
+            )
+            .await;
+
+        // The poison message is not skipped, so nothing after it is processed
+        assert!(result.is_err());
+        assert_eq!(handled, vec![Some(String::from("a"))]);
+        assert_eq!(bus.attempts.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! Contains the messages sent to dead letter topics and the means to replay them.
+//!
+//! Each topic `x` has a dead letter topic `x.dlq`, which receives the messages on `x` that a
+//! consumer could not process along with the error that occurred. Once the cause has been fixed,
+//! the messages can be replayed back onto their original topic.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use tokio_stream::StreamExt;
+
+use crate::bus::{BusMessage, MessageBus};
+
+/// The suffix added to a topic to get the topic its dead letters are sent to
+pub const DEAD_LETTER_SUFFIX: &str = ".dlq";
+
+/// Gets the dead letter topic for `topic`
+pub fn dead_letter_topic(topic: &str) -> String {
+    format!("{}{}", topic, DEAD_LETTER_SUFFIX)
+}
+
+/// A message that could not be processed, along with why
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The topic the message was originally published to
+    pub topic: String,
+    /// The consumer group that failed to process the message
+    pub group: String,
+    /// The key the message was published with, if any
+    pub key: Option<String>,
+    /// The content of the message
+    pub payload: String,
+    /// The error that occurred while processing the message
+    pub error: String,
+    /// When the message was originally published, in milliseconds since the epoch
+    pub timestamp: Option<i64>,
+    /// When processing the message failed, in milliseconds since the epoch
+    pub failed_at: i64,
+}
+
+impl DeadLetter {
+    /// Creates a [`DeadLetter`] for a message that `group` failed to process
+    pub fn new(group: &str, message: &BusMessage, error: &anyhow::Error) -> Self {
+        Self {
+            topic: message.topic.clone(),
+            group: group.to_string(),
+            key: message.key.clone(),
+            payload: String::from_utf8_lossy(&message.payload).into_owned(),
+            error: format!("{:#}", error),
+            timestamp: message.timestamp,
+            failed_at: Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Sends the dead letter to the dead letter topic of the original topic
+    pub async fn send(&self, bus: &dyn MessageBus) -> Result<()> {
+        let topic = dead_letter_topic(&self.topic);
+        let key = self.key.as_deref().unwrap_or_default();
+        let payload = serde_json::to_string(self)?;
+
+        bus.produce(&topic, key, &payload).await
+    }
+}
+
+/// Publishes every dead letter for `topic` back onto it, returning how many were replayed
+///
+/// Dead letters are consumed from the oldest that has not yet been replayed until none have arrived
+/// for `idle`. Each is only committed once it has been published again, so a failure part way
+/// through leaves the rest in place.
+pub async fn replay(bus: &dyn MessageBus, topic: &str, idle: Duration) -> Result<usize> {
+    let dead_letters = dead_letter_topic(topic);
+    let group = format!("{}.replay", dead_letters);
+
+    let mut stream = bus
+        .subscribe_from_start(&group, &[dead_letters.as_str()])
+        .await?;
+    let mut replayed = 0;
+
+    while let Ok(Some(delivery)) = tokio::time::timeout(idle, stream.next()).await {
+        match serde_json::from_slice::<DeadLetter>(&delivery.message.payload) {
+            Ok(letter) => {
+                let key = letter.key.as_deref().unwrap_or_default();
+                bus.produce(topic, key, &letter.payload).await?;
+                replayed += 1;
+            }
+            Err(e) => log::warn!(
+                "Skipping a malformed dead letter on {}: {}",
+                dead_letters,
+                e
+            ),
+        }
+
+        delivery.commit()?;
+    }
+
+    log::info!("Replayed {} dead letters onto {}", replayed, topic);
+
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::bus::InMemoryBus;
+
+    fn message(topic: &str, payload: &str) -> BusMessage {
+        BusMessage {
+            topic: topic.to_string(),
+            key: Some(String::from("key")),
+            payload: payload.as_bytes().to_vec(),
+            timestamp: Some(0),
+        }
+    }
+
+    #[test]
+    fn dead_letter_topics_have_a_suffix() {
+        assert_eq!(dead_letter_topic("jobs"), "jobs.dlq");
+    }
+
+    #[tokio::test]
+    async fn dead_letters_are_sent_with_the_error() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut stream = bus.subscribe("inspect", &["jobs.dlq"]).await?;
+
+        let error = anyhow!("invalid job");
+        DeadLetter::new("dcl", &message("jobs", "{}"), &error)
+            .send(&bus)
+            .await?;
+
+        let delivery = stream.next().await.unwrap();
+        let letter: DeadLetter = serde_json::from_slice(&delivery.message.payload)?;
+
+        assert_eq!(delivery.message.key.as_deref(), Some("key"));
+        assert_eq!(letter.topic, "jobs");
+        assert_eq!(letter.group, "dcl");
+        assert_eq!(letter.payload, "{}");
+        assert_eq!(letter.error, "invalid job");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dead_letters_can_be_replayed() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut stream = bus.subscribe("analytics", &["analytics"]).await?;
+
+        for payload in &["first", "second"] {
+            let error = anyhow!("failed");
+            DeadLetter::new("analytics", &message("analytics", payload), &error)
+                .send(&bus)
+                .await?;
+        }
+
+        let replayed = replay(&bus, "analytics", Duration::from_millis(50)).await?;
+        assert_eq!(replayed, 2);
+
+        let first = stream.next().await.unwrap().message;
+        let second = stream.next().await.unwrap().message;
+
+        assert_eq!(first.payload, b"first");
+        assert_eq!(second.payload, b"second");
+        assert_eq!(first.key.as_deref(), Some("key"));
+
+        // Replayed messages have been committed, so there is nothing left to replay
+        let replayed = replay(&bus, "analytics", Duration::from_millis(50)).await?;
+        assert_eq!(replayed, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dead_letters_sent_before_replaying_are_replayed() -> Result<()> {
+        let bus = InMemoryBus::new();
+
+        // Another group consuming the dead letters means they are not simply pending
+        let mut inspect = bus.subscribe("inspect", &["analytics.dlq"]).await?;
+
+        let error = anyhow!("failed");
+        DeadLetter::new("analytics", &message("analytics", "early"), &error)
+            .send(&bus)
+            .await?;
+        inspect.next().await.unwrap();
+
+        let mut stream = bus.subscribe("analytics", &["analytics"]).await?;
+
+        let replayed = replay(&bus, "analytics", Duration::from_millis(50)).await?;
+        assert_eq!(replayed, 1);
+
+        let message = stream.next().await.unwrap().message;
+        assert_eq!(message.payload, b"early");
+
+        Ok(())
+    }
+}
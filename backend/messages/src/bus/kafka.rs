@@ -0,0 +1,314 @@
+//! Contains the [`MessageBus`] implementation backed by Kafka.
+
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
+use rdkafka::consumer::{stream_consumer::StreamConsumer, Consumer, DefaultConsumerContext};
+use rdkafka::message::{BorrowedMessage, Message};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::topic_partition_list::{Offset, TopicPartitionList};
+use rdkafka::util::Timeout;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::bus::{BusMessage, Delivery, MessageBus, MessageStream};
+
+/// The number of consumed messages that can be buffered before Kafka is no longer polled
+const CONSUMER_BUFFER: usize = 64;
+
+/// How long Kafka has to acknowledge a message before an attempt to send it fails
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for space in the producer's queue before an attempt to send fails
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Controls how a [`KafkaBus`] retries messages that could not be delivered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts to send each message
+    pub attempts: u32,
+    /// The delay before the first retry, which doubles after every failed attempt
+    pub initial_backoff: Duration,
+    /// The longest delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Gets the delay to wait for after the given number of failed attempts
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(failures.saturating_sub(1));
+
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// A [`MessageBus`] that publishes and consumes messages through Kafka
+///
+/// The producer is created once and shared by every message sent through the bus. It is
+/// configured to be idempotent and to wait for every in-sync replica to acknowledge a message,
+/// so a message is either delivered exactly once or an error is returned to the caller.
+///
+/// Consumers only store the offset of a message once its [`Delivery`] is committed, so messages
+/// that were received but not processed are delivered again after a restart.
+pub struct KafkaBus {
+    brokers: String,
+    producer: FutureProducer,
+    retry: RetryPolicy,
+}
+
+impl fmt::Debug for KafkaBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaBus")
+            .field("brokers", &self.brokers)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+impl KafkaBus {
+    /// Creates a new [`KafkaBus`] using a comma separated list of brokers
+    pub fn new(brokers: impl Into<String>) -> Result<Self> {
+        let brokers = brokers.into();
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .set("max.in.flight.requests.per.connection", "5")
+            .set(
+                "message.timeout.ms",
+                &DELIVERY_TIMEOUT.as_millis().to_string(),
+            )
+            .create()?;
+
+        Ok(Self {
+            brokers,
+            producer,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Sets how messages that could not be delivered are retried
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Creates a new [`KafkaBus`] using `BROKER_HOST` and `BROKER_PORT`
+    ///
+    /// These default to `127.0.0.1` and `9092` respectively.
+    pub fn from_env() -> Result<Self> {
+        let host = env::var("BROKER_HOST").unwrap_or_else(|_| String::from("127.0.0.1"));
+        let port = env::var("BROKER_PORT").unwrap_or_else(|_| String::from("9092"));
+
+        let port: u16 = port
+            .parse()
+            .map_err(|_| anyhow!("BROKER_PORT must be a u16"))?;
+
+        Self::new(format!("{}:{}", host, port))
+    }
+
+    /// Subscribes to a set of topics, using `offset_reset` as `auto.offset.reset`
+    ///
+    /// This decides where a group without any committed offsets starts consuming from. The
+    /// consumer is closed as soon as the returned stream is dropped, so that it leaves the group
+    /// straight away instead of holding on to its partitions until another message arrives.
+    fn subscribe_with_reset(
+        &self,
+        group: &str,
+        topics: &[&str],
+        offset_reset: &str,
+    ) -> Result<MessageStream> {
+        log::info!(
+            "Subscribing to topics={:?} on {} as group={} from offset_reset={}",
+            topics,
+            self.brokers,
+            group,
+            offset_reset
+        );
+
+        // Offsets are committed periodically, but only once they have been stored on commit
+        let consumer: StreamConsumer<DefaultConsumerContext> = ClientConfig::new()
+            .set("group.id", group)
+            .set("bootstrap.servers", &self.brokers)
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
+            .set("auto.offset.reset", offset_reset)
+            .set_log_level(RDKafkaLogLevel::Debug)
+            .create()?;
+        let consumer = Arc::new(consumer);
+
+        consumer.subscribe(topics)?;
+
+        // The consumer is owned by a task so that the stream does not borrow from it
+        let (sender, receiver) = mpsc::channel(CONSUMER_BUFFER);
+
+        tokio::spawn(async move {
+            loop {
+                // Stop consuming once the subscriber has gone away, even if nothing is arriving
+                let received = tokio::select! {
+                    received = consumer.recv() => received,
+                    _ = sender.closed() => break,
+                };
+
+                let delivery = match received {
+                    Ok(message) => to_delivery(&message, &consumer),
+                    Err(e) => {
+                        log::warn!("Failed to receive a message from Kafka: {}", e);
+                        continue;
+                    }
+                };
+
+                let delivery = match delivery {
+                    Some(delivery) => delivery,
+                    None => {
+                        log::warn!("Received an empty message from Kafka");
+                        continue;
+                    }
+                };
+
+                if sender.send(delivery).await.is_err() {
+                    break;
+                }
+            }
+
+            log::debug!("Closing a Kafka consumer as its subscriber has gone away");
+        });
+
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+}
+
+#[async_trait]
+impl MessageBus for KafkaBus {
+    async fn produce(&self, topic: &str, key: &str, payload: &str) -> Result<()> {
+        log::debug!(
+            "Sending msg={} to Kafka with key={} and topic={}",
+            payload,
+            key,
+            topic
+        );
+
+        let mut failures = 0;
+
+        loop {
+            let record = FutureRecord::to(topic).payload(payload).key(key);
+
+            let error = match self
+                .producer
+                .send(record, Timeout::After(QUEUE_TIMEOUT))
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err((error, _)) => error,
+            };
+
+            failures += 1;
+
+            if failures >= self.retry.attempts {
+                return Err(anyhow!(
+                    "Failed to send the message to Kafka after {} attempts: {}",
+                    failures,
+                    error
+                ));
+            }
+
+            let delay = self.retry.backoff(failures);
+
+            log::warn!(
+                "Failed to send the message to Kafka, retrying in {:?}: {}",
+                delay,
+                error
+            );
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn subscribe(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+        self.subscribe_with_reset(group, topics, "latest")
+    }
+
+    async fn subscribe_from_start(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+        self.subscribe_with_reset(group, topics, "earliest")
+    }
+}
+
+/// Copies a message received from Kafka, returning `None` if it has no payload
+///
+/// Committing the delivery stores the offset after the message, which the consumer then commits.
+fn to_delivery(
+    message: &BorrowedMessage<'_>,
+    consumer: &Arc<StreamConsumer<DefaultConsumerContext>>,
+) -> Option<Delivery> {
+    let key = message
+        .key()
+        .and_then(|key| std::str::from_utf8(key).ok())
+        .map(String::from);
+
+    let bus_message = BusMessage {
+        topic: message.topic().to_string(),
+        key,
+        payload: message.payload()?.to_vec(),
+        timestamp: message.timestamp().to_millis(),
+    };
+
+    let consumer = Arc::clone(consumer);
+    let topic = message.topic().to_string();
+    let partition = message.partition();
+    let offset = message.offset();
+
+    let receipt = Box::new(move || {
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset(&topic, partition, Offset::Offset(offset + 1))?;
+        consumer.store_offsets(&offsets)?;
+
+        Ok(())
+    });
+
+    Some(Delivery::with_receipt(bus_message, receipt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_after_each_failure() {
+        let retry = RetryPolicy {
+            attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        };
+
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(200));
+        assert_eq!(retry.backoff(4), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let retry = RetryPolicy::default();
+
+        assert_eq!(retry.backoff(10), retry.max_backoff);
+        assert_eq!(retry.backoff(u32::MAX), retry.max_backoff);
+    }
+}
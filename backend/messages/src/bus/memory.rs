@@ -0,0 +1,280 @@
+//! Contains a [`MessageBus`] implementation that delivers messages within a single process.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::bus::{BusMessage, Delivery, MessageBus, MessageStream};
+
+/// The number of messages a subscriber can have waiting before delivery pauses
+const SUBSCRIBER_BUFFER: usize = 64;
+
+/// The number of messages kept on each topic for groups that subscribe from the start
+const RETAINED_MESSAGES: usize = 1024;
+
+/// The queue of messages waiting to be consumed by one consumer group
+#[derive(Debug, Clone)]
+struct Group {
+    sender: mpsc::UnboundedSender<BusMessage>,
+    receiver: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<BusMessage>>>,
+}
+
+impl Group {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            sender,
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+        }
+    }
+}
+
+/// The consumer groups subscribed to a topic
+#[derive(Debug, Default)]
+struct Topic {
+    /// Messages published before any group subscribed, given to the first group to do so
+    pending: Vec<BusMessage>,
+    /// The most recent messages published, given to new groups that subscribe from the start
+    retained: VecDeque<BusMessage>,
+    groups: HashMap<String, Group>,
+}
+
+/// A [`MessageBus`] that delivers messages between components in the same process.
+///
+/// Clones share the same topics, so a single bus can be handed to every component. As with
+/// Kafka, every consumer group receives each message, and subscribers within a group share them.
+/// Messages are lost when the process exits, so committing them has no effect, and only the last
+/// [`RETAINED_MESSAGES`] on each topic are kept for groups that subscribe from the start.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryBus {
+    topics: Arc<Mutex<HashMap<String, Topic>>>,
+}
+
+impl InMemoryBus {
+    /// Creates a new [`InMemoryBus`] with no topics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds the queue for a group on a topic, creating it if this is the first subscription
+    ///
+    /// New groups that join `from_start` receive every retained message on the topic.
+    fn join(&self, group: &str, topic: &str, from_start: bool) -> Result<Group> {
+        let mut topics = self
+            .topics
+            .lock()
+            .map_err(|_| anyhow!("The message bus was poisoned"))?;
+
+        let topic = topics.entry(topic.to_string()).or_default();
+
+        if let Some(existing) = topic.groups.get(group) {
+            return Ok(existing.clone());
+        }
+
+        let created = Group::new();
+
+        // Pending messages are also retained, so they are only sent once
+        if from_start {
+            topic.pending.clear();
+
+            for message in &topic.retained {
+                let _ = created.sender.send(message.clone());
+            }
+        }
+
+        // Nothing has consumed the pending messages yet, so hand them to the first group
+        if topic.groups.is_empty() {
+            for message in topic.pending.drain(..) {
+                let _ = created.sender.send(message);
+            }
+        }
+
+        topic.groups.insert(group.to_string(), created.clone());
+
+        Ok(created)
+    }
+
+    /// Subscribes a group to each topic, forwarding messages from their queues to one stream
+    fn subscribe_to(
+        &self,
+        group: &str,
+        topics: &[&str],
+        from_start: bool,
+    ) -> Result<MessageStream> {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+
+        for topic in topics {
+            let queue = self.join(group, topic, from_start)?.receiver;
+            let sender = sender.clone();
+
+            // Forward messages from the group's queue until the subscriber goes away
+            tokio::spawn(async move {
+                loop {
+                    let message = {
+                        let mut queue = queue.lock().await;
+
+                        tokio::select! {
+                            message = queue.recv() => message,
+                            _ = sender.closed() => None,
+                        }
+                    };
+
+                    // Messages are removed from the queue when received, so there is nothing to commit
+                    match message {
+                        Some(message) if sender.send(Delivery::new(message)).await.is_ok() => {}
+                        _ => break,
+                    }
+                }
+            });
+        }
+
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+}
+
+#[async_trait]
+impl MessageBus for InMemoryBus {
+    async fn produce(&self, topic: &str, key: &str, payload: &str) -> Result<()> {
+        log::debug!(
+            "Sending msg={} in memory with key={} and topic={}",
+            payload,
+            key,
+            topic
+        );
+
+        let message = BusMessage {
+            topic: topic.to_string(),
+            key: Some(key.to_string()),
+            payload: payload.as_bytes().to_vec(),
+            timestamp: Some(Utc::now().timestamp_millis()),
+        };
+
+        let mut topics = self
+            .topics
+            .lock()
+            .map_err(|_| anyhow!("The message bus was poisoned"))?;
+
+        let topic = topics.entry(topic.to_string()).or_default();
+
+        if topic.retained.len() == RETAINED_MESSAGES {
+            topic.retained.pop_front();
+        }
+
+        topic.retained.push_back(message.clone());
+
+        if topic.groups.is_empty() {
+            topic.pending.push(message);
+            return Ok(());
+        }
+
+        for group in topic.groups.values() {
+            // Groups hold their own receiver, so this cannot fail
+            let _ = group.sender.send(message.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+        self.subscribe_to(group, topics, false)
+    }
+
+    async fn subscribe_from_start(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+        self.subscribe_to(group, topics, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    async fn next(stream: &mut MessageStream) -> String {
+        let delivery = stream.next().await.unwrap();
+        String::from_utf8(delivery.message.payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_group_receives_each_message() -> Result<()> {
+        let bus = InMemoryBus::new();
+
+        let mut first = bus.subscribe("first", &["jobs"]).await?;
+        let mut second = bus.subscribe("second", &["jobs"]).await?;
+
+        bus.produce("jobs", "key", "content").await?;
+
+        assert_eq!(next(&mut first).await, "content");
+        assert_eq!(next(&mut second).await, "content");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn messages_are_kept_until_a_group_subscribes() -> Result<()> {
+        let bus = InMemoryBus::new();
+
+        bus.produce("analytics", "a", "early").await?;
+
+        let mut stream = bus.subscribe("analytics", &["analytics"]).await?;
+        bus.produce("analytics", "b", "late").await?;
+
+        assert_eq!(next(&mut stream).await, "early");
+        assert_eq!(next(&mut stream).await, "late");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscriptions_can_cover_multiple_topics() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut stream = bus.subscribe("group", &["first", "second"]).await?;
+
+        bus.produce("second", "user", "update").await?;
+
+        let message = stream.next().await.unwrap().message;
+
+        assert_eq!(message.topic, "second");
+        assert_eq!(message.key.as_deref(), Some("user"));
+        assert!(message.timestamp.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn groups_can_subscribe_from_the_start() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut first = bus.subscribe("first", &["jobs"]).await?;
+
+        bus.produce("jobs", "key", "early").await?;
+        assert_eq!(next(&mut first).await, "early");
+
+        let mut late = bus.subscribe("late", &["jobs"]).await?;
+        let mut replay = bus.subscribe_from_start("replay", &["jobs"]).await?;
+        bus.produce("jobs", "key", "later").await?;
+
+        assert_eq!(next(&mut late).await, "later");
+        assert_eq!(next(&mut replay).await, "early");
+        assert_eq!(next(&mut replay).await, "later");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clones_share_topics() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut stream = bus.subscribe("group", &["jobs"]).await?;
+
+        bus.clone().produce("jobs", "key", "shared").await?;
+
+        assert_eq!(next(&mut stream).await, "shared");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Contains the [`MessageBus`] trait used by every component to send and receive messages.
+//!
+//! Messages are published to topics with a key, and consumed by groups of subscribers. Each
+//! message on a topic is delivered to one subscriber in every group that is subscribed to it,
+//! matching the semantics of Kafka consumer groups.
+//!
+//! Messages are received as [`Delivery`] values, which should only be committed once they have
+//! been processed. The [`Consumer`] wrapper does this for every component, sending messages that
+//! cannot be processed to a dead letter topic instead of losing them.
+
+use std::env;
+use std::fmt::{self, Debug};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio_stream::Stream;
+
+pub mod consumer;
+pub mod dead_letter;
+pub mod kafka;
+pub mod memory;
+
+pub use consumer::Consumer;
+pub use dead_letter::{dead_letter_topic, DeadLetter};
+pub use kafka::{KafkaBus, RetryPolicy};
+pub use memory::InMemoryBus;
+
+/// A message received from a [`MessageBus`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BusMessage {
+    /// The topic the message was published to
+    pub topic: String,
+    /// The key the message was published with, if any
+    pub key: Option<String>,
+    /// The content of the message
+    pub payload: Vec<u8>,
+    /// When the message was published, in milliseconds since the epoch
+    pub timestamp: Option<i64>,
+}
+
+/// Records that a message has been processed, so that it is not delivered to the group again
+pub type Receipt = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// A message received from a subscription, which should be committed once processed
+///
+/// Messages that are never committed are delivered again when the group next subscribes.
+pub struct Delivery {
+    /// The message that was received
+    pub message: BusMessage,
+    receipt: Option<Receipt>,
+}
+
+impl Debug for Delivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Delivery")
+            .field("message", &self.message)
+            .field("committable", &self.receipt.is_some())
+            .finish()
+    }
+}
+
+impl Delivery {
+    /// Creates a [`Delivery`] for a message that does not need to be committed
+    pub fn new(message: BusMessage) -> Self {
+        Self {
+            message,
+            receipt: None,
+        }
+    }
+
+    /// Creates a [`Delivery`] that runs `receipt` when it is committed
+    pub fn with_receipt(message: BusMessage, receipt: Receipt) -> Self {
+        Self {
+            message,
+            receipt: Some(receipt),
+        }
+    }
+
+    /// Marks the message as processed
+    pub fn commit(self) -> Result<()> {
+        match self.receipt {
+            Some(receipt) => receipt(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A stream of messages from a subscription
+pub type MessageStream = Pin<Box<dyn Stream<Item = Delivery> + Send>>;
+
+/// Allows components to publish and consume messages without depending on a specific broker
+#[async_trait]
+pub trait MessageBus: Debug + Send + Sync {
+    /// Publishes a message to a topic with the given key
+    async fn produce(&self, topic: &str, key: &str, payload: &str) -> Result<()>;
+
+    /// Subscribes to a set of topics as part of a consumer group
+    ///
+    /// A group that has not committed anything yet only receives messages published from now on.
+    async fn subscribe(&self, group: &str, topics: &[&str]) -> Result<MessageStream>;
+
+    /// Subscribes to a set of topics, starting from the oldest message that is still retained if
+    /// the group has not committed anything yet
+    ///
+    /// This is used to read topics that messages are published to long before anything consumes
+    /// them, such as dead letter topics. Buses that cannot do so fall back to [`subscribe`].
+    ///
+    /// [`subscribe`]: MessageBus::subscribe
+    async fn subscribe_from_start(&self, group: &str, topics: &[&str]) -> Result<MessageStream> {
+        self.subscribe(group, topics).await
+    }
+}
+
+/// Creates the [`MessageBus`] described by the environment
+///
+/// `MESSAGE_BUS` selects the implementation, either `kafka` (the default) or `memory`. The
+/// in-memory bus only delivers messages within the current process, so it is only useful when
+/// every component is running together.
+pub fn from_env() -> Result<Arc<dyn MessageBus>> {
+    let kind = env::var("MESSAGE_BUS").unwrap_or_else(|_| String::from("kafka"));
+
+    match kind.as_str() {
+        "kafka" => Ok(Arc::new(KafkaBus::from_env()?)),
+        "memory" => {
+            log::warn!("Using an in-memory message bus, messages will not leave this process");
+            Ok(Arc::new(InMemoryBus::new()))
+        }
+        other => Err(anyhow!("Unknown MESSAGE_BUS: {}", other)),
+    }
+}
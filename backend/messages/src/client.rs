@@ -8,10 +8,59 @@ use tokio::net::TcpStream;
 use crate::ReadLengthPrefix;
 use models::jobs::{JobConfiguration, PredictionType};
 
+/// Capabilities and limits that a node declares when authenticating
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodeCapabilities {
+    /// The amount of memory available to the node, in megabytes
+    pub memory_mb: Option<u64>,
+    /// The number of CPU cores available to the node
+    pub cpus: Option<u32>,
+    /// The types of problem the node supports, or all of them if empty
+    #[serde(default)]
+    pub prediction_types: Vec<PredictionType>,
+    /// The largest dataset the node will accept, in rows
+    pub max_dataset_rows: Option<u64>,
+    /// Whether the node answers heartbeats while it is computing predictions
+    #[serde(default)]
+    pub busy_heartbeats: bool,
+}
+
+impl NodeCapabilities {
+    /// Checks whether a node with these capabilities can run a job with the given configuration.
+    ///
+    /// Any requirement the job makes that the node has not declared a value for is treated as
+    /// unsatisfied, whereas limits the node has not declared are treated as unbounded.
+    pub fn satisfies(&self, config: &JobConfiguration) -> bool {
+        let requirements = &config.requirements;
+
+        let memory = match requirements.min_memory_mb {
+            Some(required) => matches!(self.memory_mb, Some(memory) if memory as i64 >= required),
+            None => true,
+        };
+
+        let cpus = match requirements.min_cpus {
+            Some(required) => matches!(self.cpus, Some(cpus) if cpus as i32 >= required),
+            None => true,
+        };
+
+        let prediction_type = self.prediction_types.is_empty()
+            || self.prediction_types.contains(&config.prediction_type);
+
+        let rows = (config.train_size + config.predict_size).max(0) as u64;
+        let size = self.max_dataset_rows.map_or(true, |max| rows <= max);
+
+        memory && cpus && prediction_type && size
+    }
+}
+
 /// Different messages to be passed between DCL and DCN
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum ClientMessage {
     /// Hearbeat alive message
+    ///
+    /// These are sent while a node is idle, and also while it is computing predictions if it
+    /// declared [`NodeCapabilities::busy_heartbeats`], in which case it must respond to them at any
+    /// point, including in between sending progress updates.
     Alive {
         /// The current timestamp
         timestamp: u64,
@@ -55,6 +104,9 @@ pub enum ClientMessage {
         id: String,
         /// The access token itself
         token: String,
+        /// The capabilities and limits of the node, if it declares any
+        #[serde(default)]
+        capabilities: NodeCapabilities,
     },
     /// A dataset for the node to process
     Dataset {
@@ -70,6 +122,24 @@ pub enum ClientMessage {
     },
     /// Prediction data from a node after computation
     Predictions(String),
+    /// Sent by a node instead of predictions when it could not compute them, such as when its
+    /// model failed, so that the DCL does not wait for its deadline
+    PredictionsFailed {
+        /// Why the predictions could not be computed
+        reason: String,
+    },
+    /// Progress update from a node while it is computing
+    Progress {
+        /// The stage the node is currently at, such as training or predicting
+        stage: String,
+        /// The fraction of the job completed so far, between 0 and 1
+        fraction: f64,
+        /// Additional human readable information about the progress
+        message: String,
+    },
+    /// Sent to every connected node when the DCL is shutting down, after which the connection
+    /// will be closed and nodes should reconnect later
+    Shutdown,
 }
 
 impl ClientMessage {
@@ -142,3 +212,87 @@ impl From<&JobConfiguration> for ClientMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::jobs::JobRequirements;
+
+    fn config_with(requirements: JobRequirements) -> JobConfiguration {
+        JobConfiguration {
+            train_size: 1000,
+            predict_size: 100,
+            prediction_type: PredictionType::Regression,
+            requirements,
+            ..JobConfiguration::default()
+        }
+    }
+
+    #[test]
+    fn nodes_without_declarations_satisfy_jobs_without_requirements() {
+        let capabilities = NodeCapabilities::default();
+        let config = config_with(JobRequirements::default());
+
+        assert!(capabilities.satisfies(&config));
+    }
+
+    #[test]
+    fn undeclared_resources_do_not_satisfy_requirements() {
+        let capabilities = NodeCapabilities::default();
+        let config = config_with(JobRequirements {
+            min_memory_mb: Some(2048),
+            min_cpus: None,
+        });
+
+        assert!(!capabilities.satisfies(&config));
+    }
+
+    #[test]
+    fn declared_resources_are_compared_against_requirements() {
+        let capabilities = NodeCapabilities {
+            memory_mb: Some(4096),
+            cpus: Some(2),
+            ..NodeCapabilities::default()
+        };
+
+        let satisfied = config_with(JobRequirements {
+            min_memory_mb: Some(2048),
+            min_cpus: Some(2),
+        });
+        let unsatisfied = config_with(JobRequirements {
+            min_memory_mb: Some(2048),
+            min_cpus: Some(4),
+        });
+
+        assert!(capabilities.satisfies(&satisfied));
+        assert!(!capabilities.satisfies(&unsatisfied));
+    }
+
+    #[test]
+    fn prediction_types_and_dataset_sizes_are_checked() {
+        let config = config_with(JobRequirements::default());
+
+        let classification_only = NodeCapabilities {
+            prediction_types: vec![PredictionType::Classification],
+            ..NodeCapabilities::default()
+        };
+        let small_datasets_only = NodeCapabilities {
+            max_dataset_rows: Some(500),
+            ..NodeCapabilities::default()
+        };
+
+        assert!(!classification_only.satisfies(&config));
+        assert!(!small_datasets_only.satisfies(&config));
+    }
+
+    #[test]
+    fn access_tokens_without_capabilities_can_be_deserialized() {
+        let json = r#"{"AccessToken":{"id":"abc","token":"def"}}"#;
+        let message: ClientMessage = serde_json::from_str(json).unwrap();
+
+        assert!(matches!(
+            message,
+            ClientMessage::AccessToken { capabilities, .. } if capabilities == NodeCapabilities::default()
+        ));
+    }
+}
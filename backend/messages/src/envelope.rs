@@ -0,0 +1,285 @@
+//! Contains the versioned envelope that every event is sent through the [`MessageBus`] in.
+//!
+//! Events are wrapped in an [`Envelope`] recording their type, the version of their payload,
+//! when they were created and a correlation identifier linking related events together. When the
+//! structure of an event changes, its [`Event::VERSION`] is increased and [`Event::upcast`] is
+//! taught to convert the previous version, allowing messages that are already on the bus to
+//! still be read.
+//!
+//! Messages sent before envelopes were introduced, which have neither a `type` nor a `version`,
+//! are treated as version 0 of the event the consumer expects.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::bus::MessageBus;
+
+/// An event that can be sent through the [`MessageBus`]
+pub trait Event: Serialize + DeserializeOwned + Send + Sync {
+    /// The topic the event is published to
+    const TOPIC: &'static str;
+
+    /// The name of the event, such as `job.requested`
+    const TYPE: &'static str;
+
+    /// The current version of the event's payload
+    const VERSION: u32;
+
+    /// Converts the payload of an older `version` of the event to the next version
+    ///
+    /// This is called repeatedly until the payload reaches [`Event::VERSION`].
+    fn upcast(version: u32, payload: Value) -> Result<Value> {
+        let _ = payload;
+        Err(anyhow!(
+            "{} cannot be upcast from version {}",
+            Self::TYPE,
+            version
+        ))
+    }
+}
+
+/// The wrapper every event is sent in
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// The name of the event
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The version of the payload
+    pub version: u32,
+    /// When the event was created, in milliseconds since the epoch
+    pub timestamp: i64,
+    /// Links events that stem from the same request, such as the updates for a job
+    pub correlation_id: String,
+    /// The event itself
+    pub payload: Value,
+}
+
+impl Envelope {
+    /// Wraps an event in an [`Envelope`] with the current version
+    pub fn wrap<E: Event>(event: &E, correlation_id: &str) -> Result<Self> {
+        Ok(Self {
+            event_type: E::TYPE.to_string(),
+            version: E::VERSION,
+            timestamp: Utc::now().timestamp_millis(),
+            correlation_id: correlation_id.to_string(),
+            payload: serde_json::to_value(event)?,
+        })
+    }
+
+    /// Reads the [`Envelope`] for an `E` from a message payload
+    ///
+    /// Payloads without the `type` and `version` of an envelope are assumed to be version 0 of
+    /// `E`, whereas envelopes that cannot be read are rejected.
+    pub fn read<E: Event>(payload: &[u8]) -> Result<Self> {
+        let payload: Value =
+            serde_json::from_slice(payload).context("The message is not valid JSON")?;
+
+        let enveloped = payload.as_object().map_or(false, |fields| {
+            fields.contains_key("type") || fields.contains_key("version")
+        });
+
+        if enveloped {
+            return serde_json::from_value(payload).context("The message has a malformed envelope");
+        }
+
+        Ok(Self {
+            event_type: E::TYPE.to_string(),
+            version: 0,
+            timestamp: Utc::now().timestamp_millis(),
+            correlation_id: String::new(),
+            payload,
+        })
+    }
+
+    /// Unwraps the event, upcasting it from older versions if needed
+    pub fn open<E: Event>(self) -> Result<E> {
+        if self.event_type != E::TYPE {
+            return Err(anyhow!(
+                "Expected a {} event but received {}",
+                E::TYPE,
+                self.event_type
+            ));
+        }
+
+        if self.version > E::VERSION {
+            return Err(anyhow!(
+                "Received version {} of {}, but only up to version {} is understood",
+                self.version,
+                E::TYPE,
+                E::VERSION
+            ));
+        }
+
+        let mut payload = self.payload;
+
+        for version in self.version..E::VERSION {
+            payload = E::upcast(version, payload)?;
+        }
+
+        serde_json::from_value(payload)
+            .with_context(|| format!("Failed to read version {} of {}", E::VERSION, E::TYPE))
+    }
+}
+
+/// Reads an `E` from a message payload, upcasting it from older versions if needed
+pub fn decode<E: Event>(payload: &[u8]) -> Result<E> {
+    let envelope = Envelope::read::<E>(payload)?;
+
+    log::trace!(
+        "Received {} version={} correlation_id={}",
+        envelope.event_type,
+        envelope.version,
+        envelope.correlation_id
+    );
+
+    envelope.open()
+}
+
+/// Publishes an event to its topic, wrapped in an [`Envelope`]
+pub async fn publish<E: Event>(
+    bus: &dyn MessageBus,
+    key: &str,
+    event: &E,
+    correlation_id: &str,
+) -> Result<()> {
+    let message = serde_json::to_string(&Envelope::wrap(event, correlation_id)?)?;
+
+    bus.produce(E::TOPIC, key, &message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::bus::InMemoryBus;
+
+    /// Version 1 was `{ "name": .. }`, version 2 split it into a first and last name
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Renamed {
+        first: String,
+        last: String,
+    }
+
+    impl Event for Renamed {
+        const TOPIC: &'static str = "renamed";
+        const TYPE: &'static str = "test.renamed";
+        const VERSION: u32 = 2;
+
+        fn upcast(version: u32, payload: Value) -> Result<Value> {
+            match version {
+                0 => Ok(serde_json::json!({ "name": payload })),
+                1 => {
+                    let name = payload["name"].as_str().unwrap_or_default();
+                    let mut parts = name.splitn(2, ' ');
+
+                    Ok(serde_json::json!({
+                        "first": parts.next().unwrap_or_default(),
+                        "last": parts.next().unwrap_or_default(),
+                    }))
+                }
+                _ => Err(anyhow!("Unknown version {}", version)),
+            }
+        }
+    }
+
+    fn renamed() -> Renamed {
+        Renamed {
+            first: String::from("Ada"),
+            last: String::from("Lovelace"),
+        }
+    }
+
+    #[test]
+    fn events_survive_a_round_trip() -> Result<()> {
+        let envelope = Envelope::wrap(&renamed(), "correlation")?;
+        let payload = serde_json::to_vec(&envelope)?;
+
+        let read = Envelope::read::<Renamed>(&payload)?;
+
+        assert_eq!(read.event_type, "test.renamed");
+        assert_eq!(read.version, 2);
+        assert_eq!(read.correlation_id, "correlation");
+        assert_eq!(read.open::<Renamed>()?, renamed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn older_versions_are_upcast() -> Result<()> {
+        let payload = serde_json::json!({
+            "type": "test.renamed",
+            "version": 1,
+            "timestamp": 0,
+            "correlation_id": "",
+            "payload": { "name": "Ada Lovelace" },
+        });
+
+        let event: Renamed = decode(payload.to_string().as_bytes())?;
+        assert_eq!(event, renamed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn messages_without_an_envelope_are_version_zero() -> Result<()> {
+        let event: Renamed = decode(br#""Ada Lovelace""#)?;
+        assert_eq!(event, renamed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_envelopes_are_not_read_as_version_zero() {
+        let missing_payload = serde_json::json!({
+            "type": "test.renamed",
+            "version": 2,
+            "timestamp": 0,
+            "correlation_id": "",
+        });
+        let unversioned = serde_json::json!({
+            "type": "test.renamed",
+            "payload": { "first": "Ada", "last": "Lovelace" },
+        });
+
+        assert!(Envelope::read::<Renamed>(missing_payload.to_string().as_bytes()).is_err());
+        assert!(Envelope::read::<Renamed>(unversioned.to_string().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn newer_versions_and_other_types_are_rejected() {
+        let newer = serde_json::json!({
+            "type": "test.renamed",
+            "version": 3,
+            "timestamp": 0,
+            "correlation_id": "",
+            "payload": {},
+        });
+        let other = serde_json::json!({
+            "type": "test.other",
+            "version": 1,
+            "timestamp": 0,
+            "correlation_id": "",
+            "payload": {},
+        });
+
+        assert!(decode::<Renamed>(newer.to_string().as_bytes()).is_err());
+        assert!(decode::<Renamed>(other.to_string().as_bytes()).is_err());
+    }
+
+    #[tokio::test]
+    async fn events_are_published_to_their_topic() -> Result<()> {
+        let bus = InMemoryBus::new();
+        let mut stream = bus.subscribe("group", &["renamed"]).await?;
+
+        publish(&bus, "key", &renamed(), "correlation").await?;
+
+        let message = stream.next().await.unwrap().message;
+        assert_eq!(decode::<Renamed>(&message.payload)?, renamed());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Contains the events sent between the API server, the DCL and the Analytics Server.
+
+use anyhow::{anyhow, Result};
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+
+use models::jobs::Job;
+
+use crate::envelope::Event;
+
+/// Sent to the DCL when a job should be run, either for the first time or after being requeued
+///
+/// Only the job's identifiers are sent, so that the event does not change whenever a [`Job`]
+/// does. The DCL reads the job itself from the database when it receives the event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobRequested {
+    /// The job to run
+    pub job_id: ObjectId,
+    /// The project the job is for
+    pub project_id: ObjectId,
+}
+
+impl From<&Job> for JobRequested {
+    fn from(job: &Job) -> Self {
+        Self {
+            job_id: job.id.clone(),
+            project_id: job.config.project_id.clone(),
+        }
+    }
+}
+
+impl Event for JobRequested {
+    const TOPIC: &'static str = "jobs";
+    const TYPE: &'static str = "job.requested";
+    const VERSION: u32 = 2;
+
+    fn upcast(version: u32, payload: Value) -> Result<Value> {
+        match version {
+            // Before envelopes, the job itself was sent
+            0 => Ok(json!({ "job": payload })),
+            // Version 1 embedded the whole job
+            1 => {
+                let job = &payload["job"];
+
+                if job.get("_id").is_none() || job["config"].get("project_id").is_none() {
+                    return Err(anyhow!("Version 1 of {} is missing its job", Self::TYPE));
+                }
+
+                Ok(json!({
+                    "job_id": job["_id"],
+                    "project_id": job["config"]["project_id"],
+                }))
+            }
+            _ => Ok(payload),
+        }
+    }
+}
+
+/// Sent to the Analytics Server when a project's dataset should be analysed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsRequested {
+    /// The project whose dataset should be analysed
+    pub project_id: ObjectId,
+}
+
+impl Event for AnalyticsRequested {
+    const TOPIC: &'static str = "analytics";
+    const TYPE: &'static str = "analytics.requested";
+    const VERSION: u32 = 1;
+
+    fn upcast(version: u32, payload: Value) -> Result<Value> {
+        match version {
+            // Before envelopes, the project identifier itself was sent
+            0 => Ok(json!({ "project_id": payload })),
+            _ => Ok(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::{decode, Envelope};
+    use models::jobs::JobConfiguration;
+
+    #[test]
+    fn jobs_sent_without_an_envelope_can_be_read() -> Result<()> {
+        let job = Job::new(JobConfiguration::default());
+        let legacy = serde_json::to_vec(&job)?;
+
+        let event: JobRequested = decode(&legacy)?;
+        assert_eq!(event, JobRequested::from(&job));
+
+        Ok(())
+    }
+
+    #[test]
+    fn jobs_embedded_in_version_one_can_be_read() -> Result<()> {
+        let job = Job::new(JobConfiguration::default());
+        let payload = serde_json::json!({
+            "type": JobRequested::TYPE,
+            "version": 1,
+            "timestamp": 0,
+            "correlation_id": "",
+            "payload": { "job": job },
+        });
+
+        let event: JobRequested = decode(payload.to_string().as_bytes())?;
+        assert_eq!(event.job_id, job.id);
+        assert_eq!(event.project_id, job.config.project_id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn analytics_requests_sent_without_an_envelope_can_be_read() -> Result<()> {
+        let project_id = ObjectId::new();
+        let legacy = serde_json::to_vec(&project_id)?;
+
+        let event: AnalyticsRequested = decode(&legacy)?;
+        assert_eq!(event.project_id, project_id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn analytics_requests_are_enveloped() -> Result<()> {
+        let event = AnalyticsRequested {
+            project_id: ObjectId::new(),
+        };
+
+        let envelope = Envelope::wrap(&event, "correlation")?;
+
+        assert_eq!(envelope.event_type, "analytics.requested");
+        assert_eq!(envelope.version, AnalyticsRequested::VERSION);
+        assert_eq!(envelope.open::<AnalyticsRequested>()?, event);
+
+        Ok(())
+    }
+}
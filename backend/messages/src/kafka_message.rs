@@ -1,10 +1,7 @@
-//! Contains the functions to define messages sent through Kafka and the method to produce a message
+//! Contains the messages sent through the [`MessageBus`] to update websockets
 
-use std::env;
-use std::net::{Ipv4Addr, SocketAddrV4};
-use std::str::FromStr;
-
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
 
 use models::projects::Project;
 use mongodb::{
@@ -12,50 +9,21 @@ use mongodb::{
     Database,
 };
 
-use rdkafka::config::ClientConfig;
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::util::Timeout;
-
-/// Produces a message for Kafka accepting a message, the key and the topic to produce into
-pub async fn produce_message(msg: &str, key: &str, topic: &str) {
-    // Get the environment variable for the kafka broker
-    // if not set use 9092
-    let var = env::var("BROKER_PORT").unwrap_or_else(|_| "9092".to_string());
-    let port = u16::from_str(&var).expect("BROKER_PORT must be a u16");
-
-    log::debug!(
-        "Sending msg={} to Kafka with key={} and topic={}",
-        msg,
-        key,
-        topic
-    );
-
-    // Build the address to send to
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port).to_string();
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &addr)
-        .set("message.timeout.ms", "5000")
-        .create()
-        .expect("Producer creation error");
-
-    if let Err(e) = producer
-        .send(
-            FutureRecord::to(topic).payload(msg).key(key),
-            Timeout::Never,
-        )
-        .await
-    {
-        log::warn!("Failed to send the message to Kafka: {:?}", e);
-    }
-}
+use crate::bus::MessageBus;
+use crate::envelope::{self, Event};
 
 /// Enum defining all messages sent through Kafka to update a websocket
-#[derive(Debug, Serialize, Deserialize)]
-pub enum KafkaWsMessage<'a> {
+///
+/// Version 2 added every variant after `ClientProgressMessage`. Consumers that only understand
+/// version 1 reject version 2 envelopes, so their updates are dead-lettered to
+/// `project_updates.dlq` and can be replayed once the consumer is upgraded. Adding a variant
+/// changes what consumers may receive, so it should always increase the version.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum KafkaWsMessage {
     /// Message produced when a Model completes
     ClientCompleteMessage {
         /// project id which the client completed
-        project_id: &'a str,
+        project_id: String,
         /// the cluster size
         cluster_size: usize,
         /// The number of models completed for this project
@@ -66,16 +34,109 @@ pub enum KafkaWsMessage<'a> {
     /// Message sent when a project is completed
     JobCompleteMessage {
         /// Project id which job completed
-        project_id: &'a str,
+        project_id: String,
+    },
+    /// Message produced when a Model reports its progress
+    ClientProgressMessage {
+        /// project id which the client is working on
+        project_id: String,
+        /// The stage the model is currently at
+        stage: String,
+        /// The fraction of the job the model has completed
+        fraction: f64,
+        /// Additional information provided by the model
+        message: String,
+    },
+    /// Message produced when an instance of the DCL claims a job from its queue
+    JobQueuedMessage {
+        /// Project id which the job is for
+        project_id: String,
+        /// The id of the job that was queued
+        job_id: String,
+    },
+    /// Message produced when there are not enough nodes available to run a job
+    WaitingForNodesMessage {
+        /// Project id which the job is for
+        project_id: String,
+        /// The number of nodes the job needs
+        cluster_size: usize,
     },
+    /// Message produced when a cluster of nodes has been chosen to run a job
+    ClusterBuiltMessage {
+        /// Project id which the job is for
+        project_id: String,
+        /// The number of nodes in the cluster
+        cluster_size: usize,
+    },
+    /// Message produced when the data for a job has been sent to a node
+    DataSentMessage {
+        /// Project id which the data belongs to
+        project_id: String,
+    },
+    /// Message produced when the analysis of a project's dataset is finished
+    AnalysisCompleteMessage {
+        /// Project id which was analysed
+        project_id: String,
+    },
+    /// Message produced when a job cannot be run
+    JobFailedMessage {
+        /// Project id which the job is for
+        project_id: String,
+        /// Why the job failed
+        reason: String,
+    },
+}
+
+impl Event for KafkaWsMessage {
+    const TOPIC: &'static str = "project_updates";
+    const TYPE: &'static str = "project.updated";
+    const VERSION: u32 = 2;
+
+    fn upcast(version: u32, payload: Value) -> Result<Value> {
+        match version {
+            // The first version was sent without an envelope, but is otherwise the same
+            0 => Ok(payload),
+            // The second version only added variants, so every first version payload is valid
+            1 => Ok(payload),
+            _ => Err(anyhow!(
+                "{} cannot be upcast from version {}",
+                Self::TYPE,
+                version
+            )),
+        }
+    }
 }
 
-impl KafkaWsMessage<'_> {
-    /// Produce a message for Kafka
+impl KafkaWsMessage {
+    /// Gets the id of the project the message is about
+    pub fn project_id(&self) -> &str {
+        match self {
+            Self::ClientCompleteMessage { project_id, .. }
+            | Self::JobCompleteMessage { project_id }
+            | Self::ClientProgressMessage { project_id, .. }
+            | Self::JobQueuedMessage { project_id, .. }
+            | Self::WaitingForNodesMessage { project_id, .. }
+            | Self::ClusterBuiltMessage { project_id, .. }
+            | Self::DataSentMessage { project_id }
+            | Self::AnalysisCompleteMessage { project_id }
+            | Self::JobFailedMessage { project_id, .. } => project_id,
+        }
+    }
+
+    /// Produce a message on the bus
     /// if a client message increment the success status and get user key
     /// if job complete get user key
-    pub async fn produce(&self, database: &Database) -> Result<()> {
-        let (doc, message) = match self {
+    ///
+    /// `correlation_id` should identify the job the update is about. Failing to publish the
+    /// message is returned as an error, so callers that can continue without it should use
+    /// [`KafkaWsMessage::notify`] instead.
+    pub async fn produce(
+        &self,
+        database: &Database,
+        bus: &dyn MessageBus,
+        correlation_id: &str,
+    ) -> Result<()> {
+        let doc = match self {
             Self::ClientCompleteMessage {
                 project_id,
                 success,
@@ -83,8 +144,7 @@ impl KafkaWsMessage<'_> {
             } => {
                 let projects = database.collection("projects");
 
-                let message_str = serde_json::to_string(&self).unwrap();
-                let filter = doc! {"_id": ObjectId::with_string(project_id).unwrap()};
+                let filter = doc! {"_id": ObjectId::with_string(project_id)?};
 
                 let update = if *success {
                     doc! {"$inc": {"status.Processing.model_success": 1}}
@@ -92,33 +152,64 @@ impl KafkaWsMessage<'_> {
                     doc! {"$inc": {"status.Processing.model_err": 1}}
                 };
 
-                let doc = projects
+                projects
                     .find_one_and_update(filter, update, None)
                     .await?
-                    .expect("Failed to find project in db");
-
-                (doc, message_str)
+                    .ok_or_else(|| anyhow!("Failed to find project_id={}", project_id))?
             }
-            Self::JobCompleteMessage { project_id } => {
+            _ => {
                 let projects = database.collection("projects");
+                let project_id = self.project_id();
 
-                let message_str = serde_json::to_string(&self).unwrap();
-                let filter = doc! {"_id": ObjectId::with_string(project_id).unwrap()};
+                let filter = doc! {"_id": ObjectId::with_string(project_id)?};
 
-                let doc = projects
+                projects
                     .find_one(filter, None)
                     .await?
-                    .expect("Failed to find project in db");
-
-                (doc, message_str)
+                    .ok_or_else(|| anyhow!("Failed to find project_id={}", project_id))?
             }
         };
 
         let project: Project = from_document(doc)?;
         let message_key = project.user_id.to_string();
-        let topic = "project_updates";
 
-        produce_message(&message, &message_key, &topic).await;
+        envelope::publish(bus, &message_key, self, correlation_id).await
+    }
+
+    /// Produces the message, logging any failure instead of returning it
+    ///
+    /// Lifecycle updates are informational, so failing to send one should not stop processing.
+    pub async fn notify(&self, database: &Database, bus: &dyn MessageBus, correlation_id: &str) {
+        if let Err(e) = self.produce(database, bus, correlation_id).await {
+            log::warn!("Failed to send a project update: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::decode;
+
+    #[test]
+    fn first_version_updates_are_still_read() -> Result<()> {
+        let payload = serde_json::json!({
+            "type": "project.updated",
+            "version": 1,
+            "timestamp": 0,
+            "correlation_id": "",
+            "payload": { "JobCompleteMessage": { "project_id": "project" } },
+        });
+
+        let message: KafkaWsMessage = decode(payload.to_string().as_bytes())?;
+
+        assert_eq!(
+            message,
+            KafkaWsMessage::JobCompleteMessage {
+                project_id: String::from("project")
+            }
+        );
+
         Ok(())
     }
 }
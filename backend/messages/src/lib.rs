@@ -2,18 +2,28 @@
 //!
 //! interface contains messages which are shared across the interface.
 //! client contains messages for communication with clients (DCNs)
+//! bus contains the message bus used to communicate between components
+//! envelope and events contain the versioned events sent through the bus
 
 #![warn(missing_docs)]
 
 #[macro_use]
 extern crate serde;
 
+pub mod bus;
 pub mod client;
+pub mod envelope;
+pub mod events;
 pub mod kafka_message;
 pub mod length_prefix;
 pub mod raw_message;
 
-pub use client::ClientMessage;
+pub use bus::{
+    BusMessage, Consumer, DeadLetter, Delivery, InMemoryBus, KafkaBus, MessageBus, MessageStream,
+};
+pub use client::{ClientMessage, NodeCapabilities};
+pub use envelope::{Envelope, Event};
+pub use events::{AnalyticsRequested, JobRequested};
 pub use kafka_message::KafkaWsMessage;
 pub use length_prefix::{ReadLengthPrefix, WriteLengthPrefix};
 pub use raw_message::RawMessage;
@@ -0,0 +1,195 @@
+//! Authenticates models directly against the `MongoDB` instance.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::bson::de::from_document;
+use mongodb::bson::ser::to_document;
+use mongodb::bson::{self, doc, oid::ObjectId, Binary};
+use mongodb::Database;
+
+use models::models::{AccessToken, ClientModel};
+use models::users::{Client, User};
+
+use crate::{AuthBackend, AuthError, AuthResult, Challenge, Token};
+
+/// Authenticates models using the database directly
+#[derive(Clone, Debug)]
+pub struct DatabaseBackend {
+    database: Arc<Database>,
+    pepper: Arc<String>,
+}
+
+impl DatabaseBackend {
+    /// Creates a new [`DatabaseBackend`] using the pepper that passwords were hashed with
+    pub fn new(database: Arc<Database>, pepper: Arc<String>) -> Self {
+        Self { database, pepper }
+    }
+
+    /// Finds a user by their email, verifying their password if one is given
+    async fn find_user(&self, email: &str, password: Option<&str>) -> AuthResult<User> {
+        let users = self.database.collection("users");
+
+        let filter = doc! { "email": crypto::clean(email) };
+        let document = users
+            .find_one(filter, None)
+            .await?
+            .ok_or(AuthError::NotFound)?;
+
+        let user: User = from_document(document)?;
+
+        if let Some(password) = password {
+            let peppered = format!("{}{}", password, &self.pepper);
+            crypto::verify_password(&peppered, &user.hash)?;
+        }
+
+        Ok(user)
+    }
+}
+
+#[async_trait]
+impl AuthBackend for DatabaseBackend {
+    async fn new_model(
+        &self,
+        email: &str,
+        password: &str,
+        model_name: &str,
+    ) -> AuthResult<Challenge> {
+        let models = self.database.collection("models");
+
+        let user = self.find_user(email, Some(password)).await?;
+
+        if !user.client {
+            return Err(AuthError::Forbidden);
+        }
+
+        let filter = doc! { "user_id": &user.id, "name": model_name };
+        if models.find_one(filter, None).await?.is_some() {
+            log::warn!(
+                "User with id={} already has a model with name={}",
+                user.id,
+                model_name
+            );
+
+            return Err(AuthError::Conflict);
+        }
+
+        // Generate challenge
+        let challenge = crypto::generate_challenge();
+        let client_model = ClientModel::new(user.id, model_name.to_string(), challenge.clone());
+
+        // insert model into database
+        let document = to_document(&client_model)?;
+        models.insert_one(document, None).await?;
+
+        Ok(Challenge {
+            challenge: base64::encode(challenge),
+        })
+    }
+
+    async fn verify_challenge(
+        &self,
+        email: &str,
+        model_name: &str,
+        challenge_response: &str,
+    ) -> AuthResult<Token> {
+        let clients = self.database.collection("clients");
+        let models = self.database.collection("models");
+
+        let user = self.find_user(email, None).await?;
+
+        // get clients public key matching with that users id
+        let filter = doc! { "user_id": &user.id };
+        let client_doc = clients
+            .find_one(filter, None)
+            .await?
+            .ok_or(AuthError::NotFound)?;
+        let client: Client = from_document(client_doc)?;
+
+        let filter = doc! { "user_id": &user.id, "name": model_name };
+        let model_doc = models
+            .find_one(filter.clone(), None)
+            .await?
+            .ok_or(AuthError::NotFound)?;
+        let mut model: ClientModel = from_document(model_doc)?;
+
+        let challenge = &model.challenge.ok_or(AuthError::Unauthorized)?.bytes;
+
+        // needs converting to Vec<u8>
+        let challenge_response = base64::decode(challenge_response)?;
+
+        if !crypto::verify_challenge(&challenge, &challenge_response, &client.public_key) {
+            log::warn!(
+                "Provided challenge did not match expected, deleting model_id={}",
+                model.id
+            );
+
+            models.delete_one(filter, None).await?;
+
+            return Err(AuthError::Unauthorized);
+        }
+
+        let access_token = AccessToken::new();
+        model.authenticated = true;
+        model.access_token = Some(access_token.clone());
+        model.challenge = None;
+
+        let update = doc! { "$set": to_document(&model)? };
+        models.find_one_and_update(filter, update, None).await?;
+
+        Ok(Token {
+            id: model.id.to_string(),
+            token: base64::encode(access_token.token.bytes),
+            expires: access_token.expires.to_rfc3339(),
+        })
+    }
+
+    async fn authenticate_model(&self, model_id: &str, token: &str) -> AuthResult<()> {
+        let models = self.database.collection("models");
+
+        let model_id = ObjectId::with_string(model_id)?;
+        let filter = doc! { "_id": &model_id };
+        let model_doc = models
+            .find_one(filter, None)
+            .await?
+            .ok_or(AuthError::Unauthorized)?;
+        let mut model: ClientModel = from_document(model_doc)?;
+
+        if model.locked {
+            return Err(AuthError::Locked);
+        }
+
+        let decoded = base64::decode(token)?;
+
+        if !model.is_authenticated(&decoded) {
+            log::warn!(
+                "Model failed to authenticate with token={} (base-64)",
+                token
+            );
+
+            return Err(AuthError::Unauthorized);
+        }
+
+        // Check whether their token has expired
+        if model.token_has_not_expired() {
+            return Ok(());
+        }
+
+        log::warn!("Model with id={} has an expired token", model.id);
+
+        let challenge = crypto::generate_challenge();
+        model.authenticated = false;
+        model.challenge = Some(Binary {
+            subtype: bson::spec::BinarySubtype::Generic,
+            bytes: challenge.clone(),
+        });
+
+        let filter = doc! { "_id": &model_id };
+        let update = doc! { "$set": to_document(&model)? };
+        models.find_one_and_update(filter, update, None).await?;
+
+        Err(AuthError::Expired {
+            challenge: base64::encode(challenge),
+        })
+    }
+}
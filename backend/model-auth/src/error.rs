@@ -0,0 +1,90 @@
+//! Defines the errors that can occur while authenticating models.
+
+use mongodb::bson;
+use thiserror::Error;
+
+/// Defines a custom result type for authentication.
+pub type AuthResult<T> = std::result::Result<T, AuthError>;
+
+/// Defines the reasons authentication can fail
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum AuthError {
+    /// The user, client or model does not exist
+    #[error("Requested user or model was not found")]
+    NotFound,
+    /// The user is not a client
+    #[error("User is not a client")]
+    Forbidden,
+    /// The user already has a model with the given name
+    #[error("A model with this name already exists")]
+    Conflict,
+    /// The credentials, challenge response or token were incorrect
+    #[error("Unauthorized")]
+    Unauthorized,
+    /// The model has not been unlocked through the website
+    #[error("Locked")]
+    Locked,
+    /// The model's access token has expired and it must complete a new challenge
+    #[error("Access token has expired")]
+    Expired {
+        /// The base-64 encoded challenge the model must sign
+        challenge: String,
+    },
+    /// The request was malformed
+    #[error("Unprocessable Entity")]
+    Invalid,
+    /// The backend failed to process the request
+    #[error("Internal error: {0}")]
+    Internal(String),
+    /// The remote authentication service could not be reached
+    #[error("Authentication service unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl AuthError {
+    /// Gets the HTTP status code corresponding to the error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::NotFound => 404,
+            Self::Forbidden => 403,
+            Self::Conflict => 409,
+            Self::Unauthorized | Self::Locked | Self::Expired { .. } => 401,
+            Self::Invalid => 422,
+            Self::Internal(_) => 500,
+            Self::Unavailable(_) => 503,
+        }
+    }
+}
+
+/// Allows impl From<Error> to be generated more easily for various errors
+macro_rules! error_map {
+    ($($error:path => $code:ident,)*) => {
+        $(
+            impl From<$error> for AuthError {
+                fn from(_error: $error) -> Self {
+                    Self::$code
+                }
+            }
+        )*
+    }
+}
+
+error_map! {
+    bson::oid::Error => Invalid,
+    bson::ser::Error => Invalid,
+    bson::de::Error => Invalid,
+    base64::DecodeError => Invalid,
+    pbkdf2::password_hash::VerifyError => Unauthorized,
+}
+
+impl From<mongodb::error::Error> for AuthError {
+    fn from(error: mongodb::error::Error) -> Self {
+        Self::Internal(error.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AuthError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Unavailable(error.to_string())
+    }
+}
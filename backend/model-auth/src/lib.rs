@@ -0,0 +1,93 @@
+//! Authentication of compute nodes for the Sybl project.
+//!
+//! Nodes register models, respond to challenges and authenticate with access tokens before they
+//! are allowed to compute. This logic is shared between the API server and the DCL through the
+//! [`AuthBackend`] trait, which can either be backed directly by `MongoDB` through
+//! [`DatabaseBackend`] or forwarded to a remote API server through [`RemoteBackend`].
+
+#![warn(rust_2018_idioms)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_docs)]
+
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+pub mod database;
+pub mod error;
+pub mod remote;
+
+pub use database::DatabaseBackend;
+pub use error::{AuthError, AuthResult};
+pub use remote::RemoteBackend;
+
+/// The message sent to a node once it has authenticated successfully
+pub const AUTHENTICATION_SUCCESSFUL: &str = "Authentication successful";
+
+/// A challenge that a node must sign with its private key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// The base-64 encoded challenge bytes
+    pub challenge: String,
+}
+
+/// An access token issued to a node after it completes a challenge
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    /// The identifier of the model the token belongs to
+    pub id: String,
+    /// The base-64 encoded token
+    pub token: String,
+    /// When the token expires, in RFC 3339 format
+    pub expires: String,
+}
+
+/// The responses sent to a node while it registers a model
+///
+/// These are serialized with the variant as the key, such as `{"Challenge": {"challenge": ...}}`,
+/// which is the format nodes expect to receive.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AuthResponse {
+    /// A challenge for a newly registered model
+    Challenge(Challenge),
+    /// An access token for a model that completed its challenge
+    AccessToken(Token),
+}
+
+/// Authenticates models on behalf of nodes
+#[async_trait]
+pub trait AuthBackend: Debug + Send + Sync {
+    /// Registers a new model for a given user.
+    ///
+    /// Checks whether the given user is a client and that they do not already have a model with
+    /// the given name, before creating it and returning a challenge for the user to sign.
+    async fn new_model(
+        &self,
+        email: &str,
+        password: &str,
+        model_name: &str,
+    ) -> AuthResult<Challenge>;
+
+    /// Verifies a challenge response from a client.
+    ///
+    /// If the response was signed with the client's private key, the model is authenticated and
+    /// an access token is returned. Otherwise, the model is deleted and the client must register
+    /// it again.
+    async fn verify_challenge(
+        &self,
+        email: &str,
+        model_name: &str,
+        challenge_response: &str,
+    ) -> AuthResult<Token>;
+
+    /// Authenticates a model using its access token.
+    ///
+    /// Ensures the model is authenticated and unlocked and that the token matches. If the token
+    /// has expired, returns [`AuthError::Expired`] with a new challenge for the client to sign.
+    async fn authenticate_model(&self, model_id: &str, token: &str) -> AuthResult<()>;
+}
@@ -0,0 +1,141 @@
+//! Authenticates models by forwarding requests to a remote API server.
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{AuthBackend, AuthError, AuthResponse, AuthResult, Challenge, Token};
+
+/// Authenticates models using the routes of an API server
+#[derive(Clone, Debug)]
+pub struct RemoteBackend {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl RemoteBackend {
+    /// Creates a new [`RemoteBackend`] for the API server at `base_url`, such as
+    /// `http://localhost:3001`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+
+        Self {
+            base_url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Posts the body to an endpoint, returning the response text if it succeeded
+    async fn post<S: Serialize + Sync>(&self, endpoint: &str, body: &S) -> AuthResult<String> {
+        let url = format!("{}{}", self.base_url, endpoint);
+
+        log::debug!("Sending an authentication request to {}", &url);
+
+        let response = self.client.post(&url).json(body).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        log::debug!("Response body: {:?}", text);
+
+        if status.is_success() {
+            Ok(text)
+        } else {
+            Err(error_from_response(status, &text))
+        }
+    }
+
+    /// Posts the body to an endpoint and parses the response as an [`AuthResponse`]
+    async fn post_for_response<S: Serialize + Sync>(
+        &self,
+        endpoint: &str,
+        body: &S,
+    ) -> AuthResult<AuthResponse> {
+        let text = self.post(endpoint, body).await?;
+
+        serde_json::from_str(&text).map_err(|e| AuthError::Internal(e.to_string()))
+    }
+}
+
+/// Converts an unsuccessful response from the API server into an [`AuthError`]
+fn error_from_response(status: StatusCode, text: &str) -> AuthError {
+    match status {
+        StatusCode::NOT_FOUND => AuthError::NotFound,
+        StatusCode::FORBIDDEN => AuthError::Forbidden,
+        StatusCode::CONFLICT => AuthError::Conflict,
+        StatusCode::UNPROCESSABLE_ENTITY => AuthError::Invalid,
+        StatusCode::UNAUTHORIZED => {
+            // Locked models and expired tokens are distinguished by the body
+            let body: Value = serde_json::from_str(text).unwrap_or_default();
+
+            if let Some(challenge) = body["challenge"].as_str() {
+                AuthError::Expired {
+                    challenge: challenge.to_string(),
+                }
+            } else if body["message"] == "Locked" {
+                AuthError::Locked
+            } else {
+                AuthError::Unauthorized
+            }
+        }
+        _ => AuthError::Internal(format!("API server returned status={}", status.as_u16())),
+    }
+}
+
+#[async_trait]
+impl AuthBackend for RemoteBackend {
+    async fn new_model(
+        &self,
+        email: &str,
+        password: &str,
+        model_name: &str,
+    ) -> AuthResult<Challenge> {
+        let body = json!({
+            "email": email,
+            "password": password,
+            "modelName": model_name,
+        });
+
+        match self
+            .post_for_response("/api/clients/models/new", &body)
+            .await?
+        {
+            AuthResponse::Challenge(challenge) => Ok(challenge),
+            AuthResponse::AccessToken(_) => Err(AuthError::Internal(String::from(
+                "API server returned an access token instead of a challenge",
+            ))),
+        }
+    }
+
+    async fn verify_challenge(
+        &self,
+        email: &str,
+        model_name: &str,
+        challenge_response: &str,
+    ) -> AuthResult<Token> {
+        let body = json!({
+            "modelName": model_name,
+            "email": email,
+            "challengeResponse": challenge_response,
+        });
+
+        match self
+            .post_for_response("/api/clients/models/verify", &body)
+            .await?
+        {
+            AuthResponse::AccessToken(token) => Ok(token),
+            AuthResponse::Challenge(_) => Err(AuthError::Internal(String::from(
+                "API server returned a challenge instead of an access token",
+            ))),
+        }
+    }
+
+    async fn authenticate_model(&self, model_id: &str, token: &str) -> AuthResult<()> {
+        let body = json!({ "token": token });
+        let endpoint = format!("/api/clients/models/{}/authenticate", model_id);
+
+        self.post(&endpoint, &body).await?;
+
+        Ok(())
+    }
+}
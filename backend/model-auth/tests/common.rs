@@ -0,0 +1,121 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use mongodb::bson::{self, document::Document, oid::ObjectId};
+use mongodb::Database;
+
+use config::Environment;
+use model_auth::DatabaseBackend;
+use models::users::{Client, User};
+
+// Hardcoded random identifiers for various tests
+pub static USER_ID: &str = "60a3d1e84e8a7634fd3f8a01";
+pub static CLIENT_USER_ID: &str = "60a3d1ee4e8a7634fd3f8a02";
+
+pub static USER_EMAIL: &str = "user@email.com";
+pub static CLIENT_EMAIL: &str = "client@email.com";
+pub static PASSWORD: &str = "password";
+
+/// Allows for the setup of the database prior to testing.
+static MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Defines what each test needs to authenticate models against the database.
+pub struct Context {
+    /// The database the backend uses
+    pub database: Arc<Database>,
+    /// The backend under test
+    pub backend: DatabaseBackend,
+    /// The private key of the client, for signing challenges
+    pub private_key: String,
+    /// Prevents tests from running against the database concurrently
+    _lock: tokio::sync::MutexGuard<'static, ()>,
+}
+
+/// Defines the initialisation function for the tests.
+///
+/// This will clean the database and insert a user and a client with a freshly generated key pair.
+/// It should be called at the beginning of every test function, and holds a lock until the returned
+/// [`Context`] is dropped so that tests do not interfere with each other.
+pub async fn initialise() -> Context {
+    // Acquire the mutex
+    let lock = MUTEX.lock().await;
+
+    let config = config::ConfigFile::from_filesystem();
+    let resolved = config.resolve(Environment::Testing);
+    resolved.populate_environment();
+
+    // Connect to the database
+    let conn_str = env::var("CONN_STR").expect("CONN_STR must be set");
+    let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| String::from("sybl"));
+    let pepper = env::var("PEPPER").expect("PEPPER must be set");
+
+    // Ensure that we aren't using the Atlas instance
+    assert!(
+        !conn_str.starts_with("mongodb+srv"),
+        "Please setup a local MongoDB instance for running the tests"
+    );
+    let client = mongodb::Client::with_uri_str(&conn_str).await.unwrap();
+    let database = client.database(&database_name);
+    let collection_names = database.list_collection_names(None).await.unwrap();
+
+    // Delete all records currently in the database
+    for name in collection_names {
+        let collection = database.collection(&name);
+        collection.delete_many(Document::new(), None).await.unwrap();
+    }
+
+    let private_key = insert_test_users(&database, &pepper).await;
+
+    let database = Arc::new(database);
+    let backend = DatabaseBackend::new(Arc::clone(&database), Arc::new(pepper));
+
+    Context {
+        database,
+        backend,
+        private_key,
+        _lock: lock,
+    }
+}
+
+/// Inserts a user and a client, returning the client's private key.
+async fn insert_test_users(database: &Database, pepper: &str) -> String {
+    let users = database.collection("users");
+    let clients = database.collection("clients");
+
+    let peppered = format!("{}{}", PASSWORD, pepper);
+    let pbkdf2_iterations =
+        u32::from_str(&env::var("PBKDF2_ITERATIONS").expect("PBKDF2_ITERATIONS must be set"))
+            .unwrap();
+    let hash = crypto::hash_password(&peppered, pbkdf2_iterations).unwrap();
+
+    let mut user = User::new(USER_EMAIL, hash.as_str(), "Matthew", "Smith");
+    user.id = ObjectId::with_string(USER_ID).unwrap();
+
+    let mut client_user = User::new(CLIENT_EMAIL, hash.as_str(), "Freddie", "Brown");
+    client_user.id = ObjectId::with_string(CLIENT_USER_ID).unwrap();
+    client_user.client = true;
+
+    let (private_key, public_key) = crypto::encoded_key_pair();
+    let client = Client::new(client_user.id.clone(), public_key);
+
+    users
+        .insert_many(
+            vec![
+                bson::ser::to_document(&user).unwrap(),
+                bson::ser::to_document(&client_user).unwrap(),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+
+    clients
+        .insert_one(bson::ser::to_document(&client).unwrap(), None)
+        .await
+        .unwrap();
+
+    private_key
+}
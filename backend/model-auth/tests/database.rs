@@ -0,0 +1,306 @@
+use chrono::{Duration, Utc};
+use mongodb::bson::{self, doc, oid::ObjectId};
+
+use model_auth::{AuthBackend, AuthError, Token};
+use models::models::ClientModel;
+
+mod common;
+
+use common::{Context, CLIENT_EMAIL, PASSWORD, USER_EMAIL};
+
+/// Fetches a model from the database by its name, if it exists.
+async fn find_model(context: &Context, name: &str) -> Option<ClientModel> {
+    let models = context.database.collection("models");
+    let filter = doc! { "name": name };
+
+    models
+        .find_one(filter, None)
+        .await
+        .unwrap()
+        .map(|document| bson::de::from_document(document).unwrap())
+}
+
+/// Replaces the stored model with the given one.
+async fn update_model(context: &Context, model: &ClientModel) {
+    let models = context.database.collection("models");
+    let filter = doc! { "_id": &model.id };
+    let update = doc! { "$set": bson::ser::to_document(model).unwrap() };
+
+    models.update_one(filter, update, None).await.unwrap();
+}
+
+/// Signs a base-64 encoded challenge with the client's private key.
+fn sign(context: &Context, challenge: &str) -> String {
+    let challenge = base64::decode(challenge).unwrap();
+    let response = crypto::sign_challenge(&challenge, &context.private_key).unwrap();
+
+    base64::encode(response)
+}
+
+/// Registers and verifies a model, then unlocks it as if the user did so on the website.
+async fn register_model(context: &Context, name: &str) -> Token {
+    let challenge = context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, name)
+        .await
+        .unwrap();
+
+    let response = sign(context, &challenge.challenge);
+    let token = context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, name, &response)
+        .await
+        .unwrap();
+
+    let mut model = find_model(context, name).await.unwrap();
+    model.locked = false;
+    update_model(context, &model).await;
+
+    token
+}
+
+#[tokio::test]
+async fn clients_receive_challenges_for_new_models() {
+    let context = common::initialise().await;
+
+    let challenge = context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await
+        .unwrap();
+
+    let model = find_model(&context, "model").await.unwrap();
+    let stored = model.challenge.unwrap().bytes;
+
+    assert_eq!(base64::decode(&challenge.challenge).unwrap(), stored);
+    assert!(model.locked);
+    assert!(!model.authenticated);
+}
+
+#[tokio::test]
+async fn new_models_require_the_correct_password() {
+    let context = common::initialise().await;
+
+    let result = context
+        .backend
+        .new_model(CLIENT_EMAIL, "incorrect", "model")
+        .await;
+
+    assert!(result.is_err());
+    assert!(find_model(&context, "model").await.is_none());
+}
+
+#[tokio::test]
+async fn users_that_are_not_clients_cannot_create_models() {
+    let context = common::initialise().await;
+
+    let result = context
+        .backend
+        .new_model(USER_EMAIL, PASSWORD, "model")
+        .await;
+
+    assert_eq!(result, Err(AuthError::Forbidden));
+    assert!(find_model(&context, "model").await.is_none());
+}
+
+#[tokio::test]
+async fn model_names_must_be_unique_per_user() {
+    let context = common::initialise().await;
+
+    context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await
+        .unwrap();
+
+    let result = context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await;
+
+    assert_eq!(result, Err(AuthError::Conflict));
+}
+
+#[tokio::test]
+async fn signed_challenges_receive_access_tokens() {
+    let context = common::initialise().await;
+
+    let challenge = context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await
+        .unwrap();
+
+    let response = sign(&context, &challenge.challenge);
+    let token = context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, "model", &response)
+        .await
+        .unwrap();
+
+    let model = find_model(&context, "model").await.unwrap();
+    let access_token = model.access_token.unwrap();
+
+    assert_eq!(token.id, model.id.to_string());
+    assert_eq!(
+        base64::decode(&token.token).unwrap(),
+        access_token.token.bytes
+    );
+    assert!(model.authenticated);
+    assert!(model.challenge.is_none());
+}
+
+#[tokio::test]
+async fn incorrectly_signed_challenges_delete_the_model() {
+    let context = common::initialise().await;
+
+    context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await
+        .unwrap();
+
+    // Sign a different challenge to the one that was issued
+    let response = sign(&context, &base64::encode(crypto::generate_challenge()));
+    let result = context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, "model", &response)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Unauthorized));
+    assert!(find_model(&context, "model").await.is_none());
+}
+
+#[tokio::test]
+async fn challenges_cannot_be_verified_twice() {
+    let context = common::initialise().await;
+
+    let challenge = context
+        .backend
+        .new_model(CLIENT_EMAIL, PASSWORD, "model")
+        .await
+        .unwrap();
+
+    let response = sign(&context, &challenge.challenge);
+    context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, "model", &response)
+        .await
+        .unwrap();
+
+    let result = context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, "model", &response)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Unauthorized));
+}
+
+#[tokio::test]
+async fn unlocked_models_can_authenticate_with_their_token() {
+    let context = common::initialise().await;
+    let token = register_model(&context, "model").await;
+
+    let result = context
+        .backend
+        .authenticate_model(&token.id, &token.token)
+        .await;
+
+    assert_eq!(result, Ok(()));
+}
+
+#[tokio::test]
+async fn locked_models_cannot_authenticate() {
+    let context = common::initialise().await;
+    let token = register_model(&context, "model").await;
+
+    let mut model = find_model(&context, "model").await.unwrap();
+    model.locked = true;
+    update_model(&context, &model).await;
+
+    let result = context
+        .backend
+        .authenticate_model(&token.id, &token.token)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Locked));
+}
+
+#[tokio::test]
+async fn models_cannot_authenticate_with_the_wrong_token() {
+    let context = common::initialise().await;
+    let token = register_model(&context, "model").await;
+
+    let incorrect = base64::encode(crypto::generate_access_token());
+    let result = context
+        .backend
+        .authenticate_model(&token.id, &incorrect)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Unauthorized));
+}
+
+#[tokio::test]
+async fn unknown_models_cannot_authenticate() {
+    let context = common::initialise().await;
+    let token = register_model(&context, "model").await;
+
+    let model_id = ObjectId::new().to_string();
+    let result = context
+        .backend
+        .authenticate_model(&model_id, &token.token)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Unauthorized));
+}
+
+#[tokio::test]
+async fn expired_tokens_require_a_new_challenge() {
+    let context = common::initialise().await;
+    let token = register_model(&context, "model").await;
+
+    let mut model = find_model(&context, "model").await.unwrap();
+    model.access_token.as_mut().unwrap().expires = Utc::now() - Duration::hours(1);
+    update_model(&context, &model).await;
+
+    let result = context
+        .backend
+        .authenticate_model(&token.id, &token.token)
+        .await;
+
+    let challenge = match result {
+        Err(AuthError::Expired { challenge }) => challenge,
+        other => panic!("Expected an expired token, got {:?}", other),
+    };
+
+    let model = find_model(&context, "model").await.unwrap();
+
+    assert!(!model.authenticated);
+    assert_eq!(
+        base64::decode(&challenge).unwrap(),
+        model.challenge.unwrap().bytes
+    );
+
+    // The old token is no longer accepted until the new challenge is signed
+    let result = context
+        .backend
+        .authenticate_model(&token.id, &token.token)
+        .await;
+
+    assert_eq!(result, Err(AuthError::Unauthorized));
+
+    let response = sign(&context, &challenge);
+    let renewed = context
+        .backend
+        .verify_challenge(CLIENT_EMAIL, "model", &response)
+        .await
+        .unwrap();
+
+    let result = context
+        .backend
+        .authenticate_model(&renewed.id, &renewed.token)
+        .await;
+
+    assert_eq!(renewed.id, token.id);
+    assert_eq!(result, Ok(()));
+}
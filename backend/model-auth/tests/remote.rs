@@ -0,0 +1,105 @@
+use mockito::{mock, Matcher};
+
+use model_auth::{AuthBackend, AuthError, Challenge, RemoteBackend, Token};
+
+static MODEL_ID: &str = "5fe8b9d85511355cdab720aa";
+
+#[tokio::test]
+async fn new_models_receive_challenges() {
+    let new_model = mock("POST", "/api/clients/models/new")
+        .match_body(Matcher::PartialJsonString(
+            r#"{"email": "a@b.com", "modelName": "model"}"#.to_string(),
+        ))
+        .with_status(200)
+        .with_body(r#"{"Challenge": {"challenge": "Y2hhbGxlbmdl"}}"#)
+        .create();
+
+    let backend = RemoteBackend::new(mockito::server_url());
+    let challenge = backend.new_model("a@b.com", "password", "model").await;
+
+    let expected = Challenge {
+        challenge: String::from("Y2hhbGxlbmdl"),
+    };
+
+    assert_eq!(challenge, Ok(expected));
+    new_model.assert();
+}
+
+#[tokio::test]
+async fn verified_challenges_receive_access_tokens() {
+    let verify = mock("POST", "/api/clients/models/verify")
+        .with_status(200)
+        .with_body(format!(
+            r#"{{"AccessToken": {{"id": "{}", "token": "dG9rZW4=", "expires": "2021-01-01T00:00:00+00:00"}}}}"#,
+            MODEL_ID
+        ))
+        .create();
+
+    let backend = RemoteBackend::new(mockito::server_url());
+    let token = backend
+        .verify_challenge("a@b.com", "model", "cmVzcG9uc2U=")
+        .await;
+
+    let expected = Token {
+        id: String::from(MODEL_ID),
+        token: String::from("dG9rZW4="),
+        expires: String::from("2021-01-01T00:00:00+00:00"),
+    };
+
+    assert_eq!(token, Ok(expected));
+    verify.assert();
+}
+
+#[tokio::test]
+async fn expired_tokens_are_distinguished_from_locked_models() {
+    let endpoint = format!("/api/clients/models/{}/authenticate", MODEL_ID);
+    let backend = RemoteBackend::new(mockito::server_url());
+
+    let expired = mock("POST", endpoint.as_str())
+        .with_status(401)
+        .with_body(r#"{"challenge": "Y2hhbGxlbmdl"}"#)
+        .create();
+
+    let outcome = backend.authenticate_model(MODEL_ID, "dG9rZW4=").await;
+    let expected = AuthError::Expired {
+        challenge: String::from("Y2hhbGxlbmdl"),
+    };
+
+    assert_eq!(outcome, Err(expected));
+    expired.assert();
+    drop(expired);
+
+    let locked = mock("POST", endpoint.as_str())
+        .with_status(401)
+        .with_body(r#"{"message": "Locked"}"#)
+        .create();
+
+    let outcome = backend.authenticate_model(MODEL_ID, "dG9rZW4=").await;
+
+    assert_eq!(outcome, Err(AuthError::Locked));
+    locked.assert();
+}
+
+#[tokio::test]
+async fn server_errors_are_mapped_to_auth_errors() {
+    let backend = RemoteBackend::new(mockito::server_url());
+
+    let not_found = mock("POST", "/api/clients/models/missing/authenticate")
+        .with_status(404)
+        .create();
+
+    let outcome = backend.authenticate_model("missing", "dG9rZW4=").await;
+
+    assert_eq!(outcome, Err(AuthError::NotFound));
+    assert_eq!(AuthError::NotFound.status_code(), 404);
+    not_found.assert();
+}
+
+#[tokio::test]
+async fn unreachable_servers_are_unavailable() {
+    // Nothing should be listening on the discard port
+    let backend = RemoteBackend::new("http://127.0.0.1:9");
+    let outcome = backend.authenticate_model(MODEL_ID, "dG9rZW4=").await;
+
+    assert!(matches!(outcome, Err(AuthError::Unavailable(_))));
+}
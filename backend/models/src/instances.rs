@@ -0,0 +1,82 @@
+//! Defines the DCL instances advertised in the `MongoDB` instance.
+
+use anyhow::Result;
+use chrono::Utc;
+use mongodb::bson::de::from_document;
+use mongodb::options::UpdateOptions;
+use mongodb::{
+    bson::{self, doc, document::Document},
+    Database,
+};
+use tokio_stream::StreamExt;
+
+/// Defines a running DCL instance and the nodes it has available
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Instance {
+    /// The unique name of the instance
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// The number of nodes that are alive and not currently running a job
+    pub idle_nodes: i64,
+    /// The total number of nodes connected to the instance
+    pub total_nodes: i64,
+    /// The last time the instance advertised itself
+    pub last_seen: bson::DateTime,
+}
+
+impl Instance {
+    /// Advertises the node capacity of an instance, creating it if it does not exist.
+    pub async fn advertise(
+        database: &Database,
+        id: &str,
+        idle_nodes: usize,
+        total_nodes: usize,
+    ) -> Result<()> {
+        let instances = database.collection("dcl_instances");
+
+        log::trace!(
+            "Advertising instance={} with idle_nodes={} and total_nodes={}",
+            id,
+            idle_nodes,
+            total_nodes
+        );
+
+        let filter = doc! { "_id": id };
+        let update = doc! {
+            "$set": {
+                "idle_nodes": idle_nodes as i64,
+                "total_nodes": total_nodes as i64,
+                "last_seen": bson::DateTime(Utc::now()),
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        instances.update_one(filter, update, options).await?;
+
+        Ok(())
+    }
+
+    /// Removes an instance, used when it shuts down.
+    pub async fn withdraw(database: &Database, id: &str) -> Result<()> {
+        let instances = database.collection("dcl_instances");
+
+        instances.delete_one(doc! { "_id": id }, None).await?;
+
+        Ok(())
+    }
+
+    /// Gets all instances that have advertised themselves within the last `max_age`.
+    pub async fn get_live(database: &Database, max_age: chrono::Duration) -> Result<Vec<Self>> {
+        let instances = database.collection("dcl_instances");
+
+        let filter = doc! { "last_seen": { "$gte": bson::DateTime(Utc::now() - max_age) } };
+        let cursor = instances.find(filter, None).await?;
+
+        let instances = cursor
+            .filter_map(Result::ok)
+            .map(|doc: Document| from_document(doc))
+            .collect::<Result<_, _>>()
+            .await?;
+
+        Ok(instances)
+    }
+}
@@ -21,6 +21,15 @@ impl Default for PredictionType {
     }
 }
 
+/// Resources a node must have available to be considered for a job.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JobRequirements {
+    /// The minimum amount of memory a node must have, in megabytes
+    pub min_memory_mb: Option<i64>,
+    /// The minimum number of CPU cores a node must have
+    pub min_cpus: Option<i32>,
+}
+
 /// Parameters required for configuring a job.
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct JobConfiguration {
@@ -44,6 +53,9 @@ pub struct JobConfiguration {
     pub prediction_type: PredictionType,
     /// The total amount paid to run this job
     pub cost: i32,
+    /// The resources a node requires to be considered for this job
+    #[serde(default)]
+    pub requirements: JobRequirements,
 }
 
 impl JobConfiguration {
@@ -66,6 +78,39 @@ impl JobConfiguration {
     }
 }
 
+/// A claim on a [`Job`] by a single DCL instance, valid until it expires.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JobLease {
+    /// The name of the DCL instance holding the lease
+    pub instance_id: String,
+    /// The time at which the lease expires unless it is renewed
+    pub expires: bson::DateTime,
+}
+
+/// The outcome of attempting to reset a job
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum JobReset {
+    /// The job was reset and can be requeued
+    Reset(Job),
+    /// No job exists with the given identifier
+    NotFound,
+    /// The job completed successfully, so there is nothing to run again
+    Completed,
+    /// An instance holds an unexpired lease on the job, so it may still be running
+    Leased,
+}
+
+/// The outcome of recording that a job failed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FailureRecord {
+    /// The job had not failed before, and has now been marked as failed
+    First,
+    /// The job failed before being requeued, and has been marked as failed again
+    Repeated,
+    /// The job had already been processed, so nothing was changed
+    AlreadyProcessed,
+}
+
 /// Defines the information that should be stored with a job in the database.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Job {
@@ -78,6 +123,18 @@ pub struct Job {
     pub processed: bool,
     /// The timestamp at which the [`Job`] was created
     pub date_created: bson::DateTime,
+    /// The lease held by the DCL instance currently running the job, if any
+    #[serde(default)]
+    pub lease: Option<JobLease>,
+    /// Why the job failed, if it could not be run
+    #[serde(default)]
+    pub failure: Option<String>,
+    /// The dataset the job was requested for, used to detect data replaced before it runs
+    #[serde(default)]
+    pub dataset_id: Option<ObjectId>,
+    /// How many times every node in a cluster has failed to run the job
+    #[serde(default)]
+    pub attempts: i32,
 }
 
 impl Job {
@@ -90,21 +147,268 @@ impl Job {
             config,
             processed: false,
             date_created: bson::DateTime(Utc::now()),
+            lease: None,
+            failure: None,
+            dataset_id: None,
+            attempts: 0,
+        }
+    }
+
+    /// Records the dataset the job was requested for.
+    pub fn with_dataset(mut self, dataset_id: ObjectId) -> Self {
+        self.dataset_id = Some(dataset_id);
+        self
+    }
+
+    /// Attempts to claim the job for a DCL instance for the given duration.
+    ///
+    /// This happens atomically, so only one instance can hold the lease on a job at any time.
+    /// Returns `true` if the lease was acquired, or `false` if the job has already been processed
+    /// or a lease that has not yet expired is held, including by the same instance.
+    pub async fn claim(
+        &self,
+        database: &mongodb::Database,
+        instance_id: &str,
+        duration: chrono::Duration,
+    ) -> anyhow::Result<bool> {
+        let jobs = database.collection("jobs");
+
+        let now = Utc::now();
+        let filter = doc! {
+            "_id": &self.id,
+            "processed": false,
+            "$or": [
+                { "lease": null },
+                { "lease.expires": { "$lt": bson::DateTime(now) } },
+            ],
+        };
+        let update = doc! {
+            "$set": {
+                "lease": {
+                    "instance_id": instance_id,
+                    "expires": bson::DateTime(now + duration),
+                }
+            }
+        };
+
+        let claimed = jobs.find_one_and_update(filter, update, None).await?;
+
+        log::debug!(
+            "Instance={} attempted to claim job_id={}, success={}",
+            instance_id,
+            self.id,
+            claimed.is_some()
+        );
+
+        Ok(claimed.is_some())
+    }
+
+    /// Extends the lease on the job, if it is still held by the given instance.
+    ///
+    /// Returns `false` if the lease has been lost, such as if it expired and was released.
+    pub async fn renew_lease(
+        &self,
+        database: &mongodb::Database,
+        instance_id: &str,
+        duration: chrono::Duration,
+    ) -> anyhow::Result<bool> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! { "_id": &self.id, "lease.instance_id": instance_id };
+        let update = doc! { "$set": { "lease.expires": bson::DateTime(Utc::now() + duration) } };
+        let result = jobs.update_one(filter, update, None).await?;
+
+        Ok(result.matched_count == 1)
+    }
+
+    /// Releases the lease on the job, if it is held by the given instance.
+    pub async fn release_lease(
+        &self,
+        database: &mongodb::Database,
+        instance_id: &str,
+    ) -> anyhow::Result<()> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! { "_id": &self.id, "lease.instance_id": instance_id };
+        let update = doc! { "$set": { "lease": null } };
+        jobs.update_one(filter, update, None).await?;
+
+        Ok(())
+    }
+
+    /// Finds the job with the given identifier, if it exists.
+    pub async fn find(database: &mongodb::Database, id: &ObjectId) -> anyhow::Result<Option<Self>> {
+        let jobs = database.collection("jobs");
+
+        match jobs.find_one(doc! { "_id": id }, None).await? {
+            Some(document) => Ok(Some(bson::de::from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks whether the job has been processed, potentially by another DCL instance.
+    pub async fn is_processed(&self, database: &mongodb::Database) -> anyhow::Result<bool> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! { "_id": &self.id, "processed": true };
+        let processed = jobs.find_one(filter, None).await?;
+
+        Ok(processed.is_some())
+    }
+
+    /// Resets a job so that it can be processed again.
+    ///
+    /// Jobs that completed successfully, or that are leased by an instance whose lease has not
+    /// expired, are left alone. Any previous failure is kept until the job next completes, so that
+    /// it is not refunded twice if it fails again, but the count of failed attempts starts over.
+    pub async fn reset(database: &mongodb::Database, id: &ObjectId) -> anyhow::Result<JobReset> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! {
+            "_id": id,
+            "$or": [
+                { "lease": null },
+                { "lease.expires": { "$lt": bson::DateTime(Utc::now()) } },
+            ],
+            "$nor": [{ "processed": true, "failure": null }],
+        };
+        let update = doc! { "$set": { "processed": false, "lease": null, "attempts": 0 } };
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::After)
+            .build();
+
+        if let Some(document) = jobs.find_one_and_update(filter, update, options).await? {
+            return Ok(JobReset::Reset(bson::de::from_document(document)?));
+        }
+
+        // Work out why the job could not be reset
+        let job: Self = match jobs.find_one(doc! { "_id": id }, None).await? {
+            Some(document) => bson::de::from_document(document)?,
+            None => return Ok(JobReset::NotFound),
+        };
+
+        if job.processed && job.failure.is_none() {
+            Ok(JobReset::Completed)
+        } else {
+            Ok(JobReset::Leased)
         }
     }
 
-    /// Marks the job as processed in the database.
-    pub async fn mark_as_processed(&self, database: &mongodb::Database) -> anyhow::Result<()> {
+    /// Records that every node in a cluster failed to run the job.
+    ///
+    /// Returns the number of attempts that have now failed, including this one.
+    pub async fn record_failed_attempt(&self, database: &mongodb::Database) -> anyhow::Result<i32> {
         let jobs = database.collection("jobs");
 
         let filter = doc! { "_id": &self.id };
-        let update = doc! { "$set": { "processed": true } };
+        let update = doc! { "$inc": { "attempts": 1 } };
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::After)
+            .build();
+
+        let document = jobs
+            .find_one_and_update(filter, update, options)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("job_id={} no longer exists", self.id))?;
+        let job: Self = bson::de::from_document(document)?;
+
+        Ok(job.attempts)
+    }
+
+    /// Releases the leases on all unprocessed jobs which have expired.
+    ///
+    /// Returns the number of jobs that were released.
+    pub async fn release_expired_leases(database: &mongodb::Database) -> anyhow::Result<u64> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! {
+            "processed": false,
+            "lease.expires": { "$lt": bson::DateTime(Utc::now()) },
+        };
+        let update = doc! { "$set": { "lease": null } };
+        let result = jobs.update_many(filter, update, None).await?;
+
+        Ok(result.modified_count)
+    }
+
+    /// Marks the job as processed in the database, if it is still leased by the given instance.
+    ///
+    /// Returns an error if the lease has been lost, as another instance may be running the job.
+    pub async fn mark_as_processed(
+        &self,
+        database: &mongodb::Database,
+        instance_id: &str,
+    ) -> anyhow::Result<()> {
+        let jobs = database.collection("jobs");
+
+        // Clear any failure from before the job was requeued, as it has now succeeded
+        let filter = doc! { "_id": &self.id, "lease.instance_id": instance_id };
+        let update = doc! { "$set": { "processed": true, "lease": null, "failure": null } };
         let result = jobs.update_one(filter, update, None).await?;
 
-        assert!(
-            result.modified_count == 1,
-            "Failed to mark a job as processed"
-        );
+        if result.matched_count == 0 {
+            anyhow::bail!(
+                "Failed to mark job_id={} as processed, as instance={} no longer holds its lease",
+                self.id,
+                instance_id
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Marks the job as failed, unless it has already been processed.
+    ///
+    /// This happens atomically, so when several callers fail the same job only one of them sees
+    /// [`FailureRecord::First`], which can be used to decide whether to refund the user. Jobs
+    /// that failed before being requeued are still recorded as failing again, but return
+    /// [`FailureRecord::Repeated`].
+    pub async fn mark_as_failed_once(
+        &self,
+        database: &mongodb::Database,
+        reason: &str,
+    ) -> anyhow::Result<FailureRecord> {
+        let jobs = database.collection("jobs");
+
+        let update = doc! { "$set": { "processed": true, "lease": null, "failure": reason } };
+
+        let filter = doc! { "_id": &self.id, "processed": false, "failure": null };
+        if jobs
+            .find_one_and_update(filter, update.clone(), None)
+            .await?
+            .is_some()
+        {
+            log::warn!("Marked job_id={} as failed: {}", self.id, reason);
+            return Ok(FailureRecord::First);
+        }
+
+        let filter = doc! { "_id": &self.id, "processed": false };
+        if jobs
+            .find_one_and_update(filter, update, None)
+            .await?
+            .is_some()
+        {
+            log::warn!("Marked job_id={} as failed again: {}", self.id, reason);
+            return Ok(FailureRecord::Repeated);
+        }
+
+        Ok(FailureRecord::AlreadyProcessed)
+    }
+
+    /// Marks the job as failed in the database, recording the reason so it can be shown to the
+    /// user and ensuring no DCL instance attempts to run it.
+    pub async fn mark_as_failed(
+        &self,
+        database: &mongodb::Database,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let jobs = database.collection("jobs");
+
+        let filter = doc! { "_id": &self.id };
+        let update = doc! { "$set": { "processed": true, "lease": null, "failure": reason } };
+        jobs.update_one(filter, update, None).await?;
+
+        log::warn!("Marked job_id={} as failed: {}", self.id, reason);
 
         Ok(())
     }
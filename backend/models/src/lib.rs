@@ -7,9 +7,13 @@ pub mod dataset_analysis;
 pub mod dataset_details;
 pub mod datasets;
 pub mod gridfs;
+pub mod instances;
 pub mod job_performance;
 pub mod jobs;
 pub mod models;
 pub mod predictions;
+pub mod project_events;
 pub mod projects;
+pub mod telemetry;
 pub mod users;
+pub mod webhooks;
@@ -0,0 +1,159 @@
+//! Defines the history of updates sent about a project in the `MongoDB` instance.
+//!
+//! Every update is given a sequence number, which increases with each update across all
+//! projects. Clients remember the last sequence number they saw, allowing them to ask for the
+//! updates they missed while disconnected.
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use mongodb::bson::de::from_document;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::{
+    bson::{self, doc, document::Document, oid::ObjectId},
+    Database,
+};
+use tokio_stream::StreamExt;
+
+/// The document in the `counters` collection holding the last sequence number used
+const SEQUENCE_COUNTER: &str = "project_events";
+
+/// Defines an update that was sent about a project
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectEvent {
+    /// The unique identifier for the event
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// The project the event is about
+    pub project_id: ObjectId,
+    /// The user who owns the project
+    pub user_id: ObjectId,
+    /// The position of the event in the history of all events
+    pub sequence: i64,
+    /// When the event was recorded
+    pub date_created: bson::DateTime,
+    /// The update itself, as sent to clients
+    pub event: Document,
+}
+
+impl ProjectEvent {
+    /// Creates the indexes used by the `project_events` collection, if they do not already exist.
+    ///
+    /// Events are looked up by user when replaying missed updates and by project when returning
+    /// a project's history, both in order of their sequence numbers.
+    pub async fn create_indexes(database: &Database) -> Result<()> {
+        let command = doc! {
+            "createIndexes": "project_events",
+            "indexes": [
+                {
+                    "key": { "user_id": 1, "sequence": 1 },
+                    "name": "user_id_sequence",
+                },
+                {
+                    "key": { "project_id": 1, "sequence": 1 },
+                    "name": "project_id_sequence",
+                },
+            ],
+        };
+
+        database.run_command(command, None).await?;
+
+        Ok(())
+    }
+
+    /// Records an event for a project, giving it the next sequence number.
+    pub async fn record(
+        database: &Database,
+        project_id: ObjectId,
+        user_id: ObjectId,
+        event: Document,
+    ) -> Result<Self> {
+        let project_event = Self {
+            id: ObjectId::new(),
+            project_id,
+            user_id,
+            sequence: next_sequence(database).await?,
+            date_created: bson::DateTime(Utc::now()),
+            event,
+        };
+
+        log::debug!(
+            "Recording an event for project_id={} with sequence={}",
+            project_event.project_id,
+            project_event.sequence
+        );
+
+        let project_events = database.collection("project_events");
+        let document = bson::ser::to_document(&project_event)?;
+        project_events.insert_one(document, None).await?;
+
+        Ok(project_event)
+    }
+
+    /// Gets up to `limit` events for a project recorded after `sequence`, oldest first.
+    pub async fn for_project_since(
+        database: &Database,
+        project_id: &ObjectId,
+        sequence: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        Self::find(
+            database,
+            doc! { "project_id": project_id, "sequence": { "$gt": sequence } },
+            limit,
+        )
+        .await
+    }
+
+    /// Gets up to `limit` events for a user's projects recorded after `sequence`, oldest first.
+    pub async fn for_user_since(
+        database: &Database,
+        user_id: &ObjectId,
+        sequence: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        Self::find(
+            database,
+            doc! { "user_id": user_id, "sequence": { "$gt": sequence } },
+            limit,
+        )
+        .await
+    }
+
+    async fn find(database: &Database, filter: Document, limit: i64) -> Result<Vec<Self>> {
+        let project_events = database.collection("project_events");
+
+        let options = FindOptions::builder()
+            .sort(doc! { "sequence": 1 })
+            .limit(Some(limit))
+            .build();
+        let cursor = project_events.find(filter, options).await?;
+
+        let events = cursor
+            .collect::<Result<Vec<_>, _>>()
+            .await?
+            .into_iter()
+            .map(from_document)
+            .collect::<Result<_, _>>()?;
+
+        Ok(events)
+    }
+}
+
+/// Atomically increments the sequence counter, returning the new value.
+async fn next_sequence(database: &Database) -> Result<i64> {
+    let counters = database.collection("counters");
+
+    let filter = doc! { "_id": SEQUENCE_COUNTER };
+    let update = doc! { "$inc": { "value": 1_i64 } };
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+
+    let counter = counters
+        .find_one_and_update(filter, update, options)
+        .await?
+        .ok_or_else(|| anyhow!("The sequence counter was not created"))?;
+
+    Ok(counter.get_i64("value")?)
+}
@@ -73,6 +73,7 @@ impl Project {
         let projects = database.collection("projects");
         let datasets = database.collection("datasets");
         let predictions = database.collection("predictions");
+        let project_events = database.collection("project_events");
 
         log::debug!("Deleting project with id={}", self.id);
 
@@ -96,6 +97,10 @@ impl Project {
             prediction.delete(database).await?;
         }
 
+        // Remove the history of updates sent about the project
+        let events_filter = doc! { "project_id": &self.id };
+        project_events.delete_many(events_filter, None).await?;
+
         Ok(())
     }
 }
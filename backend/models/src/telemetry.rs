@@ -0,0 +1,175 @@
+//! Defines the telemetry recorded for models in the `MongoDB` instance.
+//!
+//! Samples are only kept for [`RETENTION_SECS`], after which `MongoDB` removes them through the
+//! TTL index created by [`Telemetry::create_indexes`].
+
+use anyhow::Result;
+use chrono::Utc;
+use mongodb::bson::de::from_document;
+use mongodb::{
+    bson::{self, doc, document::Document, oid::ObjectId},
+    Database,
+};
+use tokio_stream::StreamExt;
+
+/// How long telemetry samples are kept for, in seconds
+pub const RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// The kind of measurement recorded in a [`Telemetry`] sample
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Metric {
+    /// Round trip time of a heartbeat, in milliseconds
+    HeartbeatRtt,
+    /// Rate at which data was sent to the node, in bytes per second
+    TransferRate,
+    /// Time spent computing per row of data, in milliseconds
+    ComputePerRow,
+}
+
+impl From<Metric> for bson::Bson {
+    fn from(metric: Metric) -> Self {
+        bson::to_bson(&metric).expect("Failed to convert the metric to BSON")
+    }
+}
+
+/// Defines a single telemetry sample for a model
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Telemetry {
+    /// The unique identifier for the sample
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// Unique identifier for the associated model
+    pub model_id: ObjectId,
+    /// The kind of measurement
+    pub metric: Metric,
+    /// The measured value
+    pub value: f64,
+    /// The time the measurement was taken
+    pub date_created: bson::DateTime,
+}
+
+impl Telemetry {
+    /// Creates a new instance of [`Telemetry`].
+    pub fn new(model_id: ObjectId, metric: Metric, value: f64) -> Self {
+        log::trace!(
+            "Creating a new telemetry sample with model_id={}, metric={:?} and value={}",
+            model_id,
+            metric,
+            value
+        );
+
+        Self {
+            id: ObjectId::new(),
+            model_id,
+            metric,
+            value,
+            date_created: bson::DateTime(Utc::now()),
+        }
+    }
+
+    /// Creates the indexes used by the `telemetry` collection, if they do not already exist.
+    ///
+    /// Samples expire [`RETENTION_SECS`] after they are recorded, and are indexed by model and
+    /// metric so that the most recent ones can be found without scanning the collection.
+    pub async fn create_indexes(database: &Database) -> Result<()> {
+        let command = doc! {
+            "createIndexes": "telemetry",
+            "indexes": [
+                {
+                    "key": { "date_created": 1 },
+                    "name": "date_created_ttl",
+                    "expireAfterSeconds": RETENTION_SECS,
+                },
+                {
+                    "key": { "model_id": 1, "metric": 1, "date_created": -1 },
+                    "name": "model_id_metric_date_created",
+                },
+            ],
+        };
+
+        database.run_command(command, None).await?;
+
+        Ok(())
+    }
+
+    /// Records a new sample for the given model in the database.
+    pub async fn record(
+        database: &Database,
+        model_id: &str,
+        metric: Metric,
+        value: f64,
+    ) -> Result<()> {
+        let telemetry = database.collection("telemetry");
+
+        let sample = Self::new(ObjectId::with_string(model_id)?, metric, value);
+        let document = bson::ser::to_document(&sample)?;
+        telemetry.insert_one(document, None).await?;
+
+        Ok(())
+    }
+
+    /// Gets the most recent `k` samples for a model, newest first.
+    ///
+    /// If a metric is given, only samples of that metric are returned.
+    pub async fn get_recent(
+        database: &Database,
+        model_id: &ObjectId,
+        metric: Option<Metric>,
+        k: i64,
+    ) -> Result<Vec<Self>> {
+        let telemetry = database.collection("telemetry");
+
+        let mut filter = doc! { "model_id": model_id };
+
+        if let Some(metric) = metric {
+            filter.insert("metric", metric);
+        }
+
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! {"date_created": -1})
+            .limit(k)
+            .build();
+
+        let cursor = telemetry.find(filter, Some(options)).await?;
+
+        let samples = cursor
+            .filter_map(Result::ok)
+            .map(|doc: Document| from_document(doc))
+            .collect::<Result<_, _>>()
+            .await?;
+
+        Ok(samples)
+    }
+
+    /// Gets the average of the last `k` samples of a metric for a model.
+    ///
+    /// Returns `None` if the model has not recorded any samples for the metric.
+    pub async fn average(
+        database: &Database,
+        model_id: &str,
+        metric: Metric,
+        k: i64,
+    ) -> Result<Option<f64>> {
+        let telemetry = database.collection("telemetry");
+
+        let filter = doc! { "model_id": ObjectId::with_string(model_id)?, "metric": metric };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! {"date_created": -1})
+            .limit(k)
+            .build();
+
+        let cursor = telemetry.find(filter, Some(options)).await?;
+
+        let values: Vec<f64> = cursor
+            .filter_map(Result::ok)
+            .map(|doc: Document| from_document::<Self>(doc).map(|sample| sample.value))
+            .collect::<Result<_, _>>()
+            .await?;
+
+        if values.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(values.iter().sum::<f64>() / values.len() as f64))
+    }
+}
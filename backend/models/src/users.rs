@@ -30,6 +30,32 @@ pub struct User {
     pub credits: i32,
     /// Avatar Image
     pub avatar: Option<Binary>,
+    /// The emails the user wants to receive
+    #[serde(default)]
+    pub notifications: NotificationPreferences,
+}
+
+/// Defines which optional emails a user wants to receive.
+///
+/// Emails the user has asked for, such as password resets, are always sent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    /// Whether to email the user when a project finishes processing
+    pub project_complete: bool,
+    /// Whether to email the user when a project cannot be processed
+    pub project_failed: bool,
+    /// Whether to email the user when their credits are running low
+    pub low_credits: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            project_complete: true,
+            project_failed: true,
+            low_credits: true,
+        }
+    }
 }
 
 impl User {
@@ -57,6 +83,7 @@ impl User {
             client: false,
             credits: STARTING_CREDITS,
             avatar: None,
+            notifications: NotificationPreferences::default(),
         }
     }
 
@@ -0,0 +1,220 @@
+//! Defines the webhooks users register and the log of deliveries made to them in the `MongoDB`
+//! instance.
+
+use anyhow::Result;
+use chrono::Utc;
+use mongodb::bson::de::from_document;
+use mongodb::options::FindOptions;
+use mongodb::{
+    bson::{self, doc, document::Document, oid::ObjectId},
+    Database,
+};
+use tokio_stream::StreamExt;
+
+/// The length of the secret used to sign payloads
+const SECRET_SIZE: usize = 32;
+
+/// The events a webhook can be notified of
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    /// A job for a project has finished and its predictions are available
+    #[serde(rename = "job.completed")]
+    JobCompleted,
+    /// A job for a project could not be run
+    #[serde(rename = "job.failed")]
+    JobFailed,
+    /// The analysis of a project's dataset is available
+    #[serde(rename = "analysis.ready")]
+    AnalysisReady,
+    /// Sent when a user asks for a webhook to be tested
+    #[serde(rename = "webhook.test")]
+    Test,
+}
+
+impl WebhookEvent {
+    /// The events a webhook is notified of if none are specified
+    pub const DEFAULT: [WebhookEvent; 3] = [
+        WebhookEvent::JobCompleted,
+        WebhookEvent::JobFailed,
+        WebhookEvent::AnalysisReady,
+    ];
+
+    /// Gets the name of the event, as sent in payloads
+    pub fn name(self) -> &'static str {
+        match self {
+            WebhookEvent::JobCompleted => "job.completed",
+            WebhookEvent::JobFailed => "job.failed",
+            WebhookEvent::AnalysisReady => "analysis.ready",
+            WebhookEvent::Test => "webhook.test",
+        }
+    }
+}
+
+/// Defines a URL that a user wants events to be sent to
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    /// The unique identifier for the webhook
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// The user who registered the webhook
+    pub user_id: ObjectId,
+    /// The project to send events for, or `None` for all of the user's projects
+    pub project_id: Option<ObjectId>,
+    /// Where to send events
+    pub url: String,
+    /// The secret used to sign payloads
+    pub secret: String,
+    /// The events to send
+    pub events: Vec<WebhookEvent>,
+    /// When the webhook was registered
+    pub date_created: bson::DateTime,
+}
+
+impl Webhook {
+    /// Creates a new instance of [`Webhook`] with a random secret.
+    pub fn new(
+        user_id: ObjectId,
+        project_id: Option<ObjectId>,
+        url: String,
+        events: Vec<WebhookEvent>,
+    ) -> Self {
+        log::debug!(
+            "Creating a new webhook for user_id={}, project_id={:?} and url={}",
+            user_id,
+            project_id,
+            url
+        );
+
+        Self {
+            id: ObjectId::new(),
+            user_id,
+            project_id,
+            url,
+            secret: crypto::generate_string(SECRET_SIZE),
+            events,
+            date_created: bson::DateTime(Utc::now()),
+        }
+    }
+
+    /// Gets the webhooks a user has registered.
+    pub async fn for_user(database: &Database, user_id: &ObjectId) -> Result<Vec<Self>> {
+        find(database, "webhooks", doc! { "user_id": user_id }, None).await
+    }
+
+    /// Gets the webhooks that want an event for one of a user's projects.
+    pub async fn for_event(
+        database: &Database,
+        user_id: &ObjectId,
+        project_id: &ObjectId,
+        event: WebhookEvent,
+    ) -> Result<Vec<Self>> {
+        let filter = doc! {
+            "user_id": user_id,
+            "project_id": { "$in": [project_id, null] },
+            "events": event.name(),
+        };
+
+        find(database, "webhooks", filter, None).await
+    }
+
+    /// Deletes the webhook along with its delivery log.
+    pub async fn delete(&self, database: &Database) -> Result<()> {
+        let webhooks = database.collection("webhooks");
+        let deliveries = database.collection("webhook_deliveries");
+
+        log::debug!("Deleting webhook with id={}", self.id);
+
+        webhooks.delete_one(doc! { "_id": &self.id }, None).await?;
+        deliveries
+            .delete_many(doc! { "webhook_id": &self.id }, None)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Defines a single attempt to deliver an event to a webhook
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    /// The unique identifier for the attempt
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// The webhook the event was sent to
+    pub webhook_id: ObjectId,
+    /// Identifies the event, which is the same for every attempt to deliver it
+    pub delivery_id: ObjectId,
+    /// The event that was sent
+    pub event: WebhookEvent,
+    /// Which attempt this was, starting from 1
+    pub attempt: u32,
+    /// The status code of the response, if one was received
+    pub status: Option<u16>,
+    /// Why the attempt failed, if it did
+    pub error: Option<String>,
+    /// Whether the event was delivered
+    pub success: bool,
+    /// When the attempt was made
+    pub date_created: bson::DateTime,
+}
+
+impl WebhookDelivery {
+    /// Records an attempt to deliver an event.
+    pub async fn record(&self, database: &Database) -> Result<()> {
+        let deliveries = database.collection("webhook_deliveries");
+
+        log::debug!(
+            "Recording attempt {} of delivery_id={} to webhook_id={} with success={}",
+            self.attempt,
+            self.delivery_id,
+            self.webhook_id,
+            self.success
+        );
+
+        deliveries
+            .insert_one(bson::ser::to_document(self)?, None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gets the most recent `limit` attempts to deliver events to a webhook, newest first.
+    pub async fn for_webhook(
+        database: &Database,
+        webhook_id: &ObjectId,
+        limit: i64,
+    ) -> Result<Vec<Self>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "date_created": -1 })
+            .limit(limit)
+            .build();
+
+        find(
+            database,
+            "webhook_deliveries",
+            doc! { "webhook_id": webhook_id },
+            options,
+        )
+        .await
+    }
+}
+
+async fn find<T: serde::de::DeserializeOwned>(
+    database: &Database,
+    collection: &str,
+    filter: Document,
+    options: impl Into<Option<FindOptions>>,
+) -> Result<Vec<T>> {
+    let cursor = database
+        .collection(collection)
+        .find(filter, options)
+        .await?;
+
+    let documents = cursor
+        .collect::<Result<Vec<_>, _>>()
+        .await?
+        .into_iter()
+        .map(from_document)
+        .collect::<Result<_, _>>()?;
+
+    Ok(documents)
+}
@@ -4,6 +4,7 @@ use mongodb::error::Result;
 use models::datasets::Dataset;
 use models::models::ClientModel;
 use models::predictions::Prediction;
+use models::project_events::ProjectEvent;
 use models::projects::Project;
 use models::users::{Client, User};
 
@@ -61,8 +62,14 @@ async fn project_tree_can_be_deleted() -> Result<()> {
     let datasets = db.collection("datasets");
     let details = db.collection("details");
     let predictions = db.collection("predictions");
+    let project_events = db.collection("project_events");
 
     let pid = ObjectId::with_string(common::PROJECT_ID).unwrap();
+    let uid = ObjectId::with_string(common::USER_ID).unwrap();
+    ProjectEvent::record(&db, pid.clone(), uid, doc! { "dataSent": {} })
+        .await
+        .unwrap();
+
     let filter = doc! {"_id": &pid};
     let proj_doc = projects.find_one(filter.clone(), None).await?.unwrap();
     let project: Project = mongodb::bson::de::from_document(proj_doc).unwrap();
@@ -72,7 +79,11 @@ async fn project_tree_can_be_deleted() -> Result<()> {
     assert!(projects.find_one(filter, None).await?.is_none());
     assert!(datasets.find_one(pid_filter.clone(), None).await?.is_none());
     assert!(details.find_one(pid_filter.clone(), None).await?.is_none());
-    assert!(predictions.find_one(pid_filter, None).await?.is_none());
+    assert!(predictions
+        .find_one(pid_filter.clone(), None)
+        .await?
+        .is_none());
+    assert!(project_events.find_one(pid_filter, None).await?.is_none());
 
     let pid_2 = ObjectId::with_string(common::PROJECT_ID_2).unwrap();
     assert!(projects
@@ -0,0 +1,111 @@
+use chrono::{Duration, Utc};
+use mongodb::bson::{self, oid::ObjectId};
+use mongodb::Database;
+
+use models::telemetry::{Metric, Telemetry};
+
+mod common;
+
+/// Inserts a sample for the test model, as if it was recorded some seconds ago.
+async fn insert_sample(database: &Database, metric: Metric, value: f64, seconds_ago: i64) {
+    let model_id = ObjectId::with_string(common::MODEL_ID).unwrap();
+
+    let mut sample = Telemetry::new(model_id, metric, value);
+    sample.date_created = bson::DateTime(Utc::now() - Duration::seconds(seconds_ago));
+
+    let telemetry = database.collection("telemetry");
+    let document = bson::ser::to_document(&sample).unwrap();
+    telemetry.insert_one(document, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn samples_can_be_recorded() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+    let model_id = ObjectId::with_string(common::MODEL_ID)?;
+
+    Telemetry::record(&db, common::MODEL_ID, Metric::HeartbeatRtt, 12.5).await?;
+
+    let samples = Telemetry::get_recent(&db, &model_id, None, 10).await?;
+
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].model_id, model_id);
+    assert_eq!(samples[0].metric, Metric::HeartbeatRtt);
+    assert_eq!(samples[0].value, 12.5);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recent_samples_are_fetched_newest_first() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+    let model_id = ObjectId::with_string(common::MODEL_ID)?;
+
+    insert_sample(&db, Metric::HeartbeatRtt, 1.0, 30).await;
+    insert_sample(&db, Metric::HeartbeatRtt, 3.0, 10).await;
+    insert_sample(&db, Metric::HeartbeatRtt, 2.0, 20).await;
+
+    let samples = Telemetry::get_recent(&db, &model_id, None, 2).await?;
+    let values: Vec<f64> = samples.iter().map(|sample| sample.value).collect();
+
+    assert_eq!(values, vec![3.0, 2.0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recent_samples_can_be_filtered_by_metric() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+    let model_id = ObjectId::with_string(common::MODEL_ID)?;
+
+    insert_sample(&db, Metric::HeartbeatRtt, 10.0, 30).await;
+    insert_sample(&db, Metric::TransferRate, 2048.0, 20).await;
+    insert_sample(&db, Metric::ComputePerRow, 0.5, 10).await;
+
+    let all = Telemetry::get_recent(&db, &model_id, None, 10).await?;
+    let transfer_rates =
+        Telemetry::get_recent(&db, &model_id, Some(Metric::TransferRate), 10).await?;
+
+    assert_eq!(all.len(), 3);
+    assert_eq!(transfer_rates.len(), 1);
+    assert_eq!(transfer_rates[0].metric, Metric::TransferRate);
+    assert_eq!(transfer_rates[0].value, 2048.0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn averages_only_use_the_most_recent_samples_of_a_metric() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+
+    insert_sample(&db, Metric::ComputePerRow, 100.0, 30).await;
+    insert_sample(&db, Metric::ComputePerRow, 2.0, 20).await;
+    insert_sample(&db, Metric::ComputePerRow, 4.0, 10).await;
+    insert_sample(&db, Metric::HeartbeatRtt, 50.0, 0).await;
+
+    let average = Telemetry::average(&db, common::MODEL_ID, Metric::ComputePerRow, 2).await?;
+
+    assert_eq!(average, Some(3.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn models_without_samples_have_no_average() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+
+    let average = Telemetry::average(&db, common::MODEL_ID, Metric::TransferRate, 5).await?;
+
+    assert_eq!(average, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn indexes_can_be_created_more_than_once() -> anyhow::Result<()> {
+    let (db, _lock) = common::initialise().await;
+
+    Telemetry::create_indexes(&db).await?;
+    Telemetry::create_indexes(&db).await?;
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! Simulates a swarm of compute nodes connecting to a running DCL.
+//!
+//! Each node is given a [`Profile`] that controls how it behaves, from honestly computing
+//! predictions to returning garbage or dropping its connection. Running a mixture of profiles
+//! against the DCL allows throughput to be measured and shows whether health checking,
+//! penalisation of malicious models and cluster building behave as expected.
+
+#![warn(rust_2018_idioms)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_docs)]
+
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use mongodb::options::ClientOptions;
+use mongodb::Client;
+use rand::Rng;
+
+use dcn_client::node::AuthenticationError;
+use dcn_client::{CredentialStore, Disconnect, Node, NodeConfig};
+use model_auth::Token;
+
+pub mod profiles;
+pub mod seed;
+pub mod stats;
+
+pub use profiles::{parse_fleet, Behaviour, Profile, SimulatedModel};
+pub use stats::{Counters, Stats};
+
+/// The fleet that is simulated if `SIM_PROFILES` is not set
+const DEFAULT_FLEET: &str = "honest=5";
+
+/// Settings for a simulation, read from the environment
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// The address of the DCL that nodes connect to
+    pub address: String,
+    /// The number of nodes to run for each profile
+    pub fleet: Vec<(Profile, usize)>,
+    /// Controls how misbehaving profiles misbehave
+    pub behaviour: Behaviour,
+    /// How long nodes wait before reconnecting to the DCL
+    pub reconnect_delay: Duration,
+    /// How often the statistics are reported
+    pub report_interval: Duration,
+}
+
+impl Settings {
+    /// Reads the settings for a simulation from the environment
+    pub fn from_env() -> Result<Self> {
+        let address = match env::var("DCL_ADDRESS") {
+            Ok(address) => address,
+            Err(_) => format!("127.0.0.1:{}", env::var("NODE_SOCKET")?),
+        };
+
+        let fleet = env::var("SIM_PROFILES").unwrap_or_else(|_| String::from(DEFAULT_FLEET));
+        let defaults = Behaviour::default();
+
+        Ok(Self {
+            address,
+            fleet: parse_fleet(&fleet)?,
+            behaviour: Behaviour {
+                slow_delay: seconds("SIM_SLOW_DELAY", defaults.slow_delay)?,
+                drop_after: seconds("SIM_DROP_AFTER", defaults.drop_after)?,
+            },
+            reconnect_delay: seconds("SIM_RECONNECT_DELAY", Duration::from_secs(5))?,
+            report_interval: seconds("SIM_REPORT_INTERVAL", Duration::from_secs(30))?,
+        })
+    }
+}
+
+/// Reads a number of seconds from the environment, using `default` if it is not set
+fn seconds(name: &str, default: Duration) -> Result<Duration> {
+    match env::var(name) {
+        Ok(value) => {
+            let secs = u64::from_str(&value).with_context(|| format!("{} must be a u64", name))?;
+            Ok(Duration::from_secs(secs))
+        }
+        Err(_) => Ok(default),
+    }
+}
+
+/// Picks how long a dropping node stays connected for, varying around the configured value
+fn connection_lifetime(drop_after: Duration) -> Duration {
+    let millis = drop_after.as_millis().max(2) as u64;
+    let lifetime = rand::thread_rng().gen_range(millis / 2..millis * 3 / 2);
+
+    Duration::from_millis(lifetime)
+}
+
+/// Runs a single simulated node until it can no longer authenticate
+async fn run_node(
+    node: Node<SimulatedModel>,
+    profile: Profile,
+    settings: Settings,
+    counters: Arc<Counters>,
+) {
+    loop {
+        Counters::increment(&counters.connections);
+
+        let result = if profile == Profile::Dropping {
+            let lifetime = connection_lifetime(settings.behaviour.drop_after);
+
+            // Dropping the future closes the connection, regardless of what the node is doing
+            tokio::time::timeout(lifetime, node.run())
+                .await
+                .unwrap_or(Ok(Disconnect::Closed))
+        } else {
+            node.run().await
+        };
+
+        Counters::increment(&counters.disconnects);
+
+        match result {
+            Ok(Disconnect::Shutdown) => log::info!("The DCL is shutting down"),
+            Ok(Disconnect::Closed) => log::debug!("A {} node disconnected", profile),
+            Err(e) if e.is::<AuthenticationError>() => {
+                log::error!("A {} node failed to authenticate: {}", profile, e);
+                return;
+            }
+            Err(e) => log::warn!("A {} node lost its connection: {}", profile, e),
+        }
+
+        tokio::time::sleep(settings.reconnect_delay).await;
+    }
+}
+
+/// Creates a node for a simulated model that authenticates using `token`
+fn create_node(
+    profile: Profile,
+    name: &str,
+    token: Token,
+    settings: &Settings,
+    counters: Arc<Counters>,
+) -> Node<SimulatedModel> {
+    let credentials = CredentialStore::in_memory(Some(token));
+    let mut config = NodeConfig::new(
+        &settings.address,
+        seed::SIMULATOR_EMAIL,
+        name,
+        "",
+        credentials,
+    );
+    config.reconnect_delay = settings.reconnect_delay;
+
+    let model = SimulatedModel::new(profile, settings.behaviour.clone(), counters);
+
+    Node::new(config, model)
+}
+
+/// Main runner function for the simulator
+///
+/// Connects to the database to create a model for each simulated node, then runs every node
+/// against the DCL and periodically reports what has happened until interrupted.
+#[tokio::main]
+pub async fn run() -> Result<()> {
+    let conn_str = env::var("CONN_STR").expect("CONN_STR must be set");
+    let app_name = env::var("APP_NAME").expect("APP_NAME must be set");
+    let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| String::from("sybl"));
+
+    let settings = Settings::from_env()?;
+
+    let mut client_options = ClientOptions::parse(&conn_str).await?;
+    client_options.app_name = Some(app_name);
+    let database = Client::with_options(client_options)?.database(&database_name);
+
+    let nodes: Vec<(Profile, String)> = settings
+        .fleet
+        .iter()
+        .flat_map(|(profile, count)| {
+            (0..*count).map(move |i| (*profile, format!("sim-{}-{}", profile, i)))
+        })
+        .collect();
+
+    let names: Vec<String> = nodes.iter().map(|(_, name)| name.clone()).collect();
+    let tokens = seed::seed_models(&database, &names).await?;
+
+    log::info!(
+        "Simulating {} nodes against the DCL at {}",
+        nodes.len(),
+        settings.address
+    );
+
+    let stats = Stats::new();
+
+    for ((profile, name), token) in nodes.into_iter().zip(tokens) {
+        let counters = stats.counters(profile);
+        let node = create_node(profile, &name, token, &settings, Arc::clone(&counters));
+
+        tokio::spawn(run_node(node, profile, settings.clone(), counters));
+    }
+
+    loop {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                break;
+            }
+            _ = tokio::time::sleep(settings.report_interval) => {
+                log::info!("Simulation statistics:\n{}", stats.report());
+            }
+        }
+    }
+
+    log::info!("Final simulation statistics:\n{}", stats.report());
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use config::Environment;
+
+fn main() {
+    let filters = vec![
+        ("simulator", log::LevelFilter::Debug),
+        ("config", log::LevelFilter::Debug),
+        ("dcn_client", log::LevelFilter::Info),
+        ("models", log::LevelFilter::Debug),
+    ];
+
+    utils::setup_logger_with_filters(filters);
+
+    let environment = if cfg!(debug_assertions) {
+        Environment::Development
+    } else {
+        Environment::Production
+    };
+
+    config::load(environment);
+
+    if let Err(e) = simulator::run() {
+        log::error!("Error occurred: {}", e);
+    }
+}
@@ -0,0 +1,335 @@
+//! Defines how each simulated node behaves when given a job.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use csv::{Reader, StringRecord, Writer};
+
+use dcn_client::baseline::prediction_column;
+use dcn_client::{JobConfig, Model, Progress};
+use models::jobs::PredictionType;
+
+use crate::stats::Counters;
+
+/// The behaviour of a simulated node
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Profile {
+    /// Computes genuine predictions using a nearest neighbour model
+    Honest,
+    /// Computes genuine predictions, but only after a delay
+    Slow,
+    /// Computes genuine predictions, but periodically drops its connection
+    Dropping,
+    /// Returns predictions that cannot be parsed
+    Garbage,
+    /// Returns the same prediction for every record, regardless of the data
+    Constant,
+    /// Rejects every job it is offered
+    Rejecting,
+}
+
+impl Profile {
+    /// All profiles, in the order they are reported
+    pub const ALL: [Profile; 6] = [
+        Profile::Honest,
+        Profile::Slow,
+        Profile::Dropping,
+        Profile::Garbage,
+        Profile::Constant,
+        Profile::Rejecting,
+    ];
+
+    /// Gets the name used for the profile in configuration and reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Honest => "honest",
+            Self::Slow => "slow",
+            Self::Dropping => "dropping",
+            Self::Garbage => "garbage",
+            Self::Constant => "constant",
+            Self::Rejecting => "rejecting",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|profile| profile.name() == s)
+            .ok_or_else(|| anyhow!("Unknown profile: {}", s))
+    }
+}
+
+/// Parses a fleet description such as `honest=10,slow=2` into the number of nodes per profile
+pub fn parse_fleet(fleet: &str) -> Result<Vec<(Profile, usize)>> {
+    fleet
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let profile = parts.next().unwrap_or_default().parse()?;
+            let count = parts
+                .next()
+                .ok_or_else(|| anyhow!("Missing a node count for: {}", entry))?
+                .parse()?;
+
+            Ok((profile, count))
+        })
+        .collect()
+}
+
+/// A model that predicts the target of the most similar training record
+///
+/// Records are compared by the number of features with equal values, which works for the
+/// anonymised categorical data sent by the DCL without needing to understand it.
+#[derive(Debug, Default)]
+pub struct NearestNeighbour {
+    headers: StringRecord,
+    records: Vec<StringRecord>,
+}
+
+impl NearestNeighbour {
+    /// Creates a new [`NearestNeighbour`] model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds the target of the training record that shares the most features with `record`
+    fn nearest(&self, record: &StringRecord, features: &[(usize, usize)], target: usize) -> &str {
+        self.records
+            .iter()
+            .max_by_key(|train| {
+                features
+                    .iter()
+                    .filter(|(p, t)| record.get(*p) == train.get(*t))
+                    .count()
+            })
+            .and_then(|train| train.get(target))
+            .unwrap_or_default()
+    }
+}
+
+impl Model for NearestNeighbour {
+    fn train(&mut self, train: &str, _config: &JobConfig, _progress: &Progress) -> Result<()> {
+        let mut reader = Reader::from_reader(train.as_bytes());
+
+        self.headers = reader.headers()?.clone();
+        self.records = reader.records().collect::<Result<_, _>>()?;
+
+        Ok(())
+    }
+
+    fn predict(
+        &mut self,
+        predict: &str,
+        _config: &JobConfig,
+        progress: &Progress,
+    ) -> Result<String> {
+        let (headers, records) = read(predict)?;
+        let column = prediction_column(&headers, &records)
+            .ok_or_else(|| anyhow!("Failed to find the column to predict"))?;
+        let name = &headers[column];
+
+        let target = position(&self.headers, name)?;
+        let record_id = position(&headers, "record_id")?;
+
+        // Pair each feature in the prediction data with the same column in the training data
+        let features: Vec<(usize, usize)> = headers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != column && *i != record_id)
+            .filter_map(|(i, h)| self.headers.iter().position(|t| t == h).map(|t| (i, t)))
+            .collect();
+
+        let mut writer = Writer::from_writer(vec![]);
+        writer.write_record(&["record_id", name])?;
+
+        for (i, record) in records.iter().enumerate() {
+            let prediction = self.nearest(record, &features, target);
+            writer.write_record(&[&record[record_id], prediction])?;
+
+            if i % 100 == 0 {
+                let fraction = i as f64 / records.len() as f64;
+                progress.report("predicting", fraction, "Computing nearest neighbours");
+            }
+        }
+
+        let bytes = writer.into_inner().map_err(|e| anyhow!("{}", e))?;
+
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+/// Reads the headers and records of a CSV dataset
+fn read(data: &str) -> Result<(StringRecord, Vec<StringRecord>)> {
+    let mut reader = Reader::from_reader(data.as_bytes());
+    let headers = reader.headers()?.clone();
+    let records = reader.records().collect::<Result<_, _>>()?;
+
+    Ok((headers, records))
+}
+
+/// Finds the index of a column by name
+fn position(headers: &StringRecord, name: &str) -> Result<usize> {
+    headers
+        .iter()
+        .position(|header| header == name)
+        .ok_or_else(|| anyhow!("The dataset has no column named {}", name))
+}
+
+/// Settings that control how misbehaving profiles misbehave
+#[derive(Clone, Debug)]
+pub struct Behaviour {
+    /// How long slow nodes wait before computing predictions
+    pub slow_delay: Duration,
+    /// How long dropping nodes stay connected for, on average
+    pub drop_after: Duration,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            slow_delay: Duration::from_secs(90),
+            drop_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A model that behaves according to a [`Profile`] and records what happens to it
+#[derive(Debug)]
+pub struct SimulatedModel {
+    profile: Profile,
+    behaviour: Behaviour,
+    counters: Arc<Counters>,
+    inner: NearestNeighbour,
+    train: String,
+}
+
+impl SimulatedModel {
+    /// Creates a new [`SimulatedModel`] for a profile
+    pub fn new(profile: Profile, behaviour: Behaviour, counters: Arc<Counters>) -> Self {
+        Self {
+            profile,
+            behaviour,
+            counters,
+            inner: NearestNeighbour::new(),
+            train: String::new(),
+        }
+    }
+
+    /// Computes predictions according to the profile of the model
+    fn misbehave(
+        &mut self,
+        predict: &str,
+        config: &JobConfig,
+        progress: &Progress,
+    ) -> Result<String> {
+        match self.profile {
+            Profile::Slow => {
+                std::thread::sleep(self.behaviour.slow_delay);
+                self.inner.predict(predict, config, progress)
+            }
+            Profile::Garbage => Ok(garbage(predict.lines().count())),
+            Profile::Constant => constant(&self.train, predict, config.prediction_type),
+            _ => self.inner.predict(predict, config, progress),
+        }
+    }
+}
+
+impl Model for SimulatedModel {
+    fn accept_job(&mut self, _config: &JobConfig) -> bool {
+        Counters::increment(&self.counters.offers);
+
+        let accept = self.profile != Profile::Rejecting;
+
+        if accept {
+            Counters::increment(&self.counters.accepted);
+        }
+
+        accept
+    }
+
+    fn train(&mut self, train: &str, config: &JobConfig, progress: &Progress) -> Result<()> {
+        Counters::increment(&self.counters.jobs);
+
+        self.train = train.to_string();
+        self.inner.train(train, config, progress)
+    }
+
+    fn predict(
+        &mut self,
+        predict: &str,
+        config: &JobConfig,
+        progress: &Progress,
+    ) -> Result<String> {
+        let predictions = self.misbehave(predict, config, progress);
+
+        match predictions {
+            Ok(_) => Counters::increment(&self.counters.predictions),
+            Err(_) => Counters::increment(&self.counters.failures),
+        }
+
+        predictions
+    }
+}
+
+/// Generates lines of random text that cannot be parsed as predictions
+fn garbage(lines: usize) -> String {
+    (0..lines.max(1))
+        .map(|_| crypto::generate_string(16))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Predicts the same value for every record, ignoring all features
+///
+/// Classification problems use the smallest class in the training data, so that the prediction
+/// is valid but uninformed, while regression problems always predict zero.
+fn constant(train: &str, predict: &str, prediction_type: PredictionType) -> Result<String> {
+    let (headers, records) = read(predict)?;
+    let column = prediction_column(&headers, &records)
+        .ok_or_else(|| anyhow!("Failed to find the column to predict"))?;
+    let name = &headers[column];
+    let record_id = position(&headers, "record_id")?;
+
+    let prediction = match prediction_type {
+        PredictionType::Classification => {
+            let (train_headers, train_records) = read(train)?;
+            let target = position(&train_headers, name)?;
+
+            train_records
+                .iter()
+                .filter_map(|record| record.get(target))
+                .filter(|value| !value.is_empty())
+                .min()
+                .unwrap_or_default()
+                .to_string()
+        }
+        PredictionType::Regression => String::from("0"),
+    };
+
+    let mut writer = Writer::from_writer(vec![]);
+    writer.write_record(&["record_id", name])?;
+
+    for record in &records {
+        writer.write_record(&[&record[record_id], prediction.as_str()])?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| anyhow!("{}", e))?;
+
+    Ok(String::from_utf8(bytes)?)
+}
@@ -0,0 +1,91 @@
+//! Creates the user and models that simulated nodes authenticate as.
+
+use anyhow::{anyhow, Result};
+use mongodb::bson::{self, doc, Document};
+use mongodb::Database;
+
+use model_auth::Token;
+use models::models::{AccessToken, ClientModel};
+use models::users::User;
+
+/// The email address of the user that owns every simulated model
+pub const SIMULATOR_EMAIL: &str = "simulator@sybl.local";
+
+/// Finds the simulator user, creating it if it does not exist yet.
+///
+/// The user is given a random password, as nobody should ever log in as it.
+async fn find_or_create_user(database: &Database) -> Result<User> {
+    let users = database.collection("users");
+    let filter = doc! { "email": SIMULATOR_EMAIL };
+
+    if let Some(document) = users.find_one(filter, None).await? {
+        return Ok(bson::de::from_document(document)?);
+    }
+
+    let hash = crypto::hash_password(&crypto::generate_string(32), 1)
+        .map_err(|e| anyhow!("Failed to hash the password: {}", e))?;
+    let mut user = User::new(SIMULATOR_EMAIL, hash.as_str(), "Node", "Simulator");
+    user.client = true;
+
+    let document = bson::ser::to_document(&user)?;
+    users.insert_one(document, None).await?;
+
+    log::info!("Created the simulator user with id={}", user.id);
+
+    Ok(user)
+}
+
+/// Creates a model that is already unlocked and authenticated, returning its access token.
+fn unlocked_model(user: &User, name: String) -> (ClientModel, Token) {
+    let mut model = ClientModel::new(user.id.clone(), name, Vec::new());
+    let access_token = AccessToken::new();
+
+    let token = Token {
+        id: model.id.to_string(),
+        token: base64::encode(&access_token.token.bytes),
+        expires: access_token.expires.to_rfc3339(),
+    };
+
+    model.locked = false;
+    model.authenticated = true;
+    model.challenge = None;
+    model.access_token = Some(access_token);
+
+    (model, token)
+}
+
+/// Replaces the simulator's models with one model per name, returning their access tokens.
+///
+/// Models from previous simulations are removed first, so they do not accumulate in the
+/// database or get picked for clusters while nobody is serving them.
+pub async fn seed_models(database: &Database, names: &[String]) -> Result<Vec<Token>> {
+    let user = find_or_create_user(database).await?;
+    let models = database.collection("models");
+
+    let deleted = models
+        .delete_many(doc! { "user_id": &user.id }, None)
+        .await?;
+
+    log::info!(
+        "Removed {} models from previous simulations",
+        deleted.deleted_count
+    );
+
+    let mut documents: Vec<Document> = Vec::with_capacity(names.len());
+    let mut tokens = Vec::with_capacity(names.len());
+
+    for name in names {
+        let (model, token) = unlocked_model(&user, name.clone());
+
+        documents.push(bson::ser::to_document(&model)?);
+        tokens.push(token);
+    }
+
+    if !documents.is_empty() {
+        models.insert_many(documents, None).await?;
+    }
+
+    log::info!("Created {} simulated models", tokens.len());
+
+    Ok(tokens)
+}
@@ -0,0 +1,129 @@
+//! Tracks what happens to simulated nodes so the behaviour of the DCL can be measured.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::profiles::Profile;
+
+/// Counts the events seen by every node with the same profile
+#[derive(Debug, Default)]
+pub struct Counters {
+    /// The number of job offers received
+    pub offers: AtomicU64,
+    /// The number of job offers accepted
+    pub accepted: AtomicU64,
+    /// The number of jobs that sent a dataset
+    pub jobs: AtomicU64,
+    /// The number of predictions that were returned
+    pub predictions: AtomicU64,
+    /// The number of jobs where the model failed to compute predictions
+    pub failures: AtomicU64,
+    /// The number of times a node connected to the DCL
+    pub connections: AtomicU64,
+    /// The number of times a connection ended, for any reason
+    pub disconnects: AtomicU64,
+}
+
+impl Counters {
+    /// Increments one of the counters
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Gets the current value of one of the counters
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// Collects the counters for every profile in a simulation
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    counters: BTreeMap<Profile, Arc<Counters>>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    /// Creates a new [`Stats`] with empty counters for every profile
+    pub fn new() -> Self {
+        let counters = Profile::ALL
+            .iter()
+            .map(|profile| (*profile, Arc::new(Counters::default())))
+            .collect();
+
+        Self {
+            started: Instant::now(),
+            counters,
+        }
+    }
+
+    /// Gets the counters for a profile
+    pub fn counters(&self, profile: Profile) -> Arc<Counters> {
+        Arc::clone(&self.counters[&profile])
+    }
+
+    /// Calculates the number of predictions returned per minute across all nodes
+    pub fn throughput(&self) -> f64 {
+        let predictions: u64 = self
+            .counters
+            .values()
+            .map(|c| Counters::get(&c.predictions))
+            .sum();
+        let minutes = self.started.elapsed().as_secs_f64() / 60.0;
+
+        if minutes > 0.0 {
+            predictions as f64 / minutes
+        } else {
+            0.0
+        }
+    }
+
+    /// Formats a table of the counters for each profile that has seen any activity
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:<10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+            "profile", "offers", "accepted", "jobs", "preds", "failed", "connects", "drops"
+        );
+
+        for (profile, c) in &self.counters {
+            let values = [
+                &c.offers,
+                &c.accepted,
+                &c.jobs,
+                &c.predictions,
+                &c.failures,
+                &c.connections,
+                &c.disconnects,
+            ];
+
+            let values: Vec<u64> = values.iter().map(|v| Counters::get(v)).collect();
+
+            if values.iter().all(|v| *v == 0) {
+                continue;
+            }
+
+            report.push_str(&format!("{:<10}", profile.name()));
+
+            for value in values {
+                report.push_str(&format!(" {:>8}", value));
+            }
+
+            report.push('\n');
+        }
+
+        report.push_str(&format!(
+            "throughput: {:.2} predictions/minute",
+            self.throughput()
+        ));
+
+        report
+    }
+}
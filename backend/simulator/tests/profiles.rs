@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::mpsc::unbounded_channel;
+
+use dcn_client::{JobConfig, Model, Progress};
+use models::jobs::PredictionType;
+use simulator::{parse_fleet, Behaviour, Counters, Profile, SimulatedModel, Stats};
+
+static TRAIN: &str = "record_id,a,b,c\n1,x,p,yes\n2,y,q,no\n3,z,p,maybe\n";
+static PREDICT: &str = "record_id,a,b,c\n4,y,q,\n5,z,p,\n";
+
+fn config(prediction_type: PredictionType) -> JobConfig {
+    JobConfig {
+        message_creation_timestamp: 0,
+        prediction_cutoff_timestamp: 60,
+        cluster_size: 1,
+        column_types: Vec::new(),
+        prediction_column: String::from("c"),
+        prediction_type,
+    }
+}
+
+fn simulate(profile: Profile, counters: Arc<Counters>) -> Result<String> {
+    let (sender, _receiver) = unbounded_channel();
+    let progress = Progress::new(sender);
+    let config = config(PredictionType::Classification);
+    let behaviour = Behaviour {
+        slow_delay: Duration::from_millis(1),
+        drop_after: Duration::from_secs(1),
+    };
+
+    let mut model = SimulatedModel::new(profile, behaviour, counters);
+    assert!(model.accept_job(&config));
+    model.train(TRAIN, &config, &progress)?;
+    model.predict(PREDICT, &config, &progress)
+}
+
+#[test]
+fn fleets_can_be_parsed() -> Result<()> {
+    let fleet = parse_fleet("honest=10, slow=2,garbage=1")?;
+
+    assert_eq!(
+        fleet,
+        vec![
+            (Profile::Honest, 10),
+            (Profile::Slow, 2),
+            (Profile::Garbage, 1)
+        ]
+    );
+
+    assert!(parse_fleet("honest").is_err());
+    assert!(parse_fleet("lazy=1").is_err());
+    assert!(parse_fleet("honest=many").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn honest_nodes_predict_the_nearest_neighbour() -> Result<()> {
+    let counters = Arc::new(Counters::default());
+    let predictions = simulate(Profile::Honest, Arc::clone(&counters))?;
+
+    assert_eq!(predictions, "record_id,c\n4,no\n5,maybe\n");
+    assert_eq!(Counters::get(&counters.predictions), 1);
+
+    Ok(())
+}
+
+#[test]
+fn slow_nodes_still_predict_honestly() -> Result<()> {
+    let predictions = simulate(Profile::Slow, Arc::new(Counters::default()))?;
+
+    assert_eq!(predictions, "record_id,c\n4,no\n5,maybe\n");
+
+    Ok(())
+}
+
+#[test]
+fn constant_nodes_predict_the_same_class() -> Result<()> {
+    let predictions = simulate(Profile::Constant, Arc::new(Counters::default()))?;
+
+    assert_eq!(predictions, "record_id,c\n4,maybe\n5,maybe\n");
+
+    Ok(())
+}
+
+#[test]
+fn garbage_nodes_do_not_return_csv() -> Result<()> {
+    let predictions = simulate(Profile::Garbage, Arc::new(Counters::default()))?;
+
+    assert!(!predictions.starts_with("record_id"));
+    assert_eq!(predictions.lines().count(), PREDICT.lines().count());
+
+    Ok(())
+}
+
+#[test]
+fn rejecting_nodes_refuse_every_job() {
+    let stats = Stats::new();
+    let counters = stats.counters(Profile::Rejecting);
+    let mut model = SimulatedModel::new(
+        Profile::Rejecting,
+        Behaviour::default(),
+        Arc::clone(&counters),
+    );
+
+    assert!(!model.accept_job(&config(PredictionType::Classification)));
+    assert_eq!(Counters::get(&counters.offers), 1);
+    assert_eq!(Counters::get(&counters.accepted), 0);
+
+    assert!(stats.report().contains("rejecting"));
+    assert!(!stats.report().contains("honest"));
+}
@@ -10,6 +10,8 @@ use mongodb::{
 
 pub const COMMISSION_RATE: f64 = 0.25;
 pub const SIZE_DENOMINATOR: i32 = 1000;
+/// Users are emailed when their balance falls below this many credits
+pub const LOW_CREDITS_THRESHOLD: i32 = 1000;
 
 /// Reimburses a client based on their model performance.
 ///
@@ -1,5 +1,7 @@
 //! Contains utility functions and types for CSV type inference.
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate serde;
 
@@ -15,6 +17,7 @@ pub mod analysis;
 pub mod anon;
 pub mod compress;
 pub mod finance;
+pub mod metrics;
 
 /// Represents the types that a CSV column could have.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
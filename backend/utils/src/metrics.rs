@@ -0,0 +1,166 @@
+//! Defines the metrics shared by each component, exported in the Prometheus text format.
+//!
+//! Metrics are registered with the default `prometheus` registry the first time they are used, so
+//! each binary only exports the metrics it actually updates. Values that are cheaper to read when
+//! scraped than to track continuously can be updated through [`on_gather`]. The registry is
+//! rendered with [`gather`] and, with the `server` feature, can be served over HTTP on `/metrics`
+//! with [`serve`].
+//!
+//! Labels are limited to values from a small, fixed set, as every combination of them creates a
+//! new series. Identifiers such as those of models or projects should never be used as labels.
+
+use std::sync::Mutex;
+
+use chrono::Utc;
+use prometheus::{
+    register_gauge, register_gauge_vec, register_histogram_vec, register_int_counter_vec, Encoder,
+    Gauge, GaugeVec, HistogramVec, IntCounterVec, TextEncoder,
+};
+
+/// The content type of the Prometheus text format
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Histogram buckets suitable for durations, in seconds
+pub const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0,
+];
+
+/// A function run before the metrics are gathered
+type Collector = Box<dyn Fn() + Send + Sync>;
+
+lazy_static! {
+    static ref COLLECTORS: Mutex<Vec<Collector>> = Mutex::new(Vec::new());
+
+    /// The number of nodes in the DCL that are alive and not in use
+    pub static ref ACTIVE_NODES: Gauge = register_gauge!(
+        "dcl_active_nodes",
+        "The number of nodes that are alive and not in use"
+    )
+    .expect("Failed to register dcl_active_nodes");
+
+    /// The number of jobs waiting in the DCL job queue
+    pub static ref QUEUE_DEPTH: Gauge = register_gauge!(
+        "dcl_queue_depth",
+        "The number of jobs waiting in the job queue"
+    )
+    .expect("Failed to register dcl_queue_depth");
+
+    /// The time taken for clusters to run jobs, labelled by `outcome`
+    pub static ref JOB_DURATION: HistogramVec = register_histogram_vec!(
+        "dcl_job_duration_seconds",
+        "The time taken for a cluster to run a job",
+        &["outcome"],
+        DURATION_BUCKETS.to_vec()
+    )
+    .expect("Failed to register dcl_job_duration_seconds");
+
+    /// Failures of nodes while running jobs, labelled by `reason`
+    ///
+    /// Failures of each node are counted by the DCL's admin endpoint instead, as model identifiers
+    /// cannot be used as labels.
+    pub static ref NODE_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "dcl_node_failures_total",
+        "The number of times a node failed while running a job",
+        &["reason"]
+    )
+    .expect("Failed to register dcl_node_failures_total");
+
+    /// The time between a message being produced to Kafka and consumed, labelled by `topic`
+    pub static ref KAFKA_CONSUME_LAG: GaugeVec = register_gauge_vec!(
+        "kafka_consume_lag_seconds",
+        "The time between the most recent message being produced and consumed",
+        &["topic"]
+    )
+    .expect("Failed to register kafka_consume_lag_seconds");
+
+    /// The latency of API requests, labelled by `method`, `route` and `status`
+    pub static ref API_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "api_request_duration_seconds",
+        "The time taken to respond to an API request",
+        &["method", "route", "status"],
+        DURATION_BUCKETS.to_vec()
+    )
+    .expect("Failed to register api_request_duration_seconds");
+
+    /// The time taken to analyse a dataset, labelled by `outcome`
+    pub static ref ANALYTICS_PROCESSING_TIME: HistogramVec = register_histogram_vec!(
+        "analytics_processing_seconds",
+        "The time taken to analyse a dataset",
+        &["outcome"],
+        DURATION_BUCKETS.to_vec()
+    )
+    .expect("Failed to register analytics_processing_seconds");
+}
+
+/// Registers a function to run before each time the metrics are gathered.
+pub fn on_gather(collector: impl Fn() + Send + Sync + 'static) {
+    COLLECTORS.lock().unwrap().push(Box::new(collector));
+}
+
+/// Renders all registered metrics in the Prometheus text format.
+pub fn gather() -> String {
+    for collector in COLLECTORS.lock().unwrap().iter() {
+        collector();
+    }
+
+    let mut buffer = Vec::new();
+
+    // Encoding only fails for invalid metric families, which registration already rejects
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Failed to encode the metrics: {}", e);
+    }
+
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+/// Records the lag of a consumed Kafka message, given its timestamp in milliseconds.
+pub fn record_kafka_lag(topic: &str, timestamp_millis: Option<i64>) {
+    if let Some(timestamp) = timestamp_millis {
+        let lag = (Utc::now().timestamp_millis() - timestamp).max(0);
+        KAFKA_CONSUME_LAG
+            .with_label_values(&[topic])
+            .set(lag as f64 / 1000.0);
+    }
+}
+
+/// Serves the metrics on `/metrics` with the given port.
+///
+/// The metrics are only served on localhost in debug builds, and on every interface otherwise, so
+/// the port should not be exposed publicly.
+#[cfg(feature = "server")]
+pub async fn serve(port: u16) -> anyhow::Result<()> {
+    use std::convert::Infallible;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response, Server, StatusCode};
+
+    // Bind to the external socket in production mode
+    #[cfg(not(debug_assertions))]
+    let ip = Ipv4Addr::UNSPECIFIED;
+
+    #[cfg(debug_assertions)]
+    let ip = Ipv4Addr::LOCALHOST;
+
+    let socket = SocketAddr::V4(SocketAddrV4::new(ip, port));
+
+    let service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+            if request.method() == Method::GET && request.uri().path() == "/metrics" {
+                Response::builder()
+                    .header("Content-Type", CONTENT_TYPE)
+                    .body(Body::from(gather()))
+            } else {
+                Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+            }
+        }))
+    });
+
+    log::info!("Serving metrics on: {}", socket);
+
+    Server::try_bind(&socket)?.serve(service).await?;
+
+    Ok(())
+}
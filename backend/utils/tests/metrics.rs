@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use utils::metrics::Registry;
+
+#[test]
+fn counters_are_rendered_per_label_set() {
+    let registry = Registry::default();
+    let counter = registry.counter("failures_total", "The number of failures");
+
+    counter.inc(&[("reason", "timeout")]);
+    counter.inc(&[("reason", "timeout")]);
+    counter.inc_by(&[("reason", "stream")], 3.0);
+
+    let output = registry.gather();
+
+    assert!(output.contains("# TYPE failures_total counter"));
+    assert!(output.contains("failures_total{reason=\"timeout\"} 2"));
+    assert!(output.contains("failures_total{reason=\"stream\"} 3"));
+}
+
+#[test]
+fn counters_cannot_decrease() {
+    let registry = Registry::default();
+    let counter = registry.counter("requests_total", "The number of requests");
+
+    counter.inc(&[]);
+    counter.inc_by(&[], -5.0);
+
+    assert_eq!(counter.get(&[]), 1.0);
+}
+
+#[test]
+fn gauges_can_be_updated_when_gathered() {
+    let registry = Registry::default();
+    let gauge = registry.gauge("queue_depth", "The depth of the queue");
+
+    let collected = gauge.clone();
+    registry.on_gather(move || collected.set(&[], 7.0));
+
+    let output = registry.gather();
+
+    assert!(output.contains("# TYPE queue_depth gauge"));
+    assert!(output.contains("queue_depth 7"));
+}
+
+#[test]
+fn histograms_have_cumulative_buckets() {
+    let registry = Registry::default();
+    let histogram = registry.histogram("duration_seconds", "Durations", &[0.5, 1.0]);
+
+    histogram.observe(&[], 0.25);
+    histogram.observe_duration(&[], Duration::from_millis(750));
+    histogram.observe(&[], 5.0);
+
+    let output = registry.gather();
+
+    assert!(output.contains("duration_seconds_bucket{le=\"0.5\"} 1"));
+    assert!(output.contains("duration_seconds_bucket{le=\"1\"} 2"));
+    assert!(output.contains("duration_seconds_bucket{le=\"+Inf\"} 3"));
+    assert!(output.contains("duration_seconds_sum 6"));
+    assert!(output.contains("duration_seconds_count 3"));
+    assert_eq!(histogram.count(&[]), 3);
+}
+
+#[test]
+fn label_values_are_escaped() {
+    let registry = Registry::default();
+    let gauge = registry.gauge("labelled", "A labelled gauge");
+
+    gauge.set(&[("route", "a\"b\\c")], 1.0);
+
+    assert!(registry
+        .gather()
+        .contains("labelled{route=\"a\\\"b\\\\c\"} 1"));
+}